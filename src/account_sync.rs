@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+use serde_json::{Value, json};
+
+use crate::cache::CacheStore;
+use crate::client::BangumiClient;
+use crate::error::{AppError, Result};
+use crate::models::{Collection, collection_status_name};
+
+/// How far along a status is, so a sync never moves an entry backwards.
+/// 搁置 counts as far as 在看; 抛弃 sits between that and 看过.
+fn status_rank(collection_type: u8) -> u8 {
+    match collection_type {
+        1 => 0,
+        3 | 4 => 1,
+        5 => 2,
+        2 => 3,
+        _ => 0,
+    }
+}
+
+/// The fields a sync writes.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    subject_type: u8,
+    collection_type: u8,
+    rate: u8,
+    comment: String,
+    tags: Vec<String>,
+}
+
+impl Entry {
+    fn of(col: &Collection) -> Self {
+        let mut tags = col.tags.clone();
+        tags.sort();
+        tags.dedup();
+        Self {
+            subject_type: col.subject.subject_type,
+            collection_type: col.collection_type,
+            rate: col.rate,
+            comment: col.comment.as_deref().unwrap_or("").trim().to_string(),
+            tags,
+        }
+    }
+
+    fn body(&self) -> Value {
+        json!({
+            "type": self.collection_type,
+            "rate": self.rate,
+            "comment": self.comment,
+            "tags": self.tags,
+        })
+    }
+}
+
+/// A write the sync will make.
+pub struct Planned {
+    pub subject_id: u64,
+    pub name: String,
+    /// `None` for entries the target account doesn't have yet.
+    pub changes: Option<Vec<String>>,
+    body: Value,
+}
+
+/// An entry both accounts have with data that can't be merged without choosing a side.
+pub struct Conflict {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct SyncPlan {
+    pub writes: Vec<Planned>,
+    pub conflicts: Vec<Conflict>,
+    pub unchanged: usize,
+}
+
+fn display_name(col: &Collection) -> String {
+    let name = if col.subject.name_cn.is_empty() {
+        &col.subject.name
+    } else {
+        &col.subject.name_cn
+    };
+    format!("{} ({})", name, col.subject_id)
+}
+
+/// Merge a source entry into the target one without downgrading it: the status
+/// only moves forward, ratings and comments are only filled in, and tags are
+/// combined. Returns the merged entry and the fields that conflict; with
+/// `prefer_source`, conflicting ratings take the higher one and conflicting
+/// comments the source's.
+fn merge(source: &Entry, target: &Entry, prefer_source: bool) -> (Entry, Vec<String>) {
+    let mut merged = target.clone();
+    let mut conflicts = Vec::new();
+    let status = |t| collection_status_name(t, target.subject_type);
+
+    let (src_rank, dst_rank) = (
+        status_rank(source.collection_type),
+        status_rank(target.collection_type),
+    );
+    if src_rank > dst_rank {
+        merged.collection_type = source.collection_type;
+    } else if src_rank == dst_rank && source.collection_type != target.collection_type {
+        conflicts.push(format!(
+            "status {} / {}",
+            status(source.collection_type),
+            status(target.collection_type)
+        ));
+        if prefer_source {
+            merged.collection_type = source.collection_type;
+        }
+    }
+
+    if target.rate == 0 {
+        merged.rate = source.rate;
+    } else if source.rate != 0 && source.rate != target.rate {
+        conflicts.push(format!("rating {} / {}", source.rate, target.rate));
+        if prefer_source {
+            merged.rate = source.rate.max(target.rate);
+        }
+    }
+
+    if target.comment.is_empty() {
+        merged.comment = source.comment.clone();
+    } else if !source.comment.is_empty() && source.comment != target.comment {
+        conflicts.push("comment".to_string());
+        if prefer_source {
+            merged.comment = source.comment.clone();
+        }
+    }
+
+    merged.tags.extend(source.tags.iter().cloned());
+    merged.tags.sort();
+    merged.tags.dedup();
+    (merged, conflicts)
+}
+
+/// Names of the fields that differ between two entries.
+fn changed_fields(from: &Entry, to: &Entry) -> Vec<String> {
+    let mut fields = Vec::new();
+    if from.collection_type != to.collection_type {
+        fields.push(format!(
+            "status {} → {}",
+            collection_status_name(from.collection_type, from.subject_type),
+            collection_status_name(to.collection_type, to.subject_type)
+        ));
+    }
+    if from.rate != to.rate {
+        fields.push(format!("rating {} → {}", from.rate, to.rate));
+    }
+    if from.comment != to.comment {
+        fields.push("comment".to_string());
+    }
+    if from.tags != to.tags {
+        fields.push("tags".to_string());
+    }
+    fields
+}
+
+/// Work out which entries of `source` the target account is missing or has in
+/// an older state.
+pub fn plan(source: &[Collection], target: &[Collection], prefer_source: bool) -> SyncPlan {
+    let target: HashMap<u64, &Collection> = target.iter().map(|c| (c.subject_id, c)).collect();
+    let mut plan = SyncPlan::default();
+    for src in source {
+        let wanted = Entry::of(src);
+        let Some(dst) = target.get(&src.subject_id) else {
+            plan.writes.push(Planned {
+                subject_id: src.subject_id,
+                name: display_name(src),
+                changes: None,
+                body: wanted.body(),
+            });
+            continue;
+        };
+        let current = Entry::of(dst);
+        let (merged, conflicts) = merge(&wanted, &current, prefer_source);
+        if !conflicts.is_empty() && !prefer_source {
+            plan.conflicts.push(Conflict {
+                name: display_name(src),
+                fields: conflicts,
+            });
+            continue;
+        }
+        if merged == current {
+            plan.unchanged += 1;
+            continue;
+        }
+        plan.writes.push(Planned {
+            subject_id: src.subject_id,
+            name: display_name(src),
+            changes: Some(changed_fields(&current, &merged)),
+            body: merged.body(),
+        });
+    }
+    plan
+}
+
+impl SyncPlan {
+    pub fn print(&self, source_user: &str) {
+        println!(
+            "\n== 从 {} 同步: {} 条写入 ==",
+            source_user,
+            self.writes.len()
+        );
+        for w in &self.writes {
+            match &w.changes {
+                None => println!("  + {}", w.name),
+                Some(changes) => println!("  ~ {}: {}", w.name, changes.join(", ")),
+            }
+        }
+        if !self.conflicts.is_empty() {
+            println!(
+                "\n== 冲突（已跳过，--prefer-source 以来源为准）: {} ==",
+                self.conflicts.len()
+            );
+            for c in &self.conflicts {
+                println!("  ! {}: {}", c.name, c.fields.join(", "));
+            }
+        }
+        println!("\n{} entries already up to date", self.unchanged);
+    }
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+    pub written: usize,
+    pub resumed: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Apply the planned writes. Each success is recorded in the cache under the
+/// source user, so a rerun after a crash skips writes that already went through.
+pub async fn apply(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    uid: u64,
+    source_user: &str,
+    plan: &SyncPlan,
+    on_progress: impl Fn(&str),
+) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    for w in &plan.writes {
+        on_progress(&w.name);
+        let done_key = format!("{}/synced/{}/{}", uid, source_user, w.subject_id);
+        if cache.get::<Value>(&done_key).as_ref() == Some(&w.body) {
+            report.resumed += 1;
+            continue;
+        }
+        match client.update_collection(w.subject_id, &w.body).await {
+            Ok(()) => {
+                info!("Synced {}", w.name);
+                cache.set(&done_key, &w.body)?;
+                report.written += 1;
+            }
+            Err(err @ AppError::Unauthorized { .. }) => return Err(err),
+            Err(err) => {
+                warn!("Sync failed for {}: {}", w.name, err);
+                report.failed.push((w.name.clone(), err.to_string()));
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl SyncReport {
+    pub fn print(&self) {
+        if !self.failed.is_empty() {
+            println!("\n== 失败: {} ==", self.failed.len());
+            for (name, error) in &self.failed {
+                println!("  {} — {}", name, error);
+            }
+        }
+        println!(
+            "\nSync: {} written, {} already written by an earlier run, {} failed",
+            self.written,
+            self.resumed,
+            self.failed.len()
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local};
+use owo_colors::Style;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::export::write_atomic;
+use crate::models::Collection;
+use crate::summary::{group_style, paint};
+
+/// Widest bar, in characters; months with fewer items get a character each.
+const BAR_WIDTH: u64 = 40;
+
+/// Collections last updated in one month, by collection type.
+#[derive(Debug, Default, Serialize)]
+pub struct MonthActivity {
+    /// `YYYY-MM`.
+    pub month: String,
+    /// Marked as done (看过).
+    pub done: u64,
+    /// Added to the wishlist (想看).
+    pub wish: u64,
+    /// 在看, 搁置 and 抛弃.
+    pub other: u64,
+}
+
+impl MonthActivity {
+    fn total(&self) -> u64 {
+        self.done + self.wish + self.other
+    }
+}
+
+/// Collections bucketed by the month of their last update, oldest month first.
+#[derive(Debug, Serialize)]
+pub struct ActivityReport {
+    pub months: Vec<MonthActivity>,
+    /// Collections last updated before the first month.
+    pub earlier: u64,
+}
+
+/// Months since year 0, so consecutive months differ by one.
+fn month_index(date: &DateTime<Local>) -> i64 {
+    date.year() as i64 * 12 + date.month0() as i64
+}
+
+impl ActivityReport {
+    /// Bucket `collections` over the `months` months up to and including the one of `now`.
+    pub fn new(collections: &[Collection], months: u32, now: DateTime<Local>) -> Self {
+        let last = month_index(&now);
+        let first = last - months.max(1) as i64 + 1;
+        let mut buckets: Vec<MonthActivity> = (first..=last)
+            .map(|i| MonthActivity {
+                month: format!("{:04}-{:02}", i.div_euclid(12), i.rem_euclid(12) + 1),
+                ..Default::default()
+            })
+            .collect();
+        let mut earlier = 0;
+        for col in collections {
+            let i = month_index(&col.updated_at.with_timezone(&Local));
+            if i < first {
+                earlier += 1;
+                continue;
+            }
+            // Updates dated after `now` (clock skew) count toward the last month.
+            let bucket = &mut buckets[(i.min(last) - first) as usize];
+            match col.collection_type {
+                1 => bucket.wish += 1,
+                2 => bucket.done += 1,
+                _ => bucket.other += 1,
+            }
+        }
+        Self {
+            months: buckets,
+            earlier,
+        }
+    }
+
+    /// Print a horizontal bar per month, split into done (`#`), wish (`+`)
+    /// and other (`.`) segments.
+    pub fn print(&self, color: bool) {
+        let total: u64 = self.months.iter().map(MonthActivity::total).sum();
+        println!(
+            "\n== 每月标注: 最近 {} 个月, 共 {} 个 ==",
+            self.months.len(),
+            total
+        );
+        let max = self
+            .months
+            .iter()
+            .map(MonthActivity::total)
+            .max()
+            .unwrap_or(0);
+        let width = |count: u64| {
+            if max > BAR_WIDTH {
+                (count * BAR_WIDTH).div_ceil(max)
+            } else {
+                count
+            }
+        };
+        let segment = |count: u64, glyph: &str, style: Style| {
+            paint(&glyph.repeat(width(count) as usize), style, color)
+        };
+        for m in &self.months {
+            println!(
+                "  {} | {}{}{} {}",
+                m.month,
+                segment(m.done, "#", group_style(2)),
+                segment(m.wish, "+", group_style(1)),
+                segment(m.other, ".", group_style(0)),
+                m.total()
+            );
+        }
+        println!(
+            "  {} 看过  {} 想看  {} 其他",
+            paint("#", group_style(2), color),
+            paint("+", group_style(1), color),
+            paint(".", group_style(0), color)
+        );
+        if self.earlier > 0 {
+            println!("  更早: {}", self.earlier);
+        }
+        println!();
+    }
+
+    /// Write the report as `activity.json` in the output directory.
+    pub fn write_json(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("activity.json");
+        write_atomic(&path, |w| Ok(serde_json::to_writer_pretty(w, self)?))?;
+        Ok(path)
+    }
+}
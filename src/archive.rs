@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::warn;
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::client::RawObserver;
+use crate::error::Result;
+use crate::export::write_atomic;
+
+/// Raw API responses saved as downloaded, under `<output>/archive/`:
+///
+/// - `collections/<offset>.json`: collection pages
+/// - `subjects/<id>/subject.json`: subject detail
+/// - `subjects/<id>/episodes_<offset>.json`: episode pages
+/// - `subjects/<id>/progress_<offset>.json`: episode progress pages
+/// - `subjects/<id>/progress.json`: episode progress from the legacy endpoint
+/// - `subjects/<id>/relations.json`: related subjects
+///
+/// Files from earlier runs are kept, so responses served from the cache stay archived.
+pub struct Archive {
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct ArchiveIndex {
+    collections: Vec<String>,
+    /// Files archived per subject id, relative to the subject's directory.
+    subjects: BTreeMap<u64, Vec<String>>,
+}
+
+/// Archive file for a response, relative to the archive directory; `None` for
+/// endpoints that aren't archived.
+fn relative_path(url: &Url) -> Option<PathBuf> {
+    let query = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    };
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        ["v0", "users", _, "collections"] => Some(
+            Path::new("collections").join(format!("{}.json", query("offset").unwrap_or_default())),
+        ),
+        ["v0", "subjects", id] => Some(Path::new("subjects").join(id).join("subject.json")),
+        ["v0", "subjects", id, "subject_relations"] => {
+            Some(Path::new("subjects").join(id).join("relations.json"))
+        }
+        ["v0", "episodes"] => Some(
+            Path::new("subjects")
+                .join(query("subject_id")?)
+                .join(format!(
+                    "episodes_{}.json",
+                    query("offset").unwrap_or_default()
+                )),
+        ),
+        ["v0", "users", "-", "collections", id, "episodes"] => {
+            Some(Path::new("subjects").join(id).join(format!(
+                "progress_{}.json",
+                query("offset").unwrap_or_default()
+            )))
+        }
+        ["user", _, "progress"] => Some(
+            Path::new("subjects")
+                .join(query("subject_id")?)
+                .join("progress.json"),
+        ),
+        _ => None,
+    }
+}
+
+/// Sorted file names in `dir`, or none if it doesn't exist.
+fn file_names(dir: &Path) -> Result<Vec<String>> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(Vec::new());
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+impl Archive {
+    pub fn new(out_dir: &Path) -> Result<Self> {
+        let dir = out_dir.join("archive");
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Save one response body, if its endpoint is archived.
+    fn store(&self, url: &Url, body: &[u8]) -> Result<()> {
+        let Some(relative) = relative_path(url) else {
+            return Ok(());
+        };
+        let path = self.dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, |w| Ok(w.write_all(body)?))
+    }
+
+    /// A client observer that archives every response body it downloads.
+    pub fn observer(self: &Arc<Self>) -> RawObserver {
+        let archive = Arc::clone(self);
+        Arc::new(move |url, body| {
+            if let Err(e) = archive.store(url, body) {
+                warn!("Failed to archive {}: {}", url, e);
+            }
+        })
+    }
+
+    /// Write `index.json` listing everything in the archive by subject id.
+    pub fn write_index(&self) -> Result<PathBuf> {
+        let mut subjects = BTreeMap::new();
+        if let Ok(entries) = std::fs::read_dir(self.dir.join("subjects")) {
+            for entry in entries {
+                let entry = entry?;
+                let Some(id) = entry.file_name().to_string_lossy().parse::<u64>().ok() else {
+                    continue;
+                };
+                subjects.insert(id, file_names(&entry.path())?);
+            }
+        }
+        let index = ArchiveIndex {
+            collections: file_names(&self.dir.join("collections"))?,
+            subjects,
+        };
+        let path = self.dir.join("index.json");
+        write_atomic(&path, |w| Ok(serde_json::to_writer_pretty(w, &index)?))?;
+        Ok(path)
+    }
+}
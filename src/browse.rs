@@ -0,0 +1,477 @@
+use std::cmp::Reverse;
+use std::io::Write;
+
+use chrono::{DateTime, Local, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::error::Result;
+use crate::models::{Collection, ExportRecord, collection_status_name, subject_type_name};
+use crate::oauth::open_browser;
+use crate::records::NO_PROGRESS;
+use crate::summary::collation_key;
+
+/// Collection types in the order the status filter cycles through them.
+const STATUSES: [u8; 5] = [1, 3, 2, 4, 5];
+/// Subject types in the order the type filter cycles through them.
+const SUBJECT_TYPES: [u8; 5] = [2, 1, 4, 3, 6];
+
+/// One collection entry as shown in the browser.
+pub struct BrowseItem {
+    pub subject_id: u64,
+    pub name: String,
+    /// The original name when it differs from `name`.
+    pub name_orig: Option<String>,
+    pub subject_type: u8,
+    pub collection_type: u8,
+    pub rate: u8,
+    pub tags: Vec<String>,
+    pub comment: String,
+    pub updated_at: DateTime<Utc>,
+    /// Completeness such as `12/24 (50%)`, when detail data is cached.
+    pub progress: Option<String>,
+    /// Watched episodes as a run-length string, when detail data is cached.
+    pub watched: Option<String>,
+}
+
+impl BrowseItem {
+    /// An item from collection data, with progress taken from `detail` if given.
+    pub fn new(col: &Collection, detail: Option<&ExportRecord>) -> Self {
+        let subject = &col.subject;
+        let (name, name_orig) = if subject.name_cn.is_empty() || subject.name_cn == subject.name {
+            (subject.name.clone(), None)
+        } else {
+            (subject.name_cn.clone(), Some(subject.name.clone()))
+        };
+        let detail = detail.filter(|r| r.completeness != "ERROR" && r.completeness != NO_PROGRESS);
+        Self {
+            subject_id: col.subject_id,
+            name,
+            name_orig,
+            subject_type: subject.subject_type,
+            collection_type: col.collection_type,
+            rate: col.rate,
+            tags: col.tags.clone(),
+            comment: col.comment.clone().unwrap_or_default(),
+            updated_at: col.updated_at,
+            progress: detail.map(|r| format!("{} ({})", r.completeness, r.completeness_pct)),
+            watched: detail
+                .map(|r| r.watched_eps.clone())
+                .filter(|w| !w.is_empty()),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("https://bgm.tv/subject/{}", self.subject_id)
+    }
+
+    fn status(&self) -> &'static str {
+        collection_status_name(self.collection_type, self.subject_type)
+    }
+
+    /// Whether the lowercased `query` occurs in the names, tags or comment.
+    fn matches(&self, query: &str) -> bool {
+        query.is_empty()
+            || [
+                Some(&self.name),
+                self.name_orig.as_ref(),
+                Some(&self.comment),
+            ]
+            .into_iter()
+            .flatten()
+            .chain(&self.tags)
+            .any(|text| text.to_lowercase().contains(query))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Updated,
+    Rating,
+    Name,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            Self::Updated => Self::Rating,
+            Self::Rating => Self::Name,
+            Self::Name => Self::Updated,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Updated => "最后标注",
+            Self::Rating => "评分",
+            Self::Name => "名称",
+        }
+    }
+}
+
+/// The next value of a filter cycling through `values` and back to no filter.
+fn cycle(current: Option<u8>, values: &[u8]) -> Option<u8> {
+    match current {
+        None => values.first().copied(),
+        Some(v) => values
+            .iter()
+            .position(|&x| x == v)
+            .and_then(|i| values.get(i + 1))
+            .copied(),
+    }
+}
+
+struct App {
+    items: Vec<BrowseItem>,
+    /// Indexes into `items` that pass the filters, in display order.
+    visible: Vec<usize>,
+    filter: String,
+    /// Keys go to the filter text rather than to commands.
+    editing: bool,
+    status: Option<u8>,
+    subject_type: Option<u8>,
+    sort: SortKey,
+    table: TableState,
+    /// Feedback on the last command, shown in the footer.
+    message: Option<String>,
+}
+
+impl App {
+    fn new(items: Vec<BrowseItem>) -> Self {
+        let mut app = Self {
+            items,
+            visible: Vec::new(),
+            filter: String::new(),
+            editing: false,
+            status: None,
+            subject_type: None,
+            sort: SortKey::Updated,
+            table: TableState::default(),
+            message: None,
+        };
+        app.refresh();
+        app
+    }
+
+    /// Recompute the visible items, keeping the selected item if it is still shown.
+    fn refresh(&mut self) {
+        let selected = self.selected().map(|item| item.subject_id);
+        let query = self.filter.to_lowercase();
+        let mut visible: Vec<usize> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| {
+                self.status.is_none_or(|s| item.collection_type == s)
+                    && self.subject_type.is_none_or(|t| item.subject_type == t)
+                    && item.matches(&query)
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let items = &self.items;
+        match self.sort {
+            SortKey::Updated => visible.sort_by_key(|&i| Reverse(items[i].updated_at)),
+            // Unrated items sort last.
+            SortKey::Rating => visible.sort_by_key(|&i| {
+                let rate = items[i].rate;
+                (rate == 0, Reverse(rate), Reverse(items[i].updated_at))
+            }),
+            SortKey::Name => visible.sort_by_cached_key(|&i| collation_key(&items[i].name)),
+        }
+        self.visible = visible;
+        let index = selected
+            .and_then(|id| {
+                self.visible
+                    .iter()
+                    .position(|&i| self.items[i].subject_id == id)
+            })
+            .or((!self.visible.is_empty()).then_some(0));
+        self.table.select(index);
+    }
+
+    fn selected(&self) -> Option<&BrowseItem> {
+        let i = *self.visible.get(self.table.selected()?)?;
+        self.items.get(i)
+    }
+
+    /// Move the selection by `delta` rows, stopping at either end.
+    fn move_by(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.table
+            .select(Some((current + delta).clamp(0, last) as usize));
+    }
+
+    /// Handle a key press; returns `false` to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        if self.editing {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.refresh();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.refresh();
+                }
+                KeyCode::Up => self.move_by(-1),
+                KeyCode::Down => self.move_by(1),
+                _ => {}
+            }
+            return true;
+        }
+        self.message = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Char('x') => {
+                self.filter.clear();
+                self.status = None;
+                self.subject_type = None;
+                self.refresh();
+            }
+            KeyCode::Char('c') => {
+                self.status = cycle(self.status, &STATUSES);
+                self.refresh();
+            }
+            KeyCode::Char('t') => {
+                self.subject_type = cycle(self.subject_type, &SUBJECT_TYPES);
+                self.refresh();
+            }
+            KeyCode::Char('s') => {
+                self.sort = self.sort.next();
+                self.refresh();
+            }
+            KeyCode::Char('j') | KeyCode::Down => self.move_by(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_by(-1),
+            KeyCode::PageDown => self.move_by(20),
+            KeyCode::PageUp => self.move_by(-20),
+            KeyCode::Char('g') | KeyCode::Home => self.move_by(isize::MIN / 2),
+            KeyCode::Char('G') | KeyCode::End => self.move_by(isize::MAX / 2),
+            KeyCode::Char('o') | KeyCode::Enter => {
+                if let Some(url) = self.selected().map(BrowseItem::url) {
+                    open_browser(&url);
+                    self.message = Some(format!("已打开 {}", url));
+                }
+            }
+            KeyCode::Char('y') => {
+                if let Some(id) = self.selected().map(|item| item.subject_id) {
+                    self.message = Some(match copy_to_clipboard(&id.to_string()) {
+                        Ok(()) => format!("已复制 ID {}", id),
+                        Err(e) => format!("复制失败: {}", e),
+                    });
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(body);
+        self.draw_header(frame, header);
+        self.draw_list(frame, list);
+        self.draw_detail(frame, detail);
+        self.draw_footer(frame, footer);
+    }
+
+    fn draw_header(&self, frame: &mut Frame, area: Rect) {
+        let filter = if self.editing {
+            format!("{}▏", self.filter)
+        } else {
+            self.filter.clone()
+        };
+        let status = self.status.map_or("全部", |s| collection_status_name(s, 2));
+        let subject_type = self.subject_type.map_or("全部", subject_type_name);
+        let line = Line::from(vec![
+            Span::styled("筛选: ", Style::new().add_modifier(Modifier::BOLD)),
+            Span::raw(filter),
+            Span::raw(format!(
+                "   状态: {}   类型: {}   排序: {}   {}/{}",
+                status,
+                subject_type,
+                self.sort.label(),
+                self.visible.len(),
+                self.items.len()
+            )),
+        ]);
+        frame.render_widget(Paragraph::new(line), area);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let rows: Vec<Row> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let item = &self.items[i];
+                let rating = if item.rate > 0 {
+                    item.rate.to_string()
+                } else {
+                    String::new()
+                };
+                Row::new(vec![
+                    Cell::from(item.name.as_str()),
+                    Cell::from(subject_type_name(item.subject_type)),
+                    Cell::from(item.status()),
+                    Cell::from(rating),
+                ])
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(10),
+                Constraint::Length(6),
+                Constraint::Length(4),
+                Constraint::Length(2),
+            ],
+        )
+        .header(
+            Row::new(vec!["名称", "类型", "状态", "分"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::bordered().title(" 收藏 "))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_detail(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" 详情 ");
+        let Some(item) = self.selected() else {
+            frame.render_widget(Paragraph::new("没有符合条件的条目").block(block), area);
+            return;
+        };
+        let label = Style::new().fg(Color::DarkGray);
+        let field = |name: &'static str, value: String| {
+            Line::from(vec![
+                Span::styled(format!("{}: ", name), label),
+                Span::raw(value),
+            ])
+        };
+        let mut lines = vec![Line::from(Span::styled(
+            item.name.clone(),
+            Style::new().add_modifier(Modifier::BOLD),
+        ))];
+        if let Some(orig) = &item.name_orig {
+            lines.push(Line::from(orig.clone()));
+        }
+        lines.push(Line::default());
+        lines.push(field(
+            "类型",
+            subject_type_name(item.subject_type).to_string(),
+        ));
+        lines.push(field("状态", item.status().to_string()));
+        if item.rate > 0 {
+            lines.push(field("评分", item.rate.to_string()));
+        }
+        lines.push(field(
+            "最后标注",
+            item.updated_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        ));
+        if let Some(progress) = &item.progress {
+            lines.push(field("进度", progress.clone()));
+        }
+        if let Some(watched) = &item.watched {
+            lines.push(field("已看", watched.clone()));
+        }
+        if !item.tags.is_empty() {
+            lines.push(field("标签", item.tags.join(", ")));
+        }
+        lines.push(field("ID", item.subject_id.to_string()));
+        lines.push(field("地址", item.url()));
+        if !item.comment.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from(Span::styled("评论", label)));
+            lines.extend(item.comment.lines().map(|l| Line::from(l.to_string())));
+        }
+        let detail = Paragraph::new(Text::from(lines))
+            .block(block)
+            .wrap(Wrap { trim: false });
+        frame.render_widget(detail, area);
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let text = match (&self.message, self.editing) {
+            (_, true) => "输入筛选文字，Enter/Esc 结束".to_string(),
+            (Some(message), false) => message.clone(),
+            (None, false) => {
+                "/ 筛选  c 状态  t 类型  s 排序  x 清除  o 打开网页  y 复制 ID  q 退出".to_string()
+            }
+        };
+        frame.render_widget(
+            Paragraph::new(text).style(Style::new().fg(Color::DarkGray)),
+            area,
+        );
+    }
+}
+
+/// Copy `text` to the clipboard with an OSC 52 escape sequence, which most
+/// terminal emulators (also over SSH) pass to the system clipboard.
+fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !app.handle_key(key)
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Browse `items` in a full-screen terminal UI until the user quits. Everything
+/// shown is already loaded, so the UI never waits on the network.
+pub fn browse(items: Vec<BrowseItem>) -> Result<()> {
+    let mut app = App::new(items);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
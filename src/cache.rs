@@ -1,29 +1,248 @@
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
 
-use log::debug;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use log::{debug, warn};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
 use crate::error::Result;
 
+/// Serialization used for new cache entries.
+#[derive(Debug, Clone, Copy)]
+pub enum CacheFormat {
+    /// Plain JSON, readable with any text tool
+    Json,
+    /// MessagePack: smaller and faster to parse than JSON
+    MessagePack,
+}
+
+/// File extensions an entry may be stored under, in lookup order. `ndjson` is an
+/// append-only journal, read with `get_lines` rather than `get`.
+const ENTRY_EXTENSIONS: [&str; 5] = ["msgpack.gz", "msgpack", "json.gz", "json", "ndjson"];
+
+/// The key segment for a cache file name, e.g. `0` for `0.json.gz`; `None` for
+/// files that aren't entries, such as `.etag` sidecars.
+pub fn entry_stem(file_name: &str) -> Option<&str> {
+    ENTRY_EXTENSIONS
+        .iter()
+        .find_map(|ext| file_name.strip_suffix(ext)?.strip_suffix('.'))
+}
+
 /// File-based cache for API responses, enabling resume on interruption.
 ///
-/// Keys use `/` as directory separators, e.g. `484174/collections/0`
-/// maps to `.bgm_cache/484174/collections/0.json`.
+/// Keys use `/` as directory separators, e.g. `484174/subjects/253`
+/// maps to `.bgm_cache/484174/subjects/253.json` (`253.json.gz` when compressed).
 ///
 /// Empty results are recorded as zero-byte files to avoid re-fetching.
+///
+/// Lists that grow one item at a time can instead be kept as an NDJSON journal
+/// (`append`, `get_lines`, `set_lines`), so each addition writes one line rather
+/// than the whole list.
+///
+/// Writes go through a temporary file and a rename, so an interrupted run never
+/// leaves a partial entry behind; an entry that fails to parse anyway is logged as
+/// a warning and treated as a miss.
+///
+/// An entry may have an `.etag` sidecar holding the ETag the server sent with it,
+/// used to revalidate the entry with a conditional request.
+///
+/// Entries expire by file mtime after a TTL that depends on the kind of data
+/// (see `CacheTtl`); an expired entry behaves like a miss.
+///
+/// Entries are written gzip-compressed as `.json.gz` unless compression is turned
+/// off, or as MessagePack (`.msgpack.gz`/`.msgpack`) with `CacheFormat::MessagePack`.
+/// Every form is read regardless of the settings, so existing caches keep working.
+/// Empty markers stay zero-byte `.json` files either way.
 pub struct Cache {
     dir: PathBuf,
+    ttl: CacheTtl,
+    compress: bool,
+    format: CacheFormat,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    writes: AtomicU64,
+}
+
+/// How long each kind of cache entry stays fresh.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtl {
+    /// Collection, character and person pages, and index contents.
+    pub collections: Duration,
+    /// Episode progress.
+    pub progress: Duration,
+    /// Subject details and episode lists, which rarely change.
+    pub subjects: Duration,
+    /// The token owner's `/v0/me` profile (`me_<token hash>`).
+    pub me: Duration,
+}
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        Self {
+            collections: Duration::from_secs(24 * 60 * 60),
+            progress: Duration::from_secs(24 * 60 * 60),
+            subjects: Duration::from_secs(30 * 24 * 60 * 60),
+            me: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl CacheTtl {
+    /// TTL for a key, by its kind (the segment after the uid). Bookkeeping
+    /// entries such as sync state have none; the token-keyed `/v0/me` entry
+    /// sits outside any uid.
+    ///
+    /// Resume records (`done_records*`) have none either: they are checked
+    /// against the collection list when read, and expiring them would throw
+    /// away an interrupted pass.
+    fn for_key(&self, key: &str) -> Option<Duration> {
+        if key.starts_with("me_") && !key.contains('/') {
+            return Some(self.me);
+        }
+        match key.split('/').nth(1)? {
+            "collections" | "characters" | "persons" | "indexes" => Some(self.collections),
+            "progress" => Some(self.progress),
+            "subjects" | "episodes" | "relations" => Some(self.subjects),
+            _ => None,
+        }
+    }
+}
+
+/// Point-in-time copy of the cache's lookup counters.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writes: u64,
+}
+
+/// Storage for API responses and run state, keyed by `/`-separated paths such
+/// as `484174/subjects/253`.
+///
+/// An empty marker (`set_empty`) records that a key was fetched but had no
+/// data: `has` and `contains` report it, while `get` returns `None` for it and
+/// counts a hit. Journals (`append`, `get_lines`, `set_lines`) are kept apart
+/// from whole values; writing either form of a key replaces the other.
+pub trait CacheStore: Sync {
+    /// Snapshot of hits, misses and writes so far.
+    fn stats(&self) -> CacheStats;
+
+    /// Whether a fresh entry exists; a missing key counts as a miss, a present
+    /// one is counted by the `get` that follows.
+    fn has(&self, key: &str) -> bool;
+
+    /// Whether a fresh entry exists, without affecting the hit/miss counters.
+    fn contains(&self, key: &str) -> bool;
+
+    /// The stored value, or `None` on a miss, an empty marker or a value that
+    /// doesn't decode as `T`.
+    fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T>;
+
+    /// Store a value, replacing any other form of the entry.
+    fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()>;
+
+    /// The journal for `key`, or `None` on a miss.
+    fn get_lines<T: DeserializeOwned>(&self, key: &str) -> Option<Vec<T>>;
+
+    /// Append one value to the journal for `key`.
+    fn append<T: Serialize>(&self, key: &str, value: &T) -> Result<()>;
+
+    /// Replace the journal for `key` with these values.
+    fn set_lines<T: Serialize>(&self, key: &str, values: &[T]) -> Result<()>;
+
+    /// Record that the key was fetched but had no data.
+    fn set_empty(&self, key: &str) -> Result<()>;
+
+    /// The ETag stored for a key, if any.
+    fn etag(&self, key: &str) -> Option<String>;
+
+    /// Store the ETag for a key, or drop it with `None`.
+    fn set_etag(&self, key: &str, etag: Option<&str>) -> Result<()>;
+
+    /// Mark an entry as fresh again without rewriting it (e.g. after a 304).
+    fn touch(&self, key: &str) -> Result<()>;
+
+    /// Remove one entry and its ETag, if present.
+    fn remove(&self, key: &str) -> Result<()>;
+
+    /// Bytes stored for `key` in all its forms, including the ETag.
+    fn entry_size(&self, key: &str) -> u64;
+
+    /// Keys starting with `prefix` at the same level, e.g. `484174/done_records`
+    /// also matches `484174/done_records_t2_s`, and `484174/collections/`
+    /// matches every collection page.
+    fn keys(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Remove every entry matching `prefix` as in `keys`, with its ETag.
+    /// Returns the number of entries removed.
+    fn remove_prefix(&self, prefix: &str) -> Result<usize> {
+        let keys = self.keys(prefix)?;
+        for key in &keys {
+            self.remove(key)?;
+        }
+        debug!("Cache remove: {}* ({} entries)", prefix, keys.len());
+        Ok(keys.len())
+    }
+
+    /// Remove every entry.
+    fn clear(&self) -> Result<()>;
 }
 
 impl Cache {
+    /// Open the cache at `dir`, creating it if needed.
     pub fn new(dir: &Path) -> Result<Self> {
         std::fs::create_dir_all(dir)?;
         Ok(Self {
             dir: dir.to_path_buf(),
+            ttl: CacheTtl::default(),
+            compress: true,
+            format: CacheFormat::Json,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            writes: AtomicU64::new(0),
         })
     }
 
+    /// Use these TTLs instead of the defaults.
+    pub fn with_ttl(mut self, ttl: CacheTtl) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Write new entries as plain JSON instead of gzip when `compress` is false.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Serialize new entries in this format.
+    pub fn with_format(mut self, format: CacheFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Whether the file for `key` is older than the key's TTL.
+    fn is_expired(&self, key: &str, path: &Path) -> bool {
+        let Some(ttl) = self.ttl.for_key(key) else {
+            return false;
+        };
+        let age = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok())
+            .unwrap_or_default();
+        if age > ttl {
+            debug!("Cache expired: {} ({}s old)", key, age.as_secs());
+            return true;
+        }
+        false
+    }
+
     /// Build a file path from a cache key.
     /// `/` in the key becomes a directory separator.
     fn path(&self, key: &str) -> PathBuf {
@@ -35,59 +254,739 @@ impl Cache {
         p
     }
 
+    /// Every path an entry may be stored at, in lookup order.
+    fn entry_paths(&self, key: &str) -> impl Iterator<Item = PathBuf> {
+        let path = self.path(key);
+        ENTRY_EXTENSIONS
+            .into_iter()
+            .map(move |ext| path.with_extension(ext))
+    }
+
+    /// The file currently holding `key`, in whichever form, if any.
+    fn existing_path(&self, key: &str) -> Option<PathBuf> {
+        self.entry_paths(key).find(|p| p.exists())
+    }
+
+    /// The file holding `key` as a whole value, leaving out its journal.
+    fn value_path(&self, key: &str) -> Option<PathBuf> {
+        self.entry_paths(key)
+            .filter(|p| p.extension().is_none_or(|e| e != "ndjson"))
+            .find(|p| p.exists())
+    }
+
+    /// Remove every stored form of `key` except `keep`.
+    fn remove_other_forms(&self, key: &str, keep: &Path) -> Result<()> {
+        for path in self.entry_paths(key).filter(|p| p != keep) {
+            remove_if_exists(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Path of the NDJSON journal for a key.
+    fn journal_path(&self, key: &str) -> PathBuf {
+        self.path(key).with_extension("ndjson")
+    }
+
+    /// Sidecar file path holding the ETag for a cache key.
+    fn etag_path(&self, key: &str) -> PathBuf {
+        self.path(key).with_extension("etag")
+    }
+
+    /// Compress every non-empty uncompressed entry (e.g. from older versions) in place,
+    /// keeping its mtime so TTLs are unaffected. Returns the number of entries
+    /// compressed and the total size before and after.
+    pub fn compact(&self) -> Result<(usize, u64, u64)> {
+        let mut totals = (0, 0, 0);
+        compact_dir(&self.dir, &mut totals)?;
+        debug!("Cache compact: {} entries", totals.0);
+        Ok(totals)
+    }
+}
+
+impl CacheStore for Cache {
+    /// Snapshot of hits, misses and writes so far.
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+        }
+    }
+
     /// Check if a key exists in the cache (file exists).
-    pub fn has(&self, key: &str) -> bool {
-        self.path(key).exists()
+    ///
+    /// A missing key counts as a miss; a present one is counted by the `get` that follows.
+    fn has(&self, key: &str) -> bool {
+        let exists = self.contains(key);
+        if !exists {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        exists
     }
 
-    /// Try to load a cached value. Returns `None` on miss, empty file, or deserialization failure.
-    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
-        let path = self.path(key);
-        let data = std::fs::read_to_string(&path).ok()?;
+    /// Check if a fresh entry exists without affecting the hit/miss counters.
+    fn contains(&self, key: &str) -> bool {
+        self.existing_path(key)
+            .is_some_and(|path| !self.is_expired(key, &path))
+    }
+
+    /// Try to load a cached value. Returns `None` on miss, expiry, empty file, or
+    /// deserialization failure.
+    fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let Some(path) = self.value_path(key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        if self.is_expired(key, &path) {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let data = match read_entry(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Discarding unreadable cache entry {}: {}", key, e);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
         if data.is_empty() {
             debug!("Cache hit (empty marker): {}", key);
+            self.hits.fetch_add(1, Ordering::Relaxed);
             return None;
         }
-        match serde_json::from_str(&data) {
+        match decode(&path, &data) {
             Ok(val) => {
                 debug!("Cache hit: {}", key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
                 Some(val)
             }
             Err(e) => {
-                debug!("Cache parse error for {}: {}", key, e);
+                warn!("Discarding corrupt cache entry {}: {}", key, e);
+                self.misses.fetch_add(1, Ordering::Relaxed);
                 None
             }
         }
     }
 
     /// Store a value in the cache.
-    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
-        let path = self.path(key);
+    fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let (data, ext) = match self.format {
+            CacheFormat::Json => (serde_json::to_vec(value)?, "json"),
+            CacheFormat::MessagePack => (rmp_serde::to_vec_named(value)?, "msgpack"),
+        };
+        let (data, ext) = if self.compress {
+            (gzip(&data)?, format!("{}.gz", ext))
+        } else {
+            (data, ext.to_string())
+        };
+        let path = self.path(key).with_extension(ext);
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        let data = serde_json::to_string(value)?;
-        std::fs::write(&path, data)?;
+        write_atomic(&path, &data)?;
+        self.remove_other_forms(key, &path)?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
         debug!("Cache write: {}", key);
         Ok(())
     }
 
+    /// Load a journal written with `append`/`set_lines`. Returns `None` on miss or
+    /// expiry. A line that fails to parse (e.g. cut short by a crash) ends the list.
+    fn get_lines<T: DeserializeOwned>(&self, key: &str) -> Option<Vec<T>> {
+        let path = self.journal_path(key);
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) if !self.is_expired(key, &path) => data,
+            _ => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        let mut values = Vec::new();
+        for line in data.lines().filter(|l| !l.is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(value) => values.push(value),
+                Err(e) => {
+                    warn!(
+                        "Discarding the rest of cache journal {} from line {}: {}",
+                        key,
+                        values.len() + 1,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+        debug!("Cache hit: {} ({} lines)", key, values.len());
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(values)
+    }
+
+    /// Append one value to the journal for `key` as a JSON line.
+    fn append<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let path = self.journal_path(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(&line)?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache append: {}", key);
+        Ok(())
+    }
+
+    /// Replace the journal for `key` with these values, dropping any other form of
+    /// the entry (such as a list stored whole with `set` by older versions).
+    fn set_lines<T: Serialize>(&self, key: &str, values: &[T]) -> Result<()> {
+        let path = self.journal_path(key);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut data = Vec::new();
+        for value in values {
+            serde_json::to_writer(&mut data, value)?;
+            data.push(b'\n');
+        }
+        write_atomic(&path, &data)?;
+        self.remove_other_forms(key, &path)?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache write: {} ({} lines)", key, values.len());
+        Ok(())
+    }
+
     /// Write an empty marker file to record that the key was fetched but had no data.
-    pub fn set_empty(&self, key: &str) -> Result<()> {
+    fn set_empty(&self, key: &str) -> Result<()> {
         let path = self.path(key);
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
-        std::fs::write(&path, "")?;
+        write_atomic(&path, b"")?;
+        self.remove_other_forms(key, &path)?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
         debug!("Cache write (empty): {}", key);
         Ok(())
     }
 
+    /// Load the stored ETag for a key, if any.
+    fn etag(&self, key: &str) -> Option<String> {
+        let etag = std::fs::read_to_string(self.etag_path(key)).ok()?;
+        let etag = etag.trim();
+        if etag.is_empty() {
+            None
+        } else {
+            Some(etag.to_string())
+        }
+    }
+
+    /// Store the ETag for a key, or remove a stale one when the server sent none.
+    fn set_etag(&self, key: &str, etag: Option<&str>) -> Result<()> {
+        let path = self.etag_path(key);
+        match etag {
+            Some(etag) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, etag)?;
+            }
+            None if path.exists() => std::fs::remove_file(&path)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Mark an entry as fresh again without rewriting its content (e.g. after a 304).
+    fn touch(&self, key: &str) -> Result<()> {
+        let path = self.existing_path(key).unwrap_or_else(|| self.path(key));
+        let file = std::fs::File::options().append(true).open(path)?;
+        file.set_modified(std::time::SystemTime::now())?;
+        debug!("Cache touch: {}", key);
+        Ok(())
+    }
+
+    /// Remove one entry and its ETag sidecar, if present.
+    fn remove(&self, key: &str) -> Result<()> {
+        for path in self.entry_paths(key).chain([self.etag_path(key)]) {
+            remove_if_exists(&path)?;
+        }
+        debug!("Cache remove: {}", key);
+        Ok(())
+    }
+
+    /// Bytes on disk for `key` in all its stored forms, including the ETag sidecar.
+    fn entry_size(&self, key: &str) -> u64 {
+        self.entry_paths(key)
+            .chain([self.etag_path(key)])
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    /// Keys of the entries whose key starts with `prefix`, where the part after the
+    /// last `/` matches file names, e.g. `484174/done_records` also matches
+    /// `484174/done_records_t2_s`, and `484174/collections/` matches every
+    /// collection page. ETag sidecars are not listed.
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let (parents, name) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+        let mut dir = self.dir.clone();
+        for part in parents.split('/').filter(|p| !p.is_empty()) {
+            dir.push(part);
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Ok(Vec::new());
+        };
+        let mut keys = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(stem) = entry_stem(&file_name) else {
+                continue;
+            };
+            if stem.starts_with(name) {
+                keys.push(if parents.is_empty() {
+                    stem.to_string()
+                } else {
+                    format!("{}/{}", parents, stem)
+                });
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
     /// Remove the entire cache directory.
-    pub fn clear(&self) -> Result<()> {
+    fn clear(&self) -> Result<()> {
         if self.dir.exists() {
             std::fs::remove_dir_all(&self.dir)?;
         }
         Ok(())
     }
 }
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Write through a temporary sibling and rename it into place, so a crash mid-write
+/// leaves either the old entry or the new one, never a truncated file. `rename`
+/// replaces an existing target on Windows too (`MOVEFILE_REPLACE_EXISTING`).
+fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Read an entry, decompressing `.gz` files.
+fn read_entry(path: &Path) -> std::io::Result<Vec<u8>> {
+    decompress(path, std::fs::read(path)?)
+}
+
+fn decompress(path: &Path, raw: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if path.extension().is_none_or(|e| e != "gz") || raw.is_empty() {
+        return Ok(raw);
+    }
+    let mut data = Vec::new();
+    GzDecoder::new(raw.as_slice()).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Deserialize an entry according to its file extension.
+fn decode<T: DeserializeOwned>(path: &Path, data: &[u8]) -> Result<T> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if name.ends_with(".msgpack") || name.ends_with(".msgpack.gz") {
+        Ok(rmp_serde::from_slice(data)?)
+    } else {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// Check that the raw content of an entry file named like `path` parses.
+/// Empty markers are valid.
+pub fn validate_entry(path: &Path, raw: Vec<u8>) -> Result<()> {
+    let data = decompress(path, raw)?;
+    if path.extension().is_some_and(|e| e == "ndjson") {
+        for line in data.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            serde_json::from_slice::<serde::de::IgnoredAny>(line)?;
+        }
+    } else if !data.is_empty() {
+        decode::<serde::de::IgnoredAny>(path, &data)?;
+    }
+    Ok(())
+}
+
+/// Every file an entry stored at `path` may occupy, in any form.
+pub fn entry_forms(path: &Path) -> Vec<PathBuf> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(stem) = entry_stem(&name) else {
+        return vec![path.to_path_buf()];
+    };
+    ENTRY_EXTENSIONS
+        .iter()
+        .map(|ext| path.with_file_name(format!("{}.{}", stem, ext)))
+        .collect()
+}
+
+fn compact_dir(dir: &Path, totals: &mut (usize, u64, u64)) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            compact_dir(&path, totals)?;
+            continue;
+        }
+        let Some(ext) = path
+            .extension()
+            .map(|e| e.to_string_lossy().into_owned())
+            .filter(|e| e == "json" || e == "msgpack")
+        else {
+            continue;
+        };
+        let meta = entry.metadata()?;
+        if meta.len() == 0 {
+            continue;
+        }
+        let data = gzip(&std::fs::read(&path)?)?;
+        let gz_path = path.with_extension(format!("{}.gz", ext));
+        write_atomic(&gz_path, &data)?;
+        std::fs::File::options()
+            .append(true)
+            .open(&gz_path)?
+            .set_modified(meta.modified()?)?;
+        std::fs::remove_file(&path)?;
+        totals.0 += 1;
+        totals.1 += meta.len();
+        totals.2 += data.len() as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, Once};
+
+    use log::{Level, Log, Metadata, Record};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    /// Warnings logged by any test, so a test can look for those about its keys.
+    static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct CaptureLogger;
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Warn
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                WARNINGS.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_warnings() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&CaptureLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Warn);
+        });
+    }
+
+    fn warned_about(key: &str) -> bool {
+        WARNINGS.lock().unwrap().iter().any(|w| w.contains(key))
+    }
+
+    /// An empty cache in a directory of its own, removed when dropped.
+    struct TempCache {
+        cache: Cache,
+        dir: PathBuf,
+    }
+
+    impl TempCache {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "bangumi-tool-cache-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self {
+                cache: Cache::new(&dir).unwrap(),
+                dir,
+            }
+        }
+
+        /// Write raw bytes as the file of `key` with extension `ext`.
+        fn write_raw(&self, key: &str, ext: &str, data: &[u8]) {
+            let path = self.cache.path(key).with_extension(ext);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+    }
+
+    impl Drop for TempCache {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn truncated_entry_warns_and_misses() {
+        capture_warnings();
+        let tmp = TempCache::new("truncated");
+        tmp.write_raw("1/subjects/11", "json", br#"{"id": 11, "name": "cut"#);
+
+        assert_eq!(tmp.cache.get::<Value>("1/subjects/11"), None);
+        assert!(warned_about("1/subjects/11"));
+        assert_eq!(tmp.cache.stats().misses, 1);
+        assert_eq!(tmp.cache.stats().hits, 0);
+    }
+
+    #[test]
+    fn garbage_gzip_entry_warns_and_misses() {
+        capture_warnings();
+        let tmp = TempCache::new("garbage");
+        tmp.write_raw("1/subjects/12", "json.gz", b"\x1f\x8b not really gzip");
+
+        assert_eq!(tmp.cache.get::<Value>("1/subjects/12"), None);
+        assert!(warned_about("1/subjects/12"));
+        assert_eq!(tmp.cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn corrupt_entry_is_replaced_by_the_next_write() {
+        let tmp = TempCache::new("replaced");
+        tmp.write_raw("1/subjects/13", "json", b"garbage");
+        tmp.cache.set("1/subjects/13", &json!({"id": 13})).unwrap();
+
+        assert_eq!(
+            tmp.cache.get::<Value>("1/subjects/13"),
+            Some(json!({"id": 13}))
+        );
+        assert!(!tmp.cache.path("1/subjects/13").exists());
+    }
+
+    #[test]
+    fn journal_cut_short_keeps_the_complete_lines() {
+        capture_warnings();
+        let tmp = TempCache::new("journal");
+        tmp.write_raw(
+            "1/done_records_j",
+            "ndjson",
+            b"{\"a\":1}\n{\"a\":2}\n{\"a\":",
+        );
+
+        let lines: Vec<Value> = tmp.cache.get_lines("1/done_records_j").unwrap();
+        assert_eq!(lines, [json!({"a": 1}), json!({"a": 2})]);
+        assert!(warned_about("1/done_records_j"));
+    }
+
+    #[test]
+    fn writes_leave_no_temporary_files() {
+        let tmp = TempCache::new("atomic");
+        tmp.cache.set("1/subjects/14", &json!({"id": 14})).unwrap();
+        tmp.cache.set_empty("1/episodes/14").unwrap();
+        tmp.cache.set_lines("1/done_records", &[json!(1)]).unwrap();
+
+        for sub in ["1/subjects", "1/episodes", "1"] {
+            for entry in std::fs::read_dir(tmp.dir.join(sub)).unwrap() {
+                let name = entry.unwrap().file_name();
+                assert!(
+                    !name.to_string_lossy().ends_with(".tmp"),
+                    "{:?} left behind",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ttl_follows_the_key_kind() {
+        let ttl = CacheTtl::default();
+        assert_eq!(ttl.for_key("1/collections/50_0"), Some(ttl.collections));
+        assert_eq!(ttl.for_key("1/progress/2"), Some(ttl.progress));
+        assert_eq!(ttl.for_key("1/episodes/2"), Some(ttl.subjects));
+        assert_eq!(ttl.for_key("me_0123abcd"), Some(ttl.me));
+        assert_eq!(ttl.for_key("1/last_sync"), None);
+    }
+
+    #[test]
+    fn resume_records_never_expire() {
+        let ttl = CacheTtl::default();
+        assert_eq!(ttl.for_key("1/done_records"), None);
+        assert_eq!(ttl.for_key("1/done_records_t2_s"), None);
+    }
+
+    /// Store `value` as a `T` in a MessagePack cache, compressed or not, and
+    /// check it reads back the same and from a MessagePack file.
+    fn msgpack_round_trip<T: Serialize + DeserializeOwned>(name: &str, value: &Value) {
+        for compress in [true, false] {
+            let tmp = TempCache::new(&format!("msgpack-{}-{}", name, compress));
+            let cache = Cache::new(&tmp.dir)
+                .unwrap()
+                .with_format(CacheFormat::MessagePack)
+                .with_compression(compress);
+            let typed: T = serde_json::from_value(value.clone()).unwrap();
+            cache.set("1/entry", &typed).unwrap();
+
+            let ext = if compress { "msgpack.gz" } else { "msgpack" };
+            assert!(cache.path("1/entry").with_extension(ext).exists());
+            let back: T = cache.get("1/entry").expect(name);
+            assert_eq!(
+                serde_json::to_value(&back).unwrap(),
+                serde_json::to_value(&typed).unwrap(),
+                "{} changed in MessagePack",
+                name
+            );
+        }
+    }
+
+    fn fixture() -> Value {
+        serde_json::from_str(include_str!("../tests/fixtures/account.json")).unwrap()
+    }
+
+    #[test]
+    fn api_responses_round_trip_through_msgpack() {
+        use crate::models::{PagedCollection, SubjectDetail, User, UserProgress};
+
+        let fixture = fixture();
+        let page = json!({
+            "total": 3, "limit": 50, "offset": 0, "data": fixture["collections"]
+        });
+        msgpack_round_trip::<User>("user", &fixture["user"]);
+        msgpack_round_trip::<PagedCollection>("collections", &page);
+        msgpack_round_trip::<SubjectDetail>("subject", &fixture["subjects"]["101"]);
+        msgpack_round_trip::<Vec<crate::models::Episode>>("episodes", &fixture["episodes"]["101"]);
+        msgpack_round_trip::<UserProgress>("progress", &fixture["progress"]["101"]);
+    }
+
+    #[test]
+    fn subject_extras_round_trip_through_msgpack() {
+        use crate::models::{RelatedSubject, SubjectDetail};
+
+        let subject = json!({
+            "id": 1, "name": "n", "name_cn": "", "type": 4, "eps": 0, "total_episodes": 0,
+            "nsfw": true, "summary": "简介",
+            "infobox": [
+                {"key": "平台", "value": ["PC", "PS5"]},
+                {"key": "开发", "value": "某社"}
+            ],
+            "rating": {"score": 7.5}
+        });
+        let relations = json!([{"id": 2, "type": 2, "name": "n2", "relation": "续集"}]);
+        msgpack_round_trip::<SubjectDetail>("subject-extras", &subject);
+        msgpack_round_trip::<Vec<RelatedSubject>>("relations", &relations);
+    }
+
+    #[test]
+    fn account_lists_round_trip_through_msgpack() {
+        use crate::models::{
+            CalendarDay, CharacterCollection, Index, IndexSubject, Paged, PersonCollection,
+        };
+
+        let paged = |data: Value| json!({"total": 1, "limit": 30, "offset": 0, "data": [data]});
+        let characters = paged(json!({
+            "id": 1, "name": "スパイク", "type": 1, "created_at": "2024-01-01T00:00:00Z",
+            "images": {"large": "l", "medium": "m", "small": "s", "grid": "g"}
+        }));
+        let persons = paged(json!({
+            "id": 2, "name": "渡辺信一郎", "type": 1, "career": ["producer"],
+            "created_at": "2024-01-01T00:00:00Z"
+        }));
+        let index = json!({
+            "id": 3, "title": "目录", "desc": "", "total": 1,
+            "creator": {"username": "u", "nickname": "n"},
+            "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-02-01T00:00:00Z"
+        });
+        let index_subjects = paged(json!({
+            "id": 253, "type": 2, "name": "n", "comment": "c", "added_at": "2024-01-01T00:00:00Z"
+        }));
+        let calendar = json!([{
+            "weekday": {"id": 1, "en": "Mon", "cn": "星期一"},
+            "items": [{"id": 253, "name": "n", "name_cn": "星际牛仔"}]
+        }]);
+        msgpack_round_trip::<Paged<CharacterCollection>>("characters", &characters);
+        msgpack_round_trip::<Paged<PersonCollection>>("persons", &persons);
+        msgpack_round_trip::<Index>("index", &index);
+        msgpack_round_trip::<Paged<IndexSubject>>("index-subjects", &index_subjects);
+        msgpack_round_trip::<Vec<CalendarDay>>("calendar", &calendar);
+    }
+
+    #[test]
+    fn bookkeeping_round_trips_through_msgpack() {
+        use crate::models::{ExportRecord, FailedSubject, SyncState};
+
+        let fixture = fixture();
+        let state = json!({
+            "high_water": "2024-03-01T12:00:00Z",
+            "collections": fixture["collections"]
+        });
+        let records = json!([{
+            "subject_id": 253, "name": "n", "name_cn": "星际牛仔", "subject_type": "动画",
+            "url": "https://bgm.tv/subject/253", "status": "在看", "collection_type": 3,
+            "updated_at": "2024-01-01 08:00:00", "completeness": "2/3",
+            "completeness_pct": "67%", "watched_eps": "1-2", "stopped_at": null,
+            "rating": "9", "rating_diff": 0.5, "tags": "", "comment": "",
+            "infobox": [["平台", "PC"]], "watched_titles": ["第1话"],
+            "tracks": {"discs": 2, "tracks": 20}
+        }]);
+        let failed = json!([{"id": 1, "name": "n", "error": "HTTP 500"}]);
+        msgpack_round_trip::<SyncState>("sync-state", &state);
+        msgpack_round_trip::<Vec<ExportRecord>>("records", &records);
+        msgpack_round_trip::<Vec<FailedSubject>>("failed", &failed);
+    }
+
+    #[test]
+    fn corrupt_msgpack_entry_warns_and_misses() {
+        capture_warnings();
+        let tmp = TempCache::new("msgpack-corrupt");
+        // A map announcing more entries than it holds.
+        tmp.write_raw("1/subjects/15", "msgpack", &[0x85, 0xa2, b'i', b'd', 0x0f]);
+
+        assert!(
+            tmp.cache
+                .get::<crate::models::SubjectDetail>("1/subjects/15")
+                .is_none()
+        );
+        assert!(warned_about("1/subjects/15"));
+        assert_eq!(tmp.cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn corrupt_compressed_msgpack_entry_warns_and_misses() {
+        capture_warnings();
+        let tmp = TempCache::new("msgpack-gz-corrupt");
+        tmp.write_raw(
+            "1/subjects/16",
+            "msgpack.gz",
+            &gzip(b"\xc1 never valid").unwrap(),
+        );
+
+        assert_eq!(tmp.cache.get::<Value>("1/subjects/16"), None);
+        assert!(warned_about("1/subjects/16"));
+    }
+}
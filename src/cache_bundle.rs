@@ -0,0 +1,176 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::warn;
+
+use crate::cache::{entry_forms, validate_entry};
+use crate::error::Result;
+use crate::plain_progress;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Outcome of `import`.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Kept because the local copy is newer than the bundled one.
+    pub kept_newer: usize,
+    /// Entries that failed to parse, or had unsafe paths.
+    pub invalid: usize,
+}
+
+/// Relative paths of every file below `dir`, skipping leftover temporary files.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if path.extension().is_none_or(|e| e != "tmp") {
+            files.push(path.strip_prefix(root).unwrap_or(&path).to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Pack the cache (or one uid's subtree) into a zstd-compressed tar at `file`,
+/// keeping the key layout, mtimes and empty markers. Returns the number of files.
+pub fn export(
+    cache_dir: &Path,
+    uid: Option<u64>,
+    file: &Path,
+    multi: &MultiProgress,
+) -> Result<usize> {
+    let mut files = Vec::new();
+    match uid {
+        Some(uid) => {
+            let dir = cache_dir.join(uid.to_string());
+            if dir.is_dir() {
+                collect_files(cache_dir, &dir, &mut files)?;
+            }
+        }
+        None => collect_files(cache_dir, cache_dir, &mut files)?,
+    }
+    files.sort();
+
+    let pb = plain_progress::add(
+        multi,
+        ProgressBar::new(files.len() as u64),
+        "packed",
+        "files",
+    );
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    pb.set_message("packing cache");
+
+    let encoder =
+        zstd::stream::write::Encoder::new(BufWriter::new(File::create(file)?), ZSTD_LEVEL)?;
+    let mut tar = tar::Builder::new(encoder);
+    for relative in &files {
+        tar.append_path_with_name(cache_dir.join(relative), relative)?;
+        pb.inc(1);
+    }
+    tar.into_inner()?.finish()?;
+    pb.finish_and_clear();
+    multi.remove(&pb);
+    Ok(files.len())
+}
+
+/// Whether a bundled path stays inside the cache directory once joined to it.
+fn is_safe(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Unpack a bundle written by `export` into the cache directory.
+///
+/// Every entry is parsed before it's written. An entry whose local copy (in any
+/// form) is newer than the bundled one is kept unless `force` is set.
+pub fn import(
+    cache_dir: &Path,
+    file: &Path,
+    force: bool,
+    multi: &MultiProgress,
+) -> Result<ImportReport> {
+    let pb = plain_progress::add(multi, ProgressBar::new_spinner(), "unpacked", "files");
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {pos} files {msg}")
+            .unwrap(),
+    );
+    pb.set_message("unpacking cache");
+
+    let decoder = zstd::stream::read::Decoder::new(File::open(file)?)?;
+    let mut archive = tar::Archive::new(BufReader::new(decoder));
+    let mut report = ImportReport::default();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let relative = entry.path()?.into_owned();
+        pb.inc(1);
+        if !is_safe(&relative) {
+            warn!(
+                "Skipping bundle entry with unsafe path {}",
+                relative.display()
+            );
+            report.invalid += 1;
+            continue;
+        }
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(entry.header().mtime()?);
+
+        let dest = cache_dir.join(&relative);
+        let is_sidecar = dest.extension().is_some_and(|e| e == "etag");
+        if !is_sidecar && let Err(e) = validate_entry(&dest, data.clone()) {
+            warn!(
+                "Skipping corrupt bundle entry {}: {}",
+                relative.display(),
+                e
+            );
+            report.invalid += 1;
+            continue;
+        }
+        let forms = entry_forms(&dest);
+        let local_newer = forms.iter().any(|p| {
+            std::fs::metadata(p)
+                .and_then(|m| m.modified())
+                .is_ok_and(|t| t > modified)
+        });
+        if local_newer && !force {
+            report.kept_newer += 1;
+            continue;
+        }
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut tmp = dest.as_os_str().to_owned();
+        tmp.push(".import.tmp");
+        let tmp = PathBuf::from(tmp);
+        std::fs::write(&tmp, &data)?;
+        File::options()
+            .append(true)
+            .open(&tmp)?
+            .set_modified(modified)?;
+        std::fs::rename(&tmp, &dest)?;
+        // Drop other forms of the same key so lookups find the imported one.
+        for other in forms.iter().filter(|p| **p != dest) {
+            if other.exists() {
+                std::fs::remove_file(other)?;
+            }
+        }
+        report.imported += 1;
+    }
+    pb.finish_and_clear();
+    multi.remove(&pb);
+    Ok(report)
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, Utc};
+use indicatif::HumanBytes;
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cache::{CacheStore, entry_stem};
+use crate::error::Result;
+use crate::fetch::{SUBJECT_KINDS, parse_collection_page_key};
+use crate::summary::pad_to_width;
+
+/// Disk usage of one kind of cache entry for one user.
+#[derive(Debug, Serialize)]
+pub struct KindUsage {
+    pub uid: String,
+    pub kind: String,
+    /// Cached entries, not counting `.etag` sidecars.
+    pub entries: u64,
+    /// Zero-byte markers recording that a fetch returned nothing.
+    pub empty: u64,
+    /// Total size including sidecars.
+    pub bytes: u64,
+    pub oldest: Option<DateTime<Utc>>,
+    pub newest: Option<DateTime<Utc>>,
+}
+
+impl KindUsage {
+    fn add(&mut self, path: &Path) -> Result<()> {
+        let meta = std::fs::metadata(path)?;
+        self.bytes += meta.len();
+        if path.extension().is_some_and(|e| e == "etag") {
+            return Ok(());
+        }
+        self.entries += 1;
+        if meta.len() == 0 {
+            self.empty += 1;
+        }
+        let modified: DateTime<Utc> = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH).into();
+        self.oldest = Some(self.oldest.map_or(modified, |t| t.min(modified)));
+        self.newest = Some(self.newest.map_or(modified, |t| t.max(modified)));
+        Ok(())
+    }
+}
+
+/// Add every file below `dir` to `usage`.
+fn add_dir(usage: &mut KindUsage, dir: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            add_dir(usage, &path)?;
+        } else {
+            usage.add(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Kind of a file or directory directly under a uid directory, e.g.
+/// `collections` or `done_records` (for all of its per-filter variants).
+fn kind_of(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = entry_stem(&name)
+        .or_else(|| name.strip_suffix(".etag"))
+        .unwrap_or(&name);
+    match name.split_once('_') {
+        Some(("done", rest)) if rest.starts_with("records") => "done_records".to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Walk the cache directory and sum up usage per uid and kind.
+pub fn scan(cache_dir: &Path) -> Result<Vec<KindUsage>> {
+    let mut usage: BTreeMap<(String, String), KindUsage> = BTreeMap::new();
+    if !cache_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    for uid_entry in std::fs::read_dir(cache_dir)? {
+        let uid_path = uid_entry?.path();
+        if !uid_path.is_dir() {
+            continue;
+        }
+        let uid = uid_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for entry in std::fs::read_dir(&uid_path)? {
+            let path = entry?.path();
+            let kind = kind_of(&path);
+            let slot = usage
+                .entry((uid.clone(), kind.clone()))
+                .or_insert_with(|| KindUsage {
+                    uid: uid.clone(),
+                    kind,
+                    entries: 0,
+                    empty: 0,
+                    bytes: 0,
+                    oldest: None,
+                    newest: None,
+                });
+            if path.is_dir() {
+                add_dir(slot, &path)?;
+            } else {
+                slot.add(&path)?;
+            }
+        }
+    }
+    Ok(usage.into_values().collect())
+}
+
+/// Print usage as an aligned table with a total line.
+pub fn print_usage(usage: &[KindUsage], cache_dir: &Path) {
+    println!("\n== 缓存: {} ==", cache_dir.display());
+    let date = |t: &Option<DateTime<Utc>>| {
+        t.map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let uid_width = usage.iter().map(|u| u.uid.width()).max().unwrap_or(0);
+    let kind_width = usage.iter().map(|u| u.kind.width()).max().unwrap_or(0);
+    for u in usage {
+        println!(
+            "  {}  {}  {:>6} entries  {:>5} empty  {:>10}  {} — {}",
+            pad_to_width(&u.uid, uid_width),
+            pad_to_width(&u.kind, kind_width),
+            u.entries,
+            u.empty,
+            HumanBytes(u.bytes).to_string(),
+            date(&u.oldest),
+            date(&u.newest)
+        );
+    }
+    let entries: u64 = usage.iter().map(|u| u.entries).sum();
+    let bytes: u64 = usage.iter().map(|u| u.bytes).sum();
+    println!("\n  total: {} entries, {}", entries, HumanBytes(bytes));
+    println!();
+}
+
+/// Per-subject entries under `uid` for subjects that are no longer collected.
+pub fn orphaned_keys(
+    cache: &impl CacheStore,
+    uid: u64,
+    collected: &HashSet<u64>,
+) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for kind in SUBJECT_KINDS {
+        for key in cache.keys(&format!("{}/{}/", uid, kind))? {
+            let sid = key.rsplit('/').next().and_then(|s| s.parse::<u64>().ok());
+            if sid.is_some_and(|sid| !collected.contains(&sid)) {
+                keys.push(key);
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Collection page entries under `uid` at or past `total`, or written with a
+/// page size other than `page_size` (including keys from before the page size
+/// was part of the key).
+pub fn stale_page_keys(
+    cache: &impl CacheStore,
+    uid: u64,
+    total: u64,
+    page_size: u64,
+) -> Result<Vec<String>> {
+    Ok(cache
+        .keys(&format!("{}/collections/", uid))?
+        .into_iter()
+        .filter(|key| match parse_collection_page_key(key) {
+            Some((size, offset)) => size != page_size || offset >= total,
+            None => true,
+        })
+        .collect())
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cache::CacheStore;
+use crate::error::Result;
+use crate::models::{CalendarDay, Collection, Episode, UserProgress, collection_status_name};
+use crate::summary::{group_style, pad_to_width, paint};
+
+/// An airing subject, merged with my collection state.
+#[derive(Debug, Serialize)]
+pub struct CalendarEntry {
+    pub subject_id: u64,
+    pub name: String,
+    /// My collection status, if the subject is collected.
+    pub status: Option<String>,
+    #[serde(skip)]
+    pub collection_type: Option<u8>,
+    /// Watched/total main episodes, when the detail cache has them.
+    pub progress: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalendarWeekday {
+    pub weekday: String,
+    pub items: Vec<CalendarEntry>,
+}
+
+/// Main-episode progress from the detail cache, without fetching anything.
+fn cached_progress(cache: &impl CacheStore, uid: u64, subject_id: u64) -> Option<String> {
+    let episodes_key = format!("{}/episodes/{}", uid, subject_id);
+    let progress_key = format!("{}/progress/{}", uid, subject_id);
+    if !cache.contains(&episodes_key) || !cache.contains(&progress_key) {
+        return None;
+    }
+    let episodes: Vec<Episode> = cache.get(&episodes_key).unwrap_or_default();
+    let watched = cache
+        .get::<UserProgress>(&progress_key)
+        .map(|p| p.watched_ids())
+        .unwrap_or_default();
+    let main: Vec<&Episode> = episodes.iter().filter(|e| e.episode_type == 0).collect();
+    let seen = main.iter().filter(|e| watched.contains(&e.id)).count();
+    Some(format!("{}/{}", seen, main.len()))
+}
+
+/// Cross-reference the calendar with the collection, keeping the API's weekday order.
+pub fn merge(
+    days: &[CalendarDay],
+    collections: &[Collection],
+    cache: &impl CacheStore,
+    uid: u64,
+) -> Vec<CalendarWeekday> {
+    let collected: HashMap<u64, &Collection> =
+        collections.iter().map(|c| (c.subject_id, c)).collect();
+    days.iter()
+        .map(|day| CalendarWeekday {
+            weekday: day.weekday.cn.clone(),
+            items: day
+                .items
+                .iter()
+                .map(|item| {
+                    let col = collected.get(&item.id);
+                    CalendarEntry {
+                        subject_id: item.id,
+                        name: if item.name_cn.is_empty() {
+                            item.name.clone()
+                        } else {
+                            item.name_cn.clone()
+                        },
+                        status: col.map(|c| {
+                            collection_status_name(c.collection_type, c.subject.subject_type)
+                                .to_string()
+                        }),
+                        collection_type: col.map(|c| c.collection_type),
+                        progress: col.and_then(|_| cached_progress(cache, uid, item.id)),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Print one table per weekday; collected subjects are listed first and highlighted.
+pub fn print_calendar(days: &[CalendarWeekday], color: bool) {
+    for day in days {
+        println!("\n== {} ({}) ==", day.weekday, day.items.len());
+        let name_width = day.items.iter().map(|e| e.name.width()).max().unwrap_or(0);
+        let mut items: Vec<&CalendarEntry> = day.items.iter().collect();
+        items.sort_by_key(|e| e.collection_type.is_none());
+        for e in items {
+            let name = pad_to_width(&e.name, name_width);
+            let line = match &e.status {
+                Some(status) => format!(
+                    "  {}  {}  {}",
+                    name,
+                    pad_to_width(status, 4),
+                    e.progress.as_deref().unwrap_or("")
+                )
+                .trim_end()
+                .to_string(),
+                None => format!("  {}", name),
+            };
+            // Highlight what I'm watching or want to watch.
+            match e.collection_type {
+                Some(ct @ (1 | 3)) => println!("{}", paint(&line, group_style(ct), color)),
+                _ => println!("{}", line),
+            }
+        }
+    }
+    println!();
+}
+
+/// Write the merged calendar as `calendar.json` in the output directory.
+pub fn write_json(days: &[CalendarWeekday], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("calendar.json");
+    let file = std::fs::File::create(&path)?;
+    serde_json::to_writer_pretty(file, days)?;
+    Ok(path)
+}
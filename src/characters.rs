@@ -0,0 +1,82 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::cli::Format;
+use crate::error::Result;
+use crate::export::write_atomic;
+use crate::models::{CharacterCollection, character_type_name};
+
+/// One collected character as written to `bangumi_characters.*`.
+#[derive(Debug, Serialize)]
+pub struct CharacterRecord {
+    pub id: u64,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub character_type: &'static str,
+    pub url: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub image: String,
+    pub collected_at: String,
+}
+
+impl CharacterRecord {
+    pub fn new(c: &CharacterCollection) -> Self {
+        Self {
+            id: c.id,
+            name: c.name.clone(),
+            character_type: character_type_name(c.character_type),
+            url: format!("https://bgm.tv/character/{}", c.id),
+            image: c
+                .images
+                .as_ref()
+                .map(|i| i.large.clone())
+                .unwrap_or_default(),
+            collected_at: c
+                .created_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        }
+    }
+}
+
+fn write_json(records: &[CharacterRecord], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("bangumi_characters.json");
+    write_atomic(&path, |w| Ok(serde_json::to_writer(w, records)?))?;
+    Ok(path)
+}
+
+fn write_csv(records: &[CharacterRecord], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("bangumi_characters.csv");
+    write_atomic(&path, |w| {
+        w.write_all(b"\xEF\xBB\xBF")?;
+        let mut wtr = csv::Writer::from_writer(w);
+        wtr.write_record(["ID", "名称", "类型", "地址", "收藏时间"])?;
+        for r in records {
+            wtr.write_record([
+                &r.id.to_string(),
+                &r.name,
+                r.character_type,
+                &r.url,
+                &r.collected_at,
+            ])?;
+        }
+        wtr.flush()?;
+        Ok(())
+    })?;
+    Ok(path)
+}
+
+/// Write the character export in the formats selected for the main export.
+pub fn write(records: &[CharacterRecord], format: &Format, dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(match format {
+        Format::Json => vec![write_json(records, dir)?],
+        Format::Csv => vec![write_csv(records, dir)?],
+        Format::All | Format::Ics | Format::Txt => {
+            vec![write_json(records, dir)?, write_csv(records, dir)?]
+        }
+    })
+}
@@ -1,32 +1,924 @@
-use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+
+use crate::fetch::COLLECTIONS_PAGE_LIMIT;
+use crate::records::{DEFAULT_DATE_FORMAT, DEFAULT_SITE_TAGS_LIMIT, TimeZone, TimestampFormat};
 
 #[derive(Debug, Clone, clap::ValueEnum)]
 pub enum Format {
     Json,
     Csv,
     All,
+    /// Upcoming unwatched episodes of 在看 subjects as an iCalendar file (needs --detail)
+    Ics,
+    /// Display names, one per line, as watchlist.txt; only 想看 items unless --status is given
+    Txt,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum SubjectTypeFilter {
+    Book,
+    Anime,
+    Music,
+    Game,
+    Real,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum StatusFilter {
+    Wish,
+    Done,
+    Doing,
+    OnHold,
+    Dropped,
+}
+
+/// Which R18 subjects to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NsfwFilter {
+    /// Keep them along with everything else
+    Include,
+    /// Leave them out
+    Exclude,
+    /// Keep only them
+    Only,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// How progress is shown while fetching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressMode {
+    /// Animated progress bars
+    Bar,
+    /// A plain line now and then, without control sequences
+    Plain,
+    /// No progress output
+    None,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum SummarySort {
+    /// Rating descending, unrated last
+    Rating,
+    /// Last updated first
+    Updated,
+    /// Display name, Chinese by pinyin
+    Name,
+}
+
+/// Order of records in the export files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    /// Rating descending, unrated last
+    Rating,
+    /// Last updated first
+    Updated,
+    /// Display name, Chinese by pinyin
+    Name,
+    /// Subject type: book, anime, music, game, real
+    Type,
+    /// Collection status: wish, done, doing, on hold, dropped
+    Status,
+}
+
+/// How the summary tells which subjects belong to one franchise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FranchiseGrouping {
+    /// Sequel and prequel links (needs --detail --relations)
+    Relations,
+    /// Names that match once season suffixes such as 第二季 are removed; may misgroup
+    Names,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum CacheFormat {
+    Json,
+    /// MessagePack: smaller and faster to parse than JSON
+    #[value(name = "msgpack")]
+    MessagePack,
+}
+
+impl From<CacheFormat> for crate::cache::CacheFormat {
+    fn from(format: CacheFormat) -> Self {
+        match format {
+            CacheFormat::Json => Self::Json,
+            CacheFormat::MessagePack => Self::MessagePack,
+        }
+    }
+}
+
+/// Language of export headers, labels and the summary.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Lang {
+    /// Chinese, as on Bangumi
+    Zh,
+    /// English
+    En,
+}
+
+impl From<Lang> for crate::locale::Lang {
+    fn from(lang: Lang) -> Self {
+        match lang {
+            Lang::Zh => Self::Zh,
+            Lang::En => Self::En,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum LogFormat {
+    Plain,
+    Json,
+}
+
+/// A value that must not show up in logs; `Debug` prints it redacted.
+#[derive(Clone)]
+pub struct Secret(pub String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// An inclusive range of episode sort numbers, e.g. `1-5` or just `7`.
+#[derive(Debug, Clone, Copy)]
+pub struct EpisodeRange {
+    pub from: f64,
+    pub to: f64,
+}
+
+impl EpisodeRange {
+    pub fn contains(&self, sort: f64) -> bool {
+        self.from <= sort && sort <= self.to
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write a commented config template to ~/.config/bangumi-tool/config.toml
+    Init {
+        /// Write ./bangumi-tool.toml in the current directory instead
+        #[arg(long, default_value_t = false)]
+        local: bool,
+        /// Replace an existing config file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// Save an account under a name, prompting for its access token
+    Add {
+        /// Profile name (letters, digits, '-' and '_')
+        name: String,
+    },
+    /// List saved profiles and the accounts they belong to
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Show entry counts, size and age per user and kind of cache entry
+    Stats {
+        /// Print the stats as JSON instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Compress cache entries written by older versions in place
+    Compact,
+    /// Pack the cache into a portable bundle (.tar.zst)
+    Export {
+        /// Bundle to write
+        file: PathBuf,
+        /// Only pack this user's entries
+        #[arg(long)]
+        uid: Option<u64>,
+    },
+    /// Unpack a bundle written by `cache export` into the cache
+    Import {
+        /// Bundle to read
+        file: PathBuf,
+        /// Overwrite local entries even when they are newer than the bundled ones
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Delete cached subjects, episodes and progress of subjects no longer in my
+    /// collection, and collection pages past its end
+    Gc {
+        /// List what would be deleted without deleting it
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check the access token and show which account it belongs to, always
+    /// asking the API and refreshing the cached answer
+    Whoami {
+        /// Print the account as JSON instead
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Search subjects by keyword (narrow with --type)
+    Search {
+        /// Search keywords
+        #[arg(required = true)]
+        keywords: Vec<String>,
+        /// Number of results to show
+        #[arg(long, default_value_t = 10)]
+        limit: u64,
+        /// Print the results as JSON instead
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Show what the tool sees for one subject: detail, episodes and my progress
+    Subject {
+        /// Subject id
+        id: u64,
+        /// Print the raw API responses instead, fetched without the cache
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+    /// Show the per-episode watch state of one subject
+    Progress {
+        /// Subject id or bgm.tv subject URL
+        #[arg(value_parser = parse_subject_id)]
+        subject: u64,
+    },
+    /// Mark main episodes of a collected subject as watched
+    Mark {
+        /// Subject id or bgm.tv subject URL
+        #[arg(value_parser = parse_subject_id)]
+        subject: u64,
+        /// Episode numbers to mark, e.g. 1-5,7
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = parse_episode_range,
+            required_unless_present = "watched_until"
+        )]
+        eps: Vec<EpisodeRange>,
+        /// Mark every main episode up to and including N
+        #[arg(long, value_name = "N")]
+        watched_until: Option<f64>,
+        /// Print the episodes that would be marked without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Create or update the collection entry of one subject
+    #[command(group(
+        clap::ArgGroup::new("changes")
+            .required(true)
+            .multiple(true)
+            .args(["status", "rate", "comment", "tags", "private"])
+    ))]
+    Collect {
+        /// Subject id or bgm.tv subject URL
+        #[arg(value_parser = parse_subject_id)]
+        subject: u64,
+        /// Collection status
+        #[arg(long, value_enum)]
+        status: Option<StatusFilter>,
+        /// Rating from 1 to 10; 0 removes the rating
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=10))]
+        rate: Option<u8>,
+        /// Short comment
+        #[arg(long)]
+        comment: Option<String>,
+        /// Tags, replacing the existing ones (comma-separated)
+        #[arg(long, value_delimiter = ',', num_args = 1..)]
+        tags: Option<Vec<String>>,
+        /// Make the collection entry private
+        #[arg(long, default_value_t = false)]
+        private: bool,
+        /// Write without asking for confirmation
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+    },
+    /// Recreate the collection of a previous JSON/CSV export on this account
+    Import {
+        /// bangumi_export.json or bangumi_export.csv written by this tool
+        file: PathBuf,
+        /// Print the planned writes without sending them
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Compare my collection with another user's and write compare_<username>.csv
+    Compare {
+        /// The other user's username
+        username: String,
+    },
+    /// Copy missing or outdated entries from another account's public collection
+    /// to this one, never downgrading a status or rating
+    Sync {
+        /// Username of the account to copy from
+        #[arg(long)]
+        from_user: String,
+        /// Resolve conflicting entries in favor of the source account
+        #[arg(long, default_value_t = false)]
+        prefer_source: bool,
+        /// Print the plan without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Write without asking for confirmation
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
+    },
+    /// Export 目录 (indexes) with their subjects to <output>/indexes/
+    Indexes {
+        /// Index ids or bgm.tv/index URLs (the API has no way to list a user's indexes)
+        #[arg(required = true, value_parser = parse_index_id)]
+        ids: Vec<u64>,
+    },
+    /// Show how ratings are distributed, per subject type
+    Stats {
+        /// Also write the numbers to stats.json in the output directory
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Count how often each tag is used, per subject type, and write tags.json
+    Tags {
+        /// Number of tags to print per group
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// List 在看 entries not updated for at least DAYS days, oldest first
+    Stale {
+        /// Minimum number of days since the last update
+        days: u64,
+        /// Also write the list to stale.csv in the output directory
+        #[arg(long, default_value_t = false)]
+        csv: bool,
+    },
+    /// List completed items that have no rating, and write missing.csv
+    Missing {
+        /// Also list completed items that have no comment
+        #[arg(long, default_value_t = false)]
+        comments: bool,
+    },
+    /// Show the next episode to watch for each 在看 subject, and write next_up.json
+    NextUp,
+    /// Browse the collection in an interactive terminal UI (writes no files)
+    Browse,
+    /// Compare two JSON exports and write diff.json (no login needed)
+    Diff {
+        /// The older bangumi_export.json
+        old: PathBuf,
+        /// The newer bangumi_export.json
+        new: PathBuf,
+    },
+    /// List export snapshots, or compare two of them
+    History {
+        /// Older snapshot name to compare
+        #[arg(requires = "to")]
+        from: Option<String>,
+        /// Newer snapshot name to compare
+        to: Option<String>,
+    },
+    /// Inspect the local cache (no login needed)
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// Manage the config file of default options (no login needed)
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Manage named account profiles (no login needed)
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+    /// Log in through Bangumi OAuth and store the tokens for later runs
+    Auth {
+        /// OAuth client id of an app registered at https://bgm.tv/dev/app
+        #[arg(long, env = "BANGUMI_CLIENT_ID")]
+        client_id: String,
+        /// OAuth client secret of the app
+        #[arg(long, env = "BANGUMI_CLIENT_SECRET", value_parser = parse_secret, hide_env_values = true)]
+        client_secret: Secret,
+        /// Local port for the redirect; the app's callback must be http://localhost:<PORT>/callback
+        #[arg(long, default_value_t = crate::oauth::DEFAULT_PORT)]
+        port: u16,
+    },
+    /// Mirror collection marks (status, rating, comment) to NeoDB
+    NeodbSync {
+        /// NeoDB API token
+        #[arg(long, env = "NEODB_TOKEN", hide_env_values = true)]
+        neodb_token: String,
+        /// NeoDB instance, for self-hosted servers
+        #[arg(long, default_value = crate::neodb::DEFAULT_INSTANCE)]
+        neodb_instance: String,
+        /// Print what would change without writing to NeoDB
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Upsert one page per collection into a Notion database
+    Notion {
+        /// Notion integration token
+        #[arg(long, env = "NOTION_TOKEN", hide_env_values = true)]
+        notion_token: String,
+        /// Target database; it needs the properties listed in the README
+        #[arg(long, env = "NOTION_DATABASE_ID")]
+        notion_database_id: String,
+        /// Print what would change without writing to Notion
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Show this week's airing anime by weekday, marking the ones in my collection
+    Calendar {
+        /// Also write the merged calendar to calendar.json in the output directory
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
 }
 
 #[derive(Debug, Parser)]
 #[command(name = "bangumi-tool", about = "Export Bangumi collection data")]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Export format
     #[arg(short, long, value_enum, default_value = "all")]
     pub format: Format,
 
     /// Output directory
-    #[arg(short, long, default_value = ".")]
+    #[arg(short, long, default_value = ".", global = true)]
     pub output: String,
 
+    /// Only print the summary; write no export files
+    #[arg(long, default_value_t = false, conflicts_with_all = ["format", "quiet"])]
+    pub summary_only: bool,
+
+    /// Only include these subject types (comma-separated)
+    #[arg(long = "type", value_enum, value_delimiter = ',', global = true)]
+    pub types: Vec<SubjectTypeFilter>,
+
+    /// Only include these collection statuses (comma-separated)
+    #[arg(long, value_enum, value_delimiter = ',', global = true)]
+    pub status: Vec<StatusFilter>,
+
+    /// Keep, leave out, or keep only R18 subjects
+    #[arg(long, value_enum, default_value_t = NsfwFilter::Include, global = true)]
+    pub nsfw: NsfwFilter,
+
+    /// Leave private collections out of exports and the summary
+    #[arg(long, default_value_t = false, global = true)]
+    pub exclude_private: bool,
+
+    /// Only collections updated in this year; without a subcommand, writes a
+    /// year-in-review to review_<year>.json/.md instead of the export
+    #[arg(long, value_name = "YEAR", global = true)]
+    pub year: Option<i32>,
+
     /// Enable debug logging (prints HTTP requests and responses)
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, global = true)]
     pub debug: bool,
 
-    /// Disable cache and fetch everything fresh
+    /// Only print warnings, errors and a final one-line result; hide progress bars
+    #[arg(short, long, default_value_t = false, global = true)]
+    pub quiet: bool,
+
+    /// Progress display [default: bar on a terminal, plain when piped or under CI, none with --quiet]
+    #[arg(long, value_enum, value_name = "MODE", global = true)]
+    pub progress: Option<ProgressMode>,
+
+    /// Order of items within each group of the terminal summary (exports follow --sort-by)
+    #[arg(long, value_enum)]
+    pub summary_sort: Option<SummarySort>,
+
+    /// Order of records in the export files (default: API order); ties break by subject id
+    #[arg(long, value_enum, value_name = "KEY")]
+    pub sort_by: Option<SortBy>,
+
+    /// Reverse the --sort-by order (unrated items stay last when sorting by rating)
+    #[arg(long, default_value_t = false, requires = "sort_by")]
+    pub reverse: bool,
+
+    /// Instead of the export, chart collections per month of their last update
+    /// over the last MONTHS months (default 24) and write activity.json
+    #[arg(long, value_name = "MONTHS", num_args = 0..=1, default_missing_value = "24")]
+    pub activity: Option<u32>,
+
+    /// Nest the seasons of a franchise under one line in the terminal summary
+    /// (exports are unaffected); without a value, uses relations
+    #[arg(long, value_enum, value_name = "BY", num_args = 0..=1, default_missing_value = "relations")]
+    pub group_franchises: Option<FranchiseGrouping>,
+
+    /// When to color the terminal summary (auto respects NO_COLOR and non-TTY output)
+    #[arg(long, value_enum, default_value = "auto", global = true)]
+    pub color: ColorChoice,
+
+    /// Language of CSV headers, type and status labels in exports, and the summary
+    #[arg(long, value_enum, default_value = "zh", global = true)]
+    pub lang: Lang,
+
+    /// Time zone of exported timestamps: an IANA name such as Asia/Shanghai, local or utc
+    #[arg(long, value_name = "ZONE", value_parser = parse_timezone, default_value = "local", global = true)]
+    pub timezone: TimeZone,
+
+    /// strftime format of exported timestamps, e.g. %Y-%m-%dT%H:%M:%S%:z for ISO 8601
+    #[arg(long, value_name = "FORMAT", value_parser = parse_date_format, default_value = DEFAULT_DATE_FORMAT, global = true)]
+    pub date_format: String,
+
+    /// Export for publishing: drop private items, empty my tags and comments, and keep only the date of updates
     #[arg(long, default_value_t = false)]
+    pub anonymize: bool,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value = "plain", global = true)]
+    pub log_format: LogFormat,
+
+    /// Append debug-level logs to this file, independent of the console level
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Ignore the config files (./bangumi-tool.toml and ~/.config/bangumi-tool/config.toml)
+    #[arg(long, default_value_t = false, global = true)]
+    pub no_config: bool,
+
+    /// Fetch everything fresh, keeping responses in memory for this run only; the on-disk cache is left untouched
+    #[arg(long, default_value_t = false, global = true)]
     pub no_cache: bool,
 
+    /// Build everything from the cache without any network access; fails listing
+    /// the missing cache entries if something isn't cached
+    #[arg(long, default_value_t = false, global = true)]
+    pub offline: bool,
+
+    /// Drop the cached detail, episodes and progress of this subject (ID or URL) and
+    /// refetch it; repeatable
+    #[arg(long, value_name = "SUBJECT", value_parser = parse_subject_id, global = true)]
+    pub refresh_subject: Vec<u64>,
+
+    /// Drop only the cached collection pages, keeping per-subject data
+    #[arg(long, default_value_t = false, global = true)]
+    pub refresh_collections: bool,
+
+    /// Ask the API who the token belongs to instead of reusing the cached answer
+    /// (kept for 24 hours), e.g. after switching accounts
+    #[arg(long, default_value_t = false, global = true)]
+    pub no_me_cache: bool,
+
+    /// Write cache entries as plain JSON instead of gzip (compressed entries are still read)
+    #[arg(long, default_value_t = false, global = true)]
+    pub no_cache_compression: bool,
+
+    /// Serialization of new cache entries (entries in either format are read)
+    #[arg(long, value_enum, default_value = "json", global = true)]
+    pub cache_format: CacheFormat,
+
+    /// Cache directory [default: ./.bgm_cache if it exists, else the platform cache
+    /// directory, e.g. ~/.cache/bangumi-tool]
+    #[arg(long, value_name = "DIR", env = "BANGUMI_CACHE_DIR", global = true)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// How long cached collection pages stay fresh (also character/person pages and indexes)
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, default_value = "24h", global = true)]
+    pub collections_ttl: Duration,
+
+    /// How long cached episode progress stays fresh
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, default_value = "24h", global = true)]
+    pub progress_ttl: Duration,
+
+    /// How long cached subject details and episode lists stay fresh
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, default_value = "30d", global = true)]
+    pub subjects_ttl: Duration,
+
+    /// Access token; takes precedence over --token-file, BANGUMI_ACCESS_TOKEN, .bgm_token and `auth`
+    #[arg(long, value_name = "TOKEN", value_parser = parse_secret, global = true)]
+    pub token: Option<Secret>,
+
+    /// Use a saved profile's token and its own cache directory
+    #[arg(long, value_name = "NAME", global = true)]
+    pub profile: Option<String>,
+
+    /// Read the access token from this file (`~` is expanded)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub token_file: Option<PathBuf>,
+
+    /// API base URL (e.g. a mirror or a local mock server)
+    #[arg(long, env = "BANGUMI_API_BASE", default_value = crate::client::DEFAULT_BASE_URL, global = true)]
+    pub base_url: String,
+
+    /// User-Agent sent to the API, e.g. with a contact address; a leading `+`
+    /// appends the rest to the default instead of replacing it
+    #[arg(long, env = "BANGUMI_USER_AGENT", value_name = "UA", value_parser = parse_user_agent, global = true)]
+    pub user_agent: Option<String>,
+
+    /// Warn about API responses taking longer than this (rate-limit waits not included)
+    #[arg(long, value_name = "SECONDS", value_parser = parse_interval, default_value = "10", global = true)]
+    pub slow_request: Duration,
+
+    /// Collections fetched per request (1-50); cached pages are kept per page size
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..=COLLECTIONS_PAGE_LIMIT), default_value_t = COLLECTIONS_PAGE_LIMIT, global = true)]
+    pub page_size: u64,
+
+    /// Resend a failed GET request (429, 5xx, connection error or timeout) up to
+    /// this many times; 0 makes a single attempt
+    #[arg(long, value_name = "N", default_value_t = 0, global = true)]
+    pub max_retries: u32,
+
+    /// Wait before the first retry, doubled for each further one; 0 retries at once
+    #[arg(long, value_name = "SECONDS", value_parser = parse_delay, default_value = "2", global = true)]
+    pub retry_backoff: Duration,
+
+    /// Only fetch collections updated since the last --incremental run and refresh
+    /// their cached detail; the first run does a full sync
+    #[arg(long, default_value_t = false, global = true)]
+    pub incremental: bool,
+
+    /// Keep running: re-sync (incrementally) and rewrite the exports every INTERVAL,
+    /// e.g. 30m, 6h or 1d
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, conflicts_with = "summary_only")]
+    pub watch: Option<Duration>,
+
+    /// Also export my character collection to bangumi_characters.json/.csv
+    #[arg(long, default_value_t = false, conflicts_with = "summary_only")]
+    pub characters: bool,
+
+    /// Also export my person collection to bangumi_persons.json/.csv
+    #[arg(long, default_value_t = false, conflicts_with = "summary_only")]
+    pub persons: bool,
+
+    /// Also save the raw API responses for collections, subjects, episodes and
+    /// progress under <output>/archive/, organized by subject id
+    #[arg(long, default_value_t = false)]
+    pub archive: bool,
+
     /// Fetch detailed info (episodes, progress) for each subject
     #[arg(long, default_value_t = false)]
     pub detail: bool,
+
+    /// Write the items with a comment to reviews.md as short reviews, instead
+    /// of the export
+    #[arg(long, default_value_t = false, conflicts_with_all = ["detail", "summary_only", "stream", "watch"])]
+    pub reviews: bool,
+
+    /// With --reviews, also write the reviews to reviews.json
+    #[arg(long, default_value_t = false, requires = "reviews")]
+    pub reviews_json: bool,
+
+    /// Refuse to start a --detail run that would outlast the access token
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub strict_token: bool,
+
+    /// With --detail, how many of each subject's most used community tags to export
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_SITE_TAGS_LIMIT)]
+    pub site_tags_limit: usize,
+
+    /// With --detail, also export the titles of watched episodes
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub episode_titles: bool,
+
+    /// With --detail, fetch the track lists of music subjects to count their
+    /// tracks and discs (music has no per-track progress either way)
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub music_tracks: bool,
+
+    /// With --detail, print where my ratings differ most from the site score
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub rating_diff: bool,
+
+    /// With --detail, append each record to bangumi_export.csv.partial and
+    /// bangumi_export.ndjson.partial as soon as it is built, so an interrupted
+    /// run leaves the finished records on disk; the final files are replaced at the end
+    #[arg(long, default_value_t = false, requires = "detail", conflicts_with_all = ["summary_only", "check"])]
+    pub stream: bool,
+
+    /// With --detail, instead of the export, list subjects whose collection
+    /// progress (ep_status) differs from their episode marks, and write
+    /// inconsistencies.csv; nothing is changed on the server
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub check: bool,
+
+    /// With --check, after confirmation, mark the missing episodes as watched
+    /// where the collection progress is ahead of the episode marks
+    #[arg(long, default_value_t = false, requires = "check")]
+    pub fix: bool,
+
+    /// With --detail, also export each subject's summary (简介)
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub include_summary: bool,
+
+    /// With --detail, export these infobox rows as extra columns (comma-separated, e.g. 平台,动画制作,作者)
+    #[arg(long, value_name = "KEYS", value_delimiter = ',', requires = "detail")]
+    pub infobox_fields: Vec<String>,
+
+    /// With --detail, also export each subject's related subjects (sequels, prequels,
+    /// adaptations); one more request per subject
+    #[arg(long, default_value_t = false, requires = "detail")]
+    pub relations: bool,
+
+    /// With --format ics, also include unwatched episodes that aired within this many past days
+    #[arg(long, value_name = "DAYS", default_value_t = 0)]
+    pub ics_past_days: u64,
+
+    /// With --format txt, append the subject type in brackets to each line
+    #[arg(long, default_value_t = false)]
+    pub with_types: bool,
+
+    /// With --format txt, append the subject URL to each line
+    #[arg(long, default_value_t = false)]
+    pub with_urls: bool,
+
+    /// Also keep an immutable copy of the JSON export under <output>/snapshots/
+    #[arg(long, default_value_t = false, conflicts_with = "summary_only")]
+    pub snapshot: bool,
+
+    /// Number of snapshots to keep, at least 1; older ones are pruned
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), default_value_t = 30)]
+    pub snapshot_keep: u64,
+
+    /// POST the JSON export to this URL after a successful export
+    #[arg(long, value_name = "URL", conflicts_with = "summary_only")]
+    pub webhook: Option<String>,
+
+    /// Sign webhook bodies with HMAC-SHA256 using this secret (sent as X-Signature)
+    #[arg(
+        long,
+        env = "BANGUMI_WEBHOOK_SECRET",
+        requires = "webhook",
+        hide_env_values = true
+    )]
+    pub webhook_secret: Option<String>,
+
+    /// Compare this run against a previous JSON export and write diff.json
+    #[arg(long, value_name = "OLD_JSON", conflicts_with = "summary_only")]
+    pub against: Option<PathBuf>,
+
+    /// Skip subjects that fail to fetch and report them at the end (with --detail)
+    #[arg(long, default_value_t = false)]
+    pub skip_errors: bool,
+
+    /// Revalidate cached subjects and episodes with the server using ETags
+    #[arg(long, default_value_t = false)]
+    pub revalidate: bool,
+}
+
+fn parse_secret(s: &str) -> Result<Secret, String> {
+    Ok(Secret(s.to_string()))
+}
+
+/// A `User-Agent` must be non-empty printable ASCII, with or without the `+` prefix.
+fn parse_user_agent(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    if s.trim_start_matches('+').trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if !s.bytes().all(|b| b == b' ' || b.is_ascii_graphic()) {
+        return Err("must be printable ASCII".to_string());
+    }
+    Ok(s.to_string())
+}
+
+fn parse_timezone(s: &str) -> Result<TimeZone, String> {
+    s.parse().map_err(|e: crate::error::AppError| e.to_string())
+}
+
+/// Check a strftime format up front, so a bad one fails before any request.
+fn parse_date_format(s: &str) -> Result<String, String> {
+    TimestampFormat::new(TimeZone::Local, s)
+        .map(|_| s.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Parse an interval such as `90s`, `30m`, `6h` or `1d`; a bare number means seconds.
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let interval = parse_delay(s)?;
+    if interval.is_zero() {
+        return Err("interval must be greater than zero".to_string());
+    }
+    Ok(interval)
+}
+
+/// Parse a delay like `parse_interval`, but also accept 0 for no delay.
+fn parse_delay(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}'", s))?;
+    let unit_secs: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => {
+            return Err(format!(
+                "invalid interval unit '{}' (use s, m, h or d)",
+                unit
+            ));
+        }
+    };
+    let secs = n
+        .checked_mul(unit_secs)
+        .ok_or_else(|| format!("interval '{}' is too large", s))?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Parse a subject id, or take it from a bgm.tv subject URL.
+fn parse_subject_id(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    s.parse()
+        .ok()
+        .or_else(|| crate::models::subject_id_from_url(s))
+        .ok_or_else(|| format!("'{}' is not a subject id or bgm.tv subject URL", s))
+}
+
+/// Parse `N` or `A-B` into an inclusive range of episode sort numbers.
+fn parse_episode_range(s: &str) -> Result<EpisodeRange, String> {
+    let number = |n: &str| {
+        n.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| format!("invalid episode number '{}'", n.trim()))
+    };
+    let (from, to) = match s.split_once('-') {
+        Some((a, b)) => (number(a)?, number(b)?),
+        None => {
+            let n = number(s)?;
+            (n, n)
+        }
+    };
+    if from > to {
+        return Err(format!(
+            "invalid episode range '{}' (start is after end)",
+            s
+        ));
+    }
+    Ok(EpisodeRange { from, to })
+}
+
+/// Parse an index id, or take it from an index URL such as `https://bgm.tv/index/123`.
+fn parse_index_id(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    s.parse()
+        .ok()
+        .or_else(|| {
+            s.split(['?', '#'])
+                .next()?
+                .split("/index/")
+                .nth(1)?
+                .split('/')
+                .next()?
+                .parse()
+                .ok()
+        })
+        .ok_or_else(|| format!("'{}' is not an index id or bgm.tv index URL", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_in_each_unit() {
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_interval(" 6h "), Ok(Duration::from_secs(6 * 3600)));
+        assert_eq!(parse_interval("1d"), Ok(Duration::from_secs(86400)));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("5w").is_err());
+    }
+
+    #[test]
+    fn retry_backoff_may_be_zero() {
+        assert_eq!(parse_delay("0"), Ok(Duration::ZERO));
+        assert_eq!(parse_delay("0s"), Ok(Duration::ZERO));
+        assert_eq!(parse_delay("5"), Ok(Duration::from_secs(5)));
+        assert!(parse_delay("999999999999999999d").is_err());
+
+        let args = Args::try_parse_from(["bangumi-tool", "--retry-backoff", "0"]).unwrap();
+        assert_eq!(args.retry_backoff, Duration::ZERO);
+        assert!(Args::try_parse_from(["bangumi-tool", "--watch", "0"]).is_err());
+    }
+
+    #[test]
+    fn oversized_intervals_are_rejected() {
+        let err = parse_interval("999999999999999999d").unwrap_err();
+        assert!(err.contains("too large"), "{}", err);
+        assert!(parse_interval(&format!("{}m", u64::MAX / 60 + 1)).is_err());
+        assert_eq!(
+            parse_interval(&format!("{}s", u64::MAX)),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+    }
 }
@@ -1,49 +1,467 @@
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use log::debug;
+use log::{debug, warn};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
 use tokio::time::sleep;
 
 use crate::error::{AppError, Result};
-use crate::models::{PagedCollection, PagedEpisodes, SubjectDetail, User, UserProgress};
+use crate::models::{
+    CalendarDay, CharacterCollection, Collection, EpisodeCollection, Index, IndexSubject, Paged,
+    PagedCollection, PagedEpisodes, PagedSearchResults, PersonCollection, RelatedSubject,
+    SubjectDetail, User, UserProgress,
+};
 
-const BASE_URL: &str = "https://api.bgm.tv";
+/// The public Bangumi API.
+pub const DEFAULT_BASE_URL: &str = "https://api.bgm.tv";
 const REQUEST_INTERVAL: Duration = Duration::from_secs(5);
+/// Default time after which a response is logged as slow.
+pub const SLOW_REQUEST: Duration = Duration::from_secs(10);
+/// Default delay before the first retry; doubled for each further one.
+pub const RETRY_BACKOFF: Duration = Duration::from_secs(2);
 
+/// Result of a conditional request sent with `If-None-Match`.
+pub enum Conditional<T> {
+    /// The server sent a new body, along with its ETag if present.
+    Modified { value: T, etag: Option<String> },
+    /// The server answered 304; the cached copy is still current.
+    NotModified,
+}
+
+/// Notification about a deliberate pause between requests, so the UI can show it.
+pub enum WaitEvent {
+    /// Sleeping; emitted once per second with the time left.
+    Waiting {
+        remaining: Duration,
+        reason: &'static str,
+    },
+    /// The pause is over and the request is about to be sent.
+    Done,
+}
+
+/// Callback receiving `WaitEvent`s.
+pub type WaitObserver = Arc<dyn Fn(WaitEvent) + Send + Sync>;
+
+/// Callback receiving every successful response body as downloaded, with its URL.
+pub type RawObserver = Arc<dyn Fn(&reqwest::Url, &[u8]) + Send + Sync>;
+
+/// Request counters shared by all calls made through a client.
+#[derive(Default)]
+struct Stats {
+    requests: Mutex<BTreeMap<String, u64>>,
+    /// Response times in milliseconds per endpoint, one per attempt.
+    durations: Mutex<BTreeMap<String, Vec<u64>>>,
+    bytes: AtomicU64,
+    retries: AtomicU64,
+}
+
+/// Response times of one endpoint, from sending a request to its response
+/// headers; rate-limit and retry waits are not included.
+#[derive(Debug, Clone, Serialize)]
+pub struct Latency {
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+}
+
+impl Latency {
+    /// Nearest-rank percentiles of `durations`, `None` if there are none.
+    fn new(durations: &[u64]) -> Option<Self> {
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+        let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+        (!sorted.is_empty()).then(|| Self {
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+        })
+    }
+}
+
+/// Point-in-time copy of a client's request counters.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestStats {
+    /// Requests sent per endpoint, with ids replaced by placeholders.
+    pub requests: BTreeMap<String, u64>,
+    /// Response body bytes downloaded.
+    pub bytes: u64,
+    /// Requests resent after a retryable failure.
+    pub retries: u64,
+    /// Response times per endpoint, for endpoints that got a response.
+    pub latency: BTreeMap<String, Latency>,
+}
+
+impl RequestStats {
+    /// Requests sent across all endpoints.
+    pub fn total_requests(&self) -> u64 {
+        self.requests.values().sum()
+    }
+}
+
+/// Rate-limited client for the Bangumi API, optionally answering from the cache only.
 pub struct BangumiClient {
     http: reqwest::Client,
-    token: String,
+    token: Option<String>,
+    base_url: String,
+    request_interval: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+    slow_request: Duration,
+    wait_observer: Mutex<Option<WaitObserver>>,
+    raw_observer: Mutex<Option<RawObserver>>,
+    stats: Arc<Stats>,
+    offline: bool,
+}
+
+/// Reduce a request path to an endpoint label, e.g. `/v0/subjects/12` -> `/v0/subjects/{id}`.
+fn endpoint_label(path: &str) -> String {
+    let mut parts = Vec::new();
+    let mut prev = "";
+    for part in path.split('/') {
+        if prev == "users" || prev == "user" {
+            parts.push("{user}");
+        } else if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) {
+            parts.push("{id}");
+        } else {
+            parts.push(part);
+        }
+        prev = part;
+    }
+    parts.join("/")
+}
+
+/// Validate an API base URL and strip trailing slashes so paths can be appended directly.
+pub fn normalize_base_url(base_url: &str) -> Result<String> {
+    let trimmed = base_url.trim().trim_end_matches('/');
+    let url = reqwest::Url::parse(trimmed)
+        .map_err(|e| AppError::InvalidBaseUrl(format!("{}: {}", base_url, e)))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(AppError::InvalidBaseUrl(format!(
+            "{}: must be an absolute http(s) URL",
+            base_url
+        )));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// The `User-Agent` sent unless the builder is given another.
+pub fn default_user_agent() -> String {
+    format!(
+        "bangumi-tool/{} (https://github.com/star-hengxing/bangumi-tool)",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Settings for a `BangumiClient`, from `BangumiClient::builder()`.
+#[derive(Debug, Clone)]
+pub struct BangumiClientBuilder {
+    token: Option<String>,
+    base_url: String,
+    user_agent: String,
+    request_interval: Duration,
+    timeout: Option<Duration>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    slow_request: Duration,
+}
+
+impl Default for BangumiClientBuilder {
+    fn default() -> Self {
+        Self {
+            token: None,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            user_agent: default_user_agent(),
+            request_interval: REQUEST_INTERVAL,
+            timeout: None,
+            max_retries: 0,
+            retry_backoff: RETRY_BACKOFF,
+            slow_request: SLOW_REQUEST,
+        }
+    }
+}
+
+impl BangumiClientBuilder {
+    /// Access token sent as a bearer token; `None` sends unauthenticated requests.
+    pub fn token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// API base URL, validated by `build`.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// `User-Agent` header sent with every request.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Delay inserted before each rate-limited request.
+    pub fn request_interval(mut self, interval: Duration) -> Self {
+        self.request_interval = interval;
+        self
+    }
+
+    /// Give up on a request that takes longer than `timeout`; no limit by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Resend a GET request up to `max_retries` times after a 429, a 5xx or a
+    /// connection failure, backing off between attempts. Defaults to 0, a
+    /// single attempt.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled for each further one. Defaults to
+    /// `RETRY_BACKOFF`.
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Warn about responses taking longer than `threshold`. Defaults to
+    /// `SLOW_REQUEST`.
+    pub fn slow_request(mut self, threshold: Duration) -> Self {
+        self.slow_request = threshold;
+        self
+    }
+
+    /// The configured client; fails on an invalid base URL or user agent.
+    pub fn build(self) -> Result<BangumiClient> {
+        let base_url = normalize_base_url(&self.base_url)?;
+        debug!("Using API base URL {}", base_url);
+        debug!("Using User-Agent {}", self.user_agent);
+        let mut http = reqwest::Client::builder().user_agent(self.user_agent);
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        Ok(BangumiClient {
+            http: http.build()?,
+            token: self.token,
+            base_url,
+            request_interval: self.request_interval,
+            max_retries: self.max_retries,
+            retry_backoff: self.retry_backoff,
+            slow_request: self.slow_request,
+            wait_observer: Mutex::new(None),
+            raw_observer: Mutex::new(None),
+            stats: Arc::default(),
+            offline: false,
+        })
+    }
 }
 
 impl BangumiClient {
-    pub fn new(token: String) -> Result<Self> {
-        let http = reqwest::Client::builder()
-            .user_agent(format!(
-                "bangumi-tool/{} (https://github.com/star-hengxing/bangumi-tool)",
-                env!("CARGO_PKG_VERSION")
-            ))
-            .build()?;
-        Ok(Self { http, token })
+    /// Client sending `token` to the API at `base_url`, with the default settings.
+    pub fn new(token: String, base_url: &str) -> Result<Self> {
+        Self::builder()
+            .token(Some(token))
+            .base_url(base_url)
+            .build()
+    }
+
+    /// Builder for a client with settings other than the defaults.
+    pub fn builder() -> BangumiClientBuilder {
+        BangumiClientBuilder::default()
+    }
+
+    /// Fail every request instead of sending it, for `--offline` runs.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Short hash of the access token, for keying data that belongs to the
+    /// token's owner before the uid is known; `None` without a token.
+    pub fn token_fingerprint(&self) -> Option<String> {
+        let digest = Sha256::digest(self.token.as_ref()?.as_bytes());
+        Some(digest[..8].iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Delay inserted before each rate-limited request.
+    pub fn request_interval(&self) -> Duration {
+        self.request_interval
+    }
+
+    /// Snapshot of the requests made so far.
+    pub fn stats(&self) -> RequestStats {
+        RequestStats {
+            requests: self.stats.requests.lock().unwrap().clone(),
+            bytes: self.stats.bytes.load(Ordering::Relaxed),
+            retries: self.stats.retries.load(Ordering::Relaxed),
+            latency: self
+                .stats
+                .durations
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|(endpoint, d)| Some((endpoint.clone(), Latency::new(d)?)))
+                .collect(),
+        }
+    }
+
+    /// Read a response body, counting its size in the stats.
+    async fn read_body(&self, resp: reqwest::Response) -> Result<Vec<u8>> {
+        let url = resp.url().clone();
+        let body = resp.bytes().await?;
+        self.stats
+            .bytes
+            .fetch_add(body.len() as u64, Ordering::Relaxed);
+        let observer = self.raw_observer.lock().unwrap().clone();
+        if let Some(observer) = observer {
+            observer(&url, &body);
+        }
+        Ok(body.into())
+    }
+
+    /// Read and parse a JSON response body.
+    async fn read_json<T: DeserializeOwned>(&self, resp: reqwest::Response) -> Result<T> {
+        let body = self.read_body(resp).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Parse a response that may be a 304, keeping the ETag of a fresh body.
+    async fn read_conditional<T: DeserializeOwned>(
+        &self,
+        resp: reqwest::Response,
+    ) -> Result<Conditional<T>> {
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+        let etag = resp
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        Ok(Conditional::Modified {
+            value: self.read_json(resp).await?,
+            etag,
+        })
+    }
+
+    /// Register a callback receiving rate-limit wait events, or remove it with `None`.
+    pub fn set_wait_observer(&self, observer: Option<WaitObserver>) {
+        *self.wait_observer.lock().unwrap() = observer;
+    }
+
+    /// Register a callback receiving raw response bodies, or remove it with `None`.
+    pub fn set_raw_observer(&self, observer: Option<RawObserver>) {
+        *self.raw_observer.lock().unwrap() = observer;
     }
 
     async fn rate_limit(&self) {
-        sleep(REQUEST_INTERVAL).await;
+        self.wait(self.request_interval, "rate limit").await;
+    }
+
+    /// Sleep for `duration`, reporting a per-second countdown to the wait observer.
+    async fn wait(&self, duration: Duration, reason: &'static str) {
+        let observer = self.wait_observer.lock().unwrap().clone();
+        let Some(observer) = observer else {
+            sleep(duration).await;
+            return;
+        };
+        let mut remaining = duration;
+        while !remaining.is_zero() {
+            observer(WaitEvent::Waiting { remaining, reason });
+            let step = remaining.min(Duration::from_secs(1));
+            sleep(step).await;
+            remaining -= step;
+        }
+        observer(WaitEvent::Done);
     }
 
     async fn request(&self, path: &str, query: &[(&str, String)]) -> Result<reqwest::Response> {
-        let url = format!("{}{}", BASE_URL, path);
-        debug!("GET {} {:?}", url, query);
-        let mut builder = self.http.get(&url).bearer_auth(&self.token);
+        self.request_if_none_match(path, query, None).await
+    }
+
+    /// Send a GET request, optionally conditional on an ETag.
+    /// A 304 response is passed through to the caller instead of being treated as an error.
+    async fn request_if_none_match(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        etag: Option<&str>,
+    ) -> Result<reqwest::Response> {
+        self.send(Method::GET, path, query, None, etag).await
+    }
+
+    /// Send a POST request with a JSON body.
+    async fn post(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        body: &Value,
+    ) -> Result<reqwest::Response> {
+        self.send(Method::POST, path, query, Some(body), None).await
+    }
+
+    /// Send a PATCH request with a JSON body.
+    async fn patch(&self, path: &str, body: &Value) -> Result<reqwest::Response> {
+        self.send(Method::PATCH, path, &[], Some(body), None).await
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<&Value>,
+        etag: Option<&str>,
+    ) -> Result<reqwest::Response> {
+        if self.offline {
+            return Err(AppError::Offline(format!("{} {}", method, path)));
+        }
+        let url = format!("{}{}", self.base_url, path);
+        debug!(url = url.as_str(); "{} {} {:?}", method, url, query);
+        let endpoint = endpoint_label(path);
+        *self
+            .stats
+            .requests
+            .lock()
+            .unwrap()
+            .entry(endpoint.clone())
+            .or_default() += 1;
+        let mut builder = self.http.request(method.clone(), &url);
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
         if !query.is_empty() {
             builder = builder.query(query);
         }
-        let resp = builder.send().await?;
-        debug!("Response: {} {}", resp.status(), url);
+        if let Some(body) = body {
+            debug!("Request body: {}", body);
+            builder = builder.json(body);
+        }
+        if let Some(etag) = etag {
+            debug!("If-None-Match: {}", etag);
+            builder = builder.header(IF_NONE_MATCH, etag);
+        }
+        let resp = self
+            .send_with_retries(&method, &url, &endpoint, builder)
+            .await?;
         let status = resp.status();
-        if status.is_success() {
+        if status.is_success() || status == StatusCode::NOT_MODIFIED {
             Ok(resp)
+        } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            debug!("Error body: {}", resp.text().await.unwrap_or_default());
+            Err(AppError::Unauthorized {
+                status: status.as_u16(),
+            })
         } else {
             let body = resp.text().await.unwrap_or_default();
-            debug!("Error body: {}", body);
+            debug!("Error body for {}: {}", url, body);
             Err(AppError::Api {
                 status: status.as_u16(),
                 message: body,
@@ -51,11 +469,114 @@ impl BangumiClient {
         }
     }
 
+    /// Send a prepared request, resending a GET up to `max_retries` times after
+    /// a 429, a 5xx or a connection failure. Other methods are sent once, as
+    /// they may not be safe to repeat. The last response is returned whatever
+    /// its status.
+    async fn send_with_retries(
+        &self,
+        method: &Method,
+        url: &str,
+        endpoint: &str,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let mut delay = self.retry_backoff;
+        let mut attempt = 0;
+        let retries = if *method == Method::GET {
+            self.max_retries
+        } else {
+            0
+        };
+        loop {
+            let request = match builder.try_clone() {
+                Some(request) if attempt < retries => request,
+                _ => return self.send_once(method, url, endpoint, builder).await,
+            };
+            let result = self.send_once(method, url, endpoint, request).await;
+            let retry = match &result {
+                Ok(resp) => {
+                    resp.status() == StatusCode::TOO_MANY_REQUESTS
+                        || resp.status().is_server_error()
+                }
+                Err(AppError::Http(e)) => e.is_connect() || e.is_timeout(),
+                Err(_) => false,
+            };
+            if !retry {
+                return result;
+            }
+            attempt += 1;
+            let next = format!("attempt {}/{}", attempt + 1, retries + 1);
+            match &result {
+                Ok(resp) => warn!(
+                    "{} {} returned {}, retrying ({})",
+                    method,
+                    url,
+                    resp.status(),
+                    next
+                ),
+                Err(e) => warn!("{} {} failed, retrying ({}): {}", method, url, next, e),
+            }
+            self.stats.retries.fetch_add(1, Ordering::Relaxed);
+            self.wait(delay, "retry").await;
+            delay *= 2;
+        }
+    }
+
+    /// Send a prepared request once, logging how it went and how long the
+    /// response took.
+    async fn send_once(
+        &self,
+        method: &Method,
+        url: &str,
+        endpoint: &str,
+        builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let started = Instant::now();
+        let resp = builder
+            .send()
+            .await
+            .inspect_err(|e| debug!(url = url; "Request failed: {} {}: {}", method, url, e))?;
+        let status = resp.status();
+        let elapsed = started.elapsed();
+        let duration_ms = elapsed.as_millis() as u64;
+        debug!(
+            url = url, status = status.as_u16(), duration_ms = duration_ms;
+            "Response: {} {} in {}ms", status, url, duration_ms
+        );
+        if elapsed > self.slow_request {
+            warn!(
+                "Slow response: {} {} took {:.1}s",
+                method,
+                url,
+                elapsed.as_secs_f64()
+            );
+        }
+        self.stats
+            .durations
+            .lock()
+            .unwrap()
+            .entry(endpoint.to_string())
+            .or_default()
+            .push(duration_ms);
+        Ok(resp)
+    }
+
+    /// The user the token belongs to.
     pub async fn get_me(&self) -> Result<User> {
         let resp = self.request("/v0/me", &[]).await?;
-        Ok(resp.json().await?)
+        self.read_json(resp).await
+    }
+
+    /// Public profile of another user.
+    pub async fn get_user(&self, username: &str) -> Result<User> {
+        self.rate_limit().await;
+        let resp = self
+            .request(&format!("/v0/users/{}", username), &[])
+            .await?;
+        self.read_json(resp).await
     }
 
+    /// One page of a user's subject collections.
     pub async fn get_collections(
         &self,
         username: &str,
@@ -70,36 +591,197 @@ impl BangumiClient {
                 &[("limit", limit.to_string()), ("offset", offset.to_string())],
             )
             .await?;
-        Ok(resp.json().await?)
+        self.read_json(resp).await
+    }
+
+    /// One page of a user's collected characters.
+    pub async fn get_character_collections(
+        &self,
+        username: &str,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Paged<CharacterCollection>> {
+        self.rate_limit().await;
+        let path = format!("/v0/users/{}/collections/-/characters", username);
+        let resp = self
+            .request(
+                &path,
+                &[("limit", limit.to_string()), ("offset", offset.to_string())],
+            )
+            .await?;
+        self.read_json(resp).await
     }
 
-    pub async fn get_subject(&self, id: u64) -> Result<SubjectDetail> {
+    /// One page of a user's collected persons.
+    pub async fn get_person_collections(
+        &self,
+        username: &str,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Paged<PersonCollection>> {
+        self.rate_limit().await;
+        let path = format!("/v0/users/{}/collections/-/persons", username);
+        let resp = self
+            .request(
+                &path,
+                &[("limit", limit.to_string()), ("offset", offset.to_string())],
+            )
+            .await?;
+        self.read_json(resp).await
+    }
+
+    /// An index (目录) by id.
+    pub async fn get_index(&self, id: u64) -> Result<Index> {
+        self.rate_limit().await;
+        let resp = self.request(&format!("/v0/indices/{}", id), &[]).await?;
+        self.read_json(resp).await
+    }
+
+    /// One page of the subjects in an index.
+    pub async fn get_index_subjects(
+        &self,
+        id: u64,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Paged<IndexSubject>> {
+        self.rate_limit().await;
+        let path = format!("/v0/indices/{}/subjects", id);
+        let resp = self
+            .request(
+                &path,
+                &[("limit", limit.to_string()), ("offset", offset.to_string())],
+            )
+            .await?;
+        self.read_json(resp).await
+    }
+
+    /// My collection entry for one subject, or `None` if it isn't collected.
+    pub async fn get_user_collection(
+        &self,
+        username: &str,
+        subject_id: u64,
+    ) -> Result<Option<Collection>> {
+        self.rate_limit().await;
+        let path = format!("/v0/users/{}/collections/{}", username, subject_id);
+        match self.request(&path, &[]).await {
+            Ok(resp) => Ok(Some(self.read_json(resp).await?)),
+            Err(AppError::Api { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// A subject's detail, or `NotModified` if `etag` still matches.
+    pub async fn get_subject(
+        &self,
+        id: u64,
+        etag: Option<&str>,
+    ) -> Result<Conditional<SubjectDetail>> {
         self.rate_limit().await;
         let path = format!("/v0/subjects/{}", id);
+        let resp = self.request_if_none_match(&path, &[], etag).await?;
+        self.read_conditional(resp).await
+    }
+
+    /// The subjects linked to a subject, such as its sequels, prequels and adaptations.
+    pub async fn get_subject_relations(&self, id: u64) -> Result<Vec<RelatedSubject>> {
+        self.rate_limit().await;
+        let path = format!("/v0/subjects/{}/subject_relations", id);
         let resp = self.request(&path, &[]).await?;
-        Ok(resp.json().await?)
+        self.read_json(resp).await
     }
 
+    /// One page of a subject's episodes, or `NotModified` if `etag` still matches.
     pub async fn get_episodes(
         &self,
         subject_id: u64,
         limit: u64,
         offset: u64,
-    ) -> Result<PagedEpisodes> {
+        etag: Option<&str>,
+    ) -> Result<Conditional<PagedEpisodes>> {
         self.rate_limit().await;
         let resp = self
-            .request(
+            .request_if_none_match(
                 "/v0/episodes",
                 &[
                     ("subject_id", subject_id.to_string()),
                     ("limit", limit.to_string()),
                     ("offset", offset.to_string()),
                 ],
+                etag,
             )
             .await?;
-        Ok(resp.json().await?)
+        self.read_conditional(resp).await
     }
 
+    /// One page of the token owner's episode statuses on a subject. Fails with
+    /// a 404 `AppError::Api` when the subject isn't collected.
+    pub async fn get_episode_collections(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Paged<EpisodeCollection>> {
+        self.rate_limit().await;
+        let path = format!("/v0/users/-/collections/{}/episodes", subject_id);
+        let resp = self
+            .request(
+                &path,
+                &[("limit", limit.to_string()), ("offset", offset.to_string())],
+            )
+            .await?;
+        self.read_json(resp).await
+    }
+
+    /// Search subjects by keyword, best matches first, optionally restricted to subject types.
+    pub async fn search_subjects(
+        &self,
+        keyword: &str,
+        types: &[u8],
+        limit: u64,
+    ) -> Result<PagedSearchResults> {
+        self.rate_limit().await;
+        let mut body = json!({ "keyword": keyword, "sort": "match" });
+        if !types.is_empty() {
+            body["filter"] = json!({ "type": types });
+        }
+        let resp = self
+            .post(
+                "/v0/search/subjects",
+                &[("limit", limit.to_string()), ("offset", "0".to_string())],
+                &body,
+            )
+            .await?;
+        self.read_json(resp).await
+    }
+
+    /// Create or update my collection entry for a subject with the given fields.
+    pub async fn update_collection(&self, subject_id: u64, body: &Value) -> Result<()> {
+        self.rate_limit().await;
+        let path = format!("/v0/users/-/collections/{}", subject_id);
+        self.post(&path, &[], body).await?;
+        Ok(())
+    }
+
+    /// Mark episodes of a collected subject as watched.
+    pub async fn mark_episodes_watched(&self, subject_id: u64, episode_ids: &[u64]) -> Result<()> {
+        self.rate_limit().await;
+        let path = format!("/v0/users/-/collections/{}/episodes", subject_id);
+        self.patch(&path, &json!({ "episode_id": episode_ids, "type": 2 }))
+            .await?;
+        Ok(())
+    }
+
+    /// This week's airing anime, grouped by weekday (legacy endpoint).
+    pub async fn get_calendar(&self) -> Result<Vec<CalendarDay>> {
+        self.rate_limit().await;
+        let resp = self.request("/calendar", &[]).await?;
+        self.read_json(resp).await
+    }
+
+    /// The user's episode progress on a subject, or `None` if there is none.
+    ///
+    /// Uses the legacy `/user/{uid}/progress` endpoint; `get_episode_collections`
+    /// is its v0 replacement.
     pub async fn get_progress(&self, uid: u64, subject_id: u64) -> Result<Option<UserProgress>> {
         self.rate_limit().await;
         let path = format!("/user/{}/progress", uid);
@@ -109,17 +791,221 @@ impl BangumiClient {
         {
             Ok(resp) => resp,
             Err(AppError::Api { status: 404, .. }) => {
-                debug!("Progress not found for subject_id={}", subject_id);
+                debug!(subject_id = subject_id; "Progress not found for subject_id={}", subject_id);
                 return Ok(None);
             }
             Err(e) => return Err(e),
         };
-        let body = resp.text().await?;
+        let body = self.read_body(resp).await?;
+        let body = String::from_utf8_lossy(&body);
         if body == "null" || body.is_empty() {
-            debug!("Progress is null for subject_id={}", subject_id);
+            debug!(subject_id = subject_id; "Progress is null for subject_id={}", subject_id);
             return Ok(None);
         }
         let progress: UserProgress = serde_json::from_str(&body)?;
         Ok(Some(progress))
     }
 }
+
+/// The API calls the fetch pipeline makes, so it can run against something
+/// other than the live server. Implemented by `BangumiClient`.
+pub trait BangumiApi: Sync {
+    /// The user the token belongs to.
+    fn get_me(&self) -> impl Future<Output = Result<User>> + Send;
+
+    /// One page of a user's subject collections.
+    fn get_collections(
+        &self,
+        username: &str,
+        limit: u64,
+        offset: u64,
+    ) -> impl Future<Output = Result<PagedCollection>> + Send;
+
+    /// A subject's detail, or `NotModified` if `etag` still matches.
+    fn get_subject(
+        &self,
+        id: u64,
+        etag: Option<&str>,
+    ) -> impl Future<Output = Result<Conditional<SubjectDetail>>> + Send;
+
+    /// The subjects linked to a subject, such as its sequels and adaptations.
+    fn get_subject_relations(
+        &self,
+        id: u64,
+    ) -> impl Future<Output = Result<Vec<RelatedSubject>>> + Send;
+
+    /// One page of a subject's episodes, or `NotModified` if `etag` still matches.
+    fn get_episodes(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+        etag: Option<&str>,
+    ) -> impl Future<Output = Result<Conditional<PagedEpisodes>>> + Send;
+
+    /// The user's episode progress on a subject, or `None` if there is none.
+    fn get_progress(
+        &self,
+        uid: u64,
+        subject_id: u64,
+    ) -> impl Future<Output = Result<Option<UserProgress>>> + Send;
+
+    /// One page of the token owner's episode statuses on a subject.
+    fn get_episode_collections(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+    ) -> impl Future<Output = Result<Paged<EpisodeCollection>>> + Send;
+}
+
+impl BangumiApi for BangumiClient {
+    fn get_me(&self) -> impl Future<Output = Result<User>> + Send {
+        BangumiClient::get_me(self)
+    }
+
+    fn get_collections(
+        &self,
+        username: &str,
+        limit: u64,
+        offset: u64,
+    ) -> impl Future<Output = Result<PagedCollection>> + Send {
+        BangumiClient::get_collections(self, username, limit, offset)
+    }
+
+    fn get_subject(
+        &self,
+        id: u64,
+        etag: Option<&str>,
+    ) -> impl Future<Output = Result<Conditional<SubjectDetail>>> + Send {
+        BangumiClient::get_subject(self, id, etag)
+    }
+
+    fn get_subject_relations(
+        &self,
+        id: u64,
+    ) -> impl Future<Output = Result<Vec<RelatedSubject>>> + Send {
+        BangumiClient::get_subject_relations(self, id)
+    }
+
+    fn get_episodes(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+        etag: Option<&str>,
+    ) -> impl Future<Output = Result<Conditional<PagedEpisodes>>> + Send {
+        BangumiClient::get_episodes(self, subject_id, limit, offset, etag)
+    }
+
+    fn get_progress(
+        &self,
+        uid: u64,
+        subject_id: u64,
+    ) -> impl Future<Output = Result<Option<UserProgress>>> + Send {
+        BangumiClient::get_progress(self, uid, subject_id)
+    }
+
+    fn get_episode_collections(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+    ) -> impl Future<Output = Result<Paged<EpisodeCollection>>> + Send {
+        BangumiClient::get_episode_collections(self, subject_id, limit, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_defaults() {
+        let builder = BangumiClient::builder();
+        assert_eq!(builder.timeout, None);
+        let client = builder.build().unwrap();
+        assert_eq!(client.token, None);
+        assert_eq!(client.base_url, DEFAULT_BASE_URL);
+        assert_eq!(client.request_interval(), REQUEST_INTERVAL);
+        assert_eq!(client.max_retries, 0);
+        assert_eq!(client.retry_backoff, RETRY_BACKOFF);
+        assert_eq!(client.slow_request, SLOW_REQUEST);
+        assert!(!client.offline);
+        assert!(default_user_agent().starts_with("bangumi-tool/"));
+    }
+
+    #[test]
+    fn builder_overrides() {
+        let builder = BangumiClient::builder()
+            .token(Some("secret".to_string()))
+            .base_url(" https://mirror.example/api/ ")
+            .user_agent("my-script/1.0")
+            .request_interval(Duration::from_millis(250))
+            .timeout(Duration::from_secs(30))
+            .max_retries(3)
+            .retry_backoff(Duration::from_secs(1))
+            .slow_request(Duration::from_secs(4));
+        assert_eq!(builder.user_agent, "my-script/1.0");
+        assert_eq!(builder.timeout, Some(Duration::from_secs(30)));
+
+        let client = builder.build().unwrap();
+        assert_eq!(client.token.as_deref(), Some("secret"));
+        assert_eq!(client.base_url, "https://mirror.example/api");
+        assert_eq!(client.request_interval(), Duration::from_millis(250));
+        assert_eq!(client.max_retries, 3);
+        assert_eq!(client.retry_backoff, Duration::from_secs(1));
+        assert_eq!(client.slow_request, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn new_uses_the_defaults_with_a_token() {
+        let client = BangumiClient::new("secret".to_string(), DEFAULT_BASE_URL).unwrap();
+        assert_eq!(client.token.as_deref(), Some("secret"));
+        assert_eq!(client.request_interval(), REQUEST_INTERVAL);
+        assert_eq!(client.max_retries, 0);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_base_urls() {
+        for url in ["", "api.bgm.tv", "ftp://api.bgm.tv"] {
+            let err = BangumiClient::builder().base_url(url).build().err();
+            assert!(
+                matches!(err, Some(AppError::InvalidBaseUrl(_))),
+                "{:?} accepted",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn builder_rejects_invalid_user_agents() {
+        assert!(
+            BangumiClient::builder()
+                .user_agent("bad\nagent")
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn token_fingerprint_is_stable_and_short() {
+        let client = |token: &str| {
+            BangumiClient::builder()
+                .token(Some(token.to_string()))
+                .build()
+                .unwrap()
+        };
+        let fingerprint = client("a").token_fingerprint().unwrap();
+        assert_eq!(fingerprint.len(), 16);
+        assert_eq!(client("a").token_fingerprint(), Some(fingerprint.clone()));
+        assert_ne!(client("b").token_fingerprint(), Some(fingerprint));
+        assert_eq!(
+            BangumiClient::builder()
+                .build()
+                .unwrap()
+                .token_fingerprint(),
+            None
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use std::cmp::Reverse;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::export::write_atomic;
+use crate::models::{Collection, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// A collection entry with a comment, treated as a short review.
+#[derive(Debug, Serialize)]
+pub struct Review {
+    pub subject_id: u64,
+    pub name: String,
+    pub subject_type: &'static str,
+    pub url: String,
+    /// The rating, omitted when the item is unrated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<u8>,
+    pub updated_at: String,
+    pub comment: String,
+}
+
+/// Collect entries with a non-empty comment, highest rated first (unrated
+/// last), then most recently updated.
+pub fn find_reviews(collections: &[Collection]) -> Vec<Review> {
+    let mut reviewed: Vec<(&Collection, &str)> = collections
+        .iter()
+        .filter_map(|c| {
+            let comment = c.comment.as_deref()?.trim();
+            (!comment.is_empty()).then_some((c, comment))
+        })
+        .collect();
+    reviewed.sort_by_key(|(c, _)| (Reverse(c.rate), Reverse(c.updated_at), c.subject_id));
+
+    reviewed
+        .into_iter()
+        .map(|(c, comment)| Review {
+            subject_id: c.subject_id,
+            name: if c.subject.name_cn.is_empty() {
+                c.subject.name.clone()
+            } else {
+                c.subject.name_cn.clone()
+            },
+            subject_type: subject_type_name(c.subject.subject_type),
+            url: format!("https://bgm.tv/subject/{}", c.subject_id),
+            rate: (c.rate > 0).then_some(c.rate),
+            updated_at: c
+                .updated_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d")
+                .to_string(),
+            comment: comment.to_string(),
+        })
+        .collect()
+}
+
+/// Print reviews as an aligned table, without the comments themselves.
+pub fn print_reviews(reviews: &[Review]) {
+    println!("\n== 带评论的条目: {} ==", reviews.len());
+    let name_width = reviews.iter().map(|r| r.name.width()).max().unwrap_or(0);
+    for r in reviews {
+        println!(
+            "  {}  [{}]  {:>4}  {}",
+            pad_to_width(&r.name, name_width),
+            pad_to_width(r.subject_type, 6),
+            rating_label(r.rate),
+            r.updated_at
+        );
+    }
+    println!();
+}
+
+fn rating_label(rate: Option<u8>) -> String {
+    rate.map_or_else(|| "未评分".to_string(), |rate| format!("{}分", rate))
+}
+
+/// Escape the characters that would end or break a Markdown link text.
+fn escape_link_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '[' | ']' | '\\' | '*' | '_' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Render reviews as Markdown: one section per item with a linked title, the
+/// rating and date, and the comment as a blockquote.
+pub fn render_markdown(reviews: &[Review]) -> String {
+    let mut md = String::from("# 我的评论\n");
+    for r in reviews {
+        let _ = write!(
+            md,
+            "\n## [{}]({})\n\n{} · {} · {}\n\n",
+            escape_link_text(&r.name),
+            r.url,
+            r.subject_type,
+            rating_label(r.rate),
+            r.updated_at
+        );
+        for line in r.comment.lines() {
+            md.push('>');
+            if !line.trim().is_empty() {
+                md.push(' ');
+                md.push_str(line.trim_end());
+            }
+            md.push('\n');
+        }
+    }
+    md
+}
+
+/// Write `reviews.md`, and with `json` also `reviews.json`, to the output directory.
+pub fn write_reviews(reviews: &[Review], dir: &Path, json: bool) -> Result<Vec<PathBuf>> {
+    let md_path = dir.join("reviews.md");
+    let md = render_markdown(reviews);
+    write_atomic(&md_path, |w| {
+        Ok(std::io::Write::write_all(w, md.as_bytes())?)
+    })?;
+    let mut written = vec![md_path];
+    if json {
+        let json_path = dir.join("reviews.json");
+        write_atomic(&json_path, |w| {
+            Ok(serde_json::to_writer_pretty(w, reviews)?)
+        })?;
+        written.push(json_path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(
+        subject_id: u64,
+        rate: u8,
+        updated_at: &str,
+        comment: Option<&str>,
+    ) -> Collection {
+        serde_json::from_value(serde_json::json!({
+            "subject_id": subject_id,
+            "type": 2,
+            "rate": rate,
+            "ep_status": 0,
+            "updated_at": updated_at,
+            "comment": comment,
+            "tags": [],
+            "subject": {
+                "id": subject_id, "name": "Game", "name_cn": "", "type": 4, "eps": 0, "volumes": 0
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn only_commented_items_by_rating_then_date() {
+        let collections = [
+            collection(1, 0, "2024-03-01T00:00:00Z", Some("unrated")),
+            collection(2, 8, "2024-01-01T00:00:00Z", Some("older")),
+            collection(3, 8, "2024-02-01T00:00:00Z", Some("newer")),
+            collection(4, 10, "2023-01-01T00:00:00Z", Some("  ")),
+            collection(5, 10, "2023-01-01T00:00:00Z", None),
+            collection(6, 9, "2023-01-01T00:00:00Z", Some(" best ")),
+        ];
+        let reviews = find_reviews(&collections);
+
+        let ids: Vec<u64> = reviews.iter().map(|r| r.subject_id).collect();
+        assert_eq!(ids, [6, 3, 2, 1]);
+        assert_eq!(reviews[0].comment, "best");
+        assert_eq!(reviews[3].rate, None);
+    }
+
+    #[test]
+    fn markdown_quotes_each_comment_line() {
+        let collections = [collection(
+            7,
+            9,
+            "2024-01-01T00:00:00Z",
+            Some("first line\n\nthird [line]"),
+        )];
+        let md = render_markdown(&find_reviews(&collections));
+
+        assert!(md.contains("## [Game](https://bgm.tv/subject/7)\n"));
+        assert!(md.contains("游戏 · 9分 · "));
+        assert!(md.ends_with("> first line\n>\n> third [line]\n"));
+    }
+}
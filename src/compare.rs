@@ -0,0 +1,168 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::models::{Collection, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// Lowest rating of mine recommended to someone who hasn't seen the subject.
+const RECOMMEND_RATING: u8 = 8;
+
+/// One subject in a comparison; ratings are 0 when unrated or not collected.
+pub struct CompareEntry {
+    pub name: String,
+    pub subject_type: &'static str,
+    pub url: String,
+    pub my_rating: u8,
+    pub their_rating: u8,
+}
+
+impl CompareEntry {
+    fn new(col: &Collection, my_rating: u8, their_rating: u8) -> Self {
+        Self {
+            name: if col.subject.name_cn.is_empty() {
+                col.subject.name.clone()
+            } else {
+                col.subject.name_cn.clone()
+            },
+            subject_type: subject_type_name(col.subject.subject_type),
+            url: format!("https://bgm.tv/subject/{}", col.subject_id),
+            my_rating,
+            their_rating,
+        }
+    }
+
+    /// My rating minus theirs, when both rated it.
+    pub fn delta(&self) -> Option<i16> {
+        (self.my_rating > 0 && self.their_rating > 0)
+            .then(|| self.my_rating as i16 - self.their_rating as i16)
+    }
+}
+
+/// My collection joined with another user's by subject.
+pub struct Comparison {
+    pub username: String,
+    /// Completed by both, largest rating difference first.
+    pub both_done: Vec<CompareEntry>,
+    /// Completed by them and not in my collection, their best rated first.
+    pub only_theirs: Vec<CompareEntry>,
+    /// Rated highly by me and not watched by them (missing or only 想看).
+    pub recommend: Vec<CompareEntry>,
+}
+
+impl Comparison {
+    pub fn new(username: &str, mine: &[Collection], theirs: &[Collection]) -> Self {
+        let my_map: HashMap<u64, &Collection> = mine.iter().map(|c| (c.subject_id, c)).collect();
+        let their_map: HashMap<u64, &Collection> =
+            theirs.iter().map(|c| (c.subject_id, c)).collect();
+
+        let mut both_done: Vec<CompareEntry> = mine
+            .iter()
+            .filter(|c| c.collection_type == 2)
+            .filter_map(|c| {
+                let t = their_map.get(&c.subject_id)?;
+                (t.collection_type == 2).then(|| CompareEntry::new(c, c.rate, t.rate))
+            })
+            .collect();
+        both_done.sort_by_key(|e| Reverse(e.delta().map(i16::abs)));
+
+        let mut only_theirs: Vec<CompareEntry> = theirs
+            .iter()
+            .filter(|c| c.collection_type == 2 && !my_map.contains_key(&c.subject_id))
+            .map(|c| CompareEntry::new(c, 0, c.rate))
+            .collect();
+        only_theirs.sort_by_key(|e| Reverse(e.their_rating));
+
+        let mut recommend: Vec<CompareEntry> = mine
+            .iter()
+            .filter(|c| c.rate >= RECOMMEND_RATING)
+            .filter(|c| {
+                their_map
+                    .get(&c.subject_id)
+                    .is_none_or(|t| t.collection_type == 1)
+            })
+            .map(|c| CompareEntry::new(c, c.rate, 0))
+            .collect();
+        recommend.sort_by_key(|e| Reverse(e.my_rating));
+
+        Self {
+            username: username.to_string(),
+            both_done,
+            only_theirs,
+            recommend,
+        }
+    }
+
+    fn sections(&self) -> [(&'static str, &[CompareEntry]); 3] {
+        [
+            ("都看过", &self.both_done),
+            ("对方看过、我未收藏", &self.only_theirs),
+            ("我评分 ≥8、对方未看", &self.recommend),
+        ]
+    }
+
+    /// Print the three lists as aligned tables.
+    pub fn print(&self) {
+        let rating = |r: u8| {
+            if r == 0 {
+                "-".to_string()
+            } else {
+                r.to_string()
+            }
+        };
+        for (title, entries) in self.sections() {
+            println!("\n== {}: {} ==", title, entries.len());
+            let name_width = entries.iter().map(|e| e.name.width()).max().unwrap_or(0);
+            for e in entries {
+                let delta = e.delta().map(|d| format!("{:+}", d)).unwrap_or_default();
+                println!(
+                    "  {}  [{}]  我 {:>2}  {} {:>2}  {}",
+                    pad_to_width(&e.name, name_width),
+                    pad_to_width(e.subject_type, 6),
+                    rating(e.my_rating),
+                    self.username,
+                    rating(e.their_rating),
+                    delta
+                );
+            }
+        }
+        println!();
+    }
+
+    /// Write all three lists to `compare_<username>.csv` in the output directory.
+    pub fn write_csv(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(format!("compare_{}.csv", self.username));
+        let mut file = std::fs::File::create(&path)?;
+        std::io::Write::write_all(&mut file, b"\xEF\xBB\xBF")?;
+
+        let mut wtr = csv::Writer::from_writer(file);
+        wtr.write_record([
+            "分类",
+            "名称",
+            "条目类型",
+            "地址",
+            "我的评分",
+            "对方评分",
+            "差值",
+        ])?;
+        let rating = |r: u8| if r == 0 { String::new() } else { r.to_string() };
+        for (title, entries) in self.sections() {
+            for e in entries {
+                wtr.write_record([
+                    title,
+                    &e.name,
+                    e.subject_type,
+                    &e.url,
+                    &rating(e.my_rating),
+                    &rating(e.their_rating),
+                    &e.delta().map(|d| d.to_string()).unwrap_or_default(),
+                ])?;
+            }
+        }
+        wtr.flush()?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, CommandFactory, FromArgMatches};
+use toml::{Table, Value};
+
+use crate::cli::{Args, Command};
+use crate::error::{AppError, Result};
+use crate::export::write_atomic;
+use crate::last_run;
+
+/// Per-directory config file, taking precedence over the user-wide one.
+const LOCAL_FILE: &str = "bangumi-tool.toml";
+
+const TEMPLATE: &str = r#"# bangumi-tool defaults. Keys are the long option names, with `-` or `_`;
+# options given on the command line or through their environment variable win.
+
+# format = "csv"
+# output = "~/exports"
+# detail = true
+# type = ["anime", "book"]
+# status = ["done", "doing"]
+# cache_dir = "~/.cache/bangumi-tool"
+# subjects_ttl = "30d"
+# progress_ttl = "24h"
+# skip_errors = true
+# max_retries = 3
+# retry_backoff = "5s"
+# quiet = false
+"#;
+
+/// The user-wide config file, `bangumi-tool/config.toml` in the platform config directory.
+pub fn global_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("bangumi-tool").join("config.toml"))
+}
+
+pub fn local_path() -> PathBuf {
+    PathBuf::from(LOCAL_FILE)
+}
+
+/// The file's table, or `None` if it doesn't exist.
+fn read_table(path: &Path) -> Result<Option<Table>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text)
+            .map(Some)
+            .map_err(|e| AppError::Config {
+                path: path.display().to_string(),
+                source: e,
+            }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A config value with the key it was written as and the file it came from.
+struct Entry {
+    key: String,
+    value: Value,
+    path: PathBuf,
+}
+
+/// Config entries keyed by option name; the local file overrides the user-wide
+/// one key by key.
+fn load_entries() -> Result<BTreeMap<String, Entry>> {
+    let mut entries = BTreeMap::new();
+    for path in global_path().into_iter().chain([local_path()]) {
+        if let Some(table) = read_table(&path)? {
+            for (key, value) in table {
+                let entry = Entry {
+                    key: key.clone(),
+                    value,
+                    path: path.clone(),
+                };
+                entries.insert(key.replace('_', "-"), entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// A scalar config value as a command-line value, with a leading `~/` expanded.
+fn scalar(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => match (s.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest).display().to_string(),
+            _ => s.clone(),
+        },
+        Value::Integer(n) => n.to_string(),
+        Value::Float(n) => n.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(d) => d.to_string(),
+        Value::Array(_) | Value::Table(_) => return None,
+    };
+    Some(text)
+}
+
+/// Command-line arguments for the config entries whose options were left at
+/// their defaults: not given on the command line, not set through their
+/// environment variable, and not conflicting with anything that was given.
+fn config_args(entries: &BTreeMap<String, Entry>, matches: &ArgMatches) -> Vec<OsString> {
+    let cmd = Args::command();
+    let given: Vec<&Arg> = cmd
+        .get_arguments()
+        .filter(|a| matches.value_source(a.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+    let conflicts = |a: &Arg, b: &Arg| {
+        cmd.get_arg_conflicts_with(a)
+            .iter()
+            .any(|c| c.get_id() == b.get_id())
+    };
+
+    let mut argv = Vec::new();
+    for (long, entry) in entries {
+        let warn = |message: &str| {
+            eprintln!(
+                "Warning: {} in {}: {}",
+                entry.key,
+                entry.path.display(),
+                message
+            );
+        };
+        let Some(arg) = cmd
+            .get_arguments()
+            .find(|a| a.get_long() == Some(long.as_str()))
+        else {
+            warn("unknown key, ignored");
+            continue;
+        };
+        if long == "token" || long == "no-config" {
+            warn("can't be set in the config file, ignored");
+            continue;
+        }
+        let source = matches.value_source(arg.get_id().as_str());
+        if matches!(
+            source,
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) || given.iter().any(|g| conflicts(arg, g) || conflicts(g, arg))
+        {
+            continue;
+        }
+        let values = match &entry.value {
+            Value::Array(items) => items.iter().map(scalar).collect(),
+            other => scalar(other).map(|v| vec![v]),
+        };
+        let Some(values) = values else {
+            warn("expected a value or a list of values, ignored");
+            continue;
+        };
+        if matches!(arg.get_action(), ArgAction::SetTrue) {
+            match values.as_slice() {
+                [v] if v == "true" => argv.push(format!("--{}", long).into()),
+                [v] if v == "false" => {}
+                _ => warn("expected true or false, ignored"),
+            }
+            continue;
+        }
+        for v in values {
+            argv.push(format!("--{}={}", long, v).into());
+        }
+    }
+    argv
+}
+
+/// Parse the command line, filling in defaults from the config files unless
+/// `--no-config` is given. Exits on invalid arguments like `Args::parse`.
+pub fn parse_args() -> Args {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let matches = Args::command()
+        .try_get_matches_from(&argv)
+        .unwrap_or_else(|e| last_run::exit_clap(e));
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| last_run::exit_clap(e));
+    if args.no_config || matches!(args.command, Some(Command::Config { .. })) {
+        return args;
+    }
+    let entries = match load_entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(last_run::EXIT_USAGE.into());
+        }
+    };
+    let extra = config_args(&entries, &matches);
+    if extra.is_empty() {
+        return args;
+    }
+    // Defaults go right after the program name, so top-level options are
+    // accepted even when the command line has a subcommand.
+    let mut full = vec![argv[0].clone()];
+    full.extend(extra);
+    full.extend(argv.into_iter().skip(1));
+    match Args::command().try_get_matches_from(full) {
+        Ok(matches) => Args::from_arg_matches(&matches).unwrap_or_else(|e| last_run::exit_clap(e)),
+        Err(e) => {
+            eprintln!(
+                "Note: defaults from the config file were applied; use --no-config to skip them"
+            );
+            last_run::exit_clap(e)
+        }
+    }
+}
+
+/// Write the commented template to `path`, refusing to replace an existing
+/// file unless `force` is set.
+pub fn init(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(AppError::Io(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists (use --force to replace it)",
+                path.display()
+            ),
+        )));
+    }
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, |w| Ok(w.write_all(TEMPLATE.as_bytes())?))
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::models::{Collection, ExportRecord, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// A subject whose collection `ep_status` disagrees with its episode marks.
+pub struct Inconsistency {
+    pub subject_id: u64,
+    pub name: String,
+    pub subject_type: &'static str,
+    pub url: String,
+    /// Watched count stored on the collection.
+    pub ep_status: u64,
+    /// Main episodes actually marked as watched.
+    pub actual: u64,
+    /// Main episodes in the subject's episode list.
+    pub main_eps: u64,
+}
+
+impl Inconsistency {
+    /// `actual - ep_status`.
+    pub fn delta(&self) -> i64 {
+        self.actual as i64 - self.ep_status as i64
+    }
+}
+
+/// Watched and total main episodes from a record's `completeness`, e.g. `10/12`;
+/// `None` for failed records.
+fn watched_of_total(record: &ExportRecord) -> Option<(u64, u64)> {
+    let (watched, total) = record.completeness.split_once('/')?;
+    Some((watched.parse().ok()?, total.parse().ok()?))
+}
+
+/// Compare each collection's `ep_status` with the main episodes marked in its
+/// detail record, largest gap first. Subjects without an episode list (books,
+/// most games) have nothing to compare and are left out.
+pub fn find_inconsistencies(
+    collections: &[Collection],
+    records: &[ExportRecord],
+) -> Vec<Inconsistency> {
+    let by_id: HashMap<u64, &ExportRecord> = records.iter().map(|r| (r.subject_id, r)).collect();
+    let mut found: Vec<Inconsistency> = collections
+        .iter()
+        .filter_map(|c| {
+            let (actual, main_eps) = watched_of_total(by_id.get(&c.subject_id)?)?;
+            if main_eps == 0 || actual == c.ep_status {
+                return None;
+            }
+            Some(Inconsistency {
+                subject_id: c.subject_id,
+                name: if c.subject.name_cn.is_empty() {
+                    c.subject.name.clone()
+                } else {
+                    c.subject.name_cn.clone()
+                },
+                subject_type: subject_type_name(c.subject.subject_type),
+                url: format!("https://bgm.tv/subject/{}", c.subject_id),
+                ep_status: c.ep_status,
+                actual,
+                main_eps,
+            })
+        })
+        .collect();
+    found.sort_by_key(|i| (std::cmp::Reverse(i.delta().unsigned_abs()), i.subject_id));
+    found
+}
+
+/// Print inconsistencies as an aligned table.
+pub fn print_inconsistencies(found: &[Inconsistency]) {
+    println!("\n== 收藏进度与单集标记不一致的条目: {} ==", found.len());
+    if found.is_empty() {
+        println!();
+        return;
+    }
+    let name_width = found.iter().map(|i| i.name.width()).max().unwrap_or(0);
+    println!(
+        "  {}  {}  收藏进度  单集标记  差值  正片",
+        pad_to_width("名称", name_width),
+        pad_to_width("类型", 8)
+    );
+    for i in found {
+        println!(
+            "  {}  [{}]  {:>8}  {:>8}  {:>+4}  {:>4}",
+            pad_to_width(&i.name, name_width),
+            pad_to_width(i.subject_type, 6),
+            i.ep_status,
+            i.actual,
+            i.delta(),
+            i.main_eps
+        );
+    }
+    println!();
+}
+
+/// Write inconsistencies to `inconsistencies.csv` in the output directory.
+pub fn write_csv(found: &[Inconsistency], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("inconsistencies.csv");
+    let mut file = std::fs::File::create(&path)?;
+    std::io::Write::write_all(&mut file, b"\xEF\xBB\xBF")?;
+
+    let mut wtr = csv::Writer::from_writer(file);
+    wtr.write_record([
+        "名称",
+        "条目类型",
+        "地址",
+        "收藏进度",
+        "单集标记",
+        "差值",
+        "正片数",
+    ])?;
+    for i in found {
+        wtr.write_record([
+            i.name.as_str(),
+            i.subject_type,
+            &i.url,
+            &i.ep_status.to_string(),
+            &i.actual.to_string(),
+            &i.delta().to_string(),
+            &i.main_eps.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(path)
+}
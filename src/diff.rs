@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::Result;
+
+/// A record from a compact JSON export. Optional fields are omitted when empty,
+/// so a missing rating means unrated.
+#[derive(Debug, Deserialize)]
+struct ExportedRecord {
+    name: String,
+    #[serde(default)]
+    name_orig: Option<String>,
+    #[serde(rename = "type")]
+    subject_type: String,
+    /// Exports written before the URL was included lack it.
+    #[serde(default)]
+    url: Option<String>,
+    status: String,
+    #[serde(default)]
+    progress: Option<String>,
+    #[serde(default)]
+    rating: Option<u8>,
+}
+
+impl ExportedRecord {
+    /// Match by subject URL with `by_url`, otherwise by original name and type.
+    fn key(&self, by_url: bool) -> String {
+        match &self.url {
+            Some(url) if by_url => url.clone(),
+            _ => format!(
+                "{}|{}",
+                self.name_orig.as_deref().unwrap_or(&self.name),
+                self.subject_type
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Change<T> {
+    pub name: String,
+    pub from: T,
+    pub to: T,
+}
+
+/// Differences between two exports.
+#[derive(Debug, Default, Serialize)]
+pub struct ExportDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub status: Vec<Change<String>>,
+    pub rating: Vec<Change<Option<u8>>>,
+    pub progress: Vec<Change<Option<String>>>,
+}
+
+impl ExportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.status.is_empty()
+            && self.rating.is_empty()
+            && self.progress.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            println!("\nNo changes.");
+            return;
+        }
+        let section = |title: &str, lines: Vec<String>| {
+            if !lines.is_empty() {
+                println!("\n== {} ({}) ==", title, lines.len());
+                for line in lines {
+                    println!("  {}", line);
+                }
+            }
+        };
+        let rating = |r: Option<u8>| r.map_or("未评分".to_string(), |r| r.to_string());
+        section("新增", self.added.clone());
+        section(
+            "状态变化",
+            self.status
+                .iter()
+                .map(|c| format!("{}: {} → {}", c.name, c.from, c.to))
+                .collect(),
+        );
+        section(
+            "评分变化",
+            self.rating
+                .iter()
+                .map(|c| format!("{}: {} → {}", c.name, rating(c.from), rating(c.to)))
+                .collect(),
+        );
+        section(
+            "进度变化",
+            self.progress
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{}: {} → {}",
+                        c.name,
+                        c.from.as_deref().unwrap_or("-"),
+                        c.to.as_deref().unwrap_or("-")
+                    )
+                })
+                .collect(),
+        );
+        section("移除", self.removed.clone());
+        println!();
+    }
+
+    /// Write the diff as `diff.json` in the output directory.
+    pub fn write_json(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("diff.json");
+        let file = std::fs::File::create(&path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(path)
+    }
+}
+
+/// Load a JSON export written by this tool.
+pub fn load_export(path: &Path) -> Result<Vec<Value>> {
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// Compare two exports, in the compact JSON form.
+pub fn diff_exports(old: Vec<Value>, new: Vec<Value>) -> Result<ExportDiff> {
+    let parse = |values: Vec<Value>| -> Result<Vec<ExportedRecord>> {
+        values
+            .into_iter()
+            .map(|v| Ok(serde_json::from_value(v)?))
+            .collect()
+    };
+    let old = parse(old)?;
+    let new = parse(new)?;
+
+    // Both sides must be keyed the same way, so one older export without
+    // URLs makes every record match by name and type.
+    let by_url = old.iter().chain(&new).all(|r| r.url.is_some());
+    let old_by_key: HashMap<String, &ExportedRecord> =
+        old.iter().map(|r| (r.key(by_url), r)).collect();
+    let mut seen = HashSet::new();
+    let mut diff = ExportDiff::default();
+    for r in &new {
+        let key = r.key(by_url);
+        let Some(prev) = old_by_key.get(&key) else {
+            diff.added
+                .push(format!("{} [{}] {}", r.name, r.subject_type, r.status));
+            continue;
+        };
+        seen.insert(key);
+        if prev.status != r.status {
+            diff.status.push(Change {
+                name: r.name.clone(),
+                from: prev.status.clone(),
+                to: r.status.clone(),
+            });
+        }
+        if prev.rating != r.rating {
+            diff.rating.push(Change {
+                name: r.name.clone(),
+                from: prev.rating,
+                to: r.rating,
+            });
+        }
+        if prev.progress != r.progress {
+            diff.progress.push(Change {
+                name: r.name.clone(),
+                from: prev.progress.clone(),
+                to: r.progress.clone(),
+            });
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|r| !seen.contains(&r.key(by_url)))
+        .map(|r| format!("{} [{}] {}", r.name, r.subject_type, r.status))
+        .collect();
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn records_match_by_url() {
+        let old = vec![json!({
+            "name": "旧名", "type": "动画", "url": "https://bgm.tv/subject/1", "status": "在看"
+        })];
+        let new = vec![json!({
+            "name": "新名", "type": "动画", "url": "https://bgm.tv/subject/1", "status": "看过"
+        })];
+        let diff = diff_exports(old, new).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.status.len(), 1);
+    }
+
+    #[test]
+    fn export_without_urls_matches_by_name_and_type() {
+        let old = vec![json!({
+            "name": "星际牛仔", "name_orig": "カウボーイビバップ", "type": "动画", "status": "在看"
+        })];
+        let new = vec![json!({
+            "name": "星际牛仔", "name_orig": "カウボーイビバップ", "type": "动画",
+            "url": "https://bgm.tv/subject/253", "status": "看过", "rating": 9
+        })];
+        let diff = diff_exports(old, new).unwrap();
+        assert!(diff.added.is_empty(), "{:?}", diff.added);
+        assert!(diff.removed.is_empty(), "{:?}", diff.removed);
+        assert_eq!(diff.status[0].from, "在看");
+        assert_eq!(diff.status[0].to, "看过");
+        assert_eq!(diff.rating[0].to, Some(9));
+    }
+}
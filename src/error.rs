@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+/// Everything that can go wrong, from HTTP and decoding to token and cache problems.
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("HTTP request failed: {0}")]
@@ -8,17 +9,128 @@ pub enum AppError {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("Cache encoding error: {0}")]
+    CacheEncode(#[from] rmp_serde::encode::Error),
+
+    #[error("Cache decoding error: {0}")]
+    CacheDecode(#[from] rmp_serde::decode::Error),
+
+    #[error("Invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Invalid config file {path}: {source}")]
+    Config {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    #[error("TOML encoding error: {0}")]
+    TomlEncode(#[from] toml::ser::Error),
+
     #[error("CSV error: {0}")]
     Csv(#[from] csv::Error),
 
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
-    #[error("No access token found. Set BANGUMI_ACCESS_TOKEN or create .bgm_token file.")]
-    NoToken,
+    #[error(
+        "No access token found; checked {}. Run `bangumi-tool auth`, pass --token or --token-file, set BANGUMI_ACCESS_TOKEN, or create .bgm_token",
+        .checked.join(", ")
+    )]
+    NoToken { checked: Vec<String> },
+
+    #[error("OAuth login failed: {0}")]
+    OAuth(String),
+
+    #[error("Profile {0} not found; create it with `bangumi-tool profile add {0}`")]
+    ProfileNotFound(String),
+
+    #[error("Invalid profile name {0}")]
+    InvalidProfile(String),
+
+    #[error("Invalid API base URL: {0}")]
+    InvalidBaseUrl(String),
+
+    #[error(
+        "Unknown time zone '{0}'; use an IANA name such as Asia/Shanghai or America/New_York, local or utc"
+    )]
+    UnknownTimeZone(String),
+
+    #[error(
+        "Invalid date format '{0}'; use strftime specifiers such as %Y-%m-%d %H:%M:%S or %Y-%m-%dT%H:%M:%S%:z"
+    )]
+    InvalidDateFormat(String),
+
+    #[error(
+        "Access token is invalid or expired (HTTP {status}). Update BANGUMI_ACCESS_TOKEN or .bgm_token with a new token from https://next.bgm.tv/demo/access-token, or rerun `bangumi-tool auth`"
+    )]
+    Unauthorized { status: u16 },
+
+    #[error(
+        "Access token expires at {expires_at}, before the estimated {estimated} run would finish; renew it or drop --strict-token"
+    )]
+    TokenExpiring {
+        expires_at: String,
+        estimated: String,
+    },
 
     #[error("API error ({status}): {message}")]
     Api { status: u16, message: String },
+
+    #[error("Interrupted at {done}/{total} — rerun to resume")]
+    Interrupted { done: usize, total: usize },
+
+    #[error("{failed} subjects failed to fetch; see failed.json")]
+    PartialFailure { failed: usize, completed: usize },
+
+    #[error("Refusing to request {0} in --offline mode; it isn't in the cache")]
+    Offline(String),
+
+    #[error(
+        "{missing} cache entries needed for --offline are missing (listed above); run once online to fill the cache"
+    )]
+    OfflineMissing { missing: usize },
+
+    #[error("Snapshot not found: {0} (run `bangumi-tool history` to list snapshots)")]
+    SnapshotNotFound(String),
+
+    #[error("Subject {id} ({name}): {source}")]
+    Subject {
+        id: u64,
+        name: String,
+        source: Box<AppError>,
+    },
+
+    #[error("Collections page at offset {offset}: {source}")]
+    Page { offset: u64, source: Box<AppError> },
+}
+
+impl AppError {
+    /// Wrap an error with the subject it occurred on.
+    pub fn subject(id: u64, name: &str, source: AppError) -> Self {
+        Self::Subject {
+            id,
+            name: name.to_string(),
+            source: Box::new(source),
+        }
+    }
+
+    /// Wrap an error with the collections page offset it occurred on.
+    pub fn page(offset: u64, source: AppError) -> Self {
+        Self::Page {
+            offset,
+            source: Box::new(source),
+        }
+    }
+
+    /// The underlying error with any context layers removed.
+    pub fn root(&self) -> &AppError {
+        match self {
+            Self::Subject { source, .. } | Self::Page { source, .. } => source.root(),
+            other => other,
+        }
+    }
 }
 
+/// Result with `AppError` as the error type.
 pub type Result<T> = std::result::Result<T, AppError>;
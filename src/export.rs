@@ -1,31 +1,76 @@
-use std::path::Path;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 use serde_json::{Map, Value, json};
 
 use crate::error::Result;
-use crate::models::{ExportRecord, SimpleRecord};
+use crate::locale::Lang;
+use crate::models::{
+    ExportRecord, FailedSubject, RelatedSubject, SimpleRecord, collection_type_from_name,
+};
+use crate::records::NO_PROGRESS;
 
 /// Common fields shared by all export record types.
-trait Exportable {
+pub trait Exportable {
+    /// Bangumi subject id; 0 when unknown.
+    fn subject_id(&self) -> u64;
+    /// Original title.
     fn name(&self) -> &str;
+    /// Chinese title, or empty.
     fn name_cn(&self) -> &str;
+    /// Display name of the subject type.
     fn subject_type(&self) -> &str;
+    /// Subject page on bgm.tv.
+    fn url(&self) -> &str;
+    /// Display name of the collection status.
     fn status(&self) -> &str;
+    /// When the collection was last updated, as `TimestampFormat` renders it.
     fn updated_at(&self) -> &str;
+    /// My rating, or empty if unrated.
     fn rating(&self) -> &str;
+    /// My tags, comma-separated.
     fn tags(&self) -> &str;
+    /// My comment, or empty.
     fn comment(&self) -> &str;
+    /// Whether the subject is R18.
+    fn nsfw(&self) -> bool;
+    /// Whether the collection is private.
+    fn private(&self) -> bool;
 
     /// CSV column headers.
     fn csv_headers() -> &'static [&'static str];
     /// CSV row values, in the same order as `csv_headers`.
-    fn csv_row(&self) -> Vec<&str>;
+    fn csv_row(&self) -> Vec<Cow<'_, str>>;
+    /// Optional columns after the fixed ones, as `(header, value)`. Every record
+    /// of an export must have the same headers. Default: none.
+    fn csv_extra(&self) -> Vec<(&str, Cow<'_, str>)> {
+        Vec::new()
+    }
 
     /// Extra JSON fields beyond the common ones. Default: none.
     fn extra_json_fields(&self, _m: &mut Map<String, Value>) {}
 }
 
+/// The `关联条目` CSV cell, e.g. `续集: 12345 进击的巨人 Season 2; 前传: ...`.
+fn relations_cell(relations: &[RelatedSubject]) -> String {
+    relations
+        .iter()
+        .map(|r| format!("{}: {} {}", r.relation, r.id, r.display_name()))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The `私密` CSV cell: `是` for private collections, empty otherwise.
+fn private_cell(private: bool) -> &'static str {
+    if private { "是" } else { "" }
+}
+
 impl Exportable for SimpleRecord {
+    fn subject_id(&self) -> u64 {
+        self.subject_id
+    }
     fn name(&self) -> &str {
         &self.name
     }
@@ -35,6 +80,9 @@ impl Exportable for SimpleRecord {
     fn subject_type(&self) -> &str {
         &self.subject_type
     }
+    fn url(&self) -> &str {
+        &self.url
+    }
     fn status(&self) -> &str {
         &self.status
     }
@@ -50,6 +98,12 @@ impl Exportable for SimpleRecord {
     fn comment(&self) -> &str {
         &self.comment
     }
+    fn nsfw(&self) -> bool {
+        self.nsfw
+    }
+    fn private(&self) -> bool {
+        self.private
+    }
 
     fn csv_headers() -> &'static [&'static str] {
         &[
@@ -62,25 +116,30 @@ impl Exportable for SimpleRecord {
             "我的评分",
             "我的标签",
             "我的评论",
+            "私密",
         ]
     }
 
-    fn csv_row(&self) -> Vec<&str> {
+    fn csv_row(&self) -> Vec<Cow<'_, str>> {
         vec![
-            &self.name,
-            &self.name_cn,
-            &self.subject_type,
-            &self.url,
-            &self.status,
-            &self.updated_at,
-            &self.rating,
-            &self.tags,
-            &self.comment,
+            Cow::from(&self.name),
+            Cow::from(&self.name_cn),
+            Cow::from(&self.subject_type),
+            Cow::from(&self.url),
+            Cow::from(&self.status),
+            Cow::from(&self.updated_at),
+            Cow::from(&self.rating),
+            Cow::from(&self.tags),
+            Cow::from(&self.comment),
+            Cow::from(private_cell(self.private)),
         ]
     }
 }
 
 impl Exportable for ExportRecord {
+    fn subject_id(&self) -> u64 {
+        self.subject_id
+    }
     fn name(&self) -> &str {
         &self.name
     }
@@ -90,6 +149,9 @@ impl Exportable for ExportRecord {
     fn subject_type(&self) -> &str {
         &self.subject_type
     }
+    fn url(&self) -> &str {
+        &self.url
+    }
     fn status(&self) -> &str {
         &self.status
     }
@@ -105,6 +167,12 @@ impl Exportable for ExportRecord {
     fn comment(&self) -> &str {
         &self.comment
     }
+    fn nsfw(&self) -> bool {
+        self.nsfw
+    }
+    fn private(&self) -> bool {
+        self.private
+    }
 
     fn csv_headers() -> &'static [&'static str] {
         &[
@@ -117,56 +185,131 @@ impl Exportable for ExportRecord {
             "完成度",
             "完成度(百分比)",
             "完成单集",
+            "弃于",
             "我的评分",
+            "评分差",
             "我的标签",
+            "条目标签",
             "我的评论",
+            "私密",
         ]
     }
 
-    fn csv_row(&self) -> Vec<&str> {
+    fn csv_row(&self) -> Vec<Cow<'_, str>> {
         vec![
-            &self.name,
-            &self.name_cn,
-            &self.subject_type,
-            &self.url,
-            &self.status,
-            &self.updated_at,
-            &self.completeness,
-            &self.completeness_pct,
-            &self.watched_eps,
-            &self.rating,
-            &self.tags,
-            &self.comment,
+            Cow::from(&self.name),
+            Cow::from(&self.name_cn),
+            Cow::from(&self.subject_type),
+            Cow::from(&self.url),
+            Cow::from(&self.status),
+            Cow::from(&self.updated_at),
+            Cow::from(&self.completeness),
+            Cow::from(&self.completeness_pct),
+            Cow::from(&self.watched_eps),
+            Cow::from(self.stopped_at.map(|ep| ep.to_string()).unwrap_or_default()),
+            Cow::from(&self.rating),
+            Cow::from(self.rating_diff.map(|d| d.to_string()).unwrap_or_default()),
+            Cow::from(&self.tags),
+            Cow::from(&self.site_tags),
+            Cow::from(&self.comment),
+            Cow::from(private_cell(self.private)),
         ]
     }
 
     fn extra_json_fields(&self, m: &mut Map<String, Value>) {
-        if !self.completeness.is_empty() {
+        if !self.completeness.is_empty() && self.completeness != NO_PROGRESS {
             m.insert("progress".into(), json!(self.completeness));
         }
+        if let Some(tracks) = self.tracks {
+            m.insert("discs".into(), json!(tracks.discs));
+            m.insert("tracks".into(), json!(tracks.tracks));
+        }
         if !self.completeness_pct.is_empty() && self.completeness_pct != "N/A" {
             m.insert("progress_pct".into(), json!(self.completeness_pct));
         }
         if !self.watched_eps.is_empty() {
             m.insert("watched".into(), json!(self.watched_eps));
         }
+        if !self.aired_completeness.is_empty() {
+            m.insert("aired_progress".into(), json!(self.aired_completeness));
+            m.insert("caught_up".into(), json!(self.caught_up));
+        }
+        if let Some(titles) = self.watched_titles.as_ref().filter(|t| !t.is_empty()) {
+            m.insert("watched_titles".into(), json!(titles));
+        }
+        if let Some(stopped_at) = self.stopped_at {
+            m.insert("stopped_at".into(), json!(stopped_at));
+        }
+        if let Some(diff) = self.rating_diff {
+            m.insert("rating_diff".into(), json!(diff));
+        }
+        if !self.site_tags.is_empty() {
+            let tags: Vec<&str> = self.site_tags.split(", ").collect();
+            m.insert("site_tags".into(), json!(tags));
+        }
+        if let Some(summary) = self.summary.as_deref().filter(|s| !s.is_empty()) {
+            m.insert("summary".into(), json!(summary));
+        }
+        let infobox: Map<String, Value> = self
+            .infobox
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+        if !infobox.is_empty() {
+            m.insert("infobox".into(), Value::Object(infobox));
+        }
+        if let Some(relations) = self.relations.as_ref().filter(|r| !r.is_empty()) {
+            let relations: Vec<Value> = relations
+                .iter()
+                .map(|r| json!({"relation": r.relation, "subject_id": r.id, "name": r.display_name()}))
+                .collect();
+            m.insert("relations".into(), json!(relations));
+        }
+    }
+
+    fn csv_extra(&self) -> Vec<(&str, Cow<'_, str>)> {
+        let mut extra: Vec<(&str, Cow<'_, str>)> = Vec::new();
+        if let Some(titles) = &self.watched_titles {
+            extra.push(("完成单集标题", Cow::from(titles.join(" / "))));
+        }
+        extra.extend(
+            self.infobox
+                .iter()
+                .map(|(key, value)| (key.as_str(), Cow::from(value))),
+        );
+        if let Some(relations) = &self.relations {
+            extra.push(("关联条目", Cow::from(relations_cell(relations))));
+        }
+        if let Some(summary) = &self.summary {
+            extra.push(("简介", Cow::from(summary)));
+        }
+        extra
     }
 }
 
-/// Build a compact JSON value, omitting empty optional fields.
-fn to_compact(r: &impl Exportable) -> Value {
+/// CSV columns holding labels rather than free text, translated for `--lang`.
+const LABEL_COLUMNS: [&str; 3] = ["条目类型", "状态", "私密"];
+
+/// Build a compact JSON value, omitting empty optional fields. Type and status
+/// are given in `lang`.
+fn to_compact(r: &impl Exportable, lang: Lang) -> Value {
     let name = if r.name_cn().is_empty() {
         r.name()
     } else {
         r.name_cn()
     };
     let mut m = Map::new();
+    if r.subject_id() != 0 {
+        m.insert("subject_id".into(), json!(r.subject_id()));
+    }
     m.insert("name".into(), json!(name));
     if !r.name_cn().is_empty() && r.name() != r.name_cn() {
         m.insert("name_orig".into(), json!(r.name()));
     }
-    m.insert("type".into(), json!(r.subject_type()));
-    m.insert("status".into(), json!(r.status()));
+    m.insert("type".into(), json!(lang.label(r.subject_type())));
+    m.insert("url".into(), json!(r.url()));
+    m.insert("status".into(), json!(lang.label(r.status())));
     m.insert("updated".into(), json!(r.updated_at()));
     r.extra_json_fields(&mut m);
     if !r.rating().is_empty() {
@@ -181,45 +324,422 @@ fn to_compact(r: &impl Exportable) -> Value {
     if !r.comment().is_empty() {
         m.insert("comment".into(), json!(r.comment()));
     }
+    if r.nsfw() {
+        m.insert("nsfw".into(), json!(true));
+    }
+    if r.private() {
+        m.insert("private".into(), json!(true));
+    }
     Value::Object(m)
 }
 
-fn write_json_impl(records: &[impl Exportable], dir: &Path) -> Result<()> {
-    let path = dir.join("bangumi_export.json");
-    let compact: Vec<Value> = records.iter().map(to_compact).collect();
-    let file = std::fs::File::create(&path)?;
-    serde_json::to_writer(file, &compact)?;
-    println!("JSON exported to {}", path.display());
+/// Write a file through a temporary sibling and rename it into place, so readers
+/// never see a half-written export.
+pub fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(&tmp, path)?;
     Ok(())
 }
 
-fn write_csv_impl<T: Exportable>(records: &[T], dir: &Path) -> Result<()> {
-    let path = dir.join("bangumi_export.csv");
-    let mut file = std::fs::File::create(&path)?;
-    std::io::Write::write_all(&mut file, b"\xEF\xBB\xBF")?;
+/// An output format for export records.
+///
+/// Exporters only render to a writer; where the output goes (a file, stdout, a
+/// buffer) is up to the caller.
+pub trait Exporter<R: Exportable> {
+    /// Short name of the format, e.g. `json`.
+    fn name(&self) -> &'static str;
 
-    let mut wtr = csv::Writer::from_writer(file);
-    wtr.write_record(T::csv_headers())?;
-    for r in records {
-        wtr.write_record(r.csv_row())?;
+    /// File extension, without the dot.
+    fn extension(&self) -> &'static str;
+
+    /// Render the records to `out`.
+    fn write(&self, records: &[R], out: &mut dyn Write) -> Result<()>;
+}
+
+/// The compact JSON export, with empty optional fields left out.
+pub struct JsonExporter {
+    /// Language of the type and status values; keys are English either way.
+    pub lang: Lang,
+}
+
+impl<R: Exportable> Exporter<R> for JsonExporter {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn write(&self, records: &[R], out: &mut dyn Write) -> Result<()> {
+        let compact: Vec<Value> = records.iter().map(|r| to_compact(r, self.lang)).collect();
+        serde_json::to_writer(out, &compact)?;
+        Ok(())
     }
-    wtr.flush()?;
-    println!("CSV exported to {}", path.display());
-    Ok(())
 }
 
-pub fn write_json(records: &[ExportRecord], dir: &Path) -> Result<()> {
-    write_json_impl(records, dir)
+/// CSV with one column per record field.
+pub struct CsvExporter {
+    /// Start with a UTF-8 BOM, so Excel detects the encoding.
+    pub bom: bool,
+    /// Language of the headers and of the type, status and private columns.
+    pub lang: Lang,
 }
 
-pub fn write_csv(records: &[ExportRecord], dir: &Path) -> Result<()> {
-    write_csv_impl(records, dir)
+impl<R: Exportable> Exporter<R> for CsvExporter {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn write(&self, records: &[R], out: &mut dyn Write) -> Result<()> {
+        if self.bom {
+            out.write_all(b"\xEF\xBB\xBF")?;
+        }
+        let mut wtr = csv::Writer::from_writer(out);
+        wtr.write_record(self.header(records.first()))?;
+        for r in records {
+            wtr.write_record(self.row(r).iter().map(|cell| cell.as_bytes()))?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+impl CsvExporter {
+    /// The header row, with the optional columns of `first` (the first record).
+    pub fn header<'r, R: Exportable>(&self, first: Option<&'r R>) -> Vec<&'r str> {
+        let extra_headers: Vec<&str> = first
+            .map(|r| r.csv_extra().into_iter().map(|(h, _)| h).collect())
+            .unwrap_or_default();
+        R::csv_headers()
+            .iter()
+            .copied()
+            .chain(extra_headers)
+            .map(|h| self.lang.label(h))
+            .collect()
+    }
+
+    /// One record's row, in the order of `header`.
+    pub fn row<'r, R: Exportable>(&self, r: &'r R) -> Vec<Cow<'r, str>> {
+        let row = r
+            .csv_row()
+            .into_iter()
+            .zip(R::csv_headers())
+            .map(|(cell, header)| {
+                if !LABEL_COLUMNS.contains(header) {
+                    return cell;
+                }
+                match cell {
+                    Cow::Borrowed(cell) => Cow::from(self.lang.label(cell)),
+                    Cow::Owned(cell) => Cow::from(self.lang.label(&cell).to_string()),
+                }
+            });
+        let extra = r.csv_extra().into_iter().map(|(_, cell)| cell);
+        row.chain(extra).collect()
+    }
 }
 
-pub fn write_simple_json(records: &[SimpleRecord], dir: &Path) -> Result<()> {
-    write_json_impl(records, dir)
+/// The compact JSON export with one record per line, so records can be
+/// appended as they complete.
+pub struct NdjsonExporter {
+    /// Language of the type and status values, as in `JsonExporter`.
+    pub lang: Lang,
 }
 
-pub fn write_simple_csv(records: &[SimpleRecord], dir: &Path) -> Result<()> {
-    write_csv_impl(records, dir)
+impl NdjsonExporter {
+    /// Render one record as a line.
+    pub fn write_line<R: Exportable>(&self, r: &R, out: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer(&mut *out, &to_compact(r, self.lang))?;
+        out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl<R: Exportable> Exporter<R> for NdjsonExporter {
+    fn name(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn write(&self, records: &[R], out: &mut dyn Write) -> Result<()> {
+        for r in records {
+            self.write_line(r, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// A plain-text list of display names, one per line, without a BOM.
+pub struct TxtExporter {
+    /// Language of the type annotation.
+    pub lang: Lang,
+    /// Only list wish (想看) items.
+    pub wish_only: bool,
+    /// Append the subject type in brackets, e.g. `[动画]`.
+    pub with_types: bool,
+    /// Append the subject URL.
+    pub with_urls: bool,
+}
+
+impl<R: Exportable> Exporter<R> for TxtExporter {
+    fn name(&self) -> &'static str {
+        "txt"
+    }
+
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn write(&self, records: &[R], out: &mut dyn Write) -> Result<()> {
+        for r in records {
+            if self.wish_only && collection_type_from_name(r.status()) != Some(1) {
+                continue;
+            }
+            let mut line = if r.name_cn().is_empty() {
+                r.name()
+            } else {
+                r.name_cn()
+            }
+            .to_string();
+            if self.with_types {
+                line.push_str(&format!(" [{}]", self.lang.label(r.subject_type())));
+            }
+            if self.with_urls {
+                line.push(' ');
+                line.push_str(r.url());
+            }
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The records as they appear in the JSON export.
+pub fn compact_json(records: &[ExportRecord], lang: Lang) -> Vec<Value> {
+    records.iter().map(|r| to_compact(r, lang)).collect()
+}
+
+/// The simple records as they appear in the JSON export.
+pub fn compact_simple_json(records: &[SimpleRecord], lang: Lang) -> Vec<Value> {
+    records.iter().map(|r| to_compact(r, lang)).collect()
+}
+
+/// Render the detailed records as compact JSON.
+pub fn write_json(records: &[ExportRecord], out: &mut dyn Write, lang: Lang) -> Result<()> {
+    JsonExporter { lang }.write(records, out)
+}
+
+/// Render the detailed records as CSV, with a leading BOM if `bom` is set.
+pub fn write_csv(
+    records: &[ExportRecord],
+    out: &mut dyn Write,
+    bom: bool,
+    lang: Lang,
+) -> Result<()> {
+    CsvExporter { bom, lang }.write(records, out)
+}
+
+/// Render the simple records as compact JSON.
+pub fn write_simple_json(records: &[SimpleRecord], out: &mut dyn Write, lang: Lang) -> Result<()> {
+    JsonExporter { lang }.write(records, out)
+}
+
+/// Render the simple records as CSV, with a leading BOM if `bom` is set.
+pub fn write_simple_csv(
+    records: &[SimpleRecord],
+    out: &mut dyn Write,
+    bom: bool,
+    lang: Lang,
+) -> Result<()> {
+    CsvExporter { bom, lang }.write(records, out)
+}
+
+/// Write the subjects that failed during a `--skip-errors` run.
+pub fn write_failed(failed: &[FailedSubject], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("failed.json");
+    write_atomic(&path, |w| Ok(serde_json::to_writer_pretty(w, failed)?))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple_record() -> SimpleRecord {
+        SimpleRecord {
+            subject_id: 253,
+            name: "カウボーイビバップ".to_string(),
+            name_cn: "星际牛仔".to_string(),
+            subject_type: "动画".to_string(),
+            url: "https://bgm.tv/subject/253".to_string(),
+            status: "看过".to_string(),
+            collection_type: 2,
+            updated_at: "2024-01-02 03:04:05".to_string(),
+            rating: "9".to_string(),
+            tags: "科幻, 经典".to_string(),
+            comment: "神作".to_string(),
+            nsfw: false,
+            private: true,
+        }
+    }
+
+    fn detail_record() -> ExportRecord {
+        ExportRecord {
+            subject_id: 253,
+            name: "カウボーイビバップ".to_string(),
+            name_cn: "星际牛仔".to_string(),
+            subject_type: "动画".to_string(),
+            url: "https://bgm.tv/subject/253".to_string(),
+            status: "在看".to_string(),
+            collection_type: 3,
+            updated_at: "2024-01-02 03:04:05".to_string(),
+            completeness: "2/3".to_string(),
+            completeness_pct: "67%".to_string(),
+            watched_eps: "1-2".to_string(),
+            stopped_at: None,
+            aired_completeness: String::new(),
+            caught_up: false,
+            rating: String::new(),
+            rating_diff: None,
+            tags: String::new(),
+            site_tags: "科幻".to_string(),
+            comment: String::new(),
+            summary: None,
+            infobox: Vec::new(),
+            relations: None,
+            watched_titles: Some(vec!["第1话 「A」".to_string(), "第2话 「B」".to_string()]),
+            nsfw: false,
+            private: false,
+            tracks: None,
+        }
+    }
+
+    fn render<R: Exportable>(exporter: &dyn Exporter<R>, records: &[R]) -> String {
+        let mut out = Vec::new();
+        exporter.write(records, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_starts_with_a_bom_and_chinese_headers() {
+        let csv = render(
+            &CsvExporter {
+                bom: true,
+                lang: Lang::Zh,
+            },
+            &[simple_record()],
+        );
+        assert_eq!(
+            csv,
+            "\u{feff}名称,名称(中文),条目类型,地址,状态,最后标注,我的评分,我的标签,我的评论,私密\n\
+             カウボーイビバップ,星际牛仔,动画,https://bgm.tv/subject/253,看过,2024-01-02 03:04:05,\
+             9,\"科幻, 经典\",神作,是\n"
+        );
+    }
+
+    #[test]
+    fn csv_without_bom_in_english() {
+        let csv = render(
+            &CsvExporter {
+                bom: false,
+                lang: Lang::En,
+            },
+            &[simple_record()],
+        );
+        assert_eq!(
+            csv,
+            "Name,Chinese name,Type,URL,Status,Updated,Rating,Tags,Comment,Private\n\
+             カウボーイビバップ,星际牛仔,Anime,https://bgm.tv/subject/253,Watched,2024-01-02 03:04:05,\
+             9,\"科幻, 经典\",神作,Yes\n"
+        );
+    }
+
+    #[test]
+    fn csv_of_no_records_is_just_the_header() {
+        let csv = render(
+            &CsvExporter {
+                bom: false,
+                lang: Lang::Zh,
+            },
+            &[] as &[SimpleRecord],
+        );
+        assert_eq!(
+            csv,
+            "名称,名称(中文),条目类型,地址,状态,最后标注,我的评分,我的标签,我的评论,私密\n"
+        );
+    }
+
+    #[test]
+    fn detail_csv_appends_optional_columns() {
+        let csv = render(
+            &CsvExporter {
+                bom: false,
+                lang: Lang::Zh,
+            },
+            &[detail_record()],
+        );
+        assert_eq!(
+            csv,
+            "名称,名称(中文),条目类型,地址,状态,最后标注,完成度,完成度(百分比),完成单集,弃于,\
+             我的评分,评分差,我的标签,条目标签,我的评论,私密,完成单集标题\n\
+             カウボーイビバップ,星际牛仔,动画,https://bgm.tv/subject/253,在看,2024-01-02 03:04:05,\
+             2/3,67%,1-2,,,,,科幻,,,第1话 「A」 / 第2话 「B」\n"
+        );
+    }
+
+    #[test]
+    fn json_leaves_out_empty_fields() {
+        let json = render(&JsonExporter { lang: Lang::Zh }, &[simple_record()]);
+        assert_eq!(
+            json,
+            r#"[{"comment":"神作","name":"星际牛仔","name_orig":"カウボーイビバップ","private":true,"rating":9,"status":"看过","subject_id":253,"tags":"科幻, 经典","type":"动画","updated":"2024-01-02 03:04:05","url":"https://bgm.tv/subject/253"}]"#
+        );
+    }
+
+    #[test]
+    fn json_translates_values_but_not_keys() {
+        let json = render(&JsonExporter { lang: Lang::En }, &[simple_record()]);
+        assert_eq!(
+            json,
+            r#"[{"comment":"神作","name":"星际牛仔","name_orig":"カウボーイビバップ","private":true,"rating":9,"status":"Watched","subject_id":253,"tags":"科幻, 经典","type":"Anime","updated":"2024-01-02 03:04:05","url":"https://bgm.tv/subject/253"}]"#
+        );
+    }
+
+    #[test]
+    fn detail_json_holds_progress() {
+        let json = render(&JsonExporter { lang: Lang::Zh }, &[detail_record()]);
+        assert_eq!(
+            json,
+            r#"[{"name":"星际牛仔","name_orig":"カウボーイビバップ","progress":"2/3","progress_pct":"67%","site_tags":["科幻"],"status":"在看","subject_id":253,"type":"动画","updated":"2024-01-02 03:04:05","url":"https://bgm.tv/subject/253","watched":"1-2","watched_titles":["第1话 「A」","第2话 「B」"]}]"#
+        );
+    }
+
+    #[test]
+    fn ndjson_writes_one_record_per_line() {
+        let records = [simple_record(), simple_record()];
+        let ndjson = render(&NdjsonExporter { lang: Lang::Zh }, &records);
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(ndjson.ends_with("}\n"));
+        assert_eq!(
+            format!("[{}]", lines[0]),
+            render(&JsonExporter { lang: Lang::Zh }, &records[..1])
+        );
+    }
 }
@@ -0,0 +1,716 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{debug, warn};
+
+use crate::cache::CacheStore;
+use crate::client::{BangumiApi, Conditional};
+use crate::error::{AppError, Result};
+use crate::models::{
+    self, Collection, ExportRecord, FailedSubject, PagedCollection, RelatedSubject,
+    SUBJECT_TYPE_MUSIC, SubjectDetail, UserProgress,
+};
+use crate::records::{
+    NO_PROGRESS, RecordOptions, align_subject_fields, build_detail_record, build_failed_record,
+    fill_subject_fields, last_watched_episode, record_subject_id, stopped_at,
+};
+
+/// Kinds of the per-subject cache entries, each keyed `{uid}/{kind}/{subject_id}`.
+pub const SUBJECT_KINDS: [&str; 4] = ["subjects", "episodes", "progress", "relations"];
+
+/// Largest page size the collections endpoint accepts, and the default.
+pub const COLLECTIONS_PAGE_LIMIT: u64 = 50;
+
+/// Largest page size the episodes endpoint accepts, used for every episode list.
+pub const EPISODES_PAGE_LIMIT: u64 = 200;
+
+/// Cache key of the collection page at `offset`, fetched `page_size` at a time.
+/// The page size is part of the key so pages of different sizes never mix.
+pub fn collection_page_key(uid: u64, page_size: u64, offset: u64) -> String {
+    format!("{}/collections/{}_{}", uid, page_size, offset)
+}
+
+/// The page size and offset of a collection page key.
+pub fn parse_collection_page_key(key: &str) -> Option<(u64, u64)> {
+    let (page_size, offset) = key.rsplit('/').next()?.split_once('_')?;
+    Some((page_size.parse().ok()?, offset.parse().ok()?))
+}
+
+/// Progress of a fetch, reported to an optional callback so callers can drive
+/// a progress display of their choice.
+#[derive(Debug, Clone, Copy)]
+pub enum FetchEvent<'a> {
+    /// Work begins on `total` items, `done` of which are already complete.
+    /// `requests` estimates the uncached requests the rest will make (0 when
+    /// not estimated).
+    Started {
+        total: u64,
+        done: u64,
+        requests: u64,
+    },
+    /// Work on the named item begins.
+    Item(&'a str),
+    /// `done` items are complete, with about `requests_left` uncached requests to go.
+    Progressed { done: u64, requests_left: u64 },
+    /// A request for the current item is about to be sent.
+    Step(FetchStep),
+    /// A detail record is complete: each resumed record, in collection order,
+    /// before `Started`, then each new one as it is built. Placeholders for
+    /// subjects skipped with `skip_errors` are not reported.
+    Record(&'a ExportRecord),
+}
+
+/// The request a per-item fetch is waiting on, so a slow item shows whether
+/// it is stuck on one call or paging through a long list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchStep {
+    Subject,
+    /// Page `page` (from 1) of the episode list; `pages` is known once the first is in.
+    Episodes {
+        page: u64,
+        pages: Option<u64>,
+    },
+    /// Page `page` (from 1) of the watched episodes, likewise.
+    Progress {
+        page: u64,
+        pages: Option<u64>,
+    },
+    Relations,
+}
+
+impl fmt::Display for FetchStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, page, pages) = match *self {
+            Self::Subject => return f.write_str("subject"),
+            Self::Relations => return f.write_str("relations"),
+            Self::Episodes { page, pages } => ("episodes", page, pages),
+            Self::Progress { page, pages } => ("progress", page, pages),
+        };
+        match pages {
+            Some(pages) if pages > 1 => write!(f, "{} page {}/{}", name, page, pages),
+            _ => f.write_str(name),
+        }
+    }
+}
+
+/// Callback receiving `FetchEvent`s.
+pub type ProgressFn<'a> = &'a (dyn Fn(FetchEvent) + Send + Sync);
+
+fn report(progress: Option<ProgressFn>, event: FetchEvent) {
+    if let Some(progress) = progress {
+        progress(event);
+    }
+}
+
+/// Fetch one page of collections with cache, tagging errors with the page offset.
+async fn fetch_collection_page(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    username: &str,
+    limit: u64,
+    offset: u64,
+) -> Result<PagedCollection> {
+    let cache_key = collection_page_key(uid, limit, offset);
+    if let Some(page) = cache.get(&cache_key) {
+        return Ok(page);
+    }
+    let page = client
+        .get_collections(username, limit, offset)
+        .await
+        .map_err(|e| AppError::page(offset, e))?;
+    cache.set(&cache_key, &page)?;
+    Ok(page)
+}
+
+/// Drop the cached collection pages so the next full fetch goes to the server.
+pub fn clear_collection_pages(cache: &impl CacheStore, uid: u64) -> Result<()> {
+    cache.remove_prefix(&format!("{}/collections/", uid))?;
+    Ok(())
+}
+
+/// Fetch all collections of `username` (whose id is `uid`), `page_size` per
+/// request (at most `COLLECTIONS_PAGE_LIMIT`), using cache for each page.
+pub async fn fetch_collections(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    username: &str,
+    page_size: u64,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<Vec<Collection>> {
+    let mut collections = Vec::new();
+    let mut offset = 0u64;
+    let limit = page_size.clamp(1, COLLECTIONS_PAGE_LIMIT);
+
+    // Pages are fetched at different times; once the first one has expired,
+    // refetch them all so offsets come from one consistent listing.
+    if !cache.contains(&collection_page_key(uid, limit, 0)) {
+        clear_collection_pages(cache, uid)?;
+    }
+
+    // First page to get total
+    let first_page = fetch_collection_page(client, cache, uid, username, limit, offset).await?;
+    let total = first_page.total;
+    collections.extend(first_page.data);
+    offset += limit;
+    report(
+        progress,
+        FetchEvent::Started {
+            total,
+            done: collections.len() as u64,
+            requests: 0,
+        },
+    );
+
+    while offset < total {
+        let page = fetch_collection_page(client, cache, uid, username, limit, offset).await?;
+        collections.extend(page.data);
+        report(
+            progress,
+            FetchEvent::Progressed {
+                done: collections.len() as u64,
+                requests_left: 0,
+            },
+        );
+        offset += limit;
+    }
+    Ok(collections)
+}
+
+/// Fetch subject detail with cache.
+///
+/// With `revalidate`, a cached entry is checked against the server using its stored ETag.
+pub async fn fetch_subject(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    subject_id: u64,
+    revalidate: bool,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<SubjectDetail> {
+    let cache_key = format!("{}/subjects/{}", uid, subject_id);
+    let cached = cache.get::<SubjectDetail>(&cache_key);
+    if !revalidate && let Some(detail) = cached {
+        return Ok(detail);
+    }
+    let etag = if cached.is_some() {
+        cache.etag(&cache_key)
+    } else {
+        None
+    };
+    report(progress, FetchEvent::Step(FetchStep::Subject));
+    match (
+        client.get_subject(subject_id, etag.as_deref()).await?,
+        cached,
+    ) {
+        (Conditional::NotModified, Some(detail)) => {
+            debug!("Not modified: {}", cache_key);
+            cache.touch(&cache_key)?;
+            Ok(detail)
+        }
+        (Conditional::NotModified, None) => Err(AppError::Api {
+            status: 304,
+            message: format!("unexpected 304 for uncached subject {}", subject_id),
+        }),
+        (Conditional::Modified { value, etag }, _) => {
+            cache.set(&cache_key, &value)?;
+            cache.set_etag(&cache_key, etag.as_deref())?;
+            Ok(value)
+        }
+    }
+}
+
+/// Fetch all episodes for a subject with cache.
+///
+/// Only single-page episode lists keep an ETag, since one conditional request
+/// cannot vouch for the other pages.
+pub async fn fetch_all_episodes(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    subject_id: u64,
+    revalidate: bool,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<Vec<models::Episode>> {
+    let cache_key = format!("{}/episodes/{}", uid, subject_id);
+    let mut etag = None;
+    if cache.has(&cache_key) {
+        if !revalidate {
+            return Ok(cache
+                .get::<Vec<models::Episode>>(&cache_key)
+                .unwrap_or_default());
+        }
+        etag = cache.etag(&cache_key);
+    }
+    let mut all_episodes = Vec::new();
+    let mut offset = 0u64;
+    let limit = EPISODES_PAGE_LIMIT;
+    let mut first_etag = None;
+    let mut single_page = true;
+    let mut pages = None;
+    loop {
+        let page_etag = if offset == 0 { etag.as_deref() } else { None };
+        let step = FetchStep::Episodes {
+            page: offset / limit + 1,
+            pages,
+        };
+        report(progress, FetchEvent::Step(step));
+        let page = match client
+            .get_episodes(subject_id, limit, offset, page_etag)
+            .await?
+        {
+            Conditional::NotModified => {
+                debug!("Not modified: {}", cache_key);
+                cache.touch(&cache_key)?;
+                return Ok(cache
+                    .get::<Vec<models::Episode>>(&cache_key)
+                    .unwrap_or_default());
+            }
+            Conditional::Modified { value, etag } => {
+                if offset == 0 {
+                    first_etag = etag;
+                }
+                value
+            }
+        };
+        let total = page.total;
+        pages = Some(total.div_ceil(limit).max(1));
+        all_episodes.extend(page.data);
+        offset += limit;
+        if offset >= total {
+            break;
+        }
+        single_page = false;
+    }
+    if all_episodes.is_empty() {
+        cache.set_empty(&cache_key)?;
+    } else {
+        cache.set(&cache_key, &all_episodes)?;
+    }
+    let etag = if single_page { first_etag } else { None };
+    cache.set_etag(&cache_key, etag.as_deref())?;
+    Ok(all_episodes)
+}
+
+/// The token owner's progress on a subject from the v0 episode collections,
+/// reading every page; `None` when the subject isn't collected or no episode is marked.
+async fn fetch_episode_collections(
+    client: &impl BangumiApi,
+    subject_id: u64,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<Option<UserProgress>> {
+    let mut entries = Vec::new();
+    let mut offset = 0u64;
+    let limit = 100u64;
+    let mut pages = None;
+    loop {
+        let step = FetchStep::Progress {
+            page: offset / limit + 1,
+            pages,
+        };
+        report(progress, FetchEvent::Step(step));
+        let page = match client
+            .get_episode_collections(subject_id, limit, offset)
+            .await
+        {
+            Ok(page) => page,
+            Err(AppError::Api { status: 404, .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        pages = Some(page.total.div_ceil(limit).max(1));
+        entries.extend(page.data);
+        offset += limit;
+        if offset >= page.total {
+            break;
+        }
+    }
+    Ok(UserProgress::from_episode_collections(subject_id, &entries))
+}
+
+/// Fetch user progress for a subject with cache.
+///
+/// Progress comes from the v0 episode collections, falling back to the legacy
+/// progress endpoint if the server rejects that request. Both are cached in
+/// the legacy shape under the same key.
+pub async fn fetch_progress(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    subject_id: u64,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<Option<UserProgress>> {
+    let cache_key = format!("{}/progress/{}", uid, subject_id);
+    if cache.has(&cache_key) {
+        return Ok(cache.get::<UserProgress>(&cache_key));
+    }
+    let fetched = match fetch_episode_collections(client, subject_id, progress).await {
+        Ok(fetched) => fetched,
+        Err(AppError::Api { status, message }) => {
+            debug!(
+                subject_id = subject_id;
+                "Episode collections failed ({}: {}), using legacy progress", status, message
+            );
+            let step = FetchStep::Progress {
+                page: 1,
+                pages: Some(1),
+            };
+            report(progress, FetchEvent::Step(step));
+            client.get_progress(uid, subject_id).await?
+        }
+        Err(e) => return Err(e),
+    };
+    match &fetched {
+        Some(p) => cache.set(&cache_key, p)?,
+        None => cache.set_empty(&cache_key)?,
+    }
+    Ok(fetched)
+}
+
+/// Fetch the subjects linked to a subject with cache.
+pub async fn fetch_relations(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    subject_id: u64,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<Vec<RelatedSubject>> {
+    let cache_key = format!("{}/relations/{}", uid, subject_id);
+    if cache.has(&cache_key) {
+        return Ok(cache.get(&cache_key).unwrap_or_default());
+    }
+    report(progress, FetchEvent::Step(FetchStep::Relations));
+    let relations = client.get_subject_relations(subject_id).await?;
+    if relations.is_empty() {
+        cache.set_empty(&cache_key)?;
+    } else {
+        cache.set(&cache_key, &relations)?;
+    }
+    Ok(relations)
+}
+
+/// Which per-subject data the detail pass fetches for `col`, as
+/// `(episodes, progress)`. Music subjects have no episode progress, and their
+/// track lists are only fetched with `--music-tracks`.
+pub fn episode_data_needed(col: &Collection, opts: &RecordOptions) -> (bool, bool) {
+    if col.subject.subject_type == SUBJECT_TYPE_MUSIC {
+        (opts.music_tracks, false)
+    } else {
+        (true, true)
+    }
+}
+
+/// Estimate how many rate-limited requests fetching a subject's detail will make.
+/// Multi-page episode lists are counted as a single request.
+fn estimate_requests(
+    cache: &impl CacheStore,
+    uid: u64,
+    col: &Collection,
+    opts: &DetailOptions,
+) -> u64 {
+    let subject_id = col.subject_id;
+    let subject = format!("{}/subjects/{}", uid, subject_id);
+    let episodes = format!("{}/episodes/{}", uid, subject_id);
+    let progress = format!("{}/progress/{}", uid, subject_id);
+    let relations = format!("{}/relations/{}", uid, subject_id);
+    let (need_episodes, need_progress) = episode_data_needed(col, &opts.record);
+    [
+        opts.revalidate || !cache.contains(&subject),
+        need_episodes
+            && (opts.revalidate
+                || !cache.contains(&episodes)
+                || (opts.record.episode_titles
+                    && !opts.offline
+                    && cached_untitled(cache, &episodes))),
+        need_progress && !cache.contains(&progress),
+        opts.record.relations && !cache.contains(&relations),
+    ]
+    .iter()
+    .filter(|&&missing| missing)
+    .count() as u64
+}
+
+/// Whether the episodes cached under `key` all lack titles, as entries cached
+/// before titles were stored do. Such entries are refetched for
+/// `--episode-titles`, as are subjects whose episodes really have no titles.
+fn cached_untitled(cache: &impl CacheStore, key: &str) -> bool {
+    cache
+        .get::<Vec<models::Episode>>(key)
+        .is_some_and(|eps| !eps.is_empty() && eps.iter().all(|e| e.title().is_empty()))
+}
+
+/// Whether the episodes cached under `key` all lack airdates, as entries
+/// cached before airdates were stored do. Such entries are refetched for the
+/// airing calendar, as are subjects whose episodes really have no airdates.
+pub fn cached_undated(cache: &impl CacheStore, key: &str) -> bool {
+    cache
+        .get::<Vec<models::Episode>>(key)
+        .is_some_and(|eps| !eps.is_empty() && eps.iter().all(|e| e.airdate.is_empty()))
+}
+
+/// Request estimates for the collections the detail pass still has to fetch.
+fn pending_estimates(
+    cache: &impl CacheStore,
+    uid: u64,
+    collections: &[Collection],
+    done: &HashMap<u64, ExportRecord>,
+    opts: &DetailOptions,
+) -> HashMap<u64, u64> {
+    collections
+        .iter()
+        .filter(|col| !done.contains_key(&col.subject_id))
+        .map(|col| (col.subject_id, estimate_requests(cache, uid, col, opts)))
+        .collect()
+}
+
+/// Saved detail records under `key`, from the journal or, failing that, a whole
+/// list written by older versions (flagged by the `bool`).
+pub fn load_done_records(cache: &impl CacheStore, key: &str) -> (Vec<ExportRecord>, bool) {
+    match cache.get_lines(key) {
+        Some(records) => (records, false),
+        None => (cache.get(key).unwrap_or_default(), true),
+    }
+}
+
+/// Saved resume records that still apply, by subject id.
+///
+/// A record applies while its subject is in the collection list with the same
+/// `updated_at` (as `opts.record` writes it) and hasn't changed since the
+/// last `--incremental` run. With `--relations`, the subject's relations must
+/// also be cached, and with `--episode-titles` the record must hold titles, so
+/// records saved without them are redone. Music records must be without
+/// episode progress and hold track counts just when `--music-tracks` asks for
+/// them. Later journal lines override earlier ones for the same subject.
+fn usable_records(
+    cache: &impl CacheStore,
+    uid: u64,
+    saved: Vec<ExportRecord>,
+    collections: &[Collection],
+    opts: &DetailOptions,
+) -> HashMap<u64, ExportRecord> {
+    let current: HashMap<u64, &Collection> =
+        collections.iter().map(|c| (c.subject_id, c)).collect();
+    saved
+        .into_iter()
+        .filter_map(|r| Some((record_subject_id(&r)?, r)))
+        .filter(|(sid, r)| {
+            !opts.changed.contains(sid)
+                && current.get(sid).is_some_and(|c| {
+                    r.updated_at == opts.record.timestamps.render(&c.updated_at)
+                        && (c.subject.subject_type != SUBJECT_TYPE_MUSIC
+                            || (r.completeness == NO_PROGRESS
+                                && r.tracks.is_some() == opts.record.music_tracks))
+                })
+                && (!opts.record.relations || cache.contains(&format!("{}/relations/{}", uid, sid)))
+                && (!opts.record.episode_titles || r.watched_titles.is_some())
+        })
+        .collect()
+}
+
+/// Options controlling the detail pass.
+pub struct DetailOptions {
+    /// Revalidate cached entries with ETags.
+    pub revalidate: bool,
+    /// Requests are refused, so cached entries are used even when they lack
+    /// data the record options ask for.
+    pub offline: bool,
+    /// Record failing subjects and continue instead of aborting.
+    pub skip_errors: bool,
+    /// Checked before starting each item; once set, the pass stops with
+    /// `AppError::Interrupted`.
+    pub shutdown: Arc<AtomicBool>,
+    /// Cache key of the resume state, which is specific to the active filter.
+    pub done_key: String,
+    /// Subjects changed since the last `--incremental` run; their saved records are redone.
+    pub changed: HashSet<u64>,
+    /// What the records hold.
+    pub record: RecordOptions,
+}
+
+/// Estimate the uncached requests a detail pass over `collections` would make,
+/// leaving out subjects already done according to the resume state.
+pub fn pending_requests(
+    cache: &impl CacheStore,
+    uid: u64,
+    collections: &[Collection],
+    opts: &DetailOptions,
+) -> u64 {
+    let (saved, _) = load_done_records(cache, &opts.done_key);
+    let done = usable_records(cache, uid, saved, collections, opts);
+    pending_estimates(cache, uid, collections, &done, opts)
+        .values()
+        .sum()
+}
+
+/// Fetch detail for each collection item with resume support: finished records
+/// are kept in the cache, so an interrupted pass picks up where it stopped.
+///
+/// Returns the records, in collection order, along with the subjects that failed
+/// under `skip_errors`.
+pub async fn fetch_detail_records(
+    client: &impl BangumiApi,
+    cache: &impl CacheStore,
+    uid: u64,
+    collections: &[Collection],
+    opts: &DetailOptions,
+    progress: Option<ProgressFn<'_>>,
+) -> Result<(Vec<ExportRecord>, Vec<FailedSubject>)> {
+    let revalidate = opts.revalidate;
+    let mut failed = Vec::new();
+    let done_key = &opts.done_key;
+    let (saved, legacy) = load_done_records(cache, done_key);
+    let saved_count = saved.len();
+    let mut done = usable_records(cache, uid, saved, collections, opts);
+    // Items are appended to the journal as they finish; rewrite it only to drop
+    // records that no longer apply, or to convert an old whole-list entry.
+    if (legacy && saved_count > 0) || done.len() != saved_count {
+        let kept: Vec<&ExportRecord> = collections
+            .iter()
+            .filter_map(|c| done.get(&c.subject_id))
+            .collect();
+        cache.set_lines(done_key, &kept)?;
+    }
+    // Records cached before collection_type, nsfw, private and stopped_at were
+    // stored come back as 0, false and None; private can also change without a
+    // new record.
+    for col in collections {
+        if let Some(record) = done.get_mut(&col.subject_id) {
+            if record.collection_type == 0 {
+                record.collection_type = col.collection_type;
+            }
+            record.nsfw |= col.subject.nsfw;
+            record.private = col.private;
+            if record.stopped_at.is_none() {
+                record.stopped_at = stopped_at(col, last_watched_episode(&record.watched_eps));
+            }
+        }
+    }
+    // Fields from the subject are missing from records cached before they were
+    // stored, and depend on the record options, so take them from the cached subject.
+    for (sid, record) in done.iter_mut() {
+        let key = format!("{}/subjects/{}", uid, sid);
+        match cache.get::<SubjectDetail>(&key) {
+            Some(detail) => fill_subject_fields(record, &detail, &opts.record),
+            None => align_subject_fields(record, &opts.record),
+        }
+        if opts.record.relations {
+            let key = format!("{}/relations/{}", uid, sid);
+            record.relations = Some(cache.get(&key).unwrap_or_default());
+        }
+        if !opts.record.episode_titles {
+            record.watched_titles = None;
+        }
+    }
+
+    for col in collections {
+        if let Some(record) = done.get(&col.subject_id) {
+            report(progress, FetchEvent::Record(record));
+        }
+    }
+
+    // Pre-scan the cache so the estimate only counts requests that will actually be made.
+    let estimates = pending_estimates(cache, uid, collections, &done, opts);
+    let mut requests_left: u64 = estimates.values().sum();
+    report(
+        progress,
+        FetchEvent::Started {
+            total: collections.len() as u64,
+            done: done.len() as u64,
+            requests: requests_left,
+        },
+    );
+
+    let mut failed_records = HashMap::new();
+    for col in collections {
+        let sid = col.subject_id;
+        if done.contains_key(&sid) {
+            continue;
+        }
+        if opts.shutdown.load(Ordering::SeqCst) {
+            return Err(AppError::Interrupted {
+                done: done.len(),
+                total: collections.len(),
+            });
+        }
+
+        let display_name = if col.subject.name_cn.is_empty() {
+            &col.subject.name
+        } else {
+            &col.subject.name_cn
+        };
+        report(progress, FetchEvent::Item(display_name));
+
+        let fetched = async {
+            let detail = fetch_subject(client, cache, uid, sid, revalidate, progress).await?;
+            let (need_episodes, need_progress) = episode_data_needed(col, &opts.record);
+            let all_episodes = if need_episodes {
+                let episodes_key = format!("{}/episodes/{}", uid, sid);
+                if opts.record.episode_titles
+                    && !opts.offline
+                    && cached_untitled(cache, &episodes_key)
+                {
+                    cache.remove(&episodes_key)?;
+                }
+                fetch_all_episodes(client, cache, uid, sid, revalidate, progress).await?
+            } else {
+                Vec::new()
+            };
+            let user_progress = if need_progress {
+                fetch_progress(client, cache, uid, sid, progress).await?
+            } else {
+                None
+            };
+            let relations = if opts.record.relations {
+                Some(fetch_relations(client, cache, uid, sid, progress).await?)
+            } else {
+                None
+            };
+            Ok::<_, AppError>((detail, all_episodes, user_progress, relations))
+        }
+        .await
+        .map_err(|e| AppError::subject(sid, display_name, e));
+
+        match fetched {
+            Ok((detail, all_episodes, user_progress, relations)) => {
+                let mut record =
+                    build_detail_record(col, &detail, &all_episodes, &user_progress, &opts.record);
+                record.relations = relations;
+                cache.append(done_key, &record)?;
+                report(progress, FetchEvent::Record(&record));
+                done.insert(sid, record);
+            }
+            // A rejected token fails every remaining request, so never skip it.
+            Err(e) if opts.skip_errors && !matches!(e.root(), AppError::Unauthorized { .. }) => {
+                warn!(subject_id = sid; "Skipping {}", e);
+                failed.push(FailedSubject {
+                    id: sid,
+                    name: display_name.clone(),
+                    error: e.root().to_string(),
+                });
+                // Not saved, so a later run retries it.
+                failed_records.insert(sid, build_failed_record(col, &opts.record));
+            }
+            Err(e) => return Err(e),
+        }
+
+        requests_left -= estimates[&sid];
+        report(
+            progress,
+            FetchEvent::Progressed {
+                done: (done.len() + failed_records.len()) as u64,
+                requests_left,
+            },
+        );
+    }
+
+    let records = collections
+        .iter()
+        .filter_map(|c| {
+            done.remove(&c.subject_id)
+                .or_else(|| failed_records.remove(&c.subject_id))
+        })
+        .collect();
+    Ok((records, failed))
+}
@@ -0,0 +1,101 @@
+use chrono::{Datelike, Local};
+
+use crate::cli::{Args, NsfwFilter, StatusFilter, SubjectTypeFilter};
+use crate::models::Collection;
+
+/// Restricts which collections are exported, summarized and reported on.
+/// An empty list for a dimension matches everything.
+pub struct CollectionFilter {
+    subject_types: Vec<u8>,
+    collection_types: Vec<u8>,
+    /// Only collections last updated in this (local) calendar year.
+    year: Option<i32>,
+    nsfw: NsfwFilter,
+    exclude_private: bool,
+}
+
+impl CollectionFilter {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            subject_types: args.types.iter().map(|t| t.id()).collect(),
+            collection_types: args.status.iter().map(|s| s.id()).collect(),
+            year: args.year,
+            nsfw: args.nsfw,
+            exclude_private: args.exclude_private,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subject_types.is_empty()
+            && self.collection_types.is_empty()
+            && self.year.is_none()
+            && self.nsfw == NsfwFilter::Include
+            && !self.exclude_private
+    }
+
+    pub fn matches(&self, col: &Collection) -> bool {
+        (self.subject_types.is_empty() || self.subject_types.contains(&col.subject.subject_type))
+            && (self.collection_types.is_empty()
+                || self.collection_types.contains(&col.collection_type))
+            && self
+                .year
+                .is_none_or(|y| col.updated_at.with_timezone(&Local).year() == y)
+            && match self.nsfw {
+                NsfwFilter::Include => true,
+                NsfwFilter::Exclude => !col.subject.nsfw,
+                NsfwFilter::Only => col.subject.nsfw,
+            }
+            && !(self.exclude_private && col.private)
+    }
+
+    /// Stable tag identifying this filter, used to keep per-filter resume state apart.
+    pub fn tag(&self) -> String {
+        let join = |ids: &[u8]| {
+            let mut ids = ids.to_vec();
+            ids.sort_unstable();
+            ids.dedup();
+            ids.iter().map(u8::to_string).collect::<Vec<_>>().join("-")
+        };
+        let mut tag = format!(
+            "t{}_s{}",
+            join(&self.subject_types),
+            join(&self.collection_types)
+        );
+        if let Some(year) = self.year {
+            tag.push_str(&format!("_y{}", year));
+        }
+        match self.nsfw {
+            NsfwFilter::Include => {}
+            NsfwFilter::Exclude => tag.push_str("_nsfw-x"),
+            NsfwFilter::Only => tag.push_str("_nsfw-o"),
+        }
+        if self.exclude_private {
+            tag.push_str("_priv-x");
+        }
+        tag
+    }
+}
+
+impl SubjectTypeFilter {
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Book => 1,
+            Self::Anime => 2,
+            Self::Music => 3,
+            Self::Game => 4,
+            Self::Real => 6,
+        }
+    }
+}
+
+impl StatusFilter {
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Wish => 1,
+            Self::Done => 2,
+            Self::Doing => 3,
+            Self::OnHold => 4,
+            Self::Dropped => 5,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Days, Local, NaiveDate, Utc};
+use log::warn;
+
+use crate::error::Result;
+use crate::models::{Collection, Episode, UserProgress};
+use crate::next_up::format_sort;
+
+/// iCalendar lines must be folded at 75 octets.
+const MAX_LINE: usize = 75;
+
+/// One all-day event for an unwatched episode.
+struct AiringEvent {
+    episode_id: u64,
+    date: NaiveDate,
+    summary: String,
+    description: String,
+}
+
+/// Collect unwatched main episodes airing on or after `today - past_days`.
+/// Episodes without a valid airdate are skipped and counted in `undated`.
+fn airing_events(
+    col: &Collection,
+    episodes: &[Episode],
+    progress: &Option<UserProgress>,
+    since: NaiveDate,
+    undated: &mut usize,
+) -> Vec<AiringEvent> {
+    let watched = progress
+        .as_ref()
+        .map(UserProgress::watched_ids)
+        .unwrap_or_default();
+    let name = if col.subject.name_cn.is_empty() {
+        &col.subject.name
+    } else {
+        &col.subject.name_cn
+    };
+    episodes
+        .iter()
+        .filter(|e| e.episode_type == 0 && !watched.contains(&e.id))
+        .filter_map(|e| {
+            let Ok(date) = NaiveDate::parse_from_str(&e.airdate, "%Y-%m-%d") else {
+                *undated += 1;
+                return None;
+            };
+            (date >= since).then(|| AiringEvent {
+                episode_id: e.id,
+                date,
+                summary: format!("{} 第{}话", name, format_sort(e.sort)),
+                description: format!("https://bgm.tv/subject/{}", col.subject_id),
+            })
+        })
+        .collect()
+}
+
+/// Escape TEXT values per RFC 5545.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Append a content line, folding it at character boundaries.
+fn push_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Render the calendar for the given (collection, episodes, progress) triples.
+fn render<'a>(
+    subjects: impl IntoIterator<Item = (&'a Collection, &'a [Episode], &'a Option<UserProgress>)>,
+    past_days: u64,
+) -> String {
+    let today = Local::now().date_naive();
+    let since = today
+        .checked_sub_days(Days::new(past_days))
+        .unwrap_or(today);
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut undated = 0;
+    let mut events: Vec<AiringEvent> = subjects
+        .into_iter()
+        .flat_map(|(col, episodes, progress)| {
+            airing_events(col, episodes, progress, since, &mut undated)
+        })
+        .collect();
+    events.sort_by_key(|e| e.date);
+    if undated > 0 {
+        warn!(
+            "{} unwatched episodes have no airdate and were left out of the calendar",
+            undated
+        );
+    }
+
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//bangumi-tool//airing//ZH");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    for e in &events {
+        let end = e.date.succ_opt().unwrap_or(e.date);
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:ep-{}@bgm.tv", e.episode_id));
+        push_line(&mut out, &format!("DTSTAMP:{}", stamp));
+        push_line(
+            &mut out,
+            &format!("DTSTART;VALUE=DATE:{}", e.date.format("%Y%m%d")),
+        );
+        push_line(
+            &mut out,
+            &format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+        );
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&e.summary)));
+        push_line(&mut out, &format!("DESCRIPTION:{}", escape(&e.description)));
+        push_line(&mut out, &format!("URL:{}", e.description));
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Write `bangumi_airing.ics` to the output directory.
+pub fn write_ics<'a>(
+    subjects: impl IntoIterator<Item = (&'a Collection, &'a [Episode], &'a Option<UserProgress>)>,
+    past_days: u64,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let path = dir.join("bangumi_airing.ics");
+    std::fs::write(&path, render(subjects, past_days))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection() -> Collection {
+        serde_json::from_value(serde_json::json!({
+            "subject_id": 7,
+            "type": 3,
+            "rate": 0,
+            "ep_status": 0,
+            "updated_at": "2024-04-01T00:00:00Z",
+            "comment": null,
+            "tags": [],
+            "subject": {
+                "id": 7, "name": "Airing", "name_cn": "放送中", "type": 2, "eps": 2, "volumes": 0
+            }
+        }))
+        .unwrap()
+    }
+
+    fn episode(id: u64, sort: f64, airdate: &str) -> Episode {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "type": 0, "sort": sort, "ep": sort, "airdate": airdate
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn undated_episodes_are_counted_and_skipped() {
+        let episodes = [episode(1, 1.0, "2999-01-01"), episode(2, 2.0, "")];
+        let since = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut undated = 0;
+        let events = airing_events(&collection(), &episodes, &None, since, &mut undated);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].summary, "放送中 第1话");
+        assert_eq!(undated, 1);
+    }
+
+    #[test]
+    fn calendar_has_one_event_per_dated_episode() {
+        let col = collection();
+        let episodes = [episode(1, 1.0, "2999-01-01"), episode(2, 2.0, "")];
+        let ics = render([(&col, episodes.as_slice(), &None)], 0);
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("UID:ep-1@bgm.tv\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:29990101\r\n"));
+    }
+}
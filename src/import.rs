@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use log::warn;
+use serde_json::{Value, json};
+
+use crate::cache::CacheStore;
+use crate::client::BangumiClient;
+use crate::error::{AppError, Result};
+use crate::locale;
+use crate::models::{Collection, collection_type_from_name, subject_id_from_url};
+
+/// One collection entry read from a previous export.
+pub struct ImportEntry {
+    pub subject_id: u64,
+    pub name: String,
+    pub collection_type: u8,
+    pub rate: u8,
+    pub tags: Vec<String>,
+    pub comment: String,
+}
+
+/// An entry that was not imported, as `(name, reason)`.
+pub type NotImported = (String, String);
+
+/// Split the `a, b` tag list used by the exports.
+fn split_tags(tags: &str) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Turn the fields of one exported record into an entry, or explain why it can't be imported.
+fn entry(
+    subject_id: Option<u64>,
+    url: &str,
+    name: &str,
+    status: &str,
+    rating: &str,
+    tags: &str,
+    comment: &str,
+) -> std::result::Result<ImportEntry, String> {
+    let subject_id = subject_id
+        .filter(|&id| id != 0)
+        .or_else(|| subject_id_from_url(url))
+        .ok_or_else(|| "no subject id or subject URL".to_string())?;
+    let collection_type = collection_type_from_name(locale::to_zh(status.trim()))
+        .ok_or_else(|| format!("unknown status '{}'", status))?;
+    let rate = match rating.trim() {
+        "" => 0,
+        r => r
+            .parse()
+            .ok()
+            .filter(|r| *r <= 10)
+            .ok_or_else(|| format!("invalid rating '{}'", r))?,
+    };
+    Ok(ImportEntry {
+        subject_id,
+        name: name.to_string(),
+        collection_type,
+        rate,
+        tags: split_tags(tags),
+        comment: comment.trim().to_string(),
+    })
+}
+
+/// Read the records of a JSON or CSV export written by this tool. Records that
+/// can't be imported are returned separately.
+pub fn load_entries(path: &Path) -> Result<(Vec<ImportEntry>, Vec<NotImported>)> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    let mut entries = Vec::new();
+    let mut invalid = Vec::new();
+    let mut push = |name: &str, result| match result {
+        Ok(e) => entries.push(e),
+        Err(reason) => invalid.push((name.to_string(), reason)),
+    };
+
+    if is_csv {
+        let mut rdr = csv::Reader::from_path(path)?;
+        let headers = rdr.headers()?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|h| locale::to_zh(h.trim_start_matches('\u{feff}')) == name)
+        };
+        let cols = [
+            "名称",
+            "名称(中文)",
+            "地址",
+            "状态",
+            "我的评分",
+            "我的标签",
+            "我的评论",
+        ]
+        .map(column);
+        for row in rdr.records() {
+            let row = row?;
+            let [name, name_cn, url, status, rating, tags, comment] =
+                cols.map(|c| c.and_then(|i| row.get(i)).unwrap_or(""));
+            let name = if name_cn.is_empty() { name } else { name_cn };
+            push(name, entry(None, url, name, status, rating, tags, comment));
+        }
+    } else {
+        let data = std::fs::read_to_string(path)?;
+        let records: Vec<Value> = serde_json::from_str(&data)?;
+        for r in &records {
+            let text = |key: &str| r[key].as_str().unwrap_or_default();
+            let rating = r["rating"]
+                .as_u64()
+                .map(|n| n.to_string())
+                .unwrap_or_default();
+            push(
+                text("name"),
+                entry(
+                    r["subject_id"].as_u64(),
+                    text("url"),
+                    text("name"),
+                    text("status"),
+                    &rating,
+                    text("tags"),
+                    text("comment"),
+                ),
+            );
+        }
+    }
+    Ok((entries, invalid))
+}
+
+impl ImportEntry {
+    /// Request body for the collection write API.
+    fn body(&self) -> Value {
+        json!({
+            "type": self.collection_type,
+            "rate": self.rate,
+            "comment": self.comment,
+            "tags": self.tags,
+        })
+    }
+
+    /// Whether the account already has exactly this collection entry.
+    fn matches(&self, col: &Collection) -> bool {
+        let mut tags = col.tags.clone();
+        tags.sort();
+        tags.dedup();
+        col.collection_type == self.collection_type
+            && col.rate == self.rate
+            && col.comment.as_deref().unwrap_or("").trim() == self.comment
+            && tags == self.tags
+    }
+}
+
+#[derive(Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    /// Entries left alone, e.g. because the account already has them.
+    pub skipped: Vec<NotImported>,
+    /// Entries that could not be read or written.
+    pub failed: Vec<NotImported>,
+}
+
+/// Write each entry through the collection API, skipping entries the account
+/// already has and entries recorded as imported by an earlier run.
+pub async fn import(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    uid: u64,
+    entries: &[ImportEntry],
+    existing: &[Collection],
+    dry_run: bool,
+    on_progress: impl Fn(&str),
+) -> Result<ImportReport> {
+    let existing: HashMap<u64, &Collection> = existing.iter().map(|c| (c.subject_id, c)).collect();
+    let mut report = ImportReport::default();
+    for e in entries {
+        on_progress(&e.name);
+        let label = format!("{} ({})", e.name, e.subject_id);
+        let body = e.body();
+        let done_key = format!("{}/imported/{}", uid, e.subject_id);
+        if existing.get(&e.subject_id).is_some_and(|c| e.matches(c)) {
+            report
+                .skipped
+                .push((label, "identical entry exists".to_string()));
+            continue;
+        }
+        if cache.get::<Value>(&done_key).as_ref() == Some(&body) {
+            report
+                .skipped
+                .push((label, "imported by an earlier run".to_string()));
+            continue;
+        }
+        if dry_run {
+            println!("  would write {}: {}", label, body);
+            report.imported.push(label);
+            continue;
+        }
+        match client.update_collection(e.subject_id, &body).await {
+            Ok(()) => {
+                cache.set(&done_key, &body)?;
+                report.imported.push(label);
+            }
+            Err(err @ AppError::Unauthorized { .. }) => return Err(err),
+            Err(err) => {
+                warn!("Import failed for {}: {}", label, err);
+                report.failed.push((label, err.to_string()));
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl ImportReport {
+    pub fn print(&self, dry_run: bool) {
+        let verb = if dry_run {
+            "would be imported"
+        } else {
+            "imported"
+        };
+        if !self.skipped.is_empty() {
+            println!("\n== 跳过: {} ==", self.skipped.len());
+            for (name, reason) in &self.skipped {
+                println!("  {} — {}", name, reason);
+            }
+        }
+        if !self.failed.is_empty() {
+            println!("\n== 失败: {} ==", self.failed.len());
+            for (name, error) in &self.failed {
+                println!("  {} — {}", name, error);
+            }
+        }
+        println!(
+            "\nImport: {} {}, {} skipped, {} failed",
+            self.imported.len(),
+            verb,
+            self.skipped.len(),
+            self.failed.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{CsvExporter, Exporter, JsonExporter};
+    use crate::locale::Lang;
+    use crate::models::SimpleRecord;
+
+    fn record(subject_id: u64, status: &str, collection_type: u8, rating: &str) -> SimpleRecord {
+        SimpleRecord {
+            subject_id,
+            name: "Shingeki no Kyojin".to_string(),
+            name_cn: "进击的巨人".to_string(),
+            subject_type: "动画".to_string(),
+            url: format!("https://bgm.tv/subject/{}", subject_id),
+            status: status.to_string(),
+            collection_type,
+            updated_at: "2024-01-01 08:00:00".to_string(),
+            rating: rating.to_string(),
+            tags: "热血, 漫改".to_string(),
+            comment: "好看".to_string(),
+            nsfw: false,
+            private: false,
+        }
+    }
+
+    /// Export the records, read them back and check each entry.
+    fn round_trip(exporter: &dyn Exporter<SimpleRecord>, file_name: &str) {
+        let records = [record(1, "在看", 3, "9"), record(2, "读过", 2, "")];
+        let path = std::env::temp_dir().join(format!("{}_{}", std::process::id(), file_name));
+        let mut out = Vec::new();
+        exporter.write(&records, &mut out).unwrap();
+        std::fs::write(&path, out).unwrap();
+
+        let (entries, invalid) = load_entries(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(invalid.is_empty(), "{:?}", invalid);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].subject_id, 1);
+        assert_eq!(entries[0].name, "进击的巨人");
+        assert_eq!(entries[0].collection_type, 3);
+        assert_eq!(entries[0].rate, 9);
+        assert_eq!(entries[0].tags, ["漫改", "热血"]);
+        assert_eq!(entries[0].comment, "好看");
+        assert_eq!(entries[1].collection_type, 2);
+        assert_eq!(entries[1].rate, 0);
+    }
+
+    #[test]
+    fn english_csv_export_imports() {
+        let exporter = CsvExporter {
+            bom: true,
+            lang: Lang::En,
+        };
+        round_trip(&exporter, "import_en.csv");
+    }
+
+    #[test]
+    fn english_json_export_imports() {
+        round_trip(&JsonExporter { lang: Lang::En }, "import_en.json");
+    }
+
+    #[test]
+    fn chinese_csv_export_imports() {
+        let exporter = CsvExporter {
+            bom: true,
+            lang: Lang::Zh,
+        };
+        round_trip(&exporter, "import_zh.csv");
+    }
+}
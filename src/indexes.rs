@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::export::write_atomic;
+use crate::models::{Index, IndexSubject, subject_type_name};
+
+#[derive(Debug, Serialize)]
+struct IndexItem<'a> {
+    subject_id: u64,
+    name: &'a str,
+    #[serde(rename = "type")]
+    subject_type: &'static str,
+    url: String,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    comment: &'a str,
+    added_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexExport<'a> {
+    id: u64,
+    title: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    description: &'a str,
+    url: String,
+    creator: &'a str,
+    updated_at: String,
+    /// In the order the index lists them.
+    subjects: Vec<IndexItem<'a>>,
+}
+
+fn local_time(t: &chrono::DateTime<chrono::Utc>) -> String {
+    t.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// `<id>_<title>.json`, with characters that aren't allowed in file names replaced.
+fn file_name(index: &Index) -> String {
+    let title: String = index
+        .title
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(60)
+        .collect();
+    format!("{}_{}.json", index.id, title.trim_end_matches('.'))
+}
+
+/// Write one index and its subjects to `<output>/indexes/<id>_<title>.json`.
+pub fn write_index(index: &Index, subjects: &[IndexSubject], out_dir: &Path) -> Result<PathBuf> {
+    let dir = out_dir.join("indexes");
+    std::fs::create_dir_all(&dir)?;
+    let export = IndexExport {
+        id: index.id,
+        title: &index.title,
+        description: &index.desc,
+        url: format!("https://bgm.tv/index/{}", index.id),
+        creator: &index.creator.username,
+        updated_at: local_time(&index.updated_at),
+        subjects: subjects
+            .iter()
+            .map(|s| IndexItem {
+                subject_id: s.id,
+                name: &s.name,
+                subject_type: subject_type_name(s.subject_type),
+                url: format!("https://bgm.tv/subject/{}", s.id),
+                comment: s.comment.trim(),
+                added_at: local_time(&s.added_at),
+            })
+            .collect(),
+    };
+    let path = dir.join(file_name(index));
+    write_atomic(&path, |w| Ok(serde_json::to_writer_pretty(w, &export)?))?;
+    Ok(path)
+}
@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::error::{AppError, Result};
+use crate::export::write_atomic;
+
+/// Exit code when the access token is missing, rejected or about to expire.
+pub const EXIT_AUTH: u8 = 2;
+/// Exit code when a request fails or the API returns an error.
+pub const EXIT_NETWORK: u8 = 3;
+/// Exit code when the export was written but `--skip-errors` skipped some subjects.
+pub const EXIT_PARTIAL: u8 = 4;
+/// Exit code for invalid command-line arguments or configuration.
+pub const EXIT_USAGE: u8 = 5;
+/// Exit code used when the run is stopped with Ctrl+C.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// Broad class of a failed run, which decides its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Auth,
+    Network,
+    Partial,
+    Usage,
+    Interrupted,
+    Other,
+}
+
+impl FailureKind {
+    pub fn of(e: &AppError) -> Self {
+        match e.root() {
+            AppError::NoToken { .. }
+            | AppError::OAuth(_)
+            | AppError::Unauthorized { .. }
+            | AppError::TokenExpiring { .. } => Self::Auth,
+            AppError::Http(_) | AppError::Api { .. } => Self::Network,
+            AppError::PartialFailure { .. } => Self::Partial,
+            AppError::Config { .. }
+            | AppError::ProfileNotFound(_)
+            | AppError::InvalidProfile(_)
+            | AppError::InvalidBaseUrl(_)
+            | AppError::UnknownTimeZone(_)
+            | AppError::InvalidDateFormat(_) => Self::Usage,
+            AppError::Interrupted { .. } => Self::Interrupted,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Auth => EXIT_AUTH,
+            Self::Network => EXIT_NETWORK,
+            Self::Partial => EXIT_PARTIAL,
+            Self::Usage => EXIT_USAGE,
+            Self::Interrupted => EXIT_INTERRUPTED,
+            Self::Other => 1,
+        }
+    }
+}
+
+/// Machine-readable report of a failed run, written as `last_run.json`.
+#[derive(Debug, Serialize)]
+pub struct LastRun {
+    pub finished_at: String,
+    pub exit_code: u8,
+    pub kind: FailureKind,
+    pub message: String,
+    /// Records finished before the failure, when known.
+    pub completed: Option<usize>,
+    /// Whether rerunning the same command picks up where this run stopped.
+    pub resumable: bool,
+}
+
+impl LastRun {
+    /// Report for a run that failed with `e`. `detail` tells whether it was a
+    /// `--detail` run, whose finished records are kept for resuming.
+    pub fn from_error(e: &AppError, detail: bool) -> Self {
+        let kind = FailureKind::of(e);
+        let completed = match e.root() {
+            AppError::Interrupted { done, .. } => Some(*done),
+            AppError::PartialFailure { completed, .. } => Some(*completed),
+            _ => None,
+        };
+        let resumable = match kind {
+            FailureKind::Interrupted | FailureKind::Partial => true,
+            FailureKind::Auth | FailureKind::Network => detail,
+            FailureKind::Usage | FailureKind::Other => false,
+        };
+        Self::new(kind, e.to_string(), completed, resumable)
+    }
+
+    /// Report for a run rejected before it started because of its arguments.
+    pub fn usage(message: impl Into<String>) -> Self {
+        Self::new(FailureKind::Usage, message.into(), None, false)
+    }
+
+    fn new(kind: FailureKind, message: String, completed: Option<usize>, resumable: bool) -> Self {
+        Self {
+            finished_at: Local::now().to_rfc3339(),
+            exit_code: kind.exit_code(),
+            kind,
+            message,
+            completed,
+            resumable,
+        }
+    }
+
+    /// Write the report as `last_run.json` in `dir`, creating it if needed.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("last_run.json");
+        write_atomic(&path, |w| Ok(serde_json::to_writer_pretty(w, self)?))?;
+        Ok(path)
+    }
+}
+
+/// Remove a `last_run.json` left by an earlier failed run.
+pub fn clear(dir: &Path) -> Result<()> {
+    match std::fs::remove_file(dir.join("last_run.json")) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Print a clap error and exit: with `EXIT_USAGE` for invalid arguments, or 0
+/// for `--help` and `--version`.
+pub fn exit_clap(e: clap::Error) -> ! {
+    let _ = e.print();
+    std::process::exit(if e.use_stderr() { EXIT_USAGE.into() } else { 0 })
+}
@@ -0,0 +1,27 @@
+//! Fetching and caching for exporting Bangumi (bgm.tv) collections.
+//!
+//! `BangumiClient` talks to the API, `Cache` keeps responses on disk (or
+//! `MemoryCache` for a single run), the `fetch` module ties the two together
+//! for whole collections and per-subject detail, and the `records` builders
+//! turn the results into export rows.
+
+pub mod cache;
+pub mod client;
+pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod locale;
+pub mod memory_cache;
+pub mod models;
+pub mod records;
+
+pub use cache::{Cache, CacheFormat, CacheStore, CacheTtl};
+pub use client::{BangumiApi, BangumiClient, BangumiClientBuilder};
+pub use error::{AppError, Result};
+pub use fetch::{DetailOptions, FetchEvent, FetchStep, fetch_collections, fetch_detail_records};
+pub use locale::Lang;
+pub use memory_cache::MemoryCache;
+pub use models::run_length_encode;
+pub use records::{
+    RecordOptions, TimeZone, TimestampFormat, build_detail_record, build_simple_record,
+};
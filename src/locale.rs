@@ -0,0 +1,149 @@
+/// Language of the labels in exports and the terminal summary.
+///
+/// Records always hold the Chinese labels Bangumi itself uses (resume caches,
+/// `import` and `--against` rely on them); they are translated only when
+/// rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    Zh,
+    En,
+}
+
+/// English for each Chinese label that exports and the summary print.
+const EN: &[(&str, &str)] = &[
+    // Subject types
+    ("书籍", "Book"),
+    ("动画", "Anime"),
+    ("音乐", "Music"),
+    ("游戏", "Game"),
+    ("三次元", "Real"),
+    ("未知", "Unknown"),
+    // Collection statuses, per subject type
+    ("想看", "Want to watch"),
+    ("在看", "Watching"),
+    ("看过", "Watched"),
+    ("想读", "Want to read"),
+    ("在读", "Reading"),
+    ("读过", "Read"),
+    ("想听", "Want to listen"),
+    ("在听", "Listening"),
+    ("听过", "Listened"),
+    ("想玩", "Want to play"),
+    ("在玩", "Playing"),
+    ("玩过", "Played"),
+    ("搁置", "On hold"),
+    ("抛弃", "Dropped"),
+    // Summary groups
+    ("想看/想玩/想读/想听", "Wish"),
+    ("在看/在玩/在读/在听", "In progress"),
+    ("看过/玩过/读过/听过", "Completed"),
+    // CSV headers and values
+    ("名称", "Name"),
+    ("名称(中文)", "Chinese name"),
+    ("条目类型", "Type"),
+    ("地址", "URL"),
+    ("状态", "Status"),
+    ("最后标注", "Updated"),
+    ("完成度", "Progress"),
+    ("完成度(百分比)", "Progress (%)"),
+    ("完成单集", "Watched episodes"),
+    ("完成单集标题", "Watched episode titles"),
+    ("弃于", "Stopped at"),
+    ("我的评分", "Rating"),
+    ("评分差", "Rating diff"),
+    ("我的标签", "Tags"),
+    ("条目标签", "Site tags"),
+    ("我的评论", "Comment"),
+    ("简介", "Summary"),
+    ("关联条目", "Relations"),
+    ("私密", "Private"),
+    ("是", "Yes"),
+];
+
+impl Lang {
+    /// `zh` in this language; labels without a translation are returned as is.
+    pub fn label(self, zh: &str) -> &str {
+        match self {
+            Self::Zh => zh,
+            Self::En => EN
+                .iter()
+                .find(|(key, _)| *key == zh)
+                .map_or(zh, |(_, en)| en),
+        }
+    }
+
+    /// A rating as the summary shows it, e.g. `8分` or `8/10`.
+    pub fn rating(self, rating: u8) -> String {
+        match self {
+            Self::Zh => format!("{}分", rating),
+            Self::En => format!("{}/10", rating),
+        }
+    }
+
+    /// Aggregate line of a franchise in the summary, e.g. `3 季，共 37/62` or
+    /// `37/62 across 3 seasons`; `episodes` is watched and total main episodes.
+    pub fn franchise(self, seasons: usize, episodes: Option<(u64, u64)>) -> String {
+        match (self, episodes) {
+            (Self::Zh, Some((watched, total))) => {
+                format!("{} 季，共 {}/{}", seasons, watched, total)
+            }
+            (Self::Zh, None) => format!("{} 季", seasons),
+            (Self::En, Some((watched, total))) => {
+                format!("{}/{} across {} seasons", watched, total, seasons)
+            }
+            (Self::En, None) => format!("{} seasons", seasons),
+        }
+    }
+
+    /// Marks an airing subject whose aired episodes are all watched.
+    pub fn caught_up(self) -> &'static str {
+        match self {
+            Self::Zh => "已追平",
+            Self::En => "caught up",
+        }
+    }
+
+    /// Where a dropped subject was left off, e.g. `弃于第3话`.
+    pub fn dropped_at(self, episode: u64) -> String {
+        match self {
+            Self::Zh => format!("弃于第{}话", episode),
+            Self::En => format!("dropped at ep {}", episode),
+        }
+    }
+}
+
+/// The Chinese label that `label` translates, so exports in any language can be
+/// read back; other text is returned as is.
+pub fn to_zh(label: &str) -> &str {
+    EN.iter()
+        .find(|(_, en)| *en == label)
+        .map_or(label, |(zh, _)| zh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_labels_translate_back() {
+        for (zh, en) in EN {
+            assert_eq!(Lang::En.label(zh), *en);
+            assert_eq!(to_zh(Lang::En.label(zh)), *zh, "{} does not round-trip", en);
+        }
+    }
+
+    #[test]
+    fn chinese_labels_are_kept() {
+        for (zh, _) in EN {
+            assert_eq!(Lang::Zh.label(zh), *zh);
+            assert_eq!(to_zh(Lang::Zh.label(zh)), *zh);
+        }
+    }
+
+    #[test]
+    fn untranslated_text_passes_through() {
+        assert_eq!(Lang::En.label("进击的巨人"), "进击的巨人");
+        assert_eq!(to_zh("进击的巨人"), "进击的巨人");
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use indicatif::MultiProgress;
+use indicatif_log_bridge::LogWrapper;
+use log::kv::{self, Key, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value, json};
+
+use crate::cli::LogFormat;
+use crate::error::Result;
+
+/// Format a record as `[time level module] message`.
+fn format_plain(record: &Record) -> String {
+    format!(
+        "[{} {} {}] {}",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        record.level(),
+        record.module_path().unwrap_or(""),
+        record.args()
+    )
+}
+
+/// Collects a record's structured key-values into a JSON object.
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(
+        &mut self,
+        key: Key<'kvs>,
+        value: kv::Value<'kvs>,
+    ) -> std::result::Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            json!(b)
+        } else if let Some(n) = value.to_u64() {
+            json!(n)
+        } else if let Some(n) = value.to_i64() {
+            json!(n)
+        } else if let Some(n) = value.to_f64() {
+            json!(n)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.as_str().to_string(), value);
+        Ok(())
+    }
+}
+
+/// Format a record as one JSON object with `ts`, `level`, `module`, `msg`
+/// and any structured fields attached to it.
+fn format_json(record: &Record) -> String {
+    let mut m = Map::new();
+    m.insert("ts".into(), json!(Local::now().to_rfc3339()));
+    m.insert("level".into(), json!(record.level().as_str()));
+    m.insert("module".into(), json!(record.module_path().unwrap_or("")));
+    m.insert("msg".into(), json!(record.args().to_string()));
+    let _ = record.key_values().visit(&mut JsonFields(&mut m));
+    Value::Object(m).to_string()
+}
+
+fn format_record(record: &Record, format: LogFormat) -> String {
+    match format {
+        LogFormat::Plain => format_plain(record),
+        LogFormat::Json => format_json(record),
+    }
+}
+
+/// Logger sending records to the console at its configured level and,
+/// optionally, to a log file at debug level.
+struct TeeLogger {
+    console: env_logger::Logger,
+    file: Option<Mutex<File>>,
+    format: LogFormat,
+}
+
+impl TeeLogger {
+    fn file_enabled(&self, metadata: &Metadata) -> bool {
+        self.file.is_some() && metadata.level() <= LevelFilter::Debug
+    }
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata) || self.file_enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+        if let Some(file) = &self.file
+            && self.file_enabled(record.metadata())
+        {
+            let mut file = file.lock().unwrap();
+            let _ = writeln!(file, "{}", format_record(record, self.format));
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Set up logging through the progress bar bridge.
+///
+/// The console logs at warn (or debug with `debug`); `log_file`, when given,
+/// receives debug-level logs regardless and is appended to across runs.
+/// Both use `format`.
+pub fn init_logger(
+    debug: bool,
+    log_file: Option<&Path>,
+    format: LogFormat,
+    multi: MultiProgress,
+) -> Result<()> {
+    let level = if debug { "debug" } else { "warn" };
+    let console =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level))
+            .format(move |buf, record| writeln!(buf, "{}", format_record(record, format)))
+            .build();
+
+    let file = match log_file {
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = File::options().create(true).append(true).open(path)?;
+            Some(Mutex::new(file))
+        }
+        None => None,
+    };
+
+    LogWrapper::new(
+        multi,
+        TeeLogger {
+            console,
+            file,
+            format,
+        },
+    )
+    .try_init()
+    .ok();
+    Ok(())
+}
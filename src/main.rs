@@ -1,361 +1,858 @@
-mod cache;
+mod account_sync;
+mod activity;
+mod archive;
+mod browse;
+mod cache_bundle;
+mod cache_report;
+mod calendar;
+mod characters;
 mod cli;
-mod client;
-mod error;
-mod export;
-mod models;
-
-use std::collections::BTreeMap;
-use std::path::Path;
-
-use chrono::Local;
-use clap::Parser;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use indicatif_log_bridge::LogWrapper;
-use log::info;
-
-use cache::Cache;
-use cli::{Args, Format};
-use client::BangumiClient;
-use error::Result;
+mod comment_reviews;
+mod compare;
+mod config;
+mod consistency;
+mod diff;
+mod filter;
+mod ics;
+mod import;
+mod indexes;
+mod last_run;
+mod logging;
+mod missing;
+mod neodb;
+mod next_up;
+mod notion;
+mod oauth;
+mod persons;
+mod plain_progress;
+mod profile;
+mod progress;
+mod rating_diff;
+mod rating_stats;
+mod review;
+mod run_stats;
+mod search;
+mod snapshot;
+mod sort;
+mod stale;
+mod stream;
+mod summary;
+mod tag_report;
+mod webhook;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, Utc};
+use clap::CommandFactory;
+use indicatif::{
+    HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState,
+    ProgressStyle,
+};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use activity::ActivityReport;
+use bangumi_tool::{cache, client, error, export, fetch, locale, memory_cache, models, records};
+use browse::BrowseItem;
+use cache::{Cache, CacheStore, CacheTtl};
+use cli::{
+    Args, CacheCommand, Command, ConfigCommand, EpisodeRange, Format, FranchiseGrouping,
+    ProfileCommand, ProgressMode,
+};
+use client::{BangumiClient, WaitEvent};
+use error::{AppError, Result};
+use export::{CsvExporter, Exportable, Exporter, JsonExporter, NdjsonExporter, TxtExporter};
+use fetch::{
+    DetailOptions, FetchEvent, SUBJECT_KINDS, cached_undated, clear_collection_pages,
+    collection_page_key, fetch_all_episodes, fetch_progress, fetch_subject, load_done_records,
+};
+use filter::CollectionFilter;
+use last_run::{EXIT_INTERRUPTED, FailureKind, LastRun};
+use memory_cache::MemoryCache;
 use models::{
-    Collection, ExportRecord, SimpleRecord, SubjectDetail, UserProgress, collection_status_name,
-    run_length_encode, subject_type_name,
+    Collection, ExportRecord, FailedSubject, Paged, PagedCollection, SimpleRecord, SyncState,
+    UserProgress, collection_status_name, subject_type_name, user_group_name,
 };
+use next_up::NextUp;
+use rating_diff::RatingDiffReport;
+use rating_stats::RatingReport;
+use records::{
+    RecordOptions, TimestampFormat, build_detail_record, build_simple_record, record_subject_id,
+};
+use review::YearReview;
+use run_stats::RunStats;
+use stream::StreamWriter;
+use tag_report::TagReport;
+
+/// Cache directory used before it moved to the platform cache location; still
+/// used when it exists so older setups keep their cache.
+const LEGACY_CACHE_DIR: &str = ".bgm_cache";
 
-const CACHE_DIR: &str = ".bgm_cache";
+/// A token with surrounding whitespace and any byte order mark removed; `None` if blank.
+fn clean_token(token: &str) -> Option<String> {
+    let token = token.trim_start_matches('\u{feff}').trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// `~/...` resolved against the home directory; other paths are returned as is.
+fn expand_tilde(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
 
-fn load_token() -> Result<String> {
-    if let Ok(token) = std::env::var("BANGUMI_ACCESS_TOKEN")
-        && !token.is_empty()
+/// The access token from, in order: `--token`, `--token-file`, the
+/// BANGUMI_ACCESS_TOKEN environment variable, `.bgm_token` in the current directory,
+/// or the tokens stored by `auth` (refreshed when close to expiry).
+///
+/// With `--profile`, the profile's token replaces everything after `--token-file`.
+async fn load_token(args: &Args) -> Result<String> {
+    let read_file = |path: &Path| {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|t| clean_token(&t))
+    };
+    if let Some(token) = args.token.as_ref().and_then(|t| clean_token(&t.0)) {
+        return Ok(token);
+    }
+    let mut checked = Vec::new();
+    if let Some(path) = &args.token_file {
+        let path = expand_tilde(path);
+        if let Some(token) = read_file(&path) {
+            return Ok(token);
+        }
+        checked.push(format!("--token-file {}", path.display()));
+    }
+    if let Some(name) = &args.profile {
+        if let Some(token) = clean_token(&profile::load(name)?.token) {
+            return Ok(token);
+        }
+        checked.push(format!("profile {}", name));
+        return Err(AppError::NoToken { checked });
+    }
+    if let Some(token) = std::env::var("BANGUMI_ACCESS_TOKEN")
+        .ok()
+        .and_then(|t| clean_token(&t))
     {
-        return Ok(token.trim().to_string());
+        return Ok(token);
     }
-    match std::fs::read_to_string(".bgm_token") {
-        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
-        _ => Err(error::AppError::NoToken),
+    checked.push("BANGUMI_ACCESS_TOKEN".to_string());
+    if let Some(token) = read_file(Path::new(".bgm_token")) {
+        return Ok(token);
+    }
+    checked.push(".bgm_token".to_string());
+    if let Some(path) = oauth::token_path() {
+        if let Some(token) = oauth::access_token(&path).await? {
+            return Ok(token);
+        }
+        checked.push(path.display().to_string());
     }
+    Err(AppError::NoToken { checked })
 }
 
-fn init_logger(debug: bool, multi: MultiProgress) {
-    use std::io::Write;
+/// Look up when the access token expires and print it; `None` if it doesn't
+/// expire or the lookup failed, in which case the run goes ahead unchecked.
+async fn token_expiry(token: &str, quiet: bool) -> Option<DateTime<Utc>> {
+    match oauth::token_expiry(token).await {
+        Ok(Some(expires)) => {
+            if !quiet {
+                println!(
+                    "Token expires at {} (in {})",
+                    expires.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+                    HumanDuration((expires - Utc::now()).to_std().unwrap_or_default())
+                );
+            }
+            Some(expires)
+        }
+        Ok(None) => {
+            info!("Access token doesn't expire");
+            None
+        }
+        Err(e) => {
+            warn!("Could not check when the access token expires: {}", e);
+            None
+        }
+    }
+}
 
-    let level = if debug { "debug" } else { "warn" };
-    let logger = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level))
-        .format(|buf, record| {
-            let now = Local::now().format("%Y-%m-%d %H:%M:%S");
-            writeln!(
-                buf,
-                "[{} {} {}] {}",
-                now,
-                record.level(),
-                record.module_path().unwrap_or(""),
-                record.args()
-            )
-        })
-        .build();
-    LogWrapper::new(multi, logger).try_init().ok();
+/// Show rate-limit countdowns in a progress bar's message, restoring it afterwards.
+fn attach_wait_observer(client: &BangumiClient, pb: &ProgressBar) {
+    let pb = pb.clone();
+    let saved = Mutex::new(None::<String>);
+    client.set_wait_observer(Some(Arc::new(move |event: WaitEvent| match event {
+        WaitEvent::Waiting { remaining, reason } => {
+            let mut saved = saved.lock().unwrap();
+            if saved.is_none() {
+                *saved = Some(pb.message());
+            }
+            pb.set_message(format!("⏳ waiting {}s ({})", remaining.as_secs(), reason));
+        }
+        WaitEvent::Done => {
+            if let Some(msg) = saved.lock().unwrap().take() {
+                pb.set_message(msg);
+            }
+        }
+    })));
 }
 
-/// Fetch all collections, using cache for each page.
+/// Where to keep the cache: `--cache-dir`, else an existing `./.bgm_cache`,
+/// else `bangumi-tool` under the platform cache directory.
+fn cache_dir(args: &Args) -> PathBuf {
+    if let Some(dir) = &args.cache_dir {
+        return dir.clone();
+    }
+    if let Some(name) = &args.profile {
+        return profile::cache_dir(name);
+    }
+    let legacy = Path::new(LEGACY_CACHE_DIR);
+    if legacy.is_dir() {
+        return legacy.to_path_buf();
+    }
+    dirs::cache_dir()
+        .map(|d| d.join("bangumi-tool"))
+        .unwrap_or_else(|| legacy.to_path_buf())
+}
+
+/// Fetch all collections, with a spinner that becomes a progress bar once the
+/// total is known.
 async fn fetch_collections(
     client: &BangumiClient,
-    cache: &Cache,
+    cache: &impl CacheStore,
     uid: u64,
     username: &str,
+    page_size: u64,
     multi: &MultiProgress,
 ) -> Result<Vec<Collection>> {
-    let mut collections = Vec::new();
-    let mut offset = 0u64;
-    let limit = 30u64;
-
-    let pb = multi.add(ProgressBar::new_spinner());
+    let pb = plain_progress::add(multi, ProgressBar::new_spinner(), "fetched", "collections");
     pb.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.green} {msg}")
             .unwrap(),
     );
     pb.set_message("Fetching collections...");
+    attach_wait_observer(client, &pb);
 
-    // First page to get total
-    let cache_key = format!("{}/collections/{}", uid, offset);
-    let first_page = match cache.get(&cache_key) {
-        Some(page) => page,
-        None => {
-            let page = client.get_collections(username, limit, offset).await?;
-            cache.set(&cache_key, &page)?;
-            page
+    let bar = pb.clone();
+    let progress = move |event: FetchEvent| match event {
+        FetchEvent::Started { total, done, .. } => {
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} collections {msg}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar.set_message("");
+            bar.set_length(total);
+            bar.set_position(done);
         }
+        FetchEvent::Progressed { done, .. } => bar.set_position(done),
+        FetchEvent::Item(_) | FetchEvent::Step(_) | FetchEvent::Record(_) => {}
     };
-    let total = first_page.total;
-    collections.extend(first_page.data);
-    offset += limit;
-
-    pb.finish_and_clear();
+    let result =
+        fetch::fetch_collections(client, cache, uid, username, page_size, Some(&progress)).await;
+    client.set_wait_observer(None);
+    match &result {
+        Ok(collections) => {
+            pb.finish_with_message(format!("Fetched {} collections", collections.len()))
+        }
+        Err(_) => pb.finish_and_clear(),
+    }
     multi.remove(&pb);
+    result
+}
 
-    let pb = multi.add(ProgressBar::new(total));
+/// Fetch every page of a paginated list, caching each page under
+/// `{cache_prefix}/{offset}`, with a progress bar like the collection fetch.
+async fn fetch_paged<T, F, Fut>(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    multi: &MultiProgress,
+    cache_prefix: &str,
+    what: &str,
+    fetch: F,
+) -> Result<Vec<T>>
+where
+    T: Serialize + DeserializeOwned,
+    F: Fn(u64, u64) -> Fut,
+    Fut: Future<Output = Result<Paged<T>>>,
+{
+    let limit = 30u64;
+    let pb = plain_progress::add(multi, ProgressBar::new(0), "fetched", what);
     pb.set_style(
         ProgressStyle::default_bar()
-            .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} collections")
+            .template(&format!(
+                "{{spinner:.green}} [{{bar:30.cyan/dim}}] {{pos}}/{{len}} {} {{msg}}",
+                what
+            ))
             .unwrap()
             .progress_chars("=> "),
     );
-    pb.set_position(collections.len() as u64);
+    attach_wait_observer(client, &pb);
 
-    while offset < total {
-        let cache_key = format!("{}/collections/{}", uid, offset);
-        let page = match cache.get(&cache_key) {
+    let mut items = Vec::new();
+    let mut offset = 0u64;
+    loop {
+        let cache_key = format!("{}/{}", cache_prefix, offset);
+        let page: Paged<T> = match cache.get(&cache_key) {
             Some(page) => page,
             None => {
-                let page = client.get_collections(username, limit, offset).await?;
+                let page = fetch(limit, offset).await?;
                 cache.set(&cache_key, &page)?;
                 page
             }
         };
-        collections.extend(page.data);
-        pb.set_position(collections.len() as u64);
+        pb.set_length(page.total);
+        let fetched = page.data.len() as u64;
+        items.extend(page.data);
+        pb.set_position(items.len() as u64);
         offset += limit;
+        if fetched == 0 || offset >= page.total {
+            break;
+        }
     }
-    pb.finish_with_message(format!("Fetched {} collections", collections.len()));
+    client.set_wait_observer(None);
+    pb.finish_with_message(format!("Fetched {} {}", items.len(), what));
     multi.remove(&pb);
-    Ok(collections)
+    Ok(items)
 }
 
-/// Fetch subject detail with cache.
-async fn fetch_subject(
-    client: &BangumiClient,
-    cache: &Cache,
-    uid: u64,
-    subject_id: u64,
-) -> Result<SubjectDetail> {
-    let cache_key = format!("{}/subjects/{}", uid, subject_id);
-    if let Some(detail) = cache.get(&cache_key) {
-        return Ok(detail);
+/// Drop everything cached about the given subjects so this run refetches them.
+///
+/// Their records are also dropped from every `done_records*` journal.
+fn refresh_subjects(cache: &impl CacheStore, uid: u64, subject_ids: &[u64]) -> Result<()> {
+    for sid in subject_ids {
+        for kind in SUBJECT_KINDS {
+            cache.remove(&format!("{}/{}/{}", uid, kind, sid))?;
+        }
     }
-    let detail = client.get_subject(subject_id).await?;
-    cache.set(&cache_key, &detail)?;
-    Ok(detail)
+    for key in cache.keys(&format!("{}/done_records", uid))? {
+        let (mut records, _) = load_done_records(cache, &key);
+        let before = records.len();
+        records.retain(|r| record_subject_id(r).is_none_or(|sid| !subject_ids.contains(&sid)));
+        if records.len() != before {
+            cache.set_lines(&key, &records)?;
+        }
+    }
+    info!("Refreshing {} subjects", subject_ids.len());
+    Ok(())
 }
 
-/// Fetch all episodes for a subject with cache.
-async fn fetch_all_episodes(
+/// Delete cache entries of subjects that are no longer collected, and collection
+/// pages past the end of the list.
+///
+/// Subjects in the incremental sync state are kept too, since incremental runs
+/// cache newly collected subjects without rewriting the collection pages.
+async fn gc_cache(
     client: &BangumiClient,
-    cache: &Cache,
-    uid: u64,
-    subject_id: u64,
-) -> Result<Vec<models::Episode>> {
-    let cache_key = format!("{}/episodes/{}", uid, subject_id);
-    if cache.has(&cache_key) {
-        return Ok(cache
-            .get::<Vec<models::Episode>>(&cache_key)
-            .unwrap_or_default());
-    }
-    let mut all_episodes = Vec::new();
-    let mut offset = 0u64;
-    let limit = 100u64;
-    loop {
-        let page = client.get_episodes(subject_id, limit, offset).await?;
-        let total = page.total;
-        all_episodes.extend(page.data);
-        offset += limit;
-        if offset >= total {
-            break;
-        }
+    cache: &impl CacheStore,
+    multi: &MultiProgress,
+    me: &models::User,
+    page_size: u64,
+    dry_run: bool,
+) -> Result<()> {
+    let collections =
+        fetch_collections(client, cache, me.id, &me.username, page_size, multi).await?;
+    let mut collected: HashSet<u64> = collections.iter().map(|c| c.subject_id).collect();
+    if let Some(state) = cache.get::<SyncState>(&format!("{}/last_sync", me.id)) {
+        collected.extend(state.collections.iter().map(|c| c.subject_id));
     }
-    if all_episodes.is_empty() {
-        cache.set_empty(&cache_key)?;
-    } else {
-        cache.set(&cache_key, &all_episodes)?;
+
+    let mut keys = cache_report::orphaned_keys(cache, me.id, &collected)?;
+    keys.extend(cache_report::stale_page_keys(
+        cache,
+        me.id,
+        collections.len() as u64,
+        page_size,
+    )?);
+    let mut bytes = 0;
+    for key in &keys {
+        bytes += cache.entry_size(key);
+        if dry_run {
+            println!("  {}", key);
+        } else {
+            cache.remove(key)?;
+        }
     }
-    Ok(all_episodes)
+    println!(
+        "{} {} cache entries, {}",
+        if dry_run { "Would remove" } else { "Removed" },
+        keys.len(),
+        HumanBytes(bytes)
+    );
+    Ok(())
 }
 
-/// Fetch user progress for a subject with cache.
-async fn fetch_progress(
-    client: &BangumiClient,
-    cache: &Cache,
+/// Remove the collection pages of other page sizes than `page_size`, or past
+/// the end of a list of `total`, as `cache gc` does. Returns how many there were.
+fn prune_stale_pages(
+    cache: &impl CacheStore,
     uid: u64,
-    subject_id: u64,
-) -> Result<Option<UserProgress>> {
-    let cache_key = format!("{}/progress/{}", uid, subject_id);
-    if cache.has(&cache_key) {
-        return Ok(cache.get::<UserProgress>(&cache_key));
-    }
-    let progress = client.get_progress(uid, subject_id).await?;
-    match &progress {
-        Some(p) => cache.set(&cache_key, p)?,
-        None => cache.set_empty(&cache_key)?,
-    }
-    Ok(progress)
-}
-
-/// Build a SimpleRecord from collection data only.
-fn build_simple_record(col: &Collection) -> SimpleRecord {
-    let updated_local = col.updated_at.with_timezone(&Local);
-    SimpleRecord {
-        name: col.subject.name.clone(),
-        name_cn: col.subject.name_cn.clone(),
-        subject_type: subject_type_name(col.subject.subject_type).to_string(),
-        url: format!("https://bgm.tv/subject/{}", col.subject_id),
-        status: collection_status_name(col.collection_type, col.subject.subject_type).to_string(),
-        collection_type: col.collection_type,
-        updated_at: updated_local.format("%Y-%m-%d %H:%M:%S").to_string(),
-        rating: if col.rate == 0 {
-            String::new()
-        } else {
-            col.rate.to_string()
-        },
-        tags: col.tags.join(", "),
-        comment: col.comment.clone().unwrap_or_default(),
+    total: u64,
+    page_size: u64,
+) -> Result<usize> {
+    let keys = cache_report::stale_page_keys(cache, uid, total, page_size)?;
+    for key in &keys {
+        cache.remove(key)?;
     }
+    Ok(keys.len())
 }
 
-/// Build an ExportRecord with full detail.
-fn build_detail_record(
-    col: &Collection,
-    detail: &SubjectDetail,
-    all_episodes: &[models::Episode],
-    progress: &Option<UserProgress>,
-) -> ExportRecord {
-    let sid = col.subject_id;
-    let total_eps = detail.total_episodes.max(detail.eps);
-
-    let main_eps: Vec<_> = all_episodes
-        .iter()
-        .filter(|e| e.episode_type == 0)
-        .collect();
-    let main_ep_count = main_eps.len() as u64;
-
-    let watched_ep_ids: Vec<u64> = progress
-        .as_ref()
-        .map(|p| {
-            p.eps
-                .iter()
-                .filter(|ep| ep.status.id == 2)
-                .map(|ep| ep.id)
-                .collect()
-        })
-        .unwrap_or_default();
+/// Cache key of the logged-in user, written on every online run so `--offline`
+/// knows whose cache to read.
+const IDENTITY_KEY: &str = "identity";
 
-    let watched_sort_nums: Vec<u64> = main_eps
-        .iter()
-        .filter(|e| watched_ep_ids.contains(&e.id))
-        .map(|e| e.sort as u64)
-        .collect();
+/// Print the cache entries an `--offline` run lacks and build the error for them.
+fn offline_missing(keys: &[String]) -> AppError {
+    for key in keys {
+        eprintln!("  missing: {}", key);
+    }
+    AppError::OfflineMissing {
+        missing: keys.len(),
+    }
+}
 
-    let watched_count = watched_sort_nums.len() as u64;
-    let completeness = format!("{}/{}", watched_count, main_ep_count);
-    let completeness_pct = if main_ep_count > 0 {
-        format!(
-            "{:.0}%",
-            watched_count as f64 / main_ep_count as f64 * 100.0
-        )
-    } else if total_eps > 0 {
-        format!("{:.0}%", col.ep_status as f64 / total_eps as f64 * 100.0)
-    } else {
-        "N/A".to_string()
+/// The collection list for `--offline`, from the cached pages or, failing that,
+/// the incremental sync state.
+fn offline_collections(
+    cache: &impl CacheStore,
+    uid: u64,
+    page_size: u64,
+) -> Result<Vec<Collection>> {
+    let first_key = collection_page_key(uid, page_size, 0);
+    let Some(first) = cache.get::<PagedCollection>(&first_key) else {
+        if let Some(state) = cache.get::<SyncState>(&format!("{}/last_sync", uid)) {
+            return Ok(state.collections);
+        }
+        return Err(offline_missing(&[first_key]));
     };
-
-    let watched_eps_str = run_length_encode(&watched_sort_nums);
-    let updated_local = col.updated_at.with_timezone(&Local);
-
-    ExportRecord {
-        name: col.subject.name.clone(),
-        name_cn: col.subject.name_cn.clone(),
-        subject_type: subject_type_name(col.subject.subject_type).to_string(),
-        url: format!("https://bgm.tv/subject/{}", sid),
-        status: collection_status_name(col.collection_type, col.subject.subject_type).to_string(),
-        updated_at: updated_local.format("%Y-%m-%d %H:%M:%S").to_string(),
-        completeness,
-        completeness_pct,
-        watched_eps: watched_eps_str,
-        rating: if col.rate == 0 {
-            String::new()
-        } else {
-            col.rate.to_string()
-        },
-        tags: col.tags.join(", "),
-        comment: col.comment.clone().unwrap_or_default(),
+    let mut collections = first.data;
+    let mut missing = Vec::new();
+    for offset in (page_size..first.total).step_by(page_size as usize) {
+        let key = collection_page_key(uid, page_size, offset);
+        match cache.get::<PagedCollection>(&key) {
+            Some(page) => collections.extend(page.data),
+            None => missing.push(key),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(offline_missing(&missing));
     }
+    Ok(collections)
 }
 
-/// Print collections grouped by logical status to terminal.
-fn print_summary(records: &[SimpleRecord]) {
-    let group_order: [(u8, &str); 5] = [
-        (3, "在看/在玩/在读/在听"),
-        (1, "想看/想玩/想读/想听"),
-        (2, "看过/玩过/读过/听过"),
-        (4, "搁置"),
-        (5, "抛弃"),
-    ];
+/// Make sure every entry the detail pass reads is cached before an `--offline` run
+/// starts it, so a gap is reported up front rather than one subject at a time.
+fn check_offline_detail(
+    cache: &impl CacheStore,
+    uid: u64,
+    collections: &[Collection],
+    record: &RecordOptions,
+) -> Result<()> {
+    let mut missing = Vec::new();
+    for col in collections {
+        let (episodes, progress) = fetch::episode_data_needed(col, record);
+        let needed = [true, episodes, progress, record.relations];
+        for (kind, _) in SUBJECT_KINDS
+            .iter()
+            .zip(needed)
+            .filter(|(_, needed)| *needed)
+        {
+            let key = format!("{}/{}/{}", uid, kind, col.subject_id);
+            if !cache.contains(&key) {
+                missing.push(format!("{} ({})", key, col.subject.name));
+            }
+        }
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(offline_missing(&missing))
+    }
+}
 
-    let mut by_type: BTreeMap<u8, Vec<&SimpleRecord>> = BTreeMap::new();
-    for r in records {
-        by_type.entry(r.collection_type).or_default().push(r);
+/// The collection as last fetched, read only from the cache; `None` if it isn't cached.
+fn cached_collections(
+    cache: &impl CacheStore,
+    uid: u64,
+    page_size: u64,
+) -> Option<Vec<Collection>> {
+    if let Some(state) = cache.get::<SyncState>(&format!("{}/last_sync", uid)) {
+        return Some(state.collections);
+    }
+    let mut collections = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page: PagedCollection = cache.get(&collection_page_key(uid, page_size, offset))?;
+        offset += page.data.len().max(1) as u64;
+        collections.extend(page.data);
+        if offset >= page.total {
+            return Some(collections);
+        }
     }
+}
 
-    for (ctype, label) in &group_order {
-        let Some(items) = by_type.get(ctype) else {
-            continue;
-        };
+/// Fetch collections updated after `high_water`, newest first, along with the
+/// server's current total. Pages are not cached since their offsets keep shifting.
+async fn fetch_changed_collections(
+    client: &BangumiClient,
+    username: &str,
+    high_water: chrono::DateTime<chrono::Utc>,
+    page_size: u64,
+    multi: &MultiProgress,
+) -> Result<(Vec<Collection>, u64)> {
+    let pb = plain_progress::add(
+        multi,
+        ProgressBar::new_spinner(),
+        "fetched",
+        "changed collections",
+    );
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap(),
+    );
+    pb.set_message("Fetching changed collections...");
+    attach_wait_observer(client, &pb);
 
-        let mut by_status: BTreeMap<&str, Vec<&&SimpleRecord>> = BTreeMap::new();
-        for r in items {
-            by_status.entry(&r.status).or_default().push(r);
+    let limit = page_size;
+    let mut offset = 0u64;
+    let mut changed = Vec::new();
+    let total = loop {
+        let page = client
+            .get_collections(username, limit, offset)
+            .await
+            .map_err(|e| AppError::page(offset, e))?;
+        let count = page.data.len();
+        let before = changed.len();
+        changed.extend(
+            page.data
+                .into_iter()
+                .take_while(|c| c.updated_at > high_water),
+        );
+        let reached_mark = changed.len() - before < count;
+        offset += limit;
+        if reached_mark || count == 0 || offset >= page.total {
+            break page.total;
         }
+    };
+    client.set_wait_observer(None);
+    pb.finish_and_clear();
+    multi.remove(&pb);
+    Ok((changed, total))
+}
 
-        println!("\n== {} ({}) ==", label, items.len());
-        for (status, sub_items) in &by_status {
-            println!("  --- {} ({}) ---", status, sub_items.len());
-            for r in sub_items {
-                let display_name = if r.name_cn.is_empty() {
-                    &r.name
-                } else {
-                    &r.name_cn
-                };
-                let rating_part = if r.rating.is_empty() {
-                    String::new()
-                } else {
-                    format!(" [{}分]", r.rating)
-                };
-                println!("    {} [{}]{}", display_name, r.subject_type, rating_part);
+/// Fetch the collection list, and with `incremental`, only what changed since the
+/// last incremental run.
+///
+/// Returns the collections along with the ids of subjects that changed; their
+/// cached subject, episode and progress entries have already been dropped.
+async fn sync_collections(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    uid: u64,
+    username: &str,
+    page_size: u64,
+    multi: &MultiProgress,
+    incremental: bool,
+) -> Result<(Vec<Collection>, HashSet<u64>)> {
+    if !incremental {
+        let collections = fetch_collections(client, cache, uid, username, page_size, multi).await?;
+        return Ok((collections, HashSet::new()));
+    }
+
+    let state_key = format!("{}/last_sync", uid);
+    let (collections, changed) = match cache.get::<SyncState>(&state_key) {
+        None => {
+            info!("No previous sync state, doing a full sync");
+            let collections =
+                fetch_collections(client, cache, uid, username, page_size, multi).await?;
+            (collections, HashSet::new())
+        }
+        Some(state) => {
+            let (fresh, total) =
+                fetch_changed_collections(client, username, state.high_water, page_size, multi)
+                    .await?;
+            let changed: HashSet<u64> = fresh.iter().map(|c| c.subject_id).collect();
+            let mut merged = fresh;
+            merged.extend(
+                state
+                    .collections
+                    .into_iter()
+                    .filter(|c| !changed.contains(&c.subject_id)),
+            );
+            // Removed collections don't show up as changes; a count mismatch means
+            // something was dropped, so rebuild the list from scratch.
+            if merged.len() as u64 != total {
+                info!(
+                    "Collection count changed ({} merged, {} on server), doing a full sync",
+                    merged.len(),
+                    total
+                );
+                clear_collection_pages(cache, uid)?;
+                merged = fetch_collections(client, cache, uid, username, page_size, multi).await?;
             }
+            for sid in &changed {
+                for kind in SUBJECT_KINDS {
+                    cache.remove(&format!("{}/{}/{}", uid, kind, sid))?;
+                }
+            }
+            info!("{} collections changed since the last sync", changed.len());
+            (merged, changed)
         }
-    }
-    println!();
+    };
+
+    let Some(high_water) = collections.iter().map(|c| c.updated_at).max() else {
+        return Ok((collections, changed));
+    };
+    let state = SyncState {
+        high_water,
+        collections,
+    };
+    cache.set(&state_key, &state)?;
+    Ok((state.collections, changed))
+}
+
+/// Install a Ctrl+C handler that requests a graceful stop on the first signal
+/// and force-quits on the second.
+///
+/// The handler is installed once per process, so repeated runs under `--watch`
+/// share it instead of piling up listeners.
+fn install_shutdown_handler() -> Arc<AtomicBool> {
+    static SHUTDOWN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    SHUTDOWN
+        .get_or_init(|| {
+            let shutdown = Arc::new(AtomicBool::new(false));
+            let flag = shutdown.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    return;
+                }
+                flag.store(true, Ordering::SeqCst);
+                eprintln!(
+                    "\nInterrupted, finishing the current item... (press Ctrl+C again to force quit)"
+                );
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    std::process::exit(EXIT_INTERRUPTED.into());
+                }
+            });
+            shutdown
+        })
+        .clone()
 }
 
-/// Fetch detail for each collection item with progress bar and resume support.
+/// Run the detail pass with a progress bar whose ETA counts the uncached
+/// requests left. With `--stream`, each finished record is also appended to
+/// the export files as it completes.
 async fn fetch_detail_records(
     client: &BangumiClient,
-    cache: &Cache,
+    cache: &impl CacheStore,
     multi: &MultiProgress,
     uid: u64,
     collections: &[Collection],
-) -> Result<Vec<ExportRecord>> {
-    let done_key = format!("{}/done_records", uid);
-    let mut records: Vec<ExportRecord> = cache.get(&done_key).unwrap_or_default();
-    let start_index = records.len();
+    opts: &DetailOptions,
+    args: &Args,
+) -> Result<(Vec<ExportRecord>, Vec<FailedSubject>)> {
+    let quiet = args.quiet;
+    let writer = if args.stream {
+        let anonymize = args.anonymize.then_some((collections, args.timezone));
+        Some(StreamWriter::create(
+            Path::new(&args.output),
+            &args.format,
+            args.lang.into(),
+            anonymize,
+        )?)
+    } else {
+        None
+    };
+    let stream = writer.as_ref();
+    let interval = client.request_interval();
+    let remaining = Arc::new(AtomicU64::new(0));
+    let pb = plain_progress::add(
+        multi,
+        ProgressBar::new(collections.len() as u64),
+        "fetched",
+        "subjects",
+    );
+    let eta_remaining = remaining.clone();
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} [{elapsed_precise}, ETA {request_eta}] {msg}",
+            )
+            .unwrap()
+            .with_key(
+                "request_eta",
+                move |_: &ProgressState, w: &mut dyn std::fmt::Write| {
+                    let left = interval * eta_remaining.load(Ordering::Relaxed) as u32;
+                    let _ = write!(w, "{}", HumanDuration(left));
+                },
+            )
+            .progress_chars("=> "),
+    );
+    attach_wait_observer(client, &pb);
+
+    // A second line under the bar names the request the current item waits on;
+    // it is added on the item's first request and removed when the item is done.
+    let step_line = Mutex::new(None::<ProgressBar>);
+    let clear_step = || {
+        if let Some(line) = step_line.lock().unwrap().take() {
+            line.finish_and_clear();
+            multi.remove(&line);
+        }
+    };
+    let bar = pb.clone();
+    let progress = |event: FetchEvent| match event {
+        FetchEvent::Started {
+            total,
+            done,
+            requests,
+        } => {
+            remaining.store(requests, Ordering::Relaxed);
+            bar.set_position(done);
+            if !quiet {
+                bar.suspend(|| {
+                    if done > 0 {
+                        println!("{} of {} already done", done, total);
+                    }
+                    println!(
+                        "Estimated time: {} ({} uncached requests)",
+                        HumanDuration(interval * requests as u32),
+                        requests
+                    );
+                });
+            }
+        }
+        FetchEvent::Item(name) => bar.set_message(name.to_string()),
+        FetchEvent::Record(record) => {
+            if let Some(stream) = stream {
+                stream.push(record);
+            }
+        }
+        FetchEvent::Step(step) => {
+            let mut slot = step_line.lock().unwrap();
+            let line = slot.get_or_insert_with(|| {
+                let line = multi.insert_after(&bar, ProgressBar::new_spinner());
+                line.set_style(
+                    ProgressStyle::default_spinner()
+                        .template("  ↳ {msg}")
+                        .unwrap(),
+                );
+                line
+            });
+            line.set_message(step.to_string());
+        }
+        FetchEvent::Progressed {
+            done,
+            requests_left,
+        } => {
+            clear_step();
+            remaining.store(requests_left, Ordering::Relaxed);
+            bar.set_position(done);
+        }
+    };
+    let result =
+        fetch::fetch_detail_records(client, cache, uid, collections, opts, Some(&progress)).await;
+    clear_step();
+    client.set_wait_observer(None);
+    match &result {
+        Ok(_) => pb.finish_with_message("Done processing"),
+        Err(_) => pb.finish_and_clear(),
+    }
+    multi.remove(&pb);
+    if let (Err(_), Some(stream)) = (&result, stream)
+        && stream.written() > 0
+    {
+        let paths: Vec<String> = stream
+            .paths()
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        warn!(
+            "{} finished records were written to {}; they are rebuilt when the run resumes",
+            stream.written(),
+            paths.join(", ")
+        );
+    }
+    if result.is_ok()
+        && let Some(writer) = writer
+    {
+        writer.finish()?;
+    }
+    result
+}
 
-    if start_index > 0 {
-        println!("Resuming from record {}/{}", start_index, collections.len());
+/// Warn, or with `strict` fail, when a pass estimated to take `estimated` would
+/// outlast an access token expiring at `expires`.
+fn check_token_lifetime(expires: DateTime<Utc>, estimated: Duration, strict: bool) -> Result<()> {
+    let left = (expires - Utc::now()).to_std().unwrap_or_default();
+    if estimated <= left {
+        return Ok(());
     }
+    let expires_at = expires
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    if strict {
+        return Err(AppError::TokenExpiring {
+            expires_at,
+            estimated: HumanDuration(estimated).to_string(),
+        });
+    }
+    warn!(
+        "The access token expires at {} (in {}), before this run's estimated {} is up; it will likely stop part-way. Renew the token first, or rerun afterwards to resume",
+        expires_at,
+        HumanDuration(left),
+        HumanDuration(estimated)
+    );
+    Ok(())
+}
+
+/// Episodes and progress for one 在看 subject.
+struct WatchingDetail<'a> {
+    col: &'a Collection,
+    episodes: Vec<models::Episode>,
+    progress: Option<UserProgress>,
+}
+
+/// Fetch episodes and progress for each 在看 subject (served from the cache when
+/// a detail pass already ran). With `airdates`, cached episode lists without
+/// any airdate are refetched.
+async fn fetch_watching<'a>(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    multi: &MultiProgress,
+    uid: u64,
+    collections: &'a [Collection],
+    airdates: bool,
+) -> Result<Vec<WatchingDetail<'a>>> {
+    let watching: Vec<&Collection> = collections
+        .iter()
+        .filter(|c| c.collection_type == 3)
+        .collect();
 
-    let pb = multi.add(ProgressBar::new(collections.len() as u64));
+    let pb = plain_progress::add(
+        multi,
+        ProgressBar::new(watching.len() as u64),
+        "fetched",
+        "watching subjects",
+    );
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
             .unwrap()
             .progress_chars("=> "),
     );
-    pb.set_position(start_index as u64);
-
-    for (i, col) in collections.iter().enumerate() {
-        if i < start_index {
-            continue;
-        }
+    attach_wait_observer(client, &pb);
 
+    let mut items = Vec::with_capacity(watching.len());
+    for col in watching {
         let sid = col.subject_id;
         let display_name = if col.subject.name_cn.is_empty() {
             &col.subject.name
@@ -363,73 +860,1692 @@ async fn fetch_detail_records(
             &col.subject.name_cn
         };
         pb.set_message(display_name.clone());
-        pb.set_position(i as u64);
-
-        let detail = fetch_subject(client, cache, uid, sid).await?;
-        let all_episodes = fetch_all_episodes(client, cache, uid, sid).await?;
-        let progress = fetch_progress(client, cache, uid, sid).await?;
-
-        let record = build_detail_record(col, &detail, &all_episodes, &progress);
-        records.push(record);
-
-        cache.set(&done_key, &records)?;
+        let fetched = async {
+            let episodes_key = format!("{}/episodes/{}", uid, sid);
+            if airdates && cached_undated(cache, &episodes_key) {
+                cache.remove(&episodes_key)?;
+            }
+            let episodes = fetch_all_episodes(client, cache, uid, sid, false, None).await?;
+            let progress = fetch_progress(client, cache, uid, sid, None).await?;
+            Ok::<_, AppError>(WatchingDetail {
+                col,
+                episodes,
+                progress,
+            })
+        }
+        .await
+        .map_err(|e| AppError::subject(sid, display_name, e));
+        match fetched {
+            Ok(item) => items.push(item),
+            Err(e) => {
+                client.set_wait_observer(None);
+                pb.finish_and_clear();
+                multi.remove(&pb);
+                return Err(e);
+            }
+        }
+        pb.inc(1);
     }
-    pb.finish_with_message("Done processing");
+    client.set_wait_observer(None);
+    pb.finish_and_clear();
     multi.remove(&pb);
-
-    Ok(records)
+    Ok(items)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-    let multi = MultiProgress::new();
-    init_logger(args.debug, multi.clone());
-    let token = load_token()?;
-    let client = BangumiClient::new(token)?;
-
-    let cache = Cache::new(Path::new(CACHE_DIR))?;
-    if args.no_cache {
-        cache.clear()?;
-        info!("Cache cleared");
+/// Items for the `browse` UI. Progress is read from the cache; with `fetch`,
+/// subjects, episodes and progress missing from it are fetched first, so the
+/// UI itself never waits on the network.
+async fn load_browse_items(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    multi: &MultiProgress,
+    uid: u64,
+    collections: &[Collection],
+    fetch: bool,
+) -> Result<Vec<BrowseItem>> {
+    let opts = RecordOptions::default();
+    let cached = |col: &Collection| {
+        let sid = col.subject_id;
+        let detail = cache.get::<models::SubjectDetail>(&format!("{}/subjects/{}", uid, sid))?;
+        let episodes = cache.get::<Vec<models::Episode>>(&format!("{}/episodes/{}", uid, sid))?;
+        let key = format!("{}/progress/{}", uid, sid);
+        if !cache.has(&key) {
+            return None;
+        }
+        let progress = cache.get::<UserProgress>(&key);
+        Some(build_detail_record(
+            col, &detail, &episodes, &progress, &opts,
+        ))
+    };
+    if !fetch {
+        return Ok(collections
+            .iter()
+            .map(|col| BrowseItem::new(col, cached(col).as_ref()))
+            .collect());
     }
 
-    let me = client.get_me().await?;
-    println!("Logged in as {} ({})", me.nickname, me.username);
-
-    let collections = fetch_collections(&client, &cache, me.id, &me.username, &multi).await?;
-
-    let out_dir = Path::new(&args.output);
-    std::fs::create_dir_all(out_dir)?;
-
-    if args.detail {
-        let records = fetch_detail_records(&client, &cache, &multi, me.id, &collections).await?;
+    let pb = plain_progress::add(
+        multi,
+        ProgressBar::new(collections.len() as u64),
+        "loaded",
+        "subjects",
+    );
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    attach_wait_observer(client, &pb);
 
-        match args.format {
-            Format::Json => export::write_json(&records, out_dir)?,
-            Format::Csv => export::write_csv(&records, out_dir)?,
-            Format::All => {
-                export::write_json(&records, out_dir)?;
-                export::write_csv(&records, out_dir)?;
+    let mut items = Vec::with_capacity(collections.len());
+    for col in collections {
+        let sid = col.subject_id;
+        pb.set_message(col.subject.name.clone());
+        let fetched = async {
+            let detail = fetch_subject(client, cache, uid, sid, false, None).await?;
+            let (need_episodes, need_progress) = fetch::episode_data_needed(col, &opts);
+            let episodes = if need_episodes {
+                fetch_all_episodes(client, cache, uid, sid, false, None).await?
+            } else {
+                Vec::new()
+            };
+            let progress = if need_progress {
+                fetch_progress(client, cache, uid, sid, None).await?
+            } else {
+                None
+            };
+            Ok::<_, AppError>(build_detail_record(
+                col, &detail, &episodes, &progress, &opts,
+            ))
+        }
+        .await
+        .map_err(|e| AppError::subject(sid, &col.subject.name, e));
+        match fetched {
+            Ok(record) => items.push(BrowseItem::new(col, Some(&record))),
+            Err(e) => {
+                client.set_wait_observer(None);
+                pb.finish_and_clear();
+                multi.remove(&pb);
+                return Err(e);
             }
         }
+        pb.inc(1);
+    }
+    client.set_wait_observer(None);
+    pb.finish_and_clear();
+    multi.remove(&pb);
+    Ok(items)
+}
 
-        println!("Done! Exported {} records.", records.len());
-    } else {
-        let records: Vec<SimpleRecord> = collections.iter().map(build_simple_record).collect();
-
-        print_summary(&records);
-
-        match args.format {
-            Format::Json => export::write_simple_json(&records, out_dir)?,
-            Format::Csv => export::write_simple_csv(&records, out_dir)?,
-            Format::All => {
-                export::write_simple_json(&records, out_dir)?;
-                export::write_simple_csv(&records, out_dir)?;
-            }
+/// Mark the main episodes matching `ranges` (or sorted up to `until`) as watched.
+async fn mark_episodes(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    uid: u64,
+    subject_id: u64,
+    ranges: &[EpisodeRange],
+    until: Option<f64>,
+    dry_run: bool,
+) -> Result<()> {
+    let episodes = fetch_all_episodes(client, cache, uid, subject_id, false, None).await?;
+    let mut selected: Vec<&models::Episode> = episodes
+        .iter()
+        .filter(|e| e.episode_type == 0)
+        .filter(|e| ranges.iter().any(|r| r.contains(e.sort)) || until.is_some_and(|n| e.sort <= n))
+        .collect();
+    selected.sort_by(|a, b| a.sort.total_cmp(&b.sort));
+    for r in ranges {
+        if !selected.iter().any(|e| r.contains(e.sort)) {
+            warn!(
+                "No main episode numbered {}",
+                if r.from == r.to {
+                    next_up::format_sort(r.from)
+                } else {
+                    format!(
+                        "{}-{}",
+                        next_up::format_sort(r.from),
+                        next_up::format_sort(r.to)
+                    )
+                }
+            );
         }
+    }
+    if selected.is_empty() {
+        println!("Nothing to mark");
+        return Ok(());
+    }
 
-        println!("Done! Exported {} records.", records.len());
+    let sorts: Vec<String> = selected
+        .iter()
+        .map(|e| next_up::format_sort(e.sort))
+        .collect();
+    if dry_run {
+        println!("Would mark {} episodes as watched:", selected.len());
+        for e in &selected {
+            println!("  ep {:>5}  id {}", next_up::format_sort(e.sort), e.id);
+        }
+        return Ok(());
+    }
+    let ids: Vec<u64> = selected.iter().map(|e| e.id).collect();
+    client.mark_episodes_watched(subject_id, &ids).await?;
+    // The cached progress and resumable detail records no longer match the server.
+    cache.remove(&format!("{}/progress/{}", uid, subject_id))?;
+    cache.remove_prefix(&format!("{}/done_records", uid))?;
+    println!(
+        "Marked {} episodes as watched: {}",
+        ids.len(),
+        sorts.join(" ")
+    );
+    Ok(())
+}
+
+/// Mark main episodes as watched for the subjects whose collection progress is
+/// ahead of their episode marks, up to the `ep_status`-th main episode, after
+/// asking once. Subjects with more marks than `ep_status` are left alone, since
+/// removing marks would lose data.
+async fn fix_inconsistencies(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    uid: u64,
+    found: &[consistency::Inconsistency],
+) -> Result<()> {
+    let fixable: Vec<&consistency::Inconsistency> =
+        found.iter().filter(|i| i.actual < i.ep_status).collect();
+    let skipped = found.len() - fixable.len();
+    if skipped > 0 {
+        println!(
+            "Skipping {} subjects with more episode marks than their collection progress",
+            skipped
+        );
+    }
+    if fixable.is_empty() {
+        println!("Nothing to fix");
+        return Ok(());
+    }
+    let question = format!(
+        "Mark the missing episodes of {} subjects as watched?",
+        fixable.len()
+    );
+    if !confirm(&question)? {
+        println!("Aborted");
+        return Ok(());
+    }
+    for i in fixable {
+        let episodes = fetch_all_episodes(client, cache, uid, i.subject_id, false, None).await?;
+        let mut main: Vec<f64> = episodes
+            .iter()
+            .filter(|e| e.episode_type == 0)
+            .map(|e| e.sort)
+            .collect();
+        main.sort_by(f64::total_cmp);
+        let index = (i.ep_status.min(main.len() as u64) as usize).checked_sub(1);
+        let Some(&until) = index.and_then(|n| main.get(n)) else {
+            continue;
+        };
+        println!("{}:", i.name);
+        mark_episodes(client, cache, uid, i.subject_id, &[], Some(until), false)
+            .await
+            .map_err(|e| AppError::subject(i.subject_id, &i.name, e))?;
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on the terminal; anything but `y`/`yes` means no.
+fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", question);
+    std::io::Write::flush(&mut std::io::stderr())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Send a collection update, after showing it and asking unless `yes` is set.
+async fn collect(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    uid: u64,
+    subject_id: u64,
+    body: serde_json::Value,
+    yes: bool,
+) -> Result<()> {
+    println!(
+        "POST /v0/users/-/collections/{}\n{}",
+        subject_id,
+        serde_json::to_string_pretty(&body)?
+    );
+    if !yes && !confirm("Send this update?")? {
+        println!("Aborted");
+        return Ok(());
+    }
+    client.update_collection(subject_id, &body).await?;
+    // The entry moves to the top of the list, so every cached page is stale.
+    clear_collection_pages(cache, uid)?;
+    cache.remove_prefix(&format!("{}/done_records", uid))?;
+    println!("Updated collection of subject {}", subject_id);
+    Ok(())
+}
+
+/// Print a breakdown of one subject as the detail pass sees it. With `raw`,
+/// print the API's response bodies instead, each after its URL.
+async fn inspect_subject(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    me: &models::User,
+    subject_id: u64,
+    raw: bool,
+) -> Result<()> {
+    if raw {
+        client.set_raw_observer(Some(Arc::new(|url: &reqwest::Url, body: &[u8]| {
+            println!("# {}", url);
+            println!("{}", String::from_utf8_lossy(body));
+        })));
+        // An empty cache sends every request to the API, so each body is seen.
+        let result = fetch_subject_data(client, &MemoryCache::new(), me, subject_id).await;
+        client.set_raw_observer(None);
+        return result.map(|_| ());
+    }
+    let (detail, episodes, progress, collection) =
+        fetch_subject_data(client, cache, me, subject_id).await?;
+
+    println!("{} / {}", detail.name, detail.name_cn);
+    println!(
+        "  type:           {}",
+        subject_type_name(detail.subject_type)
+    );
+    println!(
+        "  eps / total:    {} / {}",
+        detail.eps, detail.total_episodes
+    );
+
+    let watched = progress
+        .as_ref()
+        .map(UserProgress::watched_ids)
+        .unwrap_or_default();
+    let main: Vec<String> = episodes
+        .iter()
+        .filter(|e| e.episode_type == 0)
+        .map(|e| {
+            let mark = if watched.contains(&e.id) { "✓" } else { "" };
+            format!("{}{}", next_up::format_sort(e.sort), mark)
+        })
+        .collect();
+    println!(
+        "  main episodes:  {} ({} of {} episodes)",
+        main.join(" "),
+        main.len(),
+        episodes.len()
+    );
+
+    match &collection {
+        Some(col) => {
+            let record = build_detail_record(
+                col,
+                &detail,
+                &episodes,
+                &progress,
+                &RecordOptions::default(),
+            );
+            println!(
+                "  status:         {}",
+                collection_status_name(col.collection_type, col.subject.subject_type)
+            );
+            println!(
+                "  completeness:   {} ({})",
+                record.completeness, record.completeness_pct
+            );
+            println!("  watched:        {}", record.watched_eps);
+        }
+        None => println!("  status:         not in my collection"),
+    }
+    Ok(())
+}
+
+/// The detail, episodes, progress and collection of one subject.
+async fn fetch_subject_data(
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+    me: &models::User,
+    subject_id: u64,
+) -> Result<(
+    models::SubjectDetail,
+    Vec<models::Episode>,
+    Option<UserProgress>,
+    Option<Collection>,
+)> {
+    let detail = fetch_subject(client, cache, me.id, subject_id, false, None).await?;
+    let episodes = fetch_all_episodes(client, cache, me.id, subject_id, false, None).await?;
+    let progress = fetch_progress(client, cache, me.id, subject_id, None).await?;
+    let collection = client.get_user_collection(&me.username, subject_id).await?;
+    Ok((detail, episodes, progress, collection))
+}
+
+/// Reject the command line with `message`, recording it in `last_run.json`
+/// before exiting with `EXIT_USAGE`.
+fn usage_error(args: &Args, kind: clap::error::ErrorKind, message: impl Into<String>) -> ! {
+    let message = message.into();
+    if let Err(e) = LastRun::usage(message.clone()).write(Path::new(&args.output)) {
+        eprintln!("Could not write last_run.json: {}", e);
+    }
+    last_run::exit_clap(Args::command().error(kind, message))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = config::parse_args();
+    if matches!(args.format, Format::Ics) && !args.detail {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--format ics needs episode data; add --detail",
+        );
+    }
+    if args.stream && !matches!(args.format, Format::Json | Format::Csv | Format::All) {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::ArgumentConflict,
+            "--stream writes json, csv or all; it cannot be used with this --format",
+        );
+    }
+    if args.stream && args.year.is_some() {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::ArgumentConflict,
+            "--stream cannot be used with --year, which writes a review instead of the export",
+        );
+    }
+    if (args.with_types || args.with_urls) && !matches!(args.format, Format::Txt) {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::ArgumentConflict,
+            "--with-types and --with-urls only apply to --format txt",
+        );
+    }
+    if args.group_franchises == Some(FranchiseGrouping::Relations) && !args.relations {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--group-franchises relations needs relation data; add --detail --relations, or use --group-franchises names",
+        );
+    }
+    // Checked here rather than with `conflicts_with`: --year is global, and clap
+    // rejects a global argument conflicting with one subcommands don't have.
+    let network_only = [
+        ("--no-cache", args.no_cache),
+        ("--revalidate", args.revalidate),
+        ("--incremental", args.incremental),
+        ("--watch", args.watch.is_some()),
+        ("--refresh-subject", !args.refresh_subject.is_empty()),
+        ("--refresh-collections", args.refresh_collections),
+        ("--fix", args.fix),
+        ("--no-me-cache", args.no_me_cache),
+    ];
+    if let Some((flag, _)) = network_only.iter().find(|(_, set)| args.offline && *set) {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::ArgumentConflict,
+            format!("--offline cannot be used with {}", flag),
+        );
+    }
+    if args.reviews && args.command.is_some() {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::ArgumentConflict,
+            "--reviews replaces the export; it cannot be used with a subcommand",
+        );
+    }
+    if args.year.is_some() && args.summary_only {
+        usage_error(
+            &args,
+            clap::error::ErrorKind::ArgumentConflict,
+            "--year cannot be used with --summary-only",
+        );
+    }
+    let multi = MultiProgress::new();
+    let progress = plain_progress::resolve(args.progress, args.quiet);
+    if progress != ProgressMode::Bar {
+        multi.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    plain_progress::set_mode(progress);
+    if let Err(e) = logging::init_logger(
+        args.debug,
+        args.log_file.as_deref(),
+        args.log_format,
+        multi.clone(),
+    ) {
+        eprintln!("Error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    if let Some(path) = &args.log_file
+        && !args.quiet
+    {
+        println!("Logging to {}", path.display());
+    }
+    if let Some(interval) = args.watch {
+        return watch(&args, multi, interval).await;
+    }
+    let out_dir = Path::new(&args.output);
+    match run(&args, multi).await {
+        Ok(()) => {
+            if let Err(e) = last_run::clear(out_dir) {
+                warn!("Could not remove the previous last_run.json: {}", e);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            if let Err(e) = LastRun::from_error(&e, args.detail).write(out_dir) {
+                eprintln!("Could not write last_run.json: {}", e);
+            }
+            ExitCode::from(FailureKind::of(&e).exit_code())
+        }
+    }
+}
+
+/// Resolve on Ctrl+C, or on SIGTERM where available.
+async fn stop_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Run a sync every `interval` until stopped. Failed cycles are logged and retried
+/// on the next one; a stop signal ends the loop at once, even mid-cycle, which is
+/// safe since exports are replaced atomically and detail progress is saved per item.
+async fn watch(args: &Args, multi: MultiProgress, interval: Duration) -> ExitCode {
+    let stop = stop_signal();
+    tokio::pin!(stop);
+    loop {
+        if !args.quiet {
+            println!(
+                "\n[{}] Syncing...",
+                Local::now().format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+        tokio::select! {
+            result = run(args, multi.clone()) => {
+                if let Err(e) = result {
+                    error!("Sync failed, retrying in {}: {}", HumanDuration(interval), e);
+                }
+            }
+            _ = &mut stop => break,
+        }
+        if !args.quiet {
+            println!("Next sync in {}", HumanDuration(interval));
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = &mut stop => break,
+        }
+    }
+    if !args.quiet {
+        println!("Stopping watch.");
+    }
+    ExitCode::SUCCESS
+}
+
+/// Print the year review (unless quiet) and write its JSON and Markdown files.
+fn report_review(review: &YearReview, out_dir: &Path, quiet: bool) -> Result<()> {
+    if !quiet {
+        println!("\n{}", review.to_markdown());
+    }
+    report_written(&review.write(out_dir)?, quiet);
+    Ok(())
+}
+
+/// Steps that run on the compact JSON after a successful export: the snapshot
+/// copy, the webhook and the comparison against a previous export.
+async fn finish_export(
+    compact: Vec<serde_json::Value>,
+    against: Option<Vec<serde_json::Value>>,
+    args: &Args,
+    username: &str,
+    out_dir: &Path,
+) -> Result<()> {
+    if args.snapshot {
+        let path = snapshot::write_snapshot(out_dir, &compact, args.snapshot_keep)?;
+        report_written(&[path], args.quiet);
+    }
+    if let Some(url) = &args.webhook {
+        // The local export already succeeded; a failed delivery must not undo that.
+        match webhook::post_export(
+            url,
+            args.webhook_secret.as_deref(),
+            &user_agent(args.user_agent.as_deref()),
+            username,
+            args.timezone,
+            args.anonymize,
+            &compact,
+        )
+        .await
+        {
+            Ok(status) if status.is_success() => {
+                if !args.quiet {
+                    println!("Webhook: {}", status);
+                }
+            }
+            Ok(status) => error!("Webhook delivery to {} failed: HTTP {}", url, status),
+            Err(e) => error!("Webhook delivery to {} failed: {}", url, e),
+        }
+    }
+    if let Some(old) = against {
+        report_diff(old, compact, out_dir, args.quiet)?;
+    }
+    Ok(())
+}
+
+/// Print the changelog between two exports (unless quiet) and write diff.json.
+fn report_diff(
+    old: Vec<serde_json::Value>,
+    new: Vec<serde_json::Value>,
+    out_dir: &Path,
+    quiet: bool,
+) -> Result<()> {
+    let diff = diff::diff_exports(old, new)?;
+    if !quiet {
+        diff.print();
+    }
+    std::fs::create_dir_all(out_dir)?;
+    report_written(&[diff.write_json(out_dir)?], quiet);
+    Ok(())
+}
+
+/// The exporters writing `--format` in `--lang`. `Format::Ics` has none: it is
+/// built from episode data rather than from records.
+fn exporters<R: Exportable>(args: &Args) -> Vec<Box<dyn Exporter<R>>> {
+    let lang = args.lang.into();
+    let json = || Box::new(JsonExporter { lang });
+    // Exported CSV files start with a BOM so Excel opens them as UTF-8.
+    let csv = || Box::new(CsvExporter { bom: true, lang });
+    // With --stream, the NDJSON streamed during the run is written in full too.
+    let ndjson = || Box::new(NdjsonExporter { lang });
+    match args.format {
+        Format::Json if args.stream => vec![json(), ndjson()],
+        Format::All if args.stream => vec![json(), ndjson(), csv()],
+        Format::Json => vec![json()],
+        Format::Csv => vec![csv()],
+        Format::All => vec![json(), csv()],
+        Format::Ics => Vec::new(),
+        Format::Txt => vec![Box::new(TxtExporter {
+            lang,
+            wish_only: args.status.is_empty(),
+            with_types: args.with_types,
+            with_urls: args.with_urls,
+        })],
+    }
+}
+
+/// Write the records to `bangumi_export.<extension>` in `dir` with each
+/// exporter of `--format`, returning the files written. The plain-text list
+/// goes to `watchlist.txt` instead.
+fn write_exports<R: Exportable>(args: &Args, records: &[R], dir: &Path) -> Result<Vec<PathBuf>> {
+    exporters(args)
+        .iter()
+        .map(|exporter| {
+            let path = match args.format {
+                Format::Txt => dir.join("watchlist.txt"),
+                _ => dir.join(format!("bangumi_export.{}", exporter.extension())),
+            };
+            export::write_atomic(&path, |w| exporter.write(records, w))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Print the files written by an export step.
+fn report_written(paths: &[PathBuf], quiet: bool) {
+    if quiet {
+        return;
+    }
+    for path in paths {
+        println!("{} {}", written_label(path), path.display());
+    }
+}
+
+/// How a written file is announced: the export files by format, as they
+/// always have been, everything else by the plain fact.
+fn written_label(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    match name.strip_prefix("bangumi_export.") {
+        Some(extension) if !extension.contains('.') => {
+            format!("{} exported to", extension.to_uppercase())
+        }
+        _ if name == "failed.json" => "Failed subjects written to".to_string(),
+        _ => "Wrote".to_string(),
+    }
+}
+
+/// An API client sending `token`, with the connection settings from the command line.
+fn api_client(args: &Args, token: String) -> Result<BangumiClient> {
+    BangumiClient::builder()
+        .token(Some(token))
+        .base_url(&args.base_url)
+        .user_agent(&user_agent(args.user_agent.as_deref()))
+        .max_retries(args.max_retries)
+        .retry_backoff(args.retry_backoff)
+        .slow_request(args.slow_request)
+        .build()
+}
+
+/// The `User-Agent` for `--user-agent`: the default without one, the default
+/// followed by the rest with a leading `+`, otherwise the value itself.
+fn user_agent(custom: Option<&str>) -> String {
+    match custom {
+        None => client::default_user_agent(),
+        Some(custom) => match custom.strip_prefix('+') {
+            Some(extra) => format!("{} {}", client::default_user_agent(), extra.trim()),
+            None => custom.to_string(),
+        },
+    }
+}
+
+/// Run a `profile` subcommand.
+async fn profile_command(args: &Args, action: &ProfileCommand) -> Result<()> {
+    match action {
+        ProfileCommand::Add { name } => {
+            profile::validate_name(name)?;
+            eprint!("Access token for profile {}: ", name);
+            std::io::Write::flush(&mut std::io::stderr())?;
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            let token = clean_token(&input).ok_or_else(|| AppError::NoToken {
+                checked: vec!["the prompt".to_string()],
+            })?;
+            let me = api_client(args, token.clone())?.get_me().await?;
+            let path = profile::save(
+                name,
+                &profile::Profile {
+                    token,
+                    username: Some(me.username.clone()),
+                    uid: Some(me.id),
+                },
+            )?;
+            println!(
+                "Saved profile {} for {} ({}) to {}",
+                name,
+                me.nickname,
+                me.username,
+                path.display()
+            );
+        }
+        ProfileCommand::List => {
+            let names = profile::names()?;
+            if names.is_empty() {
+                println!("No profiles; add one with `bangumi-tool profile add <name>`");
+            }
+            for name in names {
+                let mut saved = profile::load(&name)?;
+                // Profiles written by hand may not know their account yet.
+                if saved.username.is_none() && !args.offline {
+                    match api_client(args, saved.token.clone())?.get_me().await {
+                        Ok(me) => {
+                            profile::remember_user(&name, &me)?;
+                            saved.username = Some(me.username);
+                            saved.uid = Some(me.id);
+                        }
+                        Err(e) => warn!("Could not look up the account of profile {}: {}", name, e),
+                    }
+                }
+                match (saved.username, saved.uid) {
+                    (Some(username), Some(uid)) => println!("{}  {} (uid {})", name, username, uid),
+                    _ => println!("{}  (unknown account)", name),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hint printed when the token is rejected part-way through the detail pass.
+const RESUME_HINT: &str =
+    "Records fetched so far are kept in the cache; re-run after fixing the token to resume.";
+
+async fn run(args: &Args, multi: MultiProgress) -> Result<()> {
+    let started = Instant::now();
+    if let Some(Command::Diff { old, new }) = &args.command {
+        let out_dir = Path::new(&args.output);
+        return report_diff(
+            diff::load_export(old)?,
+            diff::load_export(new)?,
+            out_dir,
+            args.quiet,
+        );
+    }
+    if let Some(Command::History { from, to }) = &args.command {
+        let out_dir = Path::new(&args.output);
+        if let (Some(from), Some(to)) = (from, to) {
+            let diff = diff::diff_exports(
+                snapshot::load_snapshot(out_dir, from)?,
+                snapshot::load_snapshot(out_dir, to)?,
+            )?;
+            diff.print();
+        } else {
+            snapshot::print_history(&snapshot::load_index(out_dir)?);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Cache { action }) = &args.command {
+        let cache_dir = cache_dir(args);
+        match action {
+            CacheCommand::Stats { json } => {
+                let usage = cache_report::scan(&cache_dir)?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&usage)?);
+                } else {
+                    cache_report::print_usage(&usage, &cache_dir);
+                }
+                return Ok(());
+            }
+            CacheCommand::Compact => {
+                let (count, before, after) = Cache::new(&cache_dir)?.compact()?;
+                println!(
+                    "Compressed {} entries: {} -> {}",
+                    count,
+                    HumanBytes(before),
+                    HumanBytes(after)
+                );
+                return Ok(());
+            }
+            CacheCommand::Export { file, uid } => {
+                let count = cache_bundle::export(&cache_dir, *uid, file, &multi)?;
+                println!("Packed {} files into {}", count, file.display());
+                return Ok(());
+            }
+            CacheCommand::Import { file, force } => {
+                std::fs::create_dir_all(&cache_dir)?;
+                let report = cache_bundle::import(&cache_dir, file, *force, &multi)?;
+                println!(
+                    "Imported {} files, kept {} newer local entries, skipped {} invalid",
+                    report.imported, report.kept_newer, report.invalid
+                );
+                return Ok(());
+            }
+            // Needs the current collection list, handled after login.
+            CacheCommand::Gc { .. } => {}
+        }
+    }
+    if let Some(Command::Config {
+        action: ConfigCommand::Init { local, force },
+    }) = &args.command
+    {
+        let path = if *local {
+            config::local_path()
+        } else {
+            config::global_path()
+                .ok_or_else(|| std::io::Error::other("no config directory; use --local instead"))?
+        };
+        config::init(&path, *force)?;
+        println!("Wrote {}", path.display());
+        return Ok(());
+    }
+    if let Some(Command::Profile { action }) = &args.command {
+        return profile_command(args, action).await;
+    }
+    if let Some(Command::Auth {
+        client_id,
+        client_secret,
+        port,
+    }) = &args.command
+    {
+        let path = oauth::login(client_id, &client_secret.0, *port).await?;
+        println!("Logged in; tokens saved to {}", path.display());
+        return Ok(());
+    }
+    let token = if args.offline {
+        String::new()
+    } else {
+        load_token(args).await?
+    };
+    let token_expires = if args.detail && args.command.is_none() && !args.offline {
+        token_expiry(&token, args.quiet).await
+    } else {
+        None
+    };
+    let client = api_client(args, token)?.with_offline(args.offline);
+    if args.no_cache {
+        info!("Not using the on-disk cache; responses are kept in memory for this run");
+        return run_with_cache(
+            args,
+            multi,
+            started,
+            client,
+            token_expires,
+            &MemoryCache::new(),
+        )
+        .await;
+    }
+
+    let cache_dir = cache_dir(args);
+    info!("Using cache directory {}", cache_dir.display());
+    // Offline, whatever is cached is the best data there is, however old.
+    let ttl = if args.offline {
+        CacheTtl {
+            collections: Duration::MAX,
+            progress: Duration::MAX,
+            subjects: Duration::MAX,
+            me: Duration::MAX,
+        }
+    } else {
+        CacheTtl {
+            collections: args.collections_ttl,
+            progress: args.progress_ttl,
+            subjects: args.subjects_ttl,
+            ..CacheTtl::default()
+        }
+    };
+    let cache = Cache::new(&cache_dir)?
+        .with_ttl(ttl)
+        .with_compression(!args.no_cache_compression)
+        .with_format(args.cache_format.into());
+    run_with_cache(args, multi, started, client, token_expires, &cache).await
+}
+
+/// Log in, then run the command or the export, keeping API responses in `cache`.
+/// A run rejected with 401 drops the cached `/v0/me` response, so the next
+/// run asks the API who the token belongs to.
+async fn run_with_cache(
+    args: &Args,
+    multi: MultiProgress,
+    started: Instant,
+    client: BangumiClient,
+    token_expires: Option<DateTime<Utc>>,
+    cache: &impl CacheStore,
+) -> Result<()> {
+    let me_key = me_cache_key(&client);
+    let result = run_logged_in(args, multi, started, client, token_expires, cache).await;
+    if let Err(e) = &result
+        && matches!(e.root(), AppError::Unauthorized { .. })
+        && let Some(key) = me_key
+        && let Err(e) = cache.remove(&key)
+    {
+        warn!("Could not drop the cached user profile: {}", e);
+    }
+    result
+}
+
+/// Cache key of the `/v0/me` response for the client's token.
+fn me_cache_key(client: &BangumiClient) -> Option<String> {
+    client
+        .token_fingerprint()
+        .map(|hash| format!("me_{}", hash))
+}
+
+/// The user the token belongs to: the cached `/v0/me` response while it is
+/// fresh (24 hours), otherwise fetched and cached. `--no-me-cache` always
+/// fetches, e.g. after switching accounts, and so does `whoami`, whose answer
+/// then refreshes the cached one.
+async fn current_user(
+    args: &Args,
+    client: &BangumiClient,
+    cache: &impl CacheStore,
+) -> Result<models::User> {
+    let key = me_cache_key(client);
+    let whoami = matches!(args.command, Some(Command::Whoami { .. }));
+    if !args.no_me_cache
+        && !whoami
+        && let Some(me) = key.as_deref().and_then(|k| cache.get::<models::User>(k))
+    {
+        debug!("Using the cached profile of {}", me.username);
+        return Ok(me);
+    }
+    let me = client.get_me().await?;
+    if let Some(key) = &key {
+        cache.set(key, &me)?;
+    }
+    if let Some(name) = &args.profile {
+        profile::remember_user(name, &me)?;
+    }
+    Ok(me)
+}
+
+/// `run_with_cache` once the client is set up.
+async fn run_logged_in(
+    args: &Args,
+    multi: MultiProgress,
+    started: Instant,
+    client: BangumiClient,
+    token_expires: Option<DateTime<Utc>>,
+    cache: &impl CacheStore,
+) -> Result<()> {
+    let archive = if args.archive {
+        let archive = Arc::new(archive::Archive::new(Path::new(&args.output))?);
+        client.set_raw_observer(Some(archive.observer()));
+        Some(archive)
+    } else {
+        None
+    };
+
+    // Read the old export before this run can overwrite it.
+    let against = args.against.as_deref().map(diff::load_export).transpose()?;
+
+    let me = if args.offline {
+        cache
+            .get::<models::User>(IDENTITY_KEY)
+            .ok_or_else(|| offline_missing(&[IDENTITY_KEY.to_string()]))?
+    } else {
+        let me = current_user(args, &client, cache).await?;
+        cache.set(IDENTITY_KEY, &me)?;
+        me
+    };
+    if !args.refresh_subject.is_empty() {
+        refresh_subjects(cache, me.id, &args.refresh_subject)?;
+    }
+    if args.refresh_collections {
+        clear_collection_pages(cache, me.id)?;
+        info!("Collection pages cleared");
+    }
+    if let Some(Command::Search {
+        keywords,
+        limit,
+        json,
+    }) = &args.command
+    {
+        let keyword = keywords.join(" ");
+        let types: Vec<u8> = args.types.iter().map(|t| t.id()).collect();
+        let results = client.search_subjects(&keyword, &types, *limit).await?;
+        let collections = cached_collections(cache, me.id, args.page_size);
+        let rows = search::rows(&results.data, collections.as_deref());
+        if *json {
+            println!("{}", serde_json::to_string_pretty(&rows)?);
+        } else {
+            search::print_results(&rows, &keyword, results.total);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Mark {
+        subject,
+        eps,
+        watched_until,
+        dry_run,
+    }) = &args.command
+    {
+        return mark_episodes(
+            &client,
+            cache,
+            me.id,
+            *subject,
+            eps,
+            *watched_until,
+            *dry_run,
+        )
+        .await;
+    }
+    if let Some(Command::Collect {
+        subject,
+        status,
+        rate,
+        comment,
+        tags,
+        private,
+        yes,
+    }) = &args.command
+    {
+        let mut body = serde_json::Map::new();
+        if let Some(status) = status {
+            body.insert("type".into(), status.id().into());
+        }
+        if let Some(rate) = rate {
+            body.insert("rate".into(), (*rate).into());
+        }
+        if let Some(comment) = comment {
+            body.insert("comment".into(), comment.as_str().into());
+        }
+        if let Some(tags) = tags {
+            body.insert("tags".into(), tags.clone().into());
+        }
+        if *private {
+            body.insert("private".into(), true.into());
+        }
+        return collect(&client, cache, me.id, *subject, body.into(), *yes).await;
+    }
+    if let Some(Command::Import { file, dry_run }) = &args.command {
+        let (entries, invalid) = import::load_entries(file)?;
+        // Compare against the account as it is now, not a cached copy.
+        clear_collection_pages(cache, me.id)?;
+        let existing =
+            fetch_collections(&client, cache, me.id, &me.username, args.page_size, &multi).await?;
+        let pb = plain_progress::add(
+            &multi,
+            ProgressBar::new(entries.len() as u64),
+            "imported",
+            "entries",
+        );
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        attach_wait_observer(&client, &pb);
+        let report = import::import(
+            &client,
+            cache,
+            me.id,
+            &entries,
+            &existing,
+            *dry_run,
+            |name| {
+                pb.set_message(name.to_string());
+                pb.inc(1);
+            },
+        )
+        .await;
+        client.set_wait_observer(None);
+        pb.finish_and_clear();
+        multi.remove(&pb);
+        let mut report = report?;
+        if !*dry_run && !report.imported.is_empty() {
+            clear_collection_pages(cache, me.id)?;
+            cache.remove_prefix(&format!("{}/done_records", me.id))?;
+        }
+        report.failed.splice(0..0, invalid);
+        report.print(*dry_run);
+        return Ok(());
+    }
+    if let Some(Command::Sync {
+        from_user,
+        prefer_source,
+        dry_run,
+        yes,
+    }) = &args.command
+    {
+        let source_user = client.get_user(from_user).await?;
+        // Both sides are compared as they are now, not as cached.
+        clear_collection_pages(cache, source_user.id)?;
+        let mut source = fetch_collections(
+            &client,
+            cache,
+            source_user.id,
+            &source_user.username,
+            args.page_size,
+            &multi,
+        )
+        .await?;
+        let filter = CollectionFilter::from_args(args);
+        source.retain(|c| filter.matches(c));
+        clear_collection_pages(cache, me.id)?;
+        let target =
+            fetch_collections(&client, cache, me.id, &me.username, args.page_size, &multi).await?;
+
+        let plan = account_sync::plan(&source, &target, *prefer_source);
+        plan.print(&source_user.username);
+        if *dry_run || plan.writes.is_empty() {
+            return Ok(());
+        }
+        let question = format!(
+            "Write {} entries to {}'s collection?",
+            plan.writes.len(),
+            me.username
+        );
+        if !yes && !confirm(&question)? {
+            println!("Aborted");
+            return Ok(());
+        }
+        let pb = plain_progress::add(
+            &multi,
+            ProgressBar::new(plan.writes.len() as u64),
+            "applied",
+            "changes",
+        );
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        attach_wait_observer(&client, &pb);
+        let report = account_sync::apply(
+            &client,
+            cache,
+            me.id,
+            &source_user.username,
+            &plan,
+            |name| {
+                pb.set_message(name.to_string());
+                pb.inc(1);
+            },
+        )
+        .await;
+        client.set_wait_observer(None);
+        pb.finish_and_clear();
+        multi.remove(&pb);
+        let report = report?;
+        if report.written > 0 {
+            clear_collection_pages(cache, me.id)?;
+            cache.remove_prefix(&format!("{}/done_records", me.id))?;
+        }
+        report.print();
+        return Ok(());
+    }
+    if let Some(Command::Indexes { ids }) = &args.command {
+        let out_dir = Path::new(&args.output);
+        for &id in ids {
+            let cache_key = format!("{}/indexes/{}", me.id, id);
+            let index: models::Index = match cache.get(&cache_key) {
+                Some(index) => index,
+                None => {
+                    let index = client.get_index(id).await?;
+                    cache.set(&cache_key, &index)?;
+                    index
+                }
+            };
+            if index.creator.username != me.username {
+                warn!(
+                    "Index {} ({}) was created by {}, not {}",
+                    id, index.title, index.creator.username, me.username
+                );
+            }
+            let subjects = fetch_paged(
+                &client,
+                cache,
+                &multi,
+                &format!("{}/subjects", cache_key),
+                "subjects",
+                |limit, offset| client.get_index_subjects(id, limit, offset),
+            )
+            .await?;
+            report_written(
+                &[indexes::write_index(&index, &subjects, out_dir)?],
+                args.quiet,
+            );
+        }
+        return Ok(());
+    }
+    if let Some(Command::Cache {
+        action: CacheCommand::Gc { dry_run },
+    }) = &args.command
+    {
+        return gc_cache(&client, cache, &multi, &me, args.page_size, *dry_run).await;
+    }
+    if let Some(Command::Progress { subject }) = &args.command {
+        let detail = fetch_subject(&client, cache, me.id, *subject, args.revalidate, None).await?;
+        let episodes =
+            fetch_all_episodes(&client, cache, me.id, *subject, args.revalidate, None).await?;
+        let progress = fetch_progress(&client, cache, me.id, *subject, None).await?;
+        progress::print_progress(&detail, &episodes, &progress);
+        return Ok(());
+    }
+    if let Some(Command::Subject { id, raw }) = &args.command {
+        return inspect_subject(&client, cache, &me, *id, *raw).await;
+    }
+    if let Some(Command::Whoami { json }) = &args.command {
+        if *json {
+            println!("{}", serde_json::to_string_pretty(&me)?);
+        } else {
+            println!("{} ({})", me.nickname, me.username);
+            println!("  uid:    {}", me.id);
+            println!("  group:  {}", user_group_name(me.user_group));
+            if !me.sign.is_empty() {
+                println!("  sign:   {}", me.sign);
+            }
+            if !me.avatar.large.is_empty() {
+                println!("  avatar: {}", me.avatar.large);
+            }
+        }
+        return Ok(());
+    }
+    if !args.quiet {
+        println!("Logged in as {} ({})", me.nickname, me.username);
+    }
+
+    let (mut collections, changed) = if args.offline {
+        (
+            offline_collections(cache, me.id, args.page_size)?,
+            HashSet::new(),
+        )
+    } else {
+        sync_collections(
+            &client,
+            cache,
+            me.id,
+            &me.username,
+            args.page_size,
+            &multi,
+            args.incremental || args.watch.is_some(),
+        )
+        .await?
+    };
+    if args.watch.is_some() {
+        if !args.quiet {
+            println!(
+                "Synced {} collections, {} changed since the last sync",
+                collections.len(),
+                changed.len()
+            );
+        }
+        // Cycles run for weeks; drop pages no cycle reads again so the cache
+        // doesn't keep growing.
+        let pruned = prune_stale_pages(cache, me.id, collections.len() as u64, args.page_size)?;
+        if pruned > 0 {
+            debug!("Pruned {} stale collection pages", pruned);
+        }
+    }
+    let filter = CollectionFilter::from_args(args);
+    collections.retain(|c| filter.matches(c));
+
+    let out_dir = Path::new(&args.output);
+    if let Some(Command::Stats { json }) = &args.command {
+        let report = RatingReport::new(&collections);
+        report.print();
+        if *json {
+            std::fs::create_dir_all(out_dir)?;
+            report_written(&[report.write_json(out_dir)?], args.quiet);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Stale { days, csv }) = &args.command {
+        let entries = stale::find_stale(&collections, *days, chrono::Utc::now());
+        stale::print_stale(&entries, *days);
+        if *csv {
+            std::fs::create_dir_all(out_dir)?;
+            report_written(&[stale::write_csv(&entries, out_dir)?], args.quiet);
+        }
+        return Ok(());
+    }
+    if let Some(Command::Missing { comments }) = &args.command {
+        let entries = missing::find_missing(&collections, *comments);
+        missing::print_missing(&entries);
+        std::fs::create_dir_all(out_dir)?;
+        report_written(&[missing::write_csv(&entries, out_dir)?], args.quiet);
+        return Ok(());
+    }
+    if args.reviews {
+        let reviews = comment_reviews::find_reviews(&collections);
+        if !args.quiet {
+            comment_reviews::print_reviews(&reviews);
+        }
+        std::fs::create_dir_all(out_dir)?;
+        report_written(
+            &comment_reviews::write_reviews(&reviews, out_dir, args.reviews_json)?,
+            args.quiet,
+        );
+        return Ok(());
+    }
+    if let Some(Command::NextUp) = &args.command {
+        let items: Vec<NextUp> = fetch_watching(&client, cache, &multi, me.id, &collections, false)
+            .await?
+            .iter()
+            .map(|w| NextUp::new(w.col, &w.episodes, &w.progress))
+            .collect();
+        next_up::print_next_up(&items);
+        std::fs::create_dir_all(out_dir)?;
+        report_written(&[next_up::write_json(&items, out_dir)?], args.quiet);
+        return Ok(());
+    }
+    if let Some(Command::Compare { username }) = &args.command {
+        let other = client.get_user(username).await?;
+        let mut theirs = fetch_collections(
+            &client,
+            cache,
+            other.id,
+            &other.username,
+            args.page_size,
+            &multi,
+        )
+        .await?;
+        theirs.retain(|c| filter.matches(c));
+        let comparison = compare::Comparison::new(&other.username, &collections, &theirs);
+        comparison.print();
+        std::fs::create_dir_all(out_dir)?;
+        report_written(&[comparison.write_csv(out_dir)?], args.quiet);
+        return Ok(());
+    }
+    if let Some(Command::Calendar { json }) = &args.command {
+        let days = client.get_calendar().await?;
+        let merged = calendar::merge(&days, &collections, cache, me.id);
+        calendar::print_calendar(&merged, summary::use_color(args.color));
+        if *json {
+            std::fs::create_dir_all(out_dir)?;
+            report_written(&[calendar::write_json(&merged, out_dir)?], args.quiet);
+        }
+        return Ok(());
+    }
+    if let Some(Command::NeodbSync {
+        neodb_token,
+        neodb_instance,
+        dry_run,
+    }) = &args.command
+    {
+        let neodb = neodb::NeoDbClient::new(neodb_token.clone(), neodb_instance)?;
+        let pb = plain_progress::add(
+            &multi,
+            ProgressBar::new(collections.len() as u64),
+            "synced",
+            "items",
+        );
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        let report = neodb::sync(&neodb, &collections, *dry_run, |name| {
+            pb.set_message(name.to_string());
+            pb.inc(1);
+        })
+        .await;
+        pb.finish_and_clear();
+        multi.remove(&pb);
+        let report = report?;
+        report.print(*dry_run);
+        std::fs::create_dir_all(out_dir)?;
+        report_written(&[report.write_unmatched(out_dir)?], args.quiet);
+        return Ok(());
+    }
+    if let Some(Command::Notion {
+        notion_token,
+        notion_database_id,
+        dry_run,
+    }) = &args.command
+    {
+        let notion = notion::NotionClient::new(notion_token.clone(), notion_database_id.clone());
+        let pb = plain_progress::add(
+            &multi,
+            ProgressBar::new(collections.len() as u64),
+            "synced",
+            "items",
+        );
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{bar:30.cyan/dim}] {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+        let report = notion::sync(&notion, &collections, *dry_run, |name| {
+            pb.set_message(name.to_string());
+            pb.inc(1);
+        })
+        .await;
+        pb.finish_and_clear();
+        multi.remove(&pb);
+        report?.print(*dry_run);
+        return Ok(());
+    }
+    if let Some(Command::Browse) = &args.command {
+        let fetch = args.detail && !args.offline;
+        let items = load_browse_items(&client, cache, &multi, me.id, &collections, fetch).await?;
+        tokio::task::block_in_place(|| browse::browse(items))?;
+        return Ok(());
+    }
+    if let Some(Command::Tags { top }) = &args.command {
+        let report = TagReport::new(&collections);
+        report.print(*top);
+        std::fs::create_dir_all(out_dir)?;
+        report_written(&[report.write_json(out_dir)?], args.quiet);
+        return Ok(());
+    }
+
+    if let Some(months) = args.activity {
+        let report = ActivityReport::new(&collections, months, Local::now());
+        report.print(summary::use_color(args.color));
+        std::fs::create_dir_all(out_dir)?;
+        report_written(&[report.write_json(out_dir)?], args.quiet);
+        return Ok(());
+    }
+
+    if !args.summary_only {
+        std::fs::create_dir_all(out_dir)?;
+    }
+
+    let summary_opts = summary::SummaryOptions {
+        color: summary::use_color(args.color),
+        sort: args.summary_sort,
+        lang: args.lang.into(),
+        group_franchises: args.group_franchises,
+    };
+    let mut failed = Vec::new();
+    let exported = if args.detail {
+        let opts = DetailOptions {
+            revalidate: args.revalidate,
+            offline: args.offline,
+            skip_errors: args.skip_errors,
+            shutdown: install_shutdown_handler(),
+            done_key: if filter.is_empty() {
+                format!("{}/done_records", me.id)
+            } else {
+                format!("{}/done_records_{}", me.id, filter.tag())
+            },
+            changed,
+            record: RecordOptions {
+                timestamps: TimestampFormat::new(args.timezone, &args.date_format)?,
+                site_tags_limit: args.site_tags_limit,
+                include_summary: args.include_summary,
+                infobox_fields: args
+                    .infobox_fields
+                    .iter()
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect(),
+                relations: args.relations,
+                episode_titles: args.episode_titles,
+                music_tracks: args.music_tracks,
+            },
+        };
+        if args.offline {
+            check_offline_detail(cache, me.id, &collections, &opts.record)?;
+        }
+        if let Some(expires) = token_expires {
+            let requests = fetch::pending_requests(cache, me.id, &collections, &opts);
+            let estimated = client.request_interval() * requests as u32;
+            check_token_lifetime(expires, estimated, args.strict_token)?;
+        }
+        let (mut records, detail_failed) =
+            fetch_detail_records(&client, cache, &multi, me.id, &collections, &opts, args)
+                .await
+                .inspect_err(|e| {
+                    if matches!(e.root(), AppError::Unauthorized { .. }) {
+                        eprintln!("{}", RESUME_HINT);
+                    }
+                })?;
+        failed = detail_failed;
+        if args.check {
+            let found = consistency::find_inconsistencies(&collections, &records);
+            consistency::print_inconsistencies(&found);
+            std::fs::create_dir_all(out_dir)?;
+            report_written(&[consistency::write_csv(&found, out_dir)?], args.quiet);
+            if !failed.is_empty() {
+                warn!(
+                    "{} subjects could not be fetched and were not checked",
+                    failed.len()
+                );
+            }
+            if args.fix {
+                fix_inconsistencies(&client, cache, me.id, &found).await?;
+            }
+            return Ok(());
+        }
+        if args.anonymize {
+            records::anonymize(&mut records, &collections, args.timezone);
+        }
+        if let Some(key) = args.sort_by {
+            sort::sort_records(&mut records, &collections, key, args.reverse);
+        }
+
+        if let Some(year) = args.year {
+            let review = YearReview::new(year, &collections, Some(&records));
+            report_review(&review, out_dir, args.quiet)?;
+        } else if !args.quiet {
+            summary::print_summary(&records, &summary_opts);
+        }
+        if args.rating_diff {
+            RatingDiffReport::new(&records).print();
+        }
+
+        if !args.summary_only && args.year.is_none() {
+            let written = if matches!(args.format, Format::Ics) {
+                let watching =
+                    fetch_watching(&client, cache, &multi, me.id, &collections, !args.offline)
+                        .await?;
+                vec![ics::write_ics(
+                    watching
+                        .iter()
+                        .map(|w| (w.col, w.episodes.as_slice(), &w.progress)),
+                    args.ics_past_days,
+                    out_dir,
+                )?]
+            } else {
+                let written = write_exports(args, &records, out_dir)?;
+                if args.stream {
+                    stream::remove_partials(out_dir)?;
+                }
+                written
+            };
+            report_written(&written, args.quiet);
+            finish_export(
+                export::compact_json(&records, args.lang.into()),
+                against,
+                args,
+                &me.username,
+                out_dir,
+            )
+            .await?;
+        }
+        records.len()
+    } else {
+        if let Some(year) = args.year {
+            let review = YearReview::new(year, &collections, None);
+            return report_review(&review, out_dir, args.quiet);
+        }
+        let timestamps = TimestampFormat::new(args.timezone, &args.date_format)?;
+        let mut records: Vec<SimpleRecord> = collections
+            .iter()
+            .map(|c| build_simple_record(c, &timestamps))
+            .collect();
+        if args.anonymize {
+            records::anonymize(&mut records, &collections, args.timezone);
+        }
+        if let Some(key) = args.sort_by {
+            sort::sort_records(&mut records, &collections, key, args.reverse);
+        }
+
+        if !args.quiet {
+            summary::print_summary(&records, &summary_opts);
+        }
+
+        if !args.summary_only {
+            let written = write_exports(args, &records, out_dir)?;
+            report_written(&written, args.quiet);
+            finish_export(
+                export::compact_simple_json(&records, args.lang.into()),
+                against,
+                args,
+                &me.username,
+                out_dir,
+            )
+            .await?;
+        }
+        records.len()
+    };
+
+    if args.summary_only {
+        return Ok(());
+    }
+
+    let mut also_exported = Vec::new();
+    if args.characters {
+        let characters = fetch_paged(
+            &client,
+            cache,
+            &multi,
+            &format!("{}/characters", me.id),
+            "characters",
+            |limit, offset| client.get_character_collections(&me.username, limit, offset),
+        )
+        .await?;
+        let records: Vec<characters::CharacterRecord> = characters
+            .iter()
+            .map(characters::CharacterRecord::new)
+            .collect();
+        std::fs::create_dir_all(out_dir)?;
+        report_written(
+            &characters::write(&records, &args.format, out_dir)?,
+            args.quiet,
+        );
+        also_exported.push(format!("{} characters", records.len()));
+    }
+    if args.persons {
+        let persons = fetch_paged(
+            &client,
+            cache,
+            &multi,
+            &format!("{}/persons", me.id),
+            "persons",
+            |limit, offset| client.get_person_collections(&me.username, limit, offset),
+        )
+        .await?;
+        let records: Vec<persons::PersonRecord> =
+            persons.iter().map(persons::PersonRecord::new).collect();
+        std::fs::create_dir_all(out_dir)?;
+        report_written(
+            &persons::write(&records, &args.format, out_dir)?,
+            args.quiet,
+        );
+        also_exported.push(format!("{} persons", records.len()));
+    }
+
+    if let Some(archive) = &archive {
+        report_written(&[archive.write_index()?], args.quiet);
+    }
+
+    // In year-review mode the review files are the only output.
+    if args.year.is_none() {
+        let also = also_exported
+            .iter()
+            .map(|s| format!(", {}", s))
+            .collect::<String>();
+        if args.quiet {
+            println!(
+                "exported {} records{} to {}",
+                exported,
+                also,
+                out_dir.display()
+            );
+        } else {
+            println!("Done! Exported {} records{}.", exported, also);
+        }
+    }
+
+    let stats = RunStats::new(started.elapsed(), exported, client.stats(), cache.stats());
+    if !args.quiet {
+        stats.print();
+    }
+    if args.debug {
+        let path = stats.write_json(out_dir)?;
+        report_written(&[path], args.quiet);
+    }
+
+    if !failed.is_empty() {
+        if !args.quiet {
+            println!("\nFailed to fetch {} subjects:", failed.len());
+            for f in &failed {
+                println!("  {} {}: {}", f.id, f.name, f.error);
+            }
+        }
+        let path = export::write_failed(&failed, out_dir)?;
+        report_written(&[path], args.quiet);
+        return Err(AppError::PartialFailure {
+            failed: failed.len(),
+            completed: exported,
+        });
     }
 
     Ok(())
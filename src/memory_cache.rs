@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::{debug, warn};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::cache::{CacheStats, CacheStore};
+use crate::error::Result;
+
+/// Cache kept in memory for the length of a run, for `--no-cache`: nothing is
+/// read from or written to disk, but a response fetched twice in one run is
+/// still only requested once.
+///
+/// Values are stored as JSON, so they round-trip exactly as through `Cache`.
+/// Entries never expire.
+#[derive(Default)]
+pub struct MemoryCache {
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    writes: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Whole values as JSON; `None` is an empty marker.
+    values: HashMap<String, Option<Vec<u8>>>,
+    /// Journals, one JSON value per line.
+    journals: HashMap<String, Vec<Vec<u8>>>,
+    etags: HashMap<String, String>,
+}
+
+impl MemoryCache {
+    /// An empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap()
+    }
+}
+
+/// Whether `key` is listed under `prefix`, with the same rules as `Cache::keys`:
+/// the part of `prefix` up to its last `/` must match exactly, the rest is a
+/// prefix of the key's last segment.
+fn matches_prefix(key: &str, prefix: &str) -> bool {
+    let (parents, name) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+    let (key_parents, key_name) = key.rsplit_once('/').unwrap_or(("", key));
+    key_parents == parents && key_name.starts_with(name)
+}
+
+impl CacheStore for MemoryCache {
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn has(&self, key: &str) -> bool {
+        let exists = self.contains(key);
+        if !exists {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        exists
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        let entries = self.entries();
+        entries.values.contains_key(key) || entries.journals.contains_key(key)
+    }
+
+    fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let data = match self.entries().values.get(key) {
+            Some(Some(data)) => data.clone(),
+            Some(None) => {
+                debug!("Cache hit (empty marker): {}", key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+        match serde_json::from_slice(&data) {
+            Ok(val) => {
+                debug!("Cache hit: {}", key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(val)
+            }
+            Err(e) => {
+                warn!("Discarding corrupt cache entry {}: {}", key, e);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let data = serde_json::to_vec(value)?;
+        let mut entries = self.entries();
+        entries.journals.remove(key);
+        entries.values.insert(key.to_string(), Some(data));
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache write: {}", key);
+        Ok(())
+    }
+
+    fn get_lines<T: DeserializeOwned>(&self, key: &str) -> Option<Vec<T>> {
+        let Some(lines) = self.entries().journals.get(key).cloned() else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        let mut values = Vec::new();
+        for line in &lines {
+            match serde_json::from_slice(line) {
+                Ok(value) => values.push(value),
+                Err(e) => {
+                    warn!(
+                        "Discarding the rest of cache journal {} from line {}: {}",
+                        key,
+                        values.len() + 1,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+        debug!("Cache hit: {} ({} lines)", key, values.len());
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(values)
+    }
+
+    fn append<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let line = serde_json::to_vec(value)?;
+        self.entries()
+            .journals
+            .entry(key.to_string())
+            .or_default()
+            .push(line);
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache append: {}", key);
+        Ok(())
+    }
+
+    fn set_lines<T: Serialize>(&self, key: &str, values: &[T]) -> Result<()> {
+        let lines = values
+            .iter()
+            .map(serde_json::to_vec)
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        let mut entries = self.entries();
+        entries.values.remove(key);
+        entries.journals.insert(key.to_string(), lines);
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache write: {} ({} lines)", key, values.len());
+        Ok(())
+    }
+
+    fn set_empty(&self, key: &str) -> Result<()> {
+        let mut entries = self.entries();
+        entries.journals.remove(key);
+        entries.values.insert(key.to_string(), None);
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        debug!("Cache write (empty): {}", key);
+        Ok(())
+    }
+
+    fn etag(&self, key: &str) -> Option<String> {
+        self.entries().etags.get(key).cloned()
+    }
+
+    fn set_etag(&self, key: &str, etag: Option<&str>) -> Result<()> {
+        let mut entries = self.entries();
+        match etag.map(str::trim).filter(|e| !e.is_empty()) {
+            Some(etag) => entries.etags.insert(key.to_string(), etag.to_string()),
+            None => entries.etags.remove(key),
+        };
+        Ok(())
+    }
+
+    /// Entries never expire, so there is nothing to refresh.
+    fn touch(&self, key: &str) -> Result<()> {
+        debug!("Cache touch: {}", key);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<()> {
+        let mut entries = self.entries();
+        entries.values.remove(key);
+        entries.journals.remove(key);
+        entries.etags.remove(key);
+        debug!("Cache remove: {}", key);
+        Ok(())
+    }
+
+    fn entry_size(&self, key: &str) -> u64 {
+        let entries = self.entries();
+        let value = entries
+            .values
+            .get(key)
+            .map_or(0, |v| v.as_ref().map_or(0, Vec::len));
+        let journal = entries
+            .journals
+            .get(key)
+            .map_or(0, |lines| lines.iter().map(|l| l.len() + 1).sum());
+        let etag = entries.etags.get(key).map_or(0, String::len);
+        (value + journal + etag) as u64
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let entries = self.entries();
+        let mut keys: Vec<String> = entries
+            .values
+            .keys()
+            .chain(entries.journals.keys())
+            .filter(|key| matches_prefix(key, prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    fn clear(&self) -> Result<()> {
+        *self.entries() = Entries::default();
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::models::{Collection, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// A completed item still lacking a rating and/or a comment.
+pub struct MissingEntry {
+    pub name: String,
+    pub subject_type: &'static str,
+    pub url: String,
+    pub updated_at: String,
+    /// What is missing, e.g. "评分" or "评分, 评论".
+    pub missing: String,
+}
+
+/// Collect completed items without a rating (and, if `comments`, without a comment),
+/// most recently updated first.
+pub fn find_missing(collections: &[Collection], comments: bool) -> Vec<MissingEntry> {
+    let mut done: Vec<&Collection> = collections
+        .iter()
+        .filter(|c| c.collection_type == 2)
+        .collect();
+    done.sort_by_key(|c| Reverse(c.updated_at));
+
+    done.into_iter()
+        .filter_map(|c| {
+            let mut missing = Vec::new();
+            if c.rate == 0 {
+                missing.push("评分");
+            }
+            if comments && c.comment.as_deref().is_none_or(|s| s.trim().is_empty()) {
+                missing.push("评论");
+            }
+            if missing.is_empty() {
+                return None;
+            }
+            Some(MissingEntry {
+                name: if c.subject.name_cn.is_empty() {
+                    c.subject.name.clone()
+                } else {
+                    c.subject.name_cn.clone()
+                },
+                subject_type: subject_type_name(c.subject.subject_type),
+                url: format!("https://bgm.tv/subject/{}", c.subject_id),
+                updated_at: c
+                    .updated_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+                missing: missing.join(", "),
+            })
+        })
+        .collect()
+}
+
+/// Print missing entries as an aligned table.
+pub fn print_missing(entries: &[MissingEntry]) {
+    println!("\n== 缺少评分/评论的已完成条目: {} ==", entries.len());
+    let name_width = entries.iter().map(|e| e.name.width()).max().unwrap_or(0);
+    for e in entries {
+        println!(
+            "  {}  [{}]  {}  缺少{}",
+            pad_to_width(&e.name, name_width),
+            pad_to_width(e.subject_type, 6),
+            e.updated_at,
+            e.missing
+        );
+    }
+    println!();
+}
+
+/// Write missing entries to `missing.csv` in the output directory.
+pub fn write_csv(entries: &[MissingEntry], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("missing.csv");
+    let mut file = std::fs::File::create(&path)?;
+    std::io::Write::write_all(&mut file, b"\xEF\xBB\xBF")?;
+
+    let mut wtr = csv::Writer::from_writer(file);
+    wtr.write_record(["名称", "条目类型", "地址", "最后标注", "缺少"])?;
+    for e in entries {
+        wtr.write_record([
+            e.name.as_str(),
+            e.subject_type,
+            &e.url,
+            &e.updated_at,
+            &e.missing,
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(path)
+}
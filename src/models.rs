@@ -1,15 +1,36 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
 // --- API response types ---
 
+/// A Bangumi user, from `/v0/me` or `/v0/users/{username}`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct User {
     pub id: u64,
     pub username: String,
     pub nickname: String,
+    #[serde(default)]
+    pub avatar: Avatar,
+    #[serde(default)]
+    pub sign: String,
+    /// See `user_group_name`.
+    #[serde(default)]
+    pub user_group: u8,
 }
 
+/// Avatar image URLs in several sizes.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Avatar {
+    #[serde(default)]
+    pub large: String,
+    #[serde(default)]
+    pub medium: String,
+    #[serde(default)]
+    pub small: String,
+}
+
+/// A page of `/v0/users/{username}/collections`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PagedCollection {
     pub total: u64,
@@ -18,6 +39,7 @@ pub struct PagedCollection {
     pub data: Vec<Collection>,
 }
 
+/// One subject in a user's collection, with their status, rating and tags.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Collection {
     pub subject_id: u64,
@@ -29,8 +51,12 @@ pub struct Collection {
     pub comment: Option<String>,
     pub tags: Vec<String>,
     pub subject: CollectionSubject,
+    /// Hidden from other users; pages cached before this field was stored come back false.
+    #[serde(default)]
+    pub private: bool,
 }
 
+/// The subject summary embedded in a `Collection`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CollectionSubject {
     pub id: u64,
@@ -40,8 +66,12 @@ pub struct CollectionSubject {
     pub subject_type: u8,
     pub eps: u64,
     pub volumes: u64,
+    /// R18 subject; pages cached before this field was stored come back false.
+    #[serde(default)]
+    pub nsfw: bool,
 }
 
+/// A subject from `/v0/subjects/{id}`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct SubjectDetail {
     pub id: u64,
@@ -51,8 +81,85 @@ pub struct SubjectDetail {
     pub subject_type: u8,
     pub eps: u64,
     pub total_episodes: u64,
+    #[serde(default)]
+    pub nsfw: bool,
+    /// Community tags, as the API orders them. Subjects cached before this
+    /// field was stored come back without any.
+    #[serde(default)]
+    pub tags: Vec<SubjectTag>,
+    /// Synopsis; empty for subjects cached before this field was stored.
+    #[serde(default)]
+    pub summary: String,
+    /// Rows of the subject's infobox, such as 平台 or 动画制作; empty for
+    /// subjects cached before this field was stored.
+    #[serde(default)]
+    pub infobox: Vec<InfoboxItem>,
+    /// Community rating; unscored for subjects cached before this field was stored.
+    #[serde(default)]
+    pub rating: SubjectRating,
+}
+
+/// A subject linked to another, from `/v0/subjects/{id}/subject_relations`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelatedSubject {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub subject_type: u8,
+    pub name: String,
+    #[serde(default)]
+    pub name_cn: String,
+    /// How it relates to the subject, e.g. 续集, 前传 or 改编.
+    pub relation: String,
+}
+
+impl RelatedSubject {
+    /// The Chinese name, or the original name if there is none.
+    pub fn display_name(&self) -> &str {
+        if self.name_cn.is_empty() {
+            &self.name
+        } else {
+            &self.name_cn
+        }
+    }
 }
 
+/// One infobox row. The API gives the value either as a string or as a list
+/// of `{"k": ..., "v": ...}` objects (the `k` naming e.g. an alias's language);
+/// both are kept as the list of `v` values.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InfoboxItem {
+    pub key: String,
+    #[serde(default, deserialize_with = "infobox_values")]
+    pub value: Vec<String>,
+}
+
+/// Read an infobox value in any of its shapes: a string, a list of `{v}`
+/// objects, or the list of strings this tool caches. Numbers are kept as
+/// text and anything else is dropped, so an odd row never fails the subject.
+fn infobox_values<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    fn text(value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.trim().to_string()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::Object(entry) => entry.get("v").and_then(text),
+            _ => None,
+        }
+    }
+    let values: Vec<String> = match Value::deserialize(deserializer)? {
+        Value::Array(items) => items.iter().filter_map(text).collect(),
+        value => text(&value).into_iter().collect(),
+    };
+    Ok(values.into_iter().filter(|v| !v.is_empty()).collect())
+}
+
+/// A community tag on a subject and how many users gave it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SubjectTag {
+    pub name: String,
+    pub count: u64,
+}
+
+/// A page of `/v0/episodes`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PagedEpisodes {
     pub total: u64,
@@ -61,6 +168,7 @@ pub struct PagedEpisodes {
     pub data: Vec<Episode>,
 }
 
+/// One episode of a subject; `episode_type` 0 is a main episode.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Episode {
     pub id: u64,
@@ -68,47 +176,345 @@ pub struct Episode {
     pub episode_type: u8,
     pub sort: f64,
     pub ep: Option<f64>,
+    /// Air date as `YYYY-MM-DD`; empty or missing when unknown. Episodes cached
+    /// before this field was stored come back empty.
+    #[serde(default)]
+    pub airdate: String,
+    /// Episode titles; empty when unknown or cached before they were stored.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub name_cn: String,
+    /// Disc number of a music track; 0 for other subjects and for episodes
+    /// cached before this field was stored.
+    #[serde(default)]
+    pub disc: u8,
 }
 
+impl Episode {
+    /// The Chinese title, or the original title if there is none.
+    pub fn title(&self) -> &str {
+        if self.name_cn.is_empty() {
+            &self.name
+        } else {
+            &self.name_cn
+        }
+    }
+
+    /// Whether the episode has aired by `today`. Episodes with a missing or
+    /// malformed airdate count as aired, so they never make a show look behind.
+    pub fn aired_by(&self, today: NaiveDate) -> bool {
+        NaiveDate::parse_from_str(&self.airdate, "%Y-%m-%d")
+            .ok()
+            .is_none_or(|d| d <= today)
+    }
+}
+
+/// One episode in a progress response with its watch status.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EpisodeProgress {
     pub id: u64,
     pub status: ProgressStatus,
 }
 
+/// Watch status of an episode.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ProgressStatus {
     pub id: u8,
 }
 
+/// A user's episode progress on one subject.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UserProgress {
     pub subject_id: u64,
     pub eps: Vec<EpisodeProgress>,
 }
 
+/// An episode with my status on it, from `/v0/users/-/collections/{subject_id}/episodes`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EpisodeCollection {
+    pub episode: Episode,
+    /// 0 not collected, 1 想看, 2 看过, 3 抛弃; the same ids as legacy progress statuses.
+    #[serde(rename = "type")]
+    pub collection_type: u8,
+}
+
+impl UserProgress {
+    /// Progress from v0 episode collections, in the legacy shape so cached
+    /// progress reads the same whichever endpoint it came from. `None` when no
+    /// episode is marked, like the legacy endpoint's `null`.
+    pub fn from_episode_collections(
+        subject_id: u64,
+        entries: &[EpisodeCollection],
+    ) -> Option<Self> {
+        let eps: Vec<EpisodeProgress> = entries
+            .iter()
+            .filter(|e| e.collection_type != 0)
+            .map(|e| EpisodeProgress {
+                id: e.episode.id,
+                status: ProgressStatus {
+                    id: e.collection_type,
+                },
+            })
+            .collect();
+        if eps.is_empty() {
+            None
+        } else {
+            Some(Self { subject_id, eps })
+        }
+    }
+
+    /// Ids of episodes marked as watched (status 2).
+    pub fn watched_ids(&self) -> Vec<u64> {
+        self.eps
+            .iter()
+            .filter(|ep| ep.status.id == 2)
+            .map(|ep| ep.id)
+            .collect()
+    }
+}
+
+/// One weekday of the legacy `/calendar` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CalendarDay {
+    pub weekday: Weekday,
+    pub items: Vec<CalendarItem>,
+}
+
+/// Weekday names in a `CalendarDay`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Weekday {
+    /// 1 = Monday … 7 = Sunday.
+    pub id: u8,
+    pub en: String,
+    pub cn: String,
+}
+
+/// An airing subject in a `CalendarDay`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CalendarItem {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub name_cn: String,
+}
+
+/// A page of a paginated v0 list endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Paged<T> {
+    pub total: u64,
+    pub limit: u64,
+    pub offset: u64,
+    pub data: Vec<T>,
+}
+
+/// Image URLs in several sizes.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Images {
+    #[serde(default)]
+    pub large: String,
+    #[serde(default)]
+    pub medium: String,
+    #[serde(default)]
+    pub small: String,
+    #[serde(default)]
+    pub grid: String,
+}
+
+/// A character in my character collection.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CharacterCollection {
+    pub id: u64,
+    pub name: String,
+    /// See `character_type_name`.
+    #[serde(rename = "type")]
+    pub character_type: u8,
+    #[serde(default)]
+    pub images: Option<Images>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A person (individual, company or group) in my person collection.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PersonCollection {
+    pub id: u64,
+    pub name: String,
+    /// See `person_type_name`.
+    #[serde(rename = "type")]
+    pub person_type: u8,
+    /// Career tags such as `seiyu` or `producer`; see `career_name`.
+    #[serde(default)]
+    pub career: Vec<String>,
+    #[serde(default)]
+    pub images: Option<Images>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A 目录 (index), from `/v0/indices/{id}`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Index {
+    pub id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub desc: String,
+    /// Number of subjects in the index.
+    pub total: u64,
+    pub creator: IndexCreator,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The user who created an index.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IndexCreator {
+    pub username: String,
+    pub nickname: String,
+}
+
+/// A subject in an index, with the index author's comment on it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IndexSubject {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub subject_type: u8,
+    pub name: String,
+    #[serde(default)]
+    pub comment: String,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Response of `POST /v0/search/subjects`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PagedSearchResults {
+    pub total: u64,
+    pub limit: u64,
+    pub offset: u64,
+    pub data: Vec<SearchSubject>,
+}
+
+/// A subject in search results.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SearchSubject {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub subject_type: u8,
+    pub name: String,
+    #[serde(default)]
+    pub name_cn: String,
+    /// Air or release date, `YYYY-MM-DD`.
+    #[serde(default)]
+    pub date: Option<String>,
+    #[serde(default)]
+    pub rating: SubjectRating,
+}
+
+/// Community rating summary of a subject; `score` is 0 when it has too few
+/// ratings to be scored.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SubjectRating {
+    #[serde(default)]
+    pub score: f64,
+}
+
 // --- Export types ---
 
 /// Full record with episode/progress detail.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportRecord {
+    /// Records in resume caches written before this field existed come back as 0.
+    #[serde(default)]
+    pub subject_id: u64,
     pub name: String,
     pub name_cn: String,
     pub subject_type: String,
     pub url: String,
     pub status: String,
+    /// Raw collection type (1=wish, 2=done, 3=doing, 4=on_hold, 5=dropped).
+    /// Kept in the resume cache, so records from older caches default to 0.
+    #[serde(default)]
+    pub collection_type: u8,
     pub updated_at: String,
     pub completeness: String,
     pub completeness_pct: String,
     pub watched_eps: String,
+    /// For on-hold and dropped items, the last main episode watched, or the
+    /// collection's episode count without per-episode progress. Records from
+    /// older resume caches are refilled from `watched_eps` when loaded.
+    #[serde(default)]
+    pub stopped_at: Option<u64>,
+    /// Watched main episodes against those aired by the time of the run, e.g.
+    /// `6/6`; episodes without an airdate count as aired. Empty when the
+    /// subject has no episode list or in records from older resume caches.
+    #[serde(default)]
+    pub aired_completeness: String,
+    /// Every aired main episode is watched, even if more are still to come.
+    #[serde(default)]
+    pub caught_up: bool,
     pub rating: String,
+    /// My rating minus the site score, `None` when either is missing. Refilled
+    /// from the cached subject like `site_tags`.
+    #[serde(default)]
+    pub rating_diff: Option<f64>,
     pub tags: String,
+    /// The subject's most used community tags, comma-separated. Records from
+    /// older resume caches come back empty and are refilled from the cached
+    /// subject when loaded.
+    #[serde(default)]
+    pub site_tags: String,
     pub comment: String,
+    /// The subject's synopsis with `--include-summary`, `None` otherwise.
+    /// Refilled from the cached subject like `site_tags`.
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// The infobox rows picked with `--infobox-fields`, as `(key, values)` in
+    /// the order asked for, values joined with `、`. Refilled like `site_tags`.
+    #[serde(default)]
+    pub infobox: Vec<(String, String)>,
+    /// Related subjects with `--relations`, `None` otherwise. Refilled from
+    /// the cached relations when loaded.
+    #[serde(default)]
+    pub relations: Option<Vec<RelatedSubject>>,
+    /// Watched main episodes with their titles, e.g. `第7话 「タイトル」`, with
+    /// `--episode-titles`; `None` otherwise. Saved records without them are redone.
+    #[serde(default)]
+    pub watched_titles: Option<Vec<String>>,
+    /// R18 subject. Records from older resume caches default to false and are
+    /// corrected from the collection when loaded.
+    #[serde(default)]
+    pub nsfw: bool,
+    /// Private collection; corrected from the collection like `nsfw`.
+    #[serde(default)]
+    pub private: bool,
+    /// Discs and tracks of a music subject with `--music-tracks`, `None` otherwise.
+    #[serde(default)]
+    pub tracks: Option<TrackCount>,
+}
+
+/// How many discs and tracks a music subject has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackCount {
+    pub discs: u64,
+    pub tracks: u64,
+}
+
+impl TrackCount {
+    /// Count the tracks in a music subject's episode list. Tracks without a
+    /// disc number are counted as disc 1.
+    pub fn new(episodes: &[Episode]) -> Self {
+        let mut discs: Vec<u8> = episodes.iter().map(|e| e.disc.max(1)).collect();
+        discs.sort_unstable();
+        discs.dedup();
+        Self {
+            discs: discs.len() as u64,
+            tracks: episodes.len() as u64,
+        }
+    }
 }
 
 /// Simple record built from collection data only (no extra API calls).
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SimpleRecord {
+    pub subject_id: u64,
     pub name: String,
     pub name_cn: String,
     pub subject_type: String,
@@ -121,10 +527,37 @@ pub struct SimpleRecord {
     pub rating: String,
     pub tags: String,
     pub comment: String,
+    /// R18 subject.
+    #[serde(default)]
+    pub nsfw: bool,
+    /// Private collection.
+    #[serde(default)]
+    pub private: bool,
+}
+
+/// State kept between `--incremental` runs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Newest `updated_at` seen; later runs only fetch collections updated after it.
+    pub high_water: DateTime<Utc>,
+    /// The full collection list as of the last sync, newest first.
+    pub collections: Vec<Collection>,
+}
+
+/// A subject whose detail fetch failed under `--skip-errors`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailedSubject {
+    pub id: u64,
+    pub name: String,
+    pub error: String,
 }
 
 // --- Helpers ---
 
+/// Subject type of music; its episodes are tracks, which can't be marked as listened.
+pub const SUBJECT_TYPE_MUSIC: u8 = 3;
+
+/// Chinese display name of a subject type, e.g. 2 -> 动画.
 pub fn subject_type_name(t: u8) -> &'static str {
     match t {
         1 => "书籍",
@@ -136,6 +569,58 @@ pub fn subject_type_name(t: u8) -> &'static str {
     }
 }
 
+/// Display name of a character type.
+pub fn character_type_name(t: u8) -> &'static str {
+    match t {
+        1 => "角色",
+        2 => "机体",
+        3 => "舰船",
+        4 => "组织",
+        _ => "未知",
+    }
+}
+
+/// Display name of a person type.
+pub fn person_type_name(t: u8) -> &'static str {
+    match t {
+        1 => "个人",
+        2 => "公司",
+        3 => "组合",
+        _ => "未知",
+    }
+}
+
+/// Display name of a person career tag; unknown tags are shown as is.
+pub fn career_name(career: &str) -> &str {
+    match career {
+        "producer" => "制作人员",
+        "mangaka" => "漫画家",
+        "artist" => "音乐人",
+        "seiyu" => "声优",
+        "writer" => "作家",
+        "illustrator" => "绘师",
+        "actor" => "演员",
+        other => other,
+    }
+}
+
+/// Display name of a user group.
+pub fn user_group_name(group: u8) -> &'static str {
+    match group {
+        1 => "管理员",
+        2 => "Bangumi 管理猿",
+        3 => "天窗管理猿",
+        4 => "禁言用户",
+        5 => "禁止访问用户",
+        8 => "人物管理猿",
+        9 => "维基条目管理猿",
+        10 => "用户",
+        11 => "维基人",
+        _ => "未知",
+    }
+}
+
+/// Chinese display name of a collection type, worded for the subject type (看过 vs 读过).
 pub fn collection_status_name(collection_type: u8, subject_type: u8) -> &'static str {
     match (collection_type, subject_type) {
         // 书籍：想读/在读/读过
@@ -161,6 +646,32 @@ pub fn collection_status_name(collection_type: u8, subject_type: u8) -> &'static
     }
 }
 
+/// Collection type for a status name as written by `collection_status_name`,
+/// in any of its per-subject-type spellings.
+pub fn collection_type_from_name(name: &str) -> Option<u8> {
+    match name.trim() {
+        "想看" | "想读" | "想听" | "想玩" => Some(1),
+        "看过" | "读过" | "听过" | "玩过" => Some(2),
+        "在看" | "在读" | "在听" | "在玩" => Some(3),
+        "搁置" => Some(4),
+        "抛弃" => Some(5),
+        _ => None,
+    }
+}
+
+/// Subject id from a subject URL such as `https://bgm.tv/subject/253`
+/// (bangumi.tv and chii.in links work too).
+pub fn subject_id_from_url(url: &str) -> Option<u64> {
+    url.split(['?', '#'])
+        .next()?
+        .split("/subject/")
+        .nth(1)?
+        .split('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
 /// Encode a sorted list of episode numbers into run-length format like "1-5,7,9-12".
 pub fn run_length_encode(eps: &[u64]) -> String {
     if eps.is_empty() {
@@ -195,3 +706,51 @@ pub fn run_length_encode(eps: &[u64]) -> String {
 
     parts.join(",")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values(value: Value) -> Vec<String> {
+        let item: InfoboxItem =
+            serde_json::from_value(json!({ "key": "别名", "value": value })).unwrap();
+        item.value
+    }
+
+    #[test]
+    fn infobox_string_value() {
+        assert_eq!(values(json!(" Sunrise ")), ["Sunrise"]);
+        assert!(values(json!("")).is_empty());
+    }
+
+    #[test]
+    fn infobox_list_of_key_values() {
+        let list = json!([
+            { "k": "英文名", "v": "Cowboy Bebop" },
+            { "v": "COWBOY BEBOP" },
+            { "k": "简体中文名", "v": "" },
+            { "k": "空" }
+        ]);
+        assert_eq!(values(list), ["Cowboy Bebop", "COWBOY BEBOP"]);
+    }
+
+    #[test]
+    fn infobox_cached_list_of_strings() {
+        assert_eq!(values(json!(["PC", "PS4"])), ["PC", "PS4"]);
+    }
+
+    #[test]
+    fn infobox_number_value() {
+        assert_eq!(values(json!(26)), ["26"]);
+        assert_eq!(values(json!([{ "v": 1.5 }])), ["1.5"]);
+    }
+
+    #[test]
+    fn infobox_null_or_missing_value() {
+        assert!(values(Value::Null).is_empty());
+        assert!(values(json!([null, true])).is_empty());
+        let item: InfoboxItem = serde_json::from_value(json!({ "key": "平台" })).unwrap();
+        assert!(item.value.is_empty());
+    }
+}
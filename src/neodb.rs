@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::client::normalize_base_url;
+use crate::error::{AppError, Result};
+use crate::models::{Collection, collection_status_name};
+
+pub const DEFAULT_INSTANCE: &str = "https://neodb.social";
+
+/// A NeoDB shelf mark, as read from and written to `/api/me/shelf/item/{uuid}`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Mark {
+    pub shelf_type: String,
+    #[serde(default)]
+    pub rating_grade: Option<u8>,
+    #[serde(default)]
+    pub comment_text: Option<String>,
+    #[serde(default)]
+    pub created_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct MarkInput<'a> {
+    #[serde(flatten)]
+    mark: &'a Mark,
+    visibility: u8,
+    post_to_fediverse: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    uuid: String,
+}
+
+/// Outcome of resolving a Bangumi subject on NeoDB.
+enum Lookup {
+    Found(String),
+    /// NeoDB started importing the subject; it can be resolved on a later run.
+    Pending,
+    NotFound,
+}
+
+/// Minimal client for the NeoDB API.
+pub struct NeoDbClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl NeoDbClient {
+    pub fn new(token: String, instance: &str) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base_url: normalize_base_url(instance)?,
+            token,
+        })
+    }
+
+    async fn error(resp: reqwest::Response) -> AppError {
+        AppError::Api {
+            status: resp.status().as_u16(),
+            message: format!("NeoDB: {}", resp.text().await.unwrap_or_default()),
+        }
+    }
+
+    /// Resolve a subject through its bgm.tv URL.
+    async fn lookup(&self, subject_id: u64) -> Result<Lookup> {
+        let subject_url = format!("https://bgm.tv/subject/{}", subject_id);
+        let resp = self
+            .http
+            .get(format!("{}/api/catalog/fetch", self.base_url))
+            .bearer_auth(&self.token)
+            .query(&[("url", subject_url.as_str())])
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::OK => Ok(Lookup::Found(resp.json::<Item>().await?.uuid)),
+            StatusCode::ACCEPTED => Ok(Lookup::Pending),
+            StatusCode::NOT_FOUND => Ok(Lookup::NotFound),
+            _ => Err(Self::error(resp).await),
+        }
+    }
+
+    async fn get_mark(&self, uuid: &str) -> Result<Option<Mark>> {
+        let resp = self
+            .http
+            .get(format!("{}/api/me/shelf/item/{}", self.base_url, uuid))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            s if s.is_success() => Ok(Some(resp.json().await?)),
+            _ => Err(Self::error(resp).await),
+        }
+    }
+
+    /// Create or replace the mark on an item.
+    async fn put_mark(&self, uuid: &str, mark: &Mark) -> Result<()> {
+        let resp = self
+            .http
+            .post(format!("{}/api/me/shelf/item/{}", self.base_url, uuid))
+            .bearer_auth(&self.token)
+            .json(&MarkInput {
+                mark,
+                visibility: 0,
+                post_to_fediverse: false,
+            })
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Self::error(resp).await);
+        }
+        Ok(())
+    }
+}
+
+/// Whether NeoDB rejected the token, which fails every later request too.
+fn is_unauthorized(e: &AppError) -> bool {
+    matches!(e, AppError::Api { status: 401, .. })
+}
+
+/// NeoDB shelf for a collection type; 搁置 has no counterpart.
+fn shelf_type(collection_type: u8) -> Option<&'static str> {
+    match collection_type {
+        1 => Some("wishlist"),
+        2 => Some("complete"),
+        3 => Some("progress"),
+        5 => Some("dropped"),
+        _ => None,
+    }
+}
+
+/// A subject that could not be synced to NeoDB.
+pub struct Unmatched {
+    pub name: String,
+    pub url: String,
+    pub reason: String,
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub unmatched: Vec<Unmatched>,
+}
+
+impl SyncReport {
+    pub fn print(&self, dry_run: bool) {
+        let verb = if dry_run { "would be " } else { "" };
+        println!(
+            "\nNeoDB: {} {}created, {} {}updated, {} unchanged, {} unmatched",
+            self.created,
+            verb,
+            self.updated,
+            verb,
+            self.unchanged,
+            self.unmatched.len()
+        );
+    }
+
+    /// Write the unmatched subjects to `neodb_unmatched.csv` in the output directory.
+    pub fn write_unmatched(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("neodb_unmatched.csv");
+        let mut file = std::fs::File::create(&path)?;
+        std::io::Write::write_all(&mut file, b"\xEF\xBB\xBF")?;
+
+        let mut wtr = csv::Writer::from_writer(file);
+        wtr.write_record(["名称", "地址", "原因"])?;
+        for u in &self.unmatched {
+            wtr.write_record([&u.name, &u.url, &u.reason])?;
+        }
+        wtr.flush()?;
+        Ok(path)
+    }
+}
+
+/// Mirror each collection's status, rating, comment and timestamp to NeoDB.
+/// A subject whose lookup or mark fails is reported as unmatched with the
+/// error, and the sync goes on; a rejected token (401) stops it.
+///
+/// With `dry_run`, lookups still happen but nothing is written; planned changes
+/// are printed instead.
+pub async fn sync(
+    neodb: &NeoDbClient,
+    collections: &[Collection],
+    dry_run: bool,
+    on_progress: impl Fn(&str),
+) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    for col in collections {
+        let name = if col.subject.name_cn.is_empty() {
+            &col.subject.name
+        } else {
+            &col.subject.name_cn
+        };
+        on_progress(name);
+        let url = format!("https://bgm.tv/subject/{}", col.subject_id);
+        let unmatched = |reason: &str| Unmatched {
+            name: name.clone(),
+            url: url.clone(),
+            reason: reason.to_string(),
+        };
+
+        let Some(shelf) = shelf_type(col.collection_type) else {
+            let status = collection_status_name(col.collection_type, col.subject.subject_type);
+            report
+                .unmatched
+                .push(unmatched(&format!("no NeoDB shelf for {}", status)));
+            continue;
+        };
+        let uuid = match neodb.lookup(col.subject_id).await {
+            Ok(Lookup::Found(uuid)) => uuid,
+            Ok(Lookup::Pending) => {
+                report
+                    .unmatched
+                    .push(unmatched("NeoDB is importing it; rerun later"));
+                continue;
+            }
+            Ok(Lookup::NotFound) => {
+                report.unmatched.push(unmatched("not found on NeoDB"));
+                continue;
+            }
+            Err(e) if is_unauthorized(&e) => return Err(e),
+            Err(e) => {
+                warn!("NeoDB lookup failed for {}: {}", name, e);
+                report.unmatched.push(unmatched(&e.to_string()));
+                continue;
+            }
+        };
+
+        let wanted = Mark {
+            shelf_type: shelf.to_string(),
+            rating_grade: (col.rate > 0).then_some(col.rate),
+            comment_text: col.comment.clone().filter(|c| !c.trim().is_empty()),
+            created_time: Some(col.updated_at),
+        };
+        let existing = match neodb.get_mark(&uuid).await {
+            Ok(existing) => existing,
+            Err(e) if is_unauthorized(&e) => return Err(e),
+            Err(e) => {
+                warn!("Reading the NeoDB mark of {} failed: {}", name, e);
+                report.unmatched.push(unmatched(&e.to_string()));
+                continue;
+            }
+        };
+        // The timestamp is only set when marking; don't rewrite a mark for it alone.
+        let same = existing.as_ref().is_some_and(|m| {
+            m.shelf_type == wanted.shelf_type
+                && m.rating_grade == wanted.rating_grade
+                && m.comment_text.as_deref().unwrap_or("")
+                    == wanted.comment_text.as_deref().unwrap_or("")
+        });
+        if same {
+            report.unchanged += 1;
+            continue;
+        }
+
+        if dry_run {
+            let action = if existing.is_some() {
+                "update"
+            } else {
+                "create"
+            };
+            println!(
+                "  would {} {}: {}{}",
+                action,
+                name,
+                wanted.shelf_type,
+                wanted
+                    .rating_grade
+                    .map(|r| format!(", rating {}", r))
+                    .unwrap_or_default()
+            );
+        } else {
+            match neodb.put_mark(&uuid, &wanted).await {
+                Ok(()) => debug!("NeoDB mark set for {} ({})", name, uuid),
+                Err(e) if is_unauthorized(&e) => return Err(e),
+                Err(e) => {
+                    warn!("Setting the NeoDB mark of {} failed: {}", name, e);
+                    report.unmatched.push(unmatched(&e.to_string()));
+                    continue;
+                }
+            }
+        }
+        if existing.is_some() {
+            report.updated += 1;
+        } else {
+            report.created += 1;
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate};
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::models::{Collection, Episode, UserProgress};
+use crate::summary::pad_to_width;
+
+/// The next main episode to watch for one subject.
+#[derive(Debug, Serialize)]
+pub struct NextUp {
+    pub subject_id: u64,
+    pub name: String,
+    pub url: String,
+    /// Sort number of the first unwatched main episode; `None` when caught up.
+    pub next_ep: Option<f64>,
+    /// Aired main episodes not yet watched, including gaps before the last
+    /// watched one.
+    pub remaining: u64,
+    /// Every aired main episode is watched, even if more are still to come.
+    pub caught_up: bool,
+}
+
+impl NextUp {
+    /// Work out the next episode from the same episode/progress data the detail
+    /// records use. Gaps come first, since the smallest unwatched sort wins.
+    /// Only episodes aired by today count, as for `aired_completeness`.
+    pub fn new(col: &Collection, episodes: &[Episode], progress: &Option<UserProgress>) -> Self {
+        Self::on(col, episodes, progress, Local::now().date_naive())
+    }
+
+    fn on(
+        col: &Collection,
+        episodes: &[Episode],
+        progress: &Option<UserProgress>,
+        today: NaiveDate,
+    ) -> Self {
+        let watched = progress
+            .as_ref()
+            .map(UserProgress::watched_ids)
+            .unwrap_or_default();
+        let mut unwatched: Vec<f64> = episodes
+            .iter()
+            .filter(|e| e.episode_type == 0 && e.aired_by(today) && !watched.contains(&e.id))
+            .map(|e| e.sort)
+            .collect();
+        unwatched.sort_by(f64::total_cmp);
+
+        Self {
+            subject_id: col.subject_id,
+            name: if col.subject.name_cn.is_empty() {
+                col.subject.name.clone()
+            } else {
+                col.subject.name_cn.clone()
+            },
+            url: format!("https://bgm.tv/subject/{}", col.subject_id),
+            next_ep: unwatched.first().copied(),
+            remaining: unwatched.len() as u64,
+            caught_up: unwatched.is_empty(),
+        }
+    }
+}
+
+/// Format an episode sort number, dropping the fraction for whole episodes.
+pub(crate) fn format_sort(sort: f64) -> String {
+    if sort.fract() == 0.0 {
+        format!("{}", sort as i64)
+    } else {
+        sort.to_string()
+    }
+}
+
+/// Print one line per subject, e.g. `进击的巨人 — next: ep 18 (5 remaining)`.
+pub fn print_next_up(items: &[NextUp]) {
+    println!("\n== 在看: {} ==", items.len());
+    let name_width = items.iter().map(|n| n.name.width()).max().unwrap_or(0);
+    for item in items {
+        let status = match item.next_ep {
+            Some(ep) => format!(
+                "next: ep {} ({} remaining)",
+                format_sort(ep),
+                item.remaining
+            ),
+            None => "caught up".to_string(),
+        };
+        println!("  {} — {}", pad_to_width(&item.name, name_width), status);
+    }
+    println!();
+}
+
+/// Write the list as `next_up.json` in the output directory.
+pub fn write_json(items: &[NextUp], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("next_up.json");
+    let file = std::fs::File::create(&path)?;
+    serde_json::to_writer_pretty(file, items)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection() -> Collection {
+        serde_json::from_value(serde_json::json!({
+            "subject_id": 7,
+            "type": 3,
+            "rate": 0,
+            "ep_status": 1,
+            "updated_at": "2024-04-01T00:00:00Z",
+            "comment": null,
+            "tags": [],
+            "subject": {
+                "id": 7, "name": "Airing", "name_cn": "", "type": 2, "eps": 4, "volumes": 0
+            }
+        }))
+        .unwrap()
+    }
+
+    fn episode(id: u64, sort: f64, airdate: &str) -> Episode {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "type": 0, "sort": sort, "ep": sort, "airdate": airdate
+        }))
+        .unwrap()
+    }
+
+    fn watched(ids: &[u64]) -> Option<UserProgress> {
+        let eps: Vec<_> = ids
+            .iter()
+            .map(|id| serde_json::json!({ "id": id, "status": { "id": 2 } }))
+            .collect();
+        serde_json::from_value(serde_json::json!({ "subject_id": 7, "eps": eps })).unwrap()
+    }
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 4, 15).unwrap()
+    }
+
+    #[test]
+    fn unaired_episodes_are_not_remaining() {
+        let episodes = [
+            episode(1, 1.0, "2024-04-01"),
+            episode(2, 2.0, "2024-04-08"),
+            episode(3, 3.0, "2024-04-15"),
+            episode(4, 4.0, "2024-04-22"),
+        ];
+        let next = NextUp::on(&collection(), &episodes, &watched(&[1]), today());
+        assert_eq!(next.next_ep, Some(2.0));
+        assert_eq!(next.remaining, 2);
+        assert!(!next.caught_up);
+    }
+
+    #[test]
+    fn watching_every_aired_episode_is_caught_up() {
+        let episodes = [episode(1, 1.0, "2024-04-01"), episode(2, 2.0, "2024-04-22")];
+        let next = NextUp::on(&collection(), &episodes, &watched(&[1]), today());
+        assert_eq!(next.next_ep, None);
+        assert_eq!(next.remaining, 0);
+        assert!(next.caught_up);
+    }
+
+    #[test]
+    fn episodes_without_an_airdate_count_as_aired() {
+        let episodes = [episode(1, 1.0, ""), episode(2, 2.0, "unknown")];
+        let next = NextUp::on(&collection(), &episodes, &watched(&[]), today());
+        assert_eq!(next.next_ep, Some(1.0));
+        assert_eq!(next.remaining, 2);
+    }
+}
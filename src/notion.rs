@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::Local;
+use log::{debug, warn};
+use reqwest::{Method, StatusCode};
+use serde_json::{Map, Value, json};
+
+use crate::error::{AppError, Result};
+use crate::models::{Collection, collection_status_name, subject_type_name};
+
+const API_BASE: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
+/// Notion allows about three requests per second per integration.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(350);
+/// Attempts per request when rate limited or on server errors.
+const ATTEMPTS: u32 = 5;
+/// Notion caps a single rich text object at 2000 characters.
+const TEXT_CHUNK: usize = 2000;
+
+/// Number property identifying the Bangumi subject of a page.
+const ID_PROPERTY: &str = "Bangumi ID";
+
+/// A database property value, in a form that compares equal when Notion would
+/// show the same thing.
+#[derive(Debug, PartialEq)]
+enum Prop {
+    Title(String),
+    Number(Option<f64>),
+    Select(Option<String>),
+    MultiSelect(Vec<String>),
+    RichText(String),
+    Url(Option<String>),
+    Date(Option<String>),
+}
+
+fn text_objects(s: &str) -> Value {
+    let chars: Vec<char> = s.chars().collect();
+    Value::Array(
+        chars
+            .chunks(TEXT_CHUNK)
+            .map(|c| json!({ "text": { "content": c.iter().collect::<String>() } }))
+            .collect(),
+    )
+}
+
+fn plain_text(v: &Value) -> String {
+    v.as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p["plain_text"].as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl Prop {
+    /// JSON for a page create/update request.
+    fn to_json(&self) -> Value {
+        match self {
+            Prop::Title(s) => json!({ "title": text_objects(s) }),
+            Prop::Number(n) => json!({ "number": n }),
+            Prop::Select(s) => json!({ "select": s.as_ref().map(|name| json!({ "name": name })) }),
+            Prop::MultiSelect(names) => {
+                let options: Vec<Value> = names.iter().map(|n| json!({ "name": n })).collect();
+                json!({ "multi_select": options })
+            }
+            Prop::RichText(s) => json!({ "rich_text": text_objects(s) }),
+            Prop::Url(u) => json!({ "url": u }),
+            Prop::Date(d) => json!({ "date": d.as_ref().map(|start| json!({ "start": start })) }),
+        }
+    }
+
+    /// Read the same kind of value back from a page's property object.
+    fn read(&self, v: &Value) -> Prop {
+        match self {
+            Prop::Title(_) => Prop::Title(plain_text(&v["title"])),
+            Prop::Number(_) => Prop::Number(v["number"].as_f64()),
+            Prop::Select(_) => Prop::Select(v["select"]["name"].as_str().map(str::to_string)),
+            Prop::MultiSelect(_) => {
+                let mut names: Vec<String> = v["multi_select"]
+                    .as_array()
+                    .map(|a| {
+                        a.iter()
+                            .filter_map(|o| o["name"].as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                names.sort();
+                Prop::MultiSelect(names)
+            }
+            Prop::RichText(_) => Prop::RichText(plain_text(&v["rich_text"])),
+            Prop::Url(_) => Prop::Url(v["url"].as_str().map(str::to_string)),
+            // Only the date part is written, so only compare that.
+            Prop::Date(_) => Prop::Date(
+                v["date"]["start"]
+                    .as_str()
+                    .map(|s| s.chars().take(10).collect()),
+            ),
+        }
+    }
+}
+
+/// The properties a collection should have in the database.
+fn properties(col: &Collection) -> Vec<(&'static str, Prop)> {
+    let title = if col.subject.name_cn.is_empty() {
+        &col.subject.name
+    } else {
+        &col.subject.name_cn
+    };
+    // Multi-select option names may not contain commas.
+    let mut tags: Vec<String> = col
+        .tags
+        .iter()
+        .map(|t| t.trim().replace(',', " "))
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    vec![
+        ("Name", Prop::Title(title.clone())),
+        (ID_PROPERTY, Prop::Number(Some(col.subject_id as f64))),
+        (
+            "Type",
+            Prop::Select(Some(
+                subject_type_name(col.subject.subject_type).to_string(),
+            )),
+        ),
+        (
+            "Status",
+            Prop::Select(Some(
+                collection_status_name(col.collection_type, col.subject.subject_type).to_string(),
+            )),
+        ),
+        (
+            "Rating",
+            Prop::Number((col.rate > 0).then_some(col.rate as f64)),
+        ),
+        ("Tags", Prop::MultiSelect(tags)),
+        (
+            "Comment",
+            Prop::RichText(col.comment.clone().unwrap_or_default()),
+        ),
+        (
+            "URL",
+            Prop::Url(Some(format!("https://bgm.tv/subject/{}", col.subject_id))),
+        ),
+        (
+            "Updated",
+            Prop::Date(Some(
+                col.updated_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            )),
+        ),
+    ]
+}
+
+/// An existing database page.
+struct Page {
+    id: String,
+    properties: Map<String, Value>,
+}
+
+pub struct NotionClient {
+    http: reqwest::Client,
+    token: String,
+    database_id: String,
+}
+
+impl NotionClient {
+    pub fn new(token: String, database_id: String) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            token,
+            database_id,
+        }
+    }
+
+    /// Send a request, backing off on 429 (honoring Retry-After) and 5xx.
+    async fn call(&self, method: Method, path: &str, body: &Value) -> Result<Value> {
+        let url = format!("{}{}", API_BASE, path);
+        let mut delay = Duration::from_secs(1);
+        for attempt in 1..=ATTEMPTS {
+            tokio::time::sleep(REQUEST_INTERVAL).await;
+            let resp = self
+                .http
+                .request(method.clone(), &url)
+                .bearer_auth(&self.token)
+                .header("Notion-Version", NOTION_VERSION)
+                .json(body)
+                .send()
+                .await?;
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(resp.json().await?);
+            }
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if !retryable || attempt == ATTEMPTS {
+                return Err(AppError::Api {
+                    status: status.as_u16(),
+                    message: format!("Notion: {}", resp.text().await.unwrap_or_default()),
+                });
+            }
+            let wait = resp
+                .headers()
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(delay);
+            warn!("Notion returned {}, retrying in {:?}", status, wait);
+            tokio::time::sleep(wait).await;
+            delay *= 2;
+        }
+        unreachable!("the last attempt always returns")
+    }
+
+    /// All pages in the database that carry a Bangumi ID, keyed by it.
+    async fn pages_by_subject(&self) -> Result<HashMap<u64, Page>> {
+        let path = format!("/databases/{}/query", self.database_id);
+        let mut pages = HashMap::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut body = json!({ "page_size": 100 });
+            if let Some(c) = &cursor {
+                body["start_cursor"] = json!(c);
+            }
+            let resp = self.call(Method::POST, &path, &body).await?;
+            for page in resp["results"].as_array().into_iter().flatten() {
+                let properties = page["properties"].as_object().cloned().unwrap_or_default();
+                let Some(id) = properties
+                    .get(ID_PROPERTY)
+                    .and_then(|p| p["number"].as_f64())
+                else {
+                    continue;
+                };
+                let page_id = page["id"].as_str().unwrap_or_default().to_string();
+                pages.insert(
+                    id as u64,
+                    Page {
+                        id: page_id,
+                        properties,
+                    },
+                );
+            }
+            cursor = resp["next_cursor"].as_str().map(str::to_string);
+            if !resp["has_more"].as_bool().unwrap_or(false) || cursor.is_none() {
+                return Ok(pages);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NotionReport {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// Upsert one page per collection. Existing pages only get the properties whose
+/// value changed, so unchanged records don't add to the page history.
+pub async fn sync(
+    notion: &NotionClient,
+    collections: &[Collection],
+    dry_run: bool,
+    on_progress: impl Fn(&str),
+) -> Result<NotionReport> {
+    let existing = notion.pages_by_subject().await?;
+    debug!("Notion database has {} Bangumi pages", existing.len());
+
+    let mut report = NotionReport::default();
+    for col in collections {
+        let props = properties(col);
+        let title = match &props[0].1 {
+            Prop::Title(t) => t.clone(),
+            _ => String::new(),
+        };
+        on_progress(&title);
+
+        match existing.get(&col.subject_id) {
+            Some(page) => {
+                let changed: Map<String, Value> = props
+                    .iter()
+                    .filter(|(name, prop)| {
+                        page.properties
+                            .get(*name)
+                            .is_none_or(|current| prop.read(current) != *prop)
+                    })
+                    .map(|(name, prop)| (name.to_string(), prop.to_json()))
+                    .collect();
+                if changed.is_empty() {
+                    report.skipped += 1;
+                    continue;
+                }
+                if dry_run {
+                    let names: Vec<&str> = changed.keys().map(String::as_str).collect();
+                    println!("  would update {}: {}", title, names.join(", "));
+                } else {
+                    notion
+                        .call(
+                            Method::PATCH,
+                            &format!("/pages/{}", page.id),
+                            &json!({ "properties": changed }),
+                        )
+                        .await?;
+                }
+                report.updated += 1;
+            }
+            None => {
+                if dry_run {
+                    println!("  would create {}", title);
+                } else {
+                    let properties: Map<String, Value> = props
+                        .iter()
+                        .map(|(name, prop)| (name.to_string(), prop.to_json()))
+                        .collect();
+                    notion
+                        .call(
+                            Method::POST,
+                            "/pages",
+                            &json!({
+                                "parent": { "database_id": notion.database_id },
+                                "properties": properties,
+                            }),
+                        )
+                        .await?;
+                }
+                report.created += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl NotionReport {
+    pub fn print(&self, dry_run: bool) {
+        let verb = if dry_run { "would be " } else { "" };
+        println!(
+            "\nNotion: {} {}created, {} {}updated, {} skipped (unchanged)",
+            self.created, verb, self.updated, verb, self.skipped
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{debug, warn};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+use crate::export::write_atomic;
+
+const AUTHORIZE_URL: &str = "https://bgm.tv/oauth/authorize";
+const TOKEN_URL: &str = "https://bgm.tv/oauth/access_token";
+const TOKEN_STATUS_URL: &str = "https://bgm.tv/oauth/token_status";
+pub const DEFAULT_PORT: u16 = 8723;
+
+/// Refresh the stored access token once it has less than this long left.
+const REFRESH_BEFORE: TimeDelta = TimeDelta::days(1);
+
+/// Tokens from `bangumi-tool auth`, with what's needed to refresh them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: DateTime<Utc>,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    /// Seconds until the access token expires.
+    expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenStatus {
+    /// Unix time the token expires at; absent or 0 for tokens that don't.
+    #[serde(default)]
+    expires: Option<i64>,
+}
+
+/// Where `auth` stores tokens: `bangumi-tool/oauth.json` in the platform config directory.
+pub fn token_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("bangumi-tool").join("oauth.json"))
+}
+
+fn load(path: &Path) -> Option<StoredToken> {
+    let data = std::fs::read(path).ok()?;
+    serde_json::from_slice(&data)
+        .inspect_err(|e| warn!("Ignoring unreadable {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Write the tokens, readable only by the current user on Unix.
+fn save(token: &StoredToken, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, |w| Ok(serde_json::to_writer_pretty(w, token)?))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// POST to the token endpoint with the given form.
+async fn request_token(form: &[(&str, &str)]) -> Result<TokenResponse> {
+    let resp = reqwest::Client::new()
+        .post(TOKEN_URL)
+        .form(form)
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(AppError::Api {
+            status: status.as_u16(),
+            message: format!("OAuth: {}", resp.text().await.unwrap_or_default()),
+        });
+    }
+    Ok(resp.json().await?)
+}
+
+/// When `token` expires according to the token status endpoint; `None` if it
+/// doesn't expire.
+pub async fn token_expiry(token: &str) -> Result<Option<DateTime<Utc>>> {
+    let resp = reqwest::Client::new()
+        .post(TOKEN_STATUS_URL)
+        .form(&[("access_token", token)])
+        .send()
+        .await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(AppError::Api {
+            status: status.as_u16(),
+            message: format!("token status: {}", resp.text().await.unwrap_or_default()),
+        });
+    }
+    let status: TokenStatus = resp.json().await?;
+    Ok(status
+        .expires
+        .filter(|&t| t > 0)
+        .and_then(|t| DateTime::from_timestamp(t, 0)))
+}
+
+impl StoredToken {
+    fn from_response(
+        resp: TokenResponse,
+        client_id: &str,
+        client_secret: &str,
+        redirect_uri: &str,
+    ) -> Self {
+        Self {
+            access_token: resp.access_token,
+            refresh_token: resp.refresh_token,
+            expires_at: Utc::now() + TimeDelta::seconds(resp.expires_in),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+        }
+    }
+}
+
+/// Random value tying the redirect to this login attempt: 16 bytes from the
+/// OS, hex-encoded.
+fn make_state() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| AppError::OAuth(format!("no OS randomness for the login state: {}", e)))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Best-effort attempt to open `url` in the default browser. The opener's own
+/// output is discarded so it can't garble a full-screen UI.
+pub fn open_browser(url: &str) {
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    // Not `cmd /C start`, which would split the URL at each `&`.
+    #[cfg(target_os = "windows")]
+    let mut command = std::process::Command::new("rundll32");
+    #[cfg(target_os = "windows")]
+    command.arg("url.dll,FileProtocolHandler");
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = std::process::Command::new("xdg-open");
+    let result = command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    if let Err(e) = result {
+        debug!("Could not open a browser: {}", e);
+    }
+}
+
+/// Answer the browser with a short page.
+fn respond(stream: &mut std::net::TcpStream, status: &str, message: &str) {
+    let body = format!(
+        "<!doctype html><meta charset=\"utf-8\"><title>bangumi-tool</title><p>{}</p>",
+        message
+    );
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+}
+
+/// Wait for the authorization redirect and return its code.
+fn wait_for_code(listener: TcpListener, state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept()?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        // e.g. "GET /callback?code=...&state=... HTTP/1.1"
+        let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+        let Ok(url) = Url::parse(&format!("http://localhost{}", target)) else {
+            respond(&mut stream, "400 Bad Request", "Bad request");
+            continue;
+        };
+        if url.path() != "/callback" {
+            respond(&mut stream, "404 Not Found", "Not found");
+            continue;
+        }
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        if param("state").as_deref() != Some(state) {
+            respond(&mut stream, "400 Bad Request", "授权失败：state 不匹配");
+            return Err(AppError::OAuth(
+                "the redirect's state doesn't match this login".to_string(),
+            ));
+        }
+        return match param("code") {
+            Some(code) => {
+                respond(&mut stream, "200 OK", "授权完成，可以关闭此页面。");
+                Ok(code)
+            }
+            None => {
+                let error = param("error").unwrap_or_else(|| "no code in the redirect".into());
+                respond(&mut stream, "400 Bad Request", "授权失败");
+                Err(AppError::OAuth(error))
+            }
+        };
+    }
+}
+
+/// Run the authorization-code flow through a local redirect listener on `port`
+/// and store the resulting tokens. Returns where they were stored.
+pub async fn login(client_id: &str, client_secret: &str, port: u16) -> Result<PathBuf> {
+    let path = token_path()
+        .ok_or_else(|| AppError::OAuth("no config directory to store tokens in".to_string()))?;
+    let redirect_uri = format!("http://localhost:{}/callback", port);
+    let state = make_state()?;
+    let url = Url::parse_with_params(
+        AUTHORIZE_URL,
+        [
+            ("client_id", client_id),
+            ("response_type", "code"),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("state", state.as_str()),
+        ],
+    )
+    .map_err(|e| AppError::OAuth(e.to_string()))?;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Open this URL to authorize bangumi-tool:\n\n  {}\n", url);
+    open_browser(url.as_str());
+    let code = tokio::task::spawn_blocking(move || wait_for_code(listener, &state))
+        .await
+        .map_err(std::io::Error::other)??;
+
+    let resp = request_token(&[
+        ("grant_type", "authorization_code"),
+        ("client_id", client_id),
+        ("client_secret", client_secret),
+        ("code", &code),
+        ("redirect_uri", &redirect_uri),
+    ])
+    .await?;
+    let token = StoredToken::from_response(resp, client_id, client_secret, &redirect_uri);
+    save(&token, &path)?;
+    Ok(path)
+}
+
+/// The stored access token, refreshed first when it is close to expiry; `None`
+/// if `auth` was never run.
+///
+/// A failed refresh falls back to the current token while it is still valid.
+pub async fn access_token(path: &Path) -> Result<Option<String>> {
+    let Some(token) = load(path) else {
+        return Ok(None);
+    };
+    let left = token.expires_at - Utc::now();
+    if left > REFRESH_BEFORE {
+        return Ok(Some(token.access_token));
+    }
+    debug!(
+        "Refreshing the OAuth access token (expires {})",
+        token.expires_at
+    );
+    let refreshed = request_token(&[
+        ("grant_type", "refresh_token"),
+        ("client_id", &token.client_id),
+        ("client_secret", &token.client_secret),
+        ("refresh_token", &token.refresh_token),
+        ("redirect_uri", &token.redirect_uri),
+    ])
+    .await;
+    match refreshed {
+        Ok(resp) => {
+            let new = StoredToken::from_response(
+                resp,
+                &token.client_id,
+                &token.client_secret,
+                &token.redirect_uri,
+            );
+            save(&new, path)?;
+            Ok(Some(new.access_token))
+        }
+        Err(e) if left > TimeDelta::zero() => {
+            warn!(
+                "Could not refresh the OAuth token, using the current one: {}",
+                e
+            );
+            Ok(Some(token.access_token))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_is_random_hex() {
+        let a = make_state().unwrap();
+        let b = make_state().unwrap();
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,83 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::cli::Format;
+use crate::error::Result;
+use crate::export::write_atomic;
+use crate::models::{PersonCollection, career_name, person_type_name};
+
+/// One collected person as written to `bangumi_persons.*`.
+#[derive(Debug, Serialize)]
+pub struct PersonRecord {
+    pub id: u64,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub person_type: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub career: Vec<String>,
+    pub url: String,
+    pub collected_at: String,
+}
+
+impl PersonRecord {
+    pub fn new(p: &PersonCollection) -> Self {
+        Self {
+            id: p.id,
+            name: p.name.clone(),
+            person_type: person_type_name(p.person_type),
+            career: p
+                .career
+                .iter()
+                .map(|c| career_name(c).to_string())
+                .collect(),
+            url: format!("https://bgm.tv/person/{}", p.id),
+            collected_at: p
+                .created_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+        }
+    }
+}
+
+fn write_json(records: &[PersonRecord], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("bangumi_persons.json");
+    write_atomic(&path, |w| Ok(serde_json::to_writer(w, records)?))?;
+    Ok(path)
+}
+
+fn write_csv(records: &[PersonRecord], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("bangumi_persons.csv");
+    write_atomic(&path, |w| {
+        w.write_all(b"\xEF\xBB\xBF")?;
+        let mut wtr = csv::Writer::from_writer(w);
+        wtr.write_record(["ID", "名称", "类型", "职业", "地址", "收藏时间"])?;
+        for r in records {
+            wtr.write_record([
+                &r.id.to_string(),
+                &r.name,
+                r.person_type,
+                &r.career.join(", "),
+                &r.url,
+                &r.collected_at,
+            ])?;
+        }
+        wtr.flush()?;
+        Ok(())
+    })?;
+    Ok(path)
+}
+
+/// Write the person export in the formats selected for the main export.
+pub fn write(records: &[PersonRecord], format: &Format, dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(match format {
+        Format::Json => vec![write_json(records, dir)?],
+        Format::Csv => vec![write_csv(records, dir)?],
+        Format::All | Format::Ics | Format::Txt => {
+            vec![write_json(records, dir)?, write_csv(records, dir)?]
+        }
+    })
+}
@@ -0,0 +1,90 @@
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use indicatif::{MultiProgress, ProgressBar};
+
+use crate::cli::ProgressMode;
+
+/// How often a plain-mode reporter looks at its bar.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// A plain progress line is printed after this many more items...
+const LINE_EVERY_ITEMS: u64 = 25;
+/// ...or this much time, whichever comes first, as long as something changed.
+const LINE_EVERY: Duration = Duration::from_secs(10);
+
+static MODE: OnceLock<ProgressMode> = OnceLock::new();
+
+/// The progress display to use when `--progress` isn't given: none with
+/// `--quiet`, plain lines when stdout or stderr isn't a terminal or the `CI`
+/// environment variable is set, bars otherwise.
+pub fn resolve(choice: Option<ProgressMode>, quiet: bool) -> ProgressMode {
+    if quiet {
+        return ProgressMode::None;
+    }
+    choice.unwrap_or_else(|| {
+        let ci = std::env::var_os("CI").is_some_and(|v| !v.is_empty());
+        if ci || !std::io::stdout().is_terminal() || !std::io::stderr().is_terminal() {
+            ProgressMode::Plain
+        } else {
+            ProgressMode::Bar
+        }
+    })
+}
+
+/// Set the progress display for the rest of the process; later calls are ignored.
+pub fn set_mode(mode: ProgressMode) {
+    let _ = MODE.set(mode);
+}
+
+fn mode() -> ProgressMode {
+    MODE.get().copied().unwrap_or(ProgressMode::Bar)
+}
+
+/// Add `pb` to `multi`. In plain mode the bars are hidden, so a background
+/// reporter prints its progress instead, e.g. `fetched 120/450 collections`.
+pub fn add(multi: &MultiProgress, pb: ProgressBar, verb: &'static str, noun: &str) -> ProgressBar {
+    let pb = multi.add(pb);
+    if mode() == ProgressMode::Plain {
+        let weak = pb.downgrade();
+        let noun = noun.to_string();
+        std::thread::spawn(move || report(weak, verb, &noun));
+    }
+    pb
+}
+
+/// A bar's state as shown in a plain progress line: the count, or for a
+/// spinner that hasn't counted anything, its message.
+fn line(pb: &ProgressBar, verb: &str, noun: &str) -> String {
+    match pb.length().filter(|&len| len > 0) {
+        Some(len) => format!("{} {}/{} {}", verb, pb.position(), len, noun),
+        None if pb.position() > 0 => format!("{} {} {}", verb, pb.position(), noun),
+        None => pb.message(),
+    }
+}
+
+/// Print plain progress lines for a bar until it finishes or is dropped.
+fn report(weak: indicatif::WeakProgressBar, verb: &str, noun: &str) {
+    let mut last_line = String::new();
+    let mut last_pos = 0;
+    let mut last_time = Instant::now();
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let Some(pb) = weak.upgrade() else {
+            return;
+        };
+        let current = line(&pb, verb, noun);
+        let finished = pb.is_finished();
+        let due = pb.position() >= last_pos + LINE_EVERY_ITEMS || last_time.elapsed() >= LINE_EVERY;
+        if !current.is_empty() && current != last_line && (due || finished || last_line.is_empty())
+        {
+            eprintln!("{}", current);
+            last_line = current;
+            last_pos = pb.position();
+            last_time = Instant::now();
+        }
+        if finished {
+            return;
+        }
+    }
+}
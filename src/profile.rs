@@ -0,0 +1,111 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+use crate::export::write_atomic;
+use crate::models::User;
+
+/// One account's settings, stored as `profiles/<name>.toml` in the config directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub token: String,
+    /// Account the token belongs to, as of the last `get_me`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u64>,
+}
+
+/// Directory holding the profile files.
+pub fn profiles_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|d| d.join("bangumi-tool").join("profiles"))
+        .ok_or_else(|| std::io::Error::other("no config directory to keep profiles in").into())
+}
+
+/// Profile names become file and directory names, so keep them to a safe set.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidProfile(format!(
+            "{:?}: use letters, digits, '-' and '_'",
+            name
+        )))
+    }
+}
+
+fn path_of(name: &str) -> Result<PathBuf> {
+    validate_name(name)?;
+    Ok(profiles_dir()?.join(format!("{}.toml", name)))
+}
+
+/// Cache directory of a profile, kept apart from the default cache and from
+/// other profiles.
+pub fn cache_dir(name: &str) -> PathBuf {
+    dirs::cache_dir()
+        .map(|d| d.join("bangumi-tool-profiles"))
+        .unwrap_or_else(|| PathBuf::from(".bgm_cache_profiles"))
+        .join(name)
+}
+
+pub fn load(name: &str) -> Result<Profile> {
+    let path = path_of(name)?;
+    let text = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => AppError::ProfileNotFound(name.to_string()),
+        _ => e.into(),
+    })?;
+    Ok(toml::from_str(&text)?)
+}
+
+/// Write a profile, readable only by the current user on Unix since it holds a token.
+pub fn save(name: &str, profile: &Profile) -> Result<PathBuf> {
+    let path = path_of(name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let text = toml::to_string(profile)?;
+    write_atomic(&path, |w| Ok(w.write_all(text.as_bytes())?))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(path)
+}
+
+/// Record the account a profile's token belongs to, if it changed.
+pub fn remember_user(name: &str, user: &User) -> Result<()> {
+    let mut profile = load(name)?;
+    if profile.uid == Some(user.id) && profile.username.as_deref() == Some(&user.username) {
+        return Ok(());
+    }
+    profile.uid = Some(user.id);
+    profile.username = Some(user.username.clone());
+    save(name, &profile)?;
+    Ok(())
+}
+
+/// Names of all saved profiles, sorted.
+pub fn names() -> Result<Vec<String>> {
+    let Ok(entries) = std::fs::read_dir(profiles_dir()?) else {
+        return Ok(Vec::new());
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "toml")
+            && let Some(stem) = path.file_stem()
+        {
+            names.push(stem.to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
@@ -0,0 +1,63 @@
+use crate::models::{Episode, SubjectDetail, UserProgress, subject_type_name};
+use crate::next_up::format_sort;
+
+/// Episode title, preferring the Chinese one.
+fn title(ep: &Episode) -> &str {
+    if ep.name_cn.is_empty() {
+        &ep.name
+    } else {
+        &ep.name_cn
+    }
+}
+
+fn print_episodes(episodes: &[&Episode], watched: &[u64]) {
+    for ep in episodes {
+        let mark = if watched.contains(&ep.id) {
+            "✓"
+        } else {
+            "✗"
+        };
+        println!("  {} {:>5}  {}", mark, format_sort(ep.sort), title(ep));
+    }
+}
+
+/// Print the watch grid of one subject: main episodes, then specials and other
+/// episode types, followed by the completeness and the next episode to watch.
+pub fn print_progress(
+    detail: &SubjectDetail,
+    episodes: &[Episode],
+    progress: &Option<UserProgress>,
+) {
+    let watched = progress
+        .as_ref()
+        .map(UserProgress::watched_ids)
+        .unwrap_or_default();
+    let mut main: Vec<&Episode> = episodes.iter().filter(|e| e.episode_type == 0).collect();
+    main.sort_by(|a, b| a.sort.total_cmp(&b.sort));
+    let others: Vec<&Episode> = episodes.iter().filter(|e| e.episode_type != 0).collect();
+
+    let name = if detail.name_cn.is_empty() {
+        &detail.name
+    } else {
+        &detail.name_cn
+    };
+    println!(
+        "\n== {} [{}] ==",
+        name,
+        subject_type_name(detail.subject_type)
+    );
+    print_episodes(&main, &watched);
+    if !others.is_empty() {
+        println!("\n  SP / 其他:");
+        print_episodes(&others, &watched);
+    }
+
+    let seen = main.iter().filter(|e| watched.contains(&e.id)).count();
+    println!("\n  completeness: {}/{}", seen, main.len());
+    match main.iter().find(|e| !watched.contains(&e.id)) {
+        Some(ep) => println!("  next: ep {} {}", format_sort(ep.sort), title(ep)),
+        None if main.is_empty() => println!("  next: no episodes listed"),
+        None => println!("  next: caught up"),
+    }
+    println!();
+}
@@ -0,0 +1,125 @@
+use std::cmp::Ordering;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::models::ExportRecord;
+use crate::summary::{display_name, pad_to_width};
+
+/// How many items each direction of the report lists.
+const TOP: usize = 10;
+
+/// An item both I and the site have rated.
+#[derive(Debug)]
+pub struct RatingDiff<'a> {
+    pub name: &'a str,
+    pub subject_type: &'a str,
+    pub rate: u8,
+    pub score: f64,
+    /// `rate - score`.
+    pub diff: f64,
+}
+
+/// How my ratings compare with the site scores, over the items that have both.
+#[derive(Debug)]
+pub struct RatingDiffReport<'a> {
+    /// Items ordered from rated most above the site to most below.
+    pub items: Vec<RatingDiff<'a>>,
+    /// Mean of `rate - score`.
+    pub mean_diff: Option<f64>,
+    /// Pearson correlation between my ratings and the site scores; `None` with
+    /// fewer than two items or when either side doesn't vary.
+    pub correlation: Option<f64>,
+}
+
+impl<'a> RatingDiffReport<'a> {
+    pub fn new(records: &'a [ExportRecord]) -> Self {
+        let mut items: Vec<RatingDiff> = records
+            .iter()
+            .filter_map(|r| {
+                let diff = r.rating_diff?;
+                let rate: u8 = r.rating.parse().ok()?;
+                Some(RatingDiff {
+                    name: display_name(r),
+                    subject_type: &r.subject_type,
+                    rate,
+                    score: rate as f64 - diff,
+                    diff,
+                })
+            })
+            .collect();
+        items.sort_by(|a, b| b.diff.partial_cmp(&a.diff).unwrap_or(Ordering::Equal));
+        let n = items.len() as f64;
+        let mean_diff = (!items.is_empty()).then(|| items.iter().map(|i| i.diff).sum::<f64>() / n);
+        Self {
+            correlation: correlation(&items),
+            mean_diff,
+            items,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("\n== 与站内评分的差异: {} 个条目 ==", self.items.len());
+        if self.items.is_empty() {
+            println!("  没有同时有我的评分和站内评分的条目\n");
+            return;
+        }
+        if let Some(mean) = self.mean_diff {
+            println!("  平均差 {:+.2}", mean);
+        }
+        match self.correlation {
+            Some(r) => println!("  相关系数 {:.2}", r),
+            None => println!("  相关系数 -"),
+        }
+        let higher: Vec<&RatingDiff> = self
+            .items
+            .iter()
+            .filter(|i| i.diff > 0.0)
+            .take(TOP)
+            .collect();
+        let lower: Vec<&RatingDiff> = self
+            .items
+            .iter()
+            .rev()
+            .filter(|i| i.diff < 0.0)
+            .take(TOP)
+            .collect();
+        print_group("比站内评分高最多", &higher);
+        print_group("比站内评分低最多", &lower);
+        println!();
+    }
+}
+
+fn print_group(title: &str, items: &[&RatingDiff]) {
+    if items.is_empty() {
+        return;
+    }
+    println!("\n  {}:", title);
+    let name_width = items.iter().map(|i| i.name.width()).max().unwrap_or(0);
+    for i in items {
+        println!(
+            "    {}  [{}]  我 {:>2}  站内 {:>4.1}  {:+.1}",
+            pad_to_width(i.name, name_width),
+            pad_to_width(i.subject_type, 6),
+            i.rate,
+            i.score,
+            i.diff
+        );
+    }
+}
+
+fn correlation(items: &[RatingDiff]) -> Option<f64> {
+    if items.len() < 2 {
+        return None;
+    }
+    let n = items.len() as f64;
+    let mean_rate = items.iter().map(|i| i.rate as f64).sum::<f64>() / n;
+    let mean_score = items.iter().map(|i| i.score).sum::<f64>() / n;
+    let (mut cov, mut var_rate, mut var_score) = (0.0, 0.0, 0.0);
+    for i in items {
+        let (dr, ds) = (i.rate as f64 - mean_rate, i.score - mean_score);
+        cov += dr * ds;
+        var_rate += dr * dr;
+        var_score += ds * ds;
+    }
+    (var_rate > 0.0 && var_score > 0.0).then(|| cov / (var_rate * var_score).sqrt())
+}
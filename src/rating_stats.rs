@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::models::{Collection, subject_type_name};
+
+/// Widest histogram bar, in characters.
+const BAR_WIDTH: u64 = 40;
+
+/// Rating distribution for a set of collections.
+#[derive(Debug, Default, Serialize)]
+pub struct RatingStats {
+    /// Count per score; index 0 is score 1.
+    pub counts: [u64; 10],
+    pub rated: u64,
+    pub unrated: u64,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+}
+
+impl RatingStats {
+    pub fn from_collections<'a>(collections: impl IntoIterator<Item = &'a Collection>) -> Self {
+        let mut stats = Self::default();
+        let mut ratings = Vec::new();
+        for col in collections {
+            match col.rate {
+                1..=10 => {
+                    stats.counts[col.rate as usize - 1] += 1;
+                    ratings.push(col.rate);
+                }
+                _ => stats.unrated += 1,
+            }
+        }
+        stats.rated = ratings.len() as u64;
+        if !ratings.is_empty() {
+            ratings.sort_unstable();
+            let sum: u64 = ratings.iter().map(|&r| r as u64).sum();
+            stats.mean = Some(sum as f64 / ratings.len() as f64);
+            let mid = ratings.len() / 2;
+            stats.median = Some(if ratings.len() % 2 == 0 {
+                (ratings[mid - 1] as f64 + ratings[mid] as f64) / 2.0
+            } else {
+                ratings[mid] as f64
+            });
+        }
+        stats
+    }
+
+    /// Print a horizontal ASCII bar chart, highest score first.
+    fn print(&self, label: &str) {
+        println!(
+            "\n== {} (rated {}, unrated {}) ==",
+            label, self.rated, self.unrated
+        );
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        for score in (1..=10).rev() {
+            let count = self.counts[score - 1];
+            let len = (count * BAR_WIDTH).checked_div(max).unwrap_or(0);
+            let bar = if count > 0 && len == 0 {
+                "#".to_string()
+            } else {
+                "#".repeat(len as usize)
+            };
+            println!("  {:>2} | {} {}", score, bar, count);
+        }
+        match (self.mean, self.median) {
+            (Some(mean), Some(median)) => println!("  mean {:.2}, median {}", mean, median),
+            _ => println!("  no ratings"),
+        }
+    }
+}
+
+/// Rating statistics overall and per subject type.
+#[derive(Debug, Serialize)]
+pub struct RatingReport {
+    pub all: RatingStats,
+    pub by_type: BTreeMap<String, RatingStats>,
+}
+
+impl RatingReport {
+    pub fn new(collections: &[Collection]) -> Self {
+        let mut grouped: BTreeMap<&str, Vec<&Collection>> = BTreeMap::new();
+        for col in collections {
+            grouped
+                .entry(subject_type_name(col.subject.subject_type))
+                .or_default()
+                .push(col);
+        }
+        Self {
+            all: RatingStats::from_collections(collections),
+            by_type: grouped
+                .into_iter()
+                .map(|(name, cols)| (name.to_string(), RatingStats::from_collections(cols)))
+                .collect(),
+        }
+    }
+
+    pub fn print(&self) {
+        self.all.print("全部");
+        for (name, stats) in &self.by_type {
+            stats.print(name);
+        }
+        println!();
+    }
+
+    /// Write the report as `stats.json` in the output directory.
+    pub fn write_json(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("stats.json");
+        let file = std::fs::File::create(&path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+
+use crate::error::{AppError, Result};
+use crate::models::{
+    self, Collection, ExportRecord, InfoboxItem, SUBJECT_TYPE_MUSIC, SimpleRecord, SubjectDetail,
+    SubjectTag, TrackCount, UserProgress, collection_status_name, run_length_encode,
+    subject_type_name,
+};
+
+/// Completeness of records without episode progress, such as music subjects.
+pub const NO_PROGRESS: &str = "—";
+
+/// Format of record timestamps unless `--date-format` says otherwise.
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Time zone that record timestamps are given in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeZone {
+    /// The zone of the machine running the export.
+    #[default]
+    Local,
+    Utc,
+    /// An IANA zone such as `Asia/Shanghai`.
+    Named(Tz),
+}
+
+impl FromStr for TimeZone {
+    type Err = AppError;
+
+    /// Parse `local`, `utc` or an IANA zone name.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("local") {
+            Ok(Self::Local)
+        } else if s.eq_ignore_ascii_case("utc") {
+            Ok(Self::Utc)
+        } else {
+            s.parse()
+                .map(Self::Named)
+                .map_err(|_| AppError::UnknownTimeZone(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => f.write_str("local"),
+            Self::Utc => f.write_str("UTC"),
+            Self::Named(tz) => write!(f, "{}", tz),
+        }
+    }
+}
+
+/// How record timestamps such as `updated_at` are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampFormat {
+    timezone: TimeZone,
+    format: String,
+}
+
+impl Default for TimestampFormat {
+    /// Local time as `2025-01-31 20:15:00`.
+    fn default() -> Self {
+        Self {
+            timezone: TimeZone::Local,
+            format: DEFAULT_DATE_FORMAT.to_string(),
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// Timestamps in `timezone`, formatted with the strftime string `format`.
+    /// Fails if `format` has an unknown or incomplete specifier.
+    pub fn new(timezone: TimeZone, format: &str) -> Result<Self> {
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            return Err(AppError::InvalidDateFormat(format.to_string()));
+        }
+        Ok(Self {
+            timezone,
+            format: format.to_string(),
+        })
+    }
+
+    /// The zone timestamps are given in.
+    pub fn timezone(&self) -> TimeZone {
+        self.timezone
+    }
+
+    /// `t` as written to records.
+    pub fn render(&self, t: &DateTime<Utc>) -> String {
+        match self.timezone {
+            TimeZone::Local => t.with_timezone(&Local).format(&self.format).to_string(),
+            TimeZone::Utc => t.format(&self.format).to_string(),
+            TimeZone::Named(tz) => t.with_timezone(&tz).format(&self.format).to_string(),
+        }
+    }
+}
+
+/// How many community tags records keep unless `--site-tags-limit` says otherwise.
+pub const DEFAULT_SITE_TAGS_LIMIT: usize = 10;
+
+/// The `limit` most used of a subject's community tags, comma-separated.
+/// Tags with the same count keep the API's order.
+pub fn site_tags(tags: &[SubjectTag], limit: usize) -> String {
+    let mut tags: Vec<&SubjectTag> = tags.iter().collect();
+    tags.sort_by_key(|t| std::cmp::Reverse(t.count));
+    tags.iter()
+        .take(limit)
+        .map(|t| t.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// What detail records hold beyond the collection and episode data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordOptions {
+    /// How timestamps such as `updated_at` are written.
+    pub timestamps: TimestampFormat,
+    /// How many community tags to keep.
+    pub site_tags_limit: usize,
+    /// Keep the subject's summary.
+    pub include_summary: bool,
+    /// Infobox keys to pick, such as 平台 or 作者.
+    pub infobox_fields: Vec<String>,
+    /// Look up related subjects, at one more request per subject.
+    pub relations: bool,
+    /// Keep the titles of watched episodes.
+    pub episode_titles: bool,
+    /// Fetch the track lists of music subjects to count their tracks and discs.
+    pub music_tracks: bool,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        Self {
+            timestamps: TimestampFormat::default(),
+            site_tags_limit: DEFAULT_SITE_TAGS_LIMIT,
+            include_summary: false,
+            infobox_fields: Vec::new(),
+            relations: false,
+            episode_titles: false,
+            music_tracks: false,
+        }
+    }
+}
+
+/// Set the fields a detail record takes from the subject, as `opts` asks.
+/// Also used to bring saved resume records up to date.
+pub fn fill_subject_fields(
+    record: &mut ExportRecord,
+    detail: &SubjectDetail,
+    opts: &RecordOptions,
+) {
+    record.site_tags = site_tags(&detail.tags, opts.site_tags_limit);
+    record.rating_diff = rating_diff(&record.rating, detail.rating.score);
+    record.summary = opts.include_summary.then(|| detail.summary.clone());
+    record.infobox = opts
+        .infobox_fields
+        .iter()
+        .map(|key| (key.clone(), infobox_field(&detail.infobox, key)))
+        .collect();
+}
+
+/// My rating minus the site score, rounded to two decimals; `None` when I
+/// haven't rated the subject or the site hasn't scored it.
+fn rating_diff(rating: &str, score: f64) -> Option<f64> {
+    let rate: u8 = rating.parse().ok().filter(|&rate| rate > 0)?;
+    (score > 0.0).then(|| ((rate as f64 - score) * 100.0).round() / 100.0)
+}
+
+/// Without the subject at hand, make a record's optional subject fields match
+/// `opts`: values it already has are kept, newly asked for ones left empty and
+/// the rest dropped.
+pub fn align_subject_fields(record: &mut ExportRecord, opts: &RecordOptions) {
+    if record.summary.is_some() != opts.include_summary {
+        record.summary = opts.include_summary.then(String::new);
+    }
+    if record.relations.is_some() != opts.relations {
+        record.relations = opts.relations.then(Vec::new);
+    }
+    let old = std::mem::take(&mut record.infobox);
+    record.infobox = opts
+        .infobox_fields
+        .iter()
+        .map(|key| {
+            let value = old.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+            (key.clone(), value.unwrap_or_default())
+        })
+        .collect();
+}
+
+/// Every value of the infobox rows named `key`, joined with `、`; empty if
+/// there are none.
+fn infobox_field(infobox: &[InfoboxItem], key: &str) -> String {
+    infobox
+        .iter()
+        .filter(|item| item.key == key)
+        .flat_map(|item| &item.value)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("、")
+}
+
+/// Highest episode in a run-length string like `1-5,7,9-12`.
+pub fn last_watched_episode(watched_eps: &str) -> Option<u64> {
+    watched_eps
+        .split(',')
+        .filter_map(|part| part.rsplit('-').next()?.trim().parse().ok())
+        .max()
+}
+
+/// Where an on-hold or dropped item stopped: the last main episode watched,
+/// falling back to the collection's `ep_status`. `None` for other statuses
+/// or when nothing was watched.
+pub fn stopped_at(col: &Collection, last_watched: Option<u64>) -> Option<u64> {
+    match col.collection_type {
+        4 | 5 => last_watched.or((col.ep_status > 0).then_some(col.ep_status)),
+        _ => None,
+    }
+}
+
+/// An episode as listed in `watched_titles`: `第7话 「title」`, or just
+/// `第7话` when it has no title.
+fn episode_label(ep: &models::Episode) -> String {
+    let number = if ep.sort.fract() == 0.0 {
+        format!("{}", ep.sort as i64)
+    } else {
+        ep.sort.to_string()
+    };
+    match ep.title() {
+        "" => format!("第{}话", number),
+        title => format!("第{}话 「{}」", number, title),
+    }
+}
+
+/// Build a SimpleRecord from collection data only.
+pub fn build_simple_record(col: &Collection, timestamps: &TimestampFormat) -> SimpleRecord {
+    SimpleRecord {
+        subject_id: col.subject_id,
+        name: col.subject.name.clone(),
+        name_cn: col.subject.name_cn.clone(),
+        subject_type: subject_type_name(col.subject.subject_type).to_string(),
+        url: format!("https://bgm.tv/subject/{}", col.subject_id),
+        status: collection_status_name(col.collection_type, col.subject.subject_type).to_string(),
+        collection_type: col.collection_type,
+        updated_at: timestamps.render(&col.updated_at),
+        rating: if col.rate == 0 {
+            String::new()
+        } else {
+            col.rate.to_string()
+        },
+        tags: col.tags.join(", "),
+        comment: col.comment.clone().unwrap_or_default(),
+        nsfw: col.subject.nsfw,
+        private: col.private,
+    }
+}
+
+/// Build an ExportRecord with full detail.
+pub fn build_detail_record(
+    col: &Collection,
+    detail: &SubjectDetail,
+    all_episodes: &[models::Episode],
+    progress: &Option<UserProgress>,
+    opts: &RecordOptions,
+) -> ExportRecord {
+    if col.subject.subject_type == SUBJECT_TYPE_MUSIC {
+        return build_music_record(col, detail, all_episodes, opts);
+    }
+    let sid = col.subject_id;
+    let total_eps = detail.total_episodes.max(detail.eps);
+
+    let main_eps: Vec<_> = all_episodes
+        .iter()
+        .filter(|e| e.episode_type == 0)
+        .collect();
+    let main_ep_count = main_eps.len() as u64;
+
+    let watched_ep_ids: Vec<u64> = progress
+        .as_ref()
+        .map(UserProgress::watched_ids)
+        .unwrap_or_default();
+
+    let watched_main_eps: Vec<&models::Episode> = main_eps
+        .iter()
+        .filter(|e| watched_ep_ids.contains(&e.id))
+        .copied()
+        .collect();
+    let watched_sort_nums: Vec<u64> = watched_main_eps.iter().map(|e| e.sort as u64).collect();
+    let watched_titles = opts.episode_titles.then(|| {
+        let mut eps = watched_main_eps.clone();
+        eps.sort_by(|a, b| a.sort.total_cmp(&b.sort));
+        eps.into_iter().map(episode_label).collect()
+    });
+
+    let watched_count = watched_sort_nums.len() as u64;
+    let completeness = format!("{}/{}", watched_count, main_ep_count);
+
+    let today = Local::now().date_naive();
+    let aired_count = main_eps.iter().filter(|e| e.aired_by(today)).count() as u64;
+    let aired_completeness = if main_ep_count > 0 {
+        format!("{}/{}", watched_count.min(aired_count), aired_count)
+    } else {
+        String::new()
+    };
+    let caught_up = main_ep_count > 0 && watched_count >= aired_count;
+    let completeness_pct = if main_ep_count > 0 {
+        format!(
+            "{:.0}%",
+            watched_count as f64 / main_ep_count as f64 * 100.0
+        )
+    } else if total_eps > 0 {
+        format!("{:.0}%", col.ep_status as f64 / total_eps as f64 * 100.0)
+    } else {
+        "N/A".to_string()
+    };
+
+    let watched_eps_str = run_length_encode(&watched_sort_nums);
+    let stopped_at = stopped_at(col, watched_sort_nums.iter().max().copied());
+
+    let mut record = ExportRecord {
+        subject_id: sid,
+        name: col.subject.name.clone(),
+        name_cn: col.subject.name_cn.clone(),
+        subject_type: subject_type_name(col.subject.subject_type).to_string(),
+        url: format!("https://bgm.tv/subject/{}", sid),
+        status: collection_status_name(col.collection_type, col.subject.subject_type).to_string(),
+        collection_type: col.collection_type,
+        updated_at: opts.timestamps.render(&col.updated_at),
+        completeness,
+        completeness_pct,
+        watched_eps: watched_eps_str,
+        stopped_at,
+        aired_completeness,
+        caught_up,
+        rating: if col.rate == 0 {
+            String::new()
+        } else {
+            col.rate.to_string()
+        },
+        rating_diff: None,
+        tags: col.tags.join(", "),
+        site_tags: String::new(),
+        comment: col.comment.clone().unwrap_or_default(),
+        summary: None,
+        infobox: Vec::new(),
+        relations: None,
+        watched_titles,
+        nsfw: col.subject.nsfw || detail.nsfw,
+        private: col.private,
+        tracks: None,
+    };
+    fill_subject_fields(&mut record, detail, opts);
+    record
+}
+
+/// Build the record of a music subject. Its episodes are tracks, which can't
+/// be marked as listened, so there is no episode progress: the completeness is
+/// `NO_PROGRESS` and the status alone tells whether it was listened to. With
+/// `opts.music_tracks`, `all_episodes` is the track list and is counted.
+fn build_music_record(
+    col: &Collection,
+    detail: &SubjectDetail,
+    all_episodes: &[models::Episode],
+    opts: &RecordOptions,
+) -> ExportRecord {
+    let mut record = record_without_progress(col, opts, NO_PROGRESS, "N/A");
+    record.nsfw |= detail.nsfw;
+    record.tracks = opts.music_tracks.then(|| TrackCount::new(all_episodes));
+    fill_subject_fields(&mut record, detail, opts);
+    record
+}
+
+/// Build a placeholder ExportRecord for a subject whose detail could not be fetched.
+pub fn build_failed_record(col: &Collection, opts: &RecordOptions) -> ExportRecord {
+    let mut record = record_without_progress(col, opts, "ERROR", "");
+    align_subject_fields(&mut record, opts);
+    record
+}
+
+/// A detail record holding only collection data, with the given completeness.
+fn record_without_progress(
+    col: &Collection,
+    opts: &RecordOptions,
+    completeness: &str,
+    completeness_pct: &str,
+) -> ExportRecord {
+    let simple = build_simple_record(col, &opts.timestamps);
+    ExportRecord {
+        subject_id: simple.subject_id,
+        name: simple.name,
+        name_cn: simple.name_cn,
+        subject_type: simple.subject_type,
+        url: simple.url,
+        status: simple.status,
+        collection_type: simple.collection_type,
+        updated_at: simple.updated_at,
+        completeness: completeness.to_string(),
+        completeness_pct: completeness_pct.to_string(),
+        watched_eps: String::new(),
+        stopped_at: stopped_at(col, None),
+        aired_completeness: String::new(),
+        caught_up: false,
+        rating: simple.rating,
+        rating_diff: None,
+        tags: simple.tags,
+        site_tags: String::new(),
+        comment: simple.comment,
+        summary: None,
+        infobox: Vec::new(),
+        relations: None,
+        watched_titles: opts.episode_titles.then(Vec::new),
+        nsfw: simple.nsfw,
+        private: simple.private,
+        tracks: None,
+    }
+}
+
+/// Subject id of a saved record; records from older versions only have the URL.
+pub fn record_subject_id(record: &ExportRecord) -> Option<u64> {
+    if record.subject_id > 0 {
+        Some(record.subject_id)
+    } else {
+        models::subject_id_from_url(&record.url)
+    }
+}
+
+/// A record whose personal fields `--anonymize` strips.
+pub trait Anonymize {
+    /// Subject id, if known.
+    fn anonymize_key(&self) -> Option<u64>;
+    /// Whether the collection is private.
+    fn is_private(&self) -> bool;
+    /// Empty my tags and comment, and replace `updated_at` with `date`.
+    fn strip_personal(&mut self, date: String);
+}
+
+impl Anonymize for SimpleRecord {
+    fn anonymize_key(&self) -> Option<u64> {
+        Some(self.subject_id)
+    }
+    fn is_private(&self) -> bool {
+        self.private
+    }
+    fn strip_personal(&mut self, date: String) {
+        self.tags.clear();
+        self.comment.clear();
+        self.updated_at = date;
+    }
+}
+
+impl Anonymize for ExportRecord {
+    fn anonymize_key(&self) -> Option<u64> {
+        record_subject_id(self)
+    }
+    fn is_private(&self) -> bool {
+        self.private
+    }
+    fn strip_personal(&mut self, date: String) {
+        self.tags.clear();
+        self.comment.clear();
+        self.updated_at = date;
+    }
+}
+
+/// Prepare records for publishing: drop private collections, empty my tags and
+/// comments, and cut `updated_at` down to the date in `timezone`. Dates come
+/// from `collections`, since records only keep the formatted timestamp; a
+/// record without a matching collection gets an empty date.
+pub fn anonymize<R: Anonymize>(
+    records: &mut Vec<R>,
+    collections: &[Collection],
+    timezone: TimeZone,
+) {
+    let dates = TimestampFormat {
+        timezone,
+        format: "%Y-%m-%d".to_string(),
+    };
+    let updated: HashMap<u64, &DateTime<Utc>> = collections
+        .iter()
+        .map(|c| (c.subject_id, &c.updated_at))
+        .collect();
+    records.retain(|r| !r.is_private());
+    for record in records {
+        let date = record
+            .anonymize_key()
+            .and_then(|id| updated.get(&id))
+            .map(|t| dates.render(t))
+            .unwrap_or_default();
+        record.strip_personal(date);
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Local};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::models::{Collection, ExportRecord, subject_type_name};
+
+/// How many top-rated titles the review lists.
+const TOP_RATED: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct RatedTitle {
+    pub name: String,
+    pub subject_type: String,
+    pub rating: u8,
+}
+
+/// Year-in-review built from the collections updated in one calendar year.
+#[derive(Debug, Serialize)]
+pub struct YearReview {
+    pub year: i32,
+    /// Collections updated during the year, in any status.
+    pub updated: u64,
+    /// Subjects marked as done, per month (index 0 is January).
+    pub completed_per_month: [u64; 12],
+    pub completed: u64,
+    /// Month (1-12) with the most completions, if any.
+    pub busiest_month: Option<u32>,
+    /// Main episodes watched; only known with `--detail`.
+    pub episodes_watched: Option<u64>,
+    pub average_rating: Option<f64>,
+    pub top_rated: Vec<RatedTitle>,
+}
+
+impl YearReview {
+    /// Build the review. `records` are the detail records matching `collections`
+    /// one-to-one, when available.
+    pub fn new(year: i32, collections: &[Collection], records: Option<&[ExportRecord]>) -> Self {
+        let mut completed_per_month = [0u64; 12];
+        for col in collections.iter().filter(|c| c.collection_type == 2) {
+            let month = col.updated_at.with_timezone(&Local).month0();
+            completed_per_month[month as usize] += 1;
+        }
+        let completed = completed_per_month.iter().sum();
+        let busiest_month = (completed > 0).then(|| {
+            // max_by_key keeps the last maximum; reverse so ties go to the earlier month
+            let (i, _) = completed_per_month
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|(_, n)| **n)
+                .unwrap_or((0, &0));
+            i as u32 + 1
+        });
+
+        // Placeholder records for failed subjects have no "watched/total" progress.
+        let episodes_watched = records.map(|records| {
+            records
+                .iter()
+                .filter_map(|r| r.completeness.split_once('/'))
+                .filter_map(|(watched, _)| watched.parse::<u64>().ok())
+                .sum()
+        });
+
+        let mut rated: Vec<&Collection> = collections.iter().filter(|c| c.rate > 0).collect();
+        let average_rating = (!rated.is_empty())
+            .then(|| rated.iter().map(|c| c.rate as f64).sum::<f64>() / rated.len() as f64);
+        rated.sort_by(|a, b| b.rate.cmp(&a.rate).then(b.updated_at.cmp(&a.updated_at)));
+        let top_rated = rated
+            .into_iter()
+            .take(TOP_RATED)
+            .map(|c| RatedTitle {
+                name: if c.subject.name_cn.is_empty() {
+                    c.subject.name.clone()
+                } else {
+                    c.subject.name_cn.clone()
+                },
+                subject_type: subject_type_name(c.subject.subject_type).to_string(),
+                rating: c.rate,
+            })
+            .collect();
+
+        Self {
+            year,
+            updated: collections.len() as u64,
+            completed_per_month,
+            completed,
+            busiest_month,
+            episodes_watched,
+            average_rating,
+            top_rated,
+        }
+    }
+
+    /// Render the review as Markdown; also used for the terminal output.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# {} 年度总结\n", self.year);
+        let _ = writeln!(out, "- 标注条目：{}", self.updated);
+        let _ = writeln!(out, "- 完成条目：{}", self.completed);
+        if let Some(eps) = self.episodes_watched {
+            let _ = writeln!(out, "- 看过正片：{} 话", eps);
+        }
+        if let Some(avg) = self.average_rating {
+            let _ = writeln!(out, "- 平均评分：{:.2}", avg);
+        }
+        if let Some(month) = self.busiest_month {
+            let _ = writeln!(
+                out,
+                "- 最忙的月份：{} 月（完成 {} 个）",
+                month,
+                self.completed_per_month[month as usize - 1]
+            );
+        }
+
+        let _ = writeln!(out, "\n## 每月完成\n");
+        let _ = writeln!(out, "| 月份 | 完成 |");
+        let _ = writeln!(out, "| ---: | ---: |");
+        for (i, n) in self.completed_per_month.iter().enumerate() {
+            let _ = writeln!(out, "| {} | {} |", i + 1, n);
+        }
+
+        if !self.top_rated.is_empty() {
+            let _ = writeln!(out, "\n## 高分作品\n");
+            for (i, t) in self.top_rated.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "{}. {} [{}] {}",
+                    i + 1,
+                    t.name,
+                    t.subject_type,
+                    t.rating
+                );
+            }
+        }
+        out
+    }
+
+    /// Write `review_<year>.json` and `review_<year>.md` to the output directory.
+    pub fn write(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let json_path = dir.join(format!("review_{}.json", self.year));
+        let file = std::fs::File::create(&json_path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        let md_path = dir.join(format!("review_{}.md", self.year));
+        std::fs::write(&md_path, self.to_markdown())?;
+        Ok(vec![json_path, md_path])
+    }
+}
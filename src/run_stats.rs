@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use indicatif::{HumanBytes, HumanDuration};
+use serde::Serialize;
+
+use crate::cache::CacheStats;
+use crate::client::{Latency, RequestStats};
+use crate::error::Result;
+
+/// End-of-run statistics: requests, cache usage and timing.
+#[derive(Debug, Serialize)]
+pub struct RunStats {
+    pub wall_time_secs: f64,
+    pub items: usize,
+    pub avg_item_secs: f64,
+    pub requests: BTreeMap<String, u64>,
+    pub total_requests: u64,
+    pub retries: u64,
+    /// Response time percentiles per endpoint.
+    pub latency: BTreeMap<String, Latency>,
+    pub bytes_downloaded: u64,
+    pub cache: CacheStats,
+}
+
+impl RunStats {
+    pub fn new(elapsed: Duration, items: usize, requests: RequestStats, cache: CacheStats) -> Self {
+        let wall_time_secs = elapsed.as_secs_f64();
+        Self {
+            wall_time_secs,
+            items,
+            avg_item_secs: if items > 0 {
+                wall_time_secs / items as f64
+            } else {
+                0.0
+            },
+            total_requests: requests.total_requests(),
+            retries: requests.retries,
+            latency: requests.latency,
+            requests: requests.requests,
+            bytes_downloaded: requests.bytes,
+            cache,
+        }
+    }
+
+    /// Print a short human-readable report.
+    pub fn print(&self) {
+        println!("\nRun statistics:");
+        println!(
+            "  Wall time:  {} ({:.1}s per item)",
+            HumanDuration(Duration::from_secs_f64(self.wall_time_secs)),
+            self.avg_item_secs
+        );
+        println!("  Requests:   {}", self.total_requests);
+        for (endpoint, count) in &self.requests {
+            match self.latency.get(endpoint) {
+                Some(l) => println!(
+                    "    {:<40} {:>5}  p50 {}ms  p95 {}ms",
+                    endpoint, count, l.p50_ms, l.p95_ms
+                ),
+                None => println!("    {:<40} {:>5}", endpoint, count),
+            }
+        }
+        println!("  Retries:    {}", self.retries);
+        println!("  Downloaded: {}", HumanBytes(self.bytes_downloaded));
+        println!(
+            "  Cache:      {} hits, {} misses, {} writes",
+            self.cache.hits, self.cache.misses, self.cache.writes
+        );
+    }
+
+    /// Write the statistics as `run_stats.json` in the output directory.
+    pub fn write_json(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("run_stats.json");
+        let file = std::fs::File::create(&path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::models::{Collection, SearchSubject, collection_status_name, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// A search result, with my collection status when it is known.
+#[derive(Debug, Serialize)]
+pub struct SearchRow {
+    pub id: u64,
+    pub name: String,
+    pub name_cn: String,
+    pub subject_type: &'static str,
+    pub date: Option<String>,
+    /// Bangumi score; `None` when the subject has no ratings yet.
+    pub score: Option<f64>,
+    /// Whether the subject is in my collection; `None` when the collection cache
+    /// was not available to check against.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_collection: Option<bool>,
+    /// My collection status, if collected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+}
+
+/// Turn search results into rows, marking collected subjects when the collection is known.
+pub fn rows(results: &[SearchSubject], collections: Option<&[Collection]>) -> Vec<SearchRow> {
+    let collected: Option<HashMap<u64, &Collection>> =
+        collections.map(|cols| cols.iter().map(|c| (c.subject_id, c)).collect());
+    results
+        .iter()
+        .map(|s| {
+            let col = collected.as_ref().and_then(|m| m.get(&s.id));
+            SearchRow {
+                id: s.id,
+                name: s.name.clone(),
+                name_cn: s.name_cn.clone(),
+                subject_type: subject_type_name(s.subject_type),
+                date: s.date.clone().filter(|d| !d.is_empty()),
+                score: (s.rating.score > 0.0).then_some(s.rating.score),
+                in_collection: collected.as_ref().map(|_| col.is_some()),
+                status: col.map(|c| {
+                    collection_status_name(c.collection_type, c.subject.subject_type).to_string()
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Print search results as an aligned table.
+pub fn print_results(rows: &[SearchRow], keyword: &str, total: u64) {
+    println!("\n== 搜索 \"{}\": {}/{} ==", keyword, rows.len(), total);
+    let id_width = rows
+        .iter()
+        .map(|r| r.id.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let name_width = rows.iter().map(|r| r.name.width()).max().unwrap_or(0);
+    let cn_width = rows.iter().map(|r| r.name_cn.width()).max().unwrap_or(0);
+    for r in rows {
+        let score = r
+            .score
+            .map(|s| format!("{:.1}", s))
+            .unwrap_or_else(|| "-".to_string());
+        let mark = r
+            .status
+            .as_ref()
+            .map(|s| format!("  [{}]", s))
+            .unwrap_or_default();
+        println!(
+            "  {:>iw$}  {}  {}  [{}]  {:<10}  {:>4}{}",
+            r.id,
+            pad_to_width(&r.name, name_width),
+            pad_to_width(&r.name_cn, cn_width),
+            pad_to_width(r.subject_type, 6),
+            r.date.as_deref().unwrap_or("-"),
+            score,
+            mark,
+            iw = id_width
+        );
+    }
+    if rows.iter().all(|r| r.in_collection.is_none()) && !rows.is_empty() {
+        println!("  (collection cache is empty; run an export first to mark collected subjects)");
+    }
+    println!();
+}
@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{AppError, Result};
+
+/// Snapshots live under `<output>/snapshots/<name>/`, listed in `index.json`.
+const SNAPSHOT_DIR: &str = "snapshots";
+const INDEX_FILE: &str = "index.json";
+const EXPORT_FILE: &str = "bangumi_export.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    /// Directory name, also the timestamp, e.g. `2024-06-01_120000`.
+    pub name: String,
+    pub created_at: String,
+    pub records: usize,
+}
+
+/// All snapshots, oldest first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnapshotIndex {
+    pub snapshots: Vec<SnapshotEntry>,
+}
+
+fn snapshots_dir(out_dir: &Path) -> PathBuf {
+    out_dir.join(SNAPSHOT_DIR)
+}
+
+/// Load the snapshot index, or an empty one when none was written yet.
+pub fn load_index(out_dir: &Path) -> Result<SnapshotIndex> {
+    let path = snapshots_dir(out_dir).join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(SnapshotIndex::default());
+    }
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn save_index(out_dir: &Path, index: &SnapshotIndex) -> Result<()> {
+    let path = snapshots_dir(out_dir).join(INDEX_FILE);
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer_pretty(file, index)?;
+    Ok(())
+}
+
+/// Load the export stored in a snapshot.
+pub fn load_snapshot(out_dir: &Path, name: &str) -> Result<Vec<Value>> {
+    let path = snapshots_dir(out_dir).join(name).join(EXPORT_FILE);
+    if !path.exists() {
+        return Err(AppError::SnapshotNotFound(name.to_string()));
+    }
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// Write the compact export into a new snapshot, record it in the index and
+/// prune the oldest snapshots beyond `keep`. The new snapshot is always kept,
+/// even with a `keep` of 0.
+pub fn write_snapshot(out_dir: &Path, records: &[Value], keep: u64) -> Result<PathBuf> {
+    let keep = (keep as usize).max(1);
+    let now = Local::now();
+    let mut name = now.format("%Y-%m-%d_%H%M%S").to_string();
+    let mut dir = snapshots_dir(out_dir).join(&name);
+    // Snapshots are immutable; never write into an existing one.
+    let mut n = 1;
+    while dir.exists() {
+        n += 1;
+        name = format!("{}_{}", now.format("%Y-%m-%d_%H%M%S"), n);
+        dir = snapshots_dir(out_dir).join(&name);
+    }
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(EXPORT_FILE);
+    let file = std::fs::File::create(&path)?;
+    serde_json::to_writer(file, records)?;
+
+    let mut index = load_index(out_dir)?;
+    index.snapshots.push(SnapshotEntry {
+        name,
+        created_at: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+        records: records.len(),
+    });
+    if index.snapshots.len() > keep {
+        let excess = index.snapshots.len() - keep;
+        for old in index.snapshots.drain(..excess) {
+            warn!(
+                "Pruning snapshot {} (keeping the newest {})",
+                old.name, keep
+            );
+            let old_dir = snapshots_dir(out_dir).join(&old.name);
+            if old_dir.exists() {
+                std::fs::remove_dir_all(old_dir)?;
+            }
+        }
+    }
+    save_index(out_dir, &index)?;
+    Ok(path)
+}
+
+/// Print the available snapshots, oldest first.
+pub fn print_history(index: &SnapshotIndex) {
+    if index.snapshots.is_empty() {
+        println!("No snapshots yet; run an export with --snapshot to create one.");
+        return;
+    }
+    println!("\n== Snapshots ({}) ==", index.snapshots.len());
+    for s in &index.snapshots {
+        println!("  {}  {}  {} records", s.name, s.created_at, s.records);
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn newest_snapshot_is_never_pruned() {
+        let dir =
+            std::env::temp_dir().join(format!("bangumi-tool-snapshot-{}-keep", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        write_snapshot(&dir, &[json!({"subject_id": 1})], 2).unwrap();
+        let newest = write_snapshot(&dir, &[json!({"subject_id": 2})], 0).unwrap();
+
+        let index = load_index(&dir).unwrap();
+        assert_eq!(index.snapshots.len(), 1);
+        assert!(newest.exists());
+        let kept = load_snapshot(&dir, &index.snapshots[0].name).unwrap();
+        assert_eq!(kept, [json!({"subject_id": 2})]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn snapshot_keep_must_be_positive() {
+        use clap::Parser;
+        let parse = |keep: &str| {
+            crate::cli::Args::try_parse_from(["bangumi-tool", "--snapshot-keep", keep])
+                .map(|args| args.snapshot_keep)
+        };
+        assert!(parse("0").is_err());
+        assert_eq!(parse("1").unwrap(), 1);
+    }
+}
@@ -0,0 +1,52 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::cli::SortBy;
+use crate::models::Collection;
+use crate::summary::{SummaryItem, collation_key, display_name};
+
+/// Sort export records by `key`, reversed with `reverse`; ties break by
+/// subject id so the order is stable across runs.
+///
+/// Records only keep formatted timestamps and type labels, so update times
+/// and subject types are looked up in `collections`.
+pub fn sort_records<T: SummaryItem>(
+    records: &mut [T],
+    collections: &[Collection],
+    key: SortBy,
+    reverse: bool,
+) {
+    let by_id: HashMap<u64, &Collection> = collections.iter().map(|c| (c.subject_id, c)).collect();
+    let collection = |r: &T| by_id.get(&r.subject_id()).copied();
+    let directed = |order: Ordering| if reverse { order.reverse() } else { order };
+    records.sort_by(|a, b| {
+        let order = match key {
+            SortBy::Rating => {
+                let rating = |r: &T| r.rating().parse::<u8>().ok();
+                match (rating(a), rating(b)) {
+                    (Some(x), Some(y)) => directed(y.cmp(&x)),
+                    // Unrated items sort last either way.
+                    (x, y) => x.is_none().cmp(&y.is_none()),
+                }
+            }
+            SortBy::Updated => {
+                let updated = |r: &T| {
+                    (
+                        collection(r).map(|c| c.updated_at),
+                        r.updated_at().to_string(),
+                    )
+                };
+                directed(updated(b).cmp(&updated(a)))
+            }
+            SortBy::Name => {
+                directed(collation_key(display_name(a)).cmp(&collation_key(display_name(b))))
+            }
+            SortBy::Type => {
+                let subject_type = |r: &T| collection(r).map(|c| c.subject.subject_type);
+                directed(subject_type(a).cmp(&subject_type(b)))
+            }
+            SortBy::Status => directed(a.collection_type().cmp(&b.collection_type())),
+        };
+        order.then_with(|| a.subject_id().cmp(&b.subject_id()))
+    });
+}
@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::models::{Collection, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// A 在看 collection that has not been updated for a while.
+pub struct StaleEntry {
+    pub name: String,
+    pub subject_type: &'static str,
+    pub url: String,
+    /// `ep_status/eps`, or just `ep_status` when the total is unknown.
+    pub progress: String,
+    pub updated_at: DateTime<Utc>,
+    pub days: i64,
+}
+
+/// Collect 在看 entries last updated at least `days` days before `now`, stalest first.
+pub fn find_stale(collections: &[Collection], days: u64, now: DateTime<Utc>) -> Vec<StaleEntry> {
+    let mut entries: Vec<StaleEntry> = collections
+        .iter()
+        .filter(|c| c.collection_type == 3)
+        .map(|c| StaleEntry {
+            name: if c.subject.name_cn.is_empty() {
+                c.subject.name.clone()
+            } else {
+                c.subject.name_cn.clone()
+            },
+            subject_type: subject_type_name(c.subject.subject_type),
+            url: format!("https://bgm.tv/subject/{}", c.subject_id),
+            progress: if c.subject.eps > 0 {
+                format!("{}/{}", c.ep_status, c.subject.eps)
+            } else {
+                c.ep_status.to_string()
+            },
+            updated_at: c.updated_at,
+            days: (now - c.updated_at).num_days(),
+        })
+        .filter(|e| e.days >= days as i64)
+        .collect();
+    entries.sort_by_key(|e| e.updated_at);
+    entries
+}
+
+fn local_date(t: &DateTime<Utc>) -> String {
+    t.with_timezone(&Local).format("%Y-%m-%d").to_string()
+}
+
+/// Print stale entries as an aligned table.
+pub fn print_stale(entries: &[StaleEntry], days: u64) {
+    println!(
+        "\n== 超过 {} 天未更新的在看条目: {} ==",
+        days,
+        entries.len()
+    );
+    let name_width = entries.iter().map(|e| e.name.width()).max().unwrap_or(0);
+    let progress_width = entries.iter().map(|e| e.progress.len()).max().unwrap_or(0);
+    for e in entries {
+        println!(
+            "  {}  [{}]  {:>pw$}  {}  {:>5}天",
+            pad_to_width(&e.name, name_width),
+            pad_to_width(e.subject_type, 6),
+            e.progress,
+            local_date(&e.updated_at),
+            e.days,
+            pw = progress_width
+        );
+    }
+    println!();
+}
+
+/// Write stale entries to `stale.csv` in the output directory.
+pub fn write_csv(entries: &[StaleEntry], dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("stale.csv");
+    let mut file = std::fs::File::create(&path)?;
+    std::io::Write::write_all(&mut file, b"\xEF\xBB\xBF")?;
+
+    let mut wtr = csv::Writer::from_writer(file);
+    wtr.write_record(["名称", "条目类型", "地址", "进度", "最后标注", "未更新天数"])?;
+    for e in entries {
+        wtr.write_record([
+            e.name.as_str(),
+            e.subject_type,
+            &e.url,
+            &e.progress,
+            &local_date(&e.updated_at),
+            &e.days.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(path)
+}
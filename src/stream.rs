@@ -0,0 +1,234 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::cli::Format;
+use crate::error::{AppError, Result};
+use crate::export::{CsvExporter, NdjsonExporter};
+use crate::locale::Lang;
+use crate::models::{Collection, ExportRecord};
+use crate::records::{self, TimeZone};
+
+/// Appends detail records to `bangumi_export.ndjson.partial` and
+/// `bangumi_export.csv.partial` as they complete, so an interrupted run still
+/// leaves the finished records on disk without touching the last complete
+/// export. The files are truncated when the writer is created, and the detail
+/// pass replays resumed records first, so a restarted run rebuilds them rather
+/// than appending to what the last run left. The end-of-run rewrite replaces
+/// the final files and then calls `remove_partials`.
+pub struct StreamWriter<'a> {
+    inner: Mutex<Inner>,
+    paths: Vec<PathBuf>,
+    /// Collections and time zone for `--anonymize`, applied to each record
+    /// before it is written.
+    anonymize: Option<(&'a [Collection], TimeZone)>,
+}
+
+struct Inner {
+    ndjson: Option<(NdjsonExporter, BufWriter<File>)>,
+    /// The CSV writer, and whether its header is written; the header waits for
+    /// the first record, whose optional columns it lists.
+    csv: Option<(CsvExporter, csv::Writer<File>, bool)>,
+    written: usize,
+    /// The first write that failed; later records are dropped.
+    error: Option<AppError>,
+}
+
+impl<'a> StreamWriter<'a> {
+    /// Create the partial stream files in `dir` for `format`: NDJSON for
+    /// `json`, CSV for `csv`, both for `all`.
+    pub fn create(
+        dir: &Path,
+        format: &Format,
+        lang: Lang,
+        anonymize: Option<(&'a [Collection], TimeZone)>,
+    ) -> Result<Self> {
+        let mut paths = Vec::new();
+        let ndjson = if matches!(format, Format::Json | Format::All) {
+            let path = partial_path(dir, "ndjson");
+            let file = BufWriter::new(File::create(&path)?);
+            paths.push(path);
+            Some((NdjsonExporter { lang }, file))
+        } else {
+            None
+        };
+        let csv = if matches!(format, Format::Csv | Format::All) {
+            let path = partial_path(dir, "csv");
+            let mut file = File::create(&path)?;
+            file.write_all(b"\xEF\xBB\xBF")?;
+            paths.push(path);
+            let exporter = CsvExporter { bom: true, lang };
+            Some((exporter, csv::Writer::from_writer(file), false))
+        } else {
+            None
+        };
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                ndjson,
+                csv,
+                written: 0,
+                error: None,
+            }),
+            paths,
+            anonymize,
+        })
+    }
+
+    /// The files being written.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Records written so far.
+    pub fn written(&self) -> usize {
+        self.inner.lock().unwrap().written
+    }
+
+    /// Append a record to every stream file and flush them. A failure is kept
+    /// for `finish` to return, and stops further writes.
+    pub fn push(&self, record: &ExportRecord) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.error.is_some() {
+            return;
+        }
+        let mut anonymized = Vec::new();
+        let record = match self.anonymize {
+            Some((collections, timezone)) => {
+                anonymized.push(record.clone());
+                records::anonymize(&mut anonymized, collections, timezone);
+                match anonymized.first() {
+                    Some(record) => record,
+                    // Private collections are left out.
+                    None => return,
+                }
+            }
+            None => record,
+        };
+        if let Err(e) = inner.write(record) {
+            inner.error = Some(e);
+        }
+    }
+
+    /// Flush the files, returning the number of records written or the first
+    /// write error.
+    pub fn finish(self) -> Result<usize> {
+        let mut inner = self.inner.into_inner().unwrap();
+        if let Some(e) = inner.error.take() {
+            return Err(e);
+        }
+        if let Some((_, file)) = &mut inner.ndjson {
+            file.flush()?;
+        }
+        if let Some((_, wtr, _)) = &mut inner.csv {
+            wtr.flush()?;
+        }
+        Ok(inner.written)
+    }
+}
+
+/// The partial stream file of the export with `extension` in `dir`.
+fn partial_path(dir: &Path, extension: &str) -> PathBuf {
+    dir.join(format!("bangumi_export.{}.partial", extension))
+}
+
+/// Remove the partial stream files in `dir`, once the final export files hold
+/// every record.
+pub fn remove_partials(dir: &Path) -> Result<()> {
+    for extension in ["ndjson", "csv"] {
+        match std::fs::remove_file(partial_path(dir, extension)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl Inner {
+    fn write(&mut self, record: &ExportRecord) -> Result<()> {
+        if let Some((exporter, file)) = &mut self.ndjson {
+            exporter.write_line(record, file)?;
+            file.flush()?;
+        }
+        if let Some((exporter, wtr, header_written)) = &mut self.csv {
+            if !*header_written {
+                wtr.write_record(exporter.header(Some(record)))?;
+                *header_written = true;
+            }
+            wtr.write_record(exporter.row(record).iter().map(|cell| cell.as_bytes()))?;
+            wtr.flush()?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(subject_id: u64) -> ExportRecord {
+        ExportRecord {
+            subject_id,
+            name: "カウボーイビバップ".to_string(),
+            name_cn: "星际牛仔".to_string(),
+            subject_type: "动画".to_string(),
+            url: format!("https://bgm.tv/subject/{}", subject_id),
+            status: "在看".to_string(),
+            collection_type: 3,
+            updated_at: "2024-01-02 03:04:05".to_string(),
+            completeness: "2/3".to_string(),
+            completeness_pct: "67%".to_string(),
+            watched_eps: "1-2".to_string(),
+            stopped_at: None,
+            aired_completeness: String::new(),
+            caught_up: false,
+            rating: String::new(),
+            rating_diff: None,
+            tags: String::new(),
+            site_tags: String::new(),
+            comment: String::new(),
+            summary: None,
+            infobox: Vec::new(),
+            relations: None,
+            watched_titles: None,
+            nsfw: false,
+            private: false,
+            tracks: None,
+        }
+    }
+
+    #[test]
+    fn streaming_leaves_the_final_files_alone() {
+        let dir = std::env::temp_dir().join(format!(
+            "bangumi-tool-stream-{}-partial",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let last_csv = dir.join("bangumi_export.csv");
+        let last_ndjson = dir.join("bangumi_export.ndjson");
+        std::fs::write(&last_csv, "last run").unwrap();
+        std::fs::write(&last_ndjson, "last run").unwrap();
+
+        let writer = StreamWriter::create(&dir, &Format::All, Lang::default(), None).unwrap();
+        writer.push(&record(253));
+        writer.push(&record(254));
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        assert_eq!(std::fs::read_to_string(&last_csv).unwrap(), "last run");
+        assert_eq!(std::fs::read_to_string(&last_ndjson).unwrap(), "last run");
+        let ndjson = std::fs::read_to_string(partial_path(&dir, "ndjson")).unwrap();
+        assert_eq!(ndjson.lines().count(), 2);
+        let csv = std::fs::read_to_string(partial_path(&dir, "csv")).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+
+        remove_partials(&dir).unwrap();
+        assert!(!partial_path(&dir, "ndjson").exists());
+        assert!(!partial_path(&dir, "csv").exists());
+        // Nothing left to remove is not an error.
+        remove_partials(&dir).unwrap();
+        assert!(last_csv.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,641 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashMap};
+use std::io::IsTerminal;
+
+use owo_colors::{OwoColorize, Style};
+use pinyin::ToPinyin;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::cli::{ColorChoice, FranchiseGrouping, SummarySort};
+use crate::export::Exportable;
+use crate::locale::Lang;
+use crate::models::{ExportRecord, RelatedSubject, SimpleRecord};
+use crate::records::NO_PROGRESS;
+
+/// A record that can be listed in the terminal summary, on top of the fields
+/// it exports.
+pub trait SummaryItem: Exportable {
+    /// Raw collection type (1=wish, 2=done, 3=doing, 4=on_hold, 5=dropped).
+    fn collection_type(&self) -> u8;
+    /// Progress shown after the item in `lang`, if any. Default: none.
+    fn progress(&self, _lang: Lang) -> Option<String> {
+        None
+    }
+    /// Watched and total main episodes, if known. Default: unknown.
+    fn episodes(&self) -> Option<(u64, u64)> {
+        None
+    }
+    /// Related subjects, if they were looked up. Default: none.
+    fn related(&self) -> &[RelatedSubject] {
+        &[]
+    }
+}
+
+impl SummaryItem for SimpleRecord {
+    fn collection_type(&self) -> u8 {
+        self.collection_type
+    }
+}
+
+impl SummaryItem for ExportRecord {
+    fn collection_type(&self) -> u8 {
+        self.collection_type
+    }
+
+    fn progress(&self, lang: Lang) -> Option<String> {
+        if self.completeness == "ERROR" {
+            return Some("ERROR".to_string());
+        }
+        match self.collection_type {
+            2 => Some("✓".to_string()),
+            3 if self.caught_up && self.episodes().is_some_and(|(w, t)| w < t) => Some(format!(
+                "{} {}",
+                progress_bar(&self.completeness, &self.completeness_pct),
+                lang.caught_up()
+            )),
+            3 | 4 if self.completeness == NO_PROGRESS => None,
+            3 | 4 => Some(progress_bar(&self.completeness, &self.completeness_pct)),
+            5 => self.stopped_at.map(|ep| lang.dropped_at(ep)),
+            _ => None,
+        }
+    }
+
+    fn episodes(&self) -> Option<(u64, u64)> {
+        let (watched, total) = self.completeness.split_once('/')?;
+        Some((watched.parse().ok()?, total.parse().ok()?))
+    }
+
+    fn related(&self) -> &[RelatedSubject] {
+        self.relations.as_deref().unwrap_or_default()
+    }
+}
+
+/// Width of the completion bar, excluding brackets.
+const PROGRESS_BAR_WIDTH: usize = 10;
+
+/// Render e.g. `[=====>    ] 13/24 (54%)`; without a percentage only the count is shown.
+fn progress_bar(completeness: &str, pct: &str) -> String {
+    let Some(pct_value) = pct.strip_suffix('%').and_then(|p| p.parse::<usize>().ok()) else {
+        return completeness.to_string();
+    };
+    let filled = (pct_value.min(100) * PROGRESS_BAR_WIDTH / 100).min(PROGRESS_BAR_WIDTH);
+    let bar = if filled == 0 {
+        " ".repeat(PROGRESS_BAR_WIDTH)
+    } else if filled == PROGRESS_BAR_WIDTH {
+        "=".repeat(PROGRESS_BAR_WIDTH)
+    } else {
+        format!(
+            "{}>{}",
+            "=".repeat(filled - 1),
+            " ".repeat(PROGRESS_BAR_WIDTH - filled)
+        )
+    };
+    format!("[{}] {} ({})", bar, completeness, pct)
+}
+
+/// Decide whether to color terminal output.
+///
+/// `auto` colors only when stdout is a terminal and `NO_COLOR` is unset or empty.
+pub fn use_color(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        }
+    }
+}
+
+/// Apply a style when color is enabled; otherwise return the text unchanged.
+pub(crate) fn paint(text: &str, style: Style, color: bool) -> String {
+    if color {
+        text.style(style).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Header style for a collection type group.
+pub(crate) fn group_style(collection_type: u8) -> Style {
+    match collection_type {
+        1 => Style::new().blue().bold(),
+        2 => Style::new().cyan().bold(),
+        3 => Style::new().green().bold(),
+        4 => Style::new().yellow().bold(),
+        _ => Style::new().dimmed(),
+    }
+}
+
+/// Indent before each item line.
+const ITEM_INDENT: usize = 4;
+/// Further indent of the seasons under a franchise line.
+const SEASON_INDENT: usize = 2;
+/// Spaces between table columns.
+const COLUMN_GAP: usize = 2;
+/// Narrowest name column worth rendering as a table.
+const MIN_NAME_WIDTH: usize = 16;
+/// Width of the date column, e.g. `2025-01-01`.
+const DATE_WIDTH: usize = 10;
+
+/// Column widths for the aligned item table.
+struct TableLayout {
+    name_width: usize,
+    type_width: usize,
+    /// Width of the rating column, e.g. `10分`.
+    rating_width: usize,
+    /// Columns left after the date for the progress suffix.
+    progress_width: usize,
+}
+
+impl TableLayout {
+    /// Fit the table into the terminal width, or `None` when stdout is not a
+    /// terminal or too narrow, in which case the simple format is used.
+    fn detect<T: SummaryItem>(records: &[T], lang: Lang) -> Option<Self> {
+        let (terminal_size::Width(term_width), _) = terminal_size::terminal_size()?;
+        let type_width = records
+            .iter()
+            .map(|r| lang.label(r.subject_type()).width())
+            .max()
+            .unwrap_or(0);
+        let rating_width = lang.rating(10).width();
+        let fixed = ITEM_INDENT + type_width + rating_width + DATE_WIDTH + 3 * COLUMN_GAP;
+        let available = (term_width as usize).checked_sub(fixed)?;
+        let longest = records
+            .iter()
+            .map(|r| display_name(r).width())
+            .max()
+            .unwrap_or(0);
+        let name_width = longest.min(available);
+        if name_width < MIN_NAME_WIDTH.min(longest) {
+            return None;
+        }
+        Some(Self {
+            name_width,
+            type_width,
+            rating_width,
+            progress_width: available - name_width,
+        })
+    }
+}
+
+pub fn display_name<T: SummaryItem>(r: &T) -> &str {
+    if r.name_cn().is_empty() {
+        r.name()
+    } else {
+        r.name_cn()
+    }
+}
+
+/// Cut a string to at most `max` display columns, ending with `…` when truncated.
+fn truncate_to_width(s: &str, max: usize) -> String {
+    if s.width() <= max {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w + 1 > max {
+            break;
+        }
+        out.push(c);
+        width += w;
+    }
+    out.push('…');
+    out
+}
+
+/// Pad a string with spaces to `width` display columns (CJK counts double).
+pub(crate) fn pad_to_width(s: &str, width: usize) -> String {
+    let pad = width.saturating_sub(s.width());
+    format!("{}{}", s, " ".repeat(pad))
+}
+
+/// Progress suffix for an item, with a leading space, or empty.
+fn progress_part<T: SummaryItem>(r: &T, lang: Lang) -> String {
+    r.progress(lang)
+        .map(|p| format!(" {}", p))
+        .unwrap_or_default()
+}
+
+/// The progress suffix cut to `width` columns, so a table row doesn't wrap.
+fn fitted_progress_part<T: SummaryItem>(r: &T, lang: Lang, width: usize) -> String {
+    match r.progress(lang) {
+        Some(p) if width > 1 => format!(" {}", truncate_to_width(&p, width - 1)),
+        _ => String::new(),
+    }
+}
+
+/// Print one item in the original `name [type] [rating]` format.
+fn print_simple_row<T: SummaryItem>(r: &T, color: bool, lang: Lang, indent: usize) {
+    let subject_type = format!("[{}]", lang.label(r.subject_type()));
+    let rating_part = match r.rating().parse::<u8>() {
+        Ok(rating) if rating >= 8 => {
+            let text = format!("[{}]", lang.rating(rating));
+            format!(" {}", paint(&text, Style::new().yellow().bold(), color))
+        }
+        Ok(rating) => format!(" [{}]", lang.rating(rating)),
+        Err(_) => String::new(),
+    };
+    println!(
+        "{}{} {}{}{}",
+        " ".repeat(indent),
+        display_name(r),
+        paint(&subject_type, Style::new().dimmed(), color),
+        rating_part,
+        progress_part(r, lang)
+    );
+}
+
+/// Print one item as an aligned table row: name, type, rating, last updated,
+/// then the progress in what is left of the terminal width.
+/// A deeper `indent` narrows the name column so the other columns stay aligned.
+fn print_table_row<T: SummaryItem>(
+    r: &T,
+    layout: &TableLayout,
+    color: bool,
+    lang: Lang,
+    indent: usize,
+) {
+    let gap = " ".repeat(COLUMN_GAP);
+    let name_width = layout.name_width.saturating_sub(indent - ITEM_INDENT);
+    let name = pad_to_width(&truncate_to_width(display_name(r), name_width), name_width);
+    let subject_type = pad_to_width(lang.label(r.subject_type()), layout.type_width);
+    let rating = match r.rating().parse::<u8>() {
+        Ok(rating) => {
+            let text = lang.rating(rating);
+            let text = format!(
+                "{}{}",
+                " ".repeat(layout.rating_width.saturating_sub(text.width())),
+                text
+            );
+            if rating >= 8 {
+                paint(&text, Style::new().yellow().bold(), color)
+            } else {
+                text
+            }
+        }
+        Err(_) => " ".repeat(layout.rating_width),
+    };
+    let date = r.updated_at().get(..DATE_WIDTH).unwrap_or(r.updated_at());
+    println!(
+        "{}{}{gap}{}{gap}{}{gap}{}{}",
+        " ".repeat(indent),
+        name,
+        paint(&subject_type, Style::new().dimmed(), color),
+        rating,
+        paint(date, Style::new().dimmed(), color),
+        fitted_progress_part(r, lang, layout.progress_width),
+    );
+}
+
+/// Options controlling how the summary is printed.
+pub struct SummaryOptions {
+    /// Emit ANSI colors.
+    pub color: bool,
+    /// Order of items within each status group; `None` keeps API order.
+    pub sort: Option<SummarySort>,
+    /// Language of group, status and type labels.
+    pub lang: Lang,
+    /// Nest the seasons of a franchise under one line, telling them apart this way.
+    pub group_franchises: Option<FranchiseGrouping>,
+}
+
+/// Relations that make two subjects seasons of one franchise.
+const SEASON_RELATIONS: [&str; 2] = ["续集", "前传"];
+
+/// One line of a status list: an item, or a franchise with its seasons.
+enum Entry<'a, T> {
+    Item(&'a T),
+    Franchise { name: String, items: Vec<&'a T> },
+}
+
+/// Root of `i` in a union-find forest; the root is the set's lowest index.
+fn find_root(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    parent[i] = root;
+    root
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find_root(parent, a), find_root(parent, b));
+    parent[a.max(b)] = a.min(b);
+}
+
+/// Whether a whitespace-separated token is a season number such as `2` or `II`.
+fn is_season_number(token: &str) -> bool {
+    token.chars().all(|c| c.is_ascii_digit())
+        || ["II", "III", "IV", "V", "VI", "VII", "VIII", "IX", "X"].contains(&token)
+}
+
+/// `name` with one season marker such as ` 第二季`, ` Season 2`, ` 2nd Season`
+/// or ` 最终季` cut from its end, or unchanged if it has none.
+fn strip_season_marker(name: &str) -> &str {
+    let lower = name.to_ascii_lowercase();
+    for suffix in [
+        "the final season",
+        "final season",
+        "最终季",
+        "最終季",
+        "最终章",
+        "完结篇",
+    ] {
+        if lower.ends_with(suffix) {
+            return &name[..name.len() - suffix.len()];
+        }
+    }
+    if let Some(i) = name.rfind('第')
+        && name.ends_with(['季', '期', '部', '章'])
+    {
+        let number = name[i + '第'.len_utf8()..].trim_end_matches(['季', '期', '部', '章']);
+        if !number.is_empty()
+            && number
+                .chars()
+                .all(|c| c.is_ascii_digit() || "一二三四五六七八九十".contains(c))
+        {
+            return &name[..i];
+        }
+    }
+    let Some((rest, last)) = name.rsplit_once(' ') else {
+        return name;
+    };
+    let rest_lower = rest.to_ascii_lowercase();
+    if is_season_number(last) {
+        for word in ["season", "part"] {
+            if rest_lower.ends_with(word) {
+                return &rest[..rest.len() - word.len()];
+            }
+        }
+        return rest;
+    }
+    if last.eq_ignore_ascii_case("season")
+        && let Some((before, ordinal)) = rest.rsplit_once(' ')
+        && ordinal.starts_with(|c: char| c.is_ascii_digit())
+        && ["st", "nd", "rd", "th"]
+            .iter()
+            .any(|s| ordinal.ends_with(s))
+    {
+        return before;
+    }
+    name
+}
+
+/// The franchise part of a season's name, with every season marker removed.
+/// A heuristic: names that merely share a word may come out the same.
+fn franchise_name(name: &str) -> &str {
+    let mut name = name.trim();
+    loop {
+        let stripped = strip_season_marker(name).trim_end_matches([' ', '　', ':', '：', '-']);
+        if stripped.is_empty() || stripped.len() == name.len() {
+            return name;
+        }
+        name = stripped;
+    }
+}
+
+/// Group the items of a status list into franchises; other items stay as they
+/// are. Each entry keeps the position of its first item.
+fn group_franchises<'a, T: SummaryItem>(
+    items: &[&'a T],
+    by: FranchiseGrouping,
+) -> Vec<Entry<'a, T>> {
+    let mut parent: Vec<usize> = (0..items.len()).collect();
+    match by {
+        FranchiseGrouping::Relations => {
+            let index: HashMap<u64, usize> = items
+                .iter()
+                .enumerate()
+                .map(|(i, r)| (r.subject_id(), i))
+                .collect();
+            for (i, r) in items.iter().enumerate() {
+                for related in r.related() {
+                    if SEASON_RELATIONS.contains(&related.relation.as_str())
+                        && let Some(&j) = index.get(&related.id)
+                    {
+                        union(&mut parent, i, j);
+                    }
+                }
+            }
+        }
+        FranchiseGrouping::Names => {
+            let mut first: HashMap<&str, usize> = HashMap::new();
+            for (i, r) in items.iter().enumerate() {
+                let j = *first.entry(franchise_name(display_name(*r))).or_insert(i);
+                union(&mut parent, i, j);
+            }
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<&T>> = BTreeMap::new();
+    for (i, r) in items.iter().enumerate() {
+        let root = find_root(&mut parent, i);
+        groups.entry(root).or_default().push(*r);
+    }
+    groups
+        .into_values()
+        .map(|group| match group.as_slice() {
+            [single] => Entry::Item(*single),
+            _ => {
+                let ids: Vec<u64> = group.iter().map(|r| r.subject_id()).collect();
+                // Name it after the first season: the one without a prequel here.
+                let first = group
+                    .iter()
+                    .find(|r| {
+                        !r.related()
+                            .iter()
+                            .any(|rel| rel.relation == "前传" && ids.contains(&rel.id))
+                    })
+                    .unwrap_or(&group[0]);
+                Entry::Franchise {
+                    name: franchise_name(display_name(*first)).to_string(),
+                    items: group,
+                }
+            }
+        })
+        .collect()
+}
+
+/// Print a franchise line: its name, season count and, when every season's
+/// episodes are known, the combined progress.
+fn print_franchise_line<T: SummaryItem>(name: &str, items: &[&T], color: bool, lang: Lang) {
+    let episodes = items
+        .iter()
+        .map(|r| r.episodes())
+        .collect::<Option<Vec<_>>>()
+        .map(|eps| eps.iter().fold((0, 0), |(w, t), (ew, et)| (w + ew, t + et)));
+    let aggregate = lang.franchise(items.len(), episodes);
+    println!(
+        "{}{}  {}",
+        " ".repeat(ITEM_INDENT),
+        paint(name, Style::new().bold(), color),
+        paint(&aggregate, Style::new().dimmed(), color),
+    );
+}
+
+/// Sort key for names that orders Chinese characters by pinyin,
+/// so that e.g. 阿 sorts before 中 regardless of code point.
+pub fn collation_key(name: &str) -> String {
+    let mut key = String::new();
+    for c in name.chars() {
+        match c.to_pinyin() {
+            Some(p) => key.push_str(p.plain()),
+            None => key.extend(c.to_lowercase()),
+        }
+    }
+    key
+}
+
+fn compare_names<T: SummaryItem>(a: &T, b: &T) -> Ordering {
+    collation_key(display_name(a)).cmp(&collation_key(display_name(b)))
+}
+
+/// Sort items within a group; ties fall back to name for deterministic output.
+fn sort_items<T: SummaryItem>(items: &mut [&T], sort: SummarySort) {
+    match sort {
+        SummarySort::Rating => items.sort_by(|a, b| {
+            // Unrated items (no parsable rating) sort last.
+            let key = |r: &T| Reverse(r.rating().parse::<u8>().ok());
+            key(a).cmp(&key(b)).then_with(|| compare_names(*a, *b))
+        }),
+        SummarySort::Updated => items.sort_by(|a, b| {
+            b.updated_at()
+                .cmp(a.updated_at())
+                .then_with(|| compare_names(*a, *b))
+        }),
+        SummarySort::Name => items.sort_by(|a, b| compare_names(*a, *b)),
+    }
+}
+
+/// Print collections grouped by logical status to terminal.
+///
+/// On a wide enough terminal items are laid out as an aligned table;
+/// otherwise each item is printed on one line in the simple format.
+/// Sorting only affects what is printed, never the export order.
+/// In detail mode items also show their progress: a completion bar for
+/// in-progress and on-hold items, ✓ for completed ones and where dropped ones stopped.
+pub fn print_summary<T: SummaryItem>(records: &[T], opts: &SummaryOptions) {
+    let color = opts.color;
+    let lang = opts.lang;
+    let layout = TableLayout::detect(records, lang);
+
+    let group_order: [(u8, &str); 5] = [
+        (3, "在看/在玩/在读/在听"),
+        (1, "想看/想玩/想读/想听"),
+        (2, "看过/玩过/读过/听过"),
+        (4, "搁置"),
+        (5, "抛弃"),
+    ];
+
+    let mut by_type: BTreeMap<u8, Vec<&T>> = BTreeMap::new();
+    for r in records {
+        by_type.entry(r.collection_type()).or_default().push(r);
+    }
+
+    for (ctype, label) in &group_order {
+        let Some(items) = by_type.get(ctype) else {
+            continue;
+        };
+
+        let mut by_status: BTreeMap<&str, Vec<&T>> = BTreeMap::new();
+        for r in items {
+            by_status.entry(r.status()).or_default().push(r);
+        }
+        if let Some(sort) = opts.sort {
+            for sub_items in by_status.values_mut() {
+                sort_items(sub_items, sort);
+            }
+        }
+
+        let style = group_style(*ctype);
+        let header = format!("== {} ({}) ==", lang.label(label), items.len());
+        println!("\n{}", paint(&header, style, color));
+        for (status, sub_items) in &by_status {
+            let header = format!("--- {} ({}) ---", lang.label(status), sub_items.len());
+            println!("  {}", paint(&header, style, color));
+            let print_row = |r: &T, indent: usize| match &layout {
+                Some(layout) => print_table_row(r, layout, color, lang, indent),
+                None => print_simple_row(r, color, lang, indent),
+            };
+            let entries = match opts.group_franchises {
+                Some(by) => group_franchises(sub_items, by),
+                None => sub_items.iter().map(|r| Entry::Item(*r)).collect(),
+            };
+            for entry in entries {
+                match entry {
+                    Entry::Item(r) => print_row(r, ITEM_INDENT),
+                    Entry::Franchise { name, items } => {
+                        print_franchise_line(&name, &items, color, lang);
+                        for r in items {
+                            print_row(r, ITEM_INDENT + SEASON_INDENT);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watching() -> ExportRecord {
+        ExportRecord {
+            subject_id: 253,
+            name: "カウボーイビバップ".to_string(),
+            name_cn: "星际牛仔".to_string(),
+            subject_type: "动画".to_string(),
+            url: "https://bgm.tv/subject/253".to_string(),
+            status: "在看".to_string(),
+            collection_type: 3,
+            updated_at: "2024-01-02 03:04:05".to_string(),
+            completeness: "13/24".to_string(),
+            completeness_pct: "54%".to_string(),
+            watched_eps: "1-13".to_string(),
+            stopped_at: None,
+            aired_completeness: String::new(),
+            caught_up: false,
+            rating: String::new(),
+            rating_diff: None,
+            tags: String::new(),
+            site_tags: String::new(),
+            comment: String::new(),
+            summary: None,
+            infobox: Vec::new(),
+            relations: None,
+            watched_titles: None,
+            nsfw: false,
+            private: false,
+            tracks: None,
+        }
+    }
+
+    #[test]
+    fn progress_is_cut_to_the_columns_left() {
+        let record = watching();
+        let full = progress_part(&record, Lang::default());
+        assert_eq!(full, " [====>     ] 13/24 (54%)");
+
+        assert_eq!(
+            fitted_progress_part(&record, Lang::default(), full.width()),
+            full
+        );
+        let cut = fitted_progress_part(&record, Lang::default(), 10);
+        assert_eq!(cut, " [====>  …");
+        assert_eq!(cut.width(), 10);
+        assert_eq!(fitted_progress_part(&record, Lang::default(), 1), "");
+    }
+
+    #[test]
+    fn names_come_from_the_exported_fields() {
+        let mut record = watching();
+        assert_eq!(display_name(&record), "星际牛仔");
+        record.name_cn.clear();
+        assert_eq!(display_name(&record), "カウボーイビバップ");
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::models::{Collection, subject_type_name};
+use crate::summary::pad_to_width;
+
+/// Tag usage counts for a set of collections.
+#[derive(Debug, Default, Serialize)]
+pub struct TagCounts {
+    /// Tags ordered by count descending, then by name.
+    pub tags: Vec<(String, u64)>,
+    /// Items carrying no tags at all.
+    pub untagged: u64,
+}
+
+impl TagCounts {
+    /// Count the items carrying each tag. A tag listed more than once on an
+    /// item, even with different surrounding spaces, counts once.
+    pub fn from_collections<'a>(collections: impl IntoIterator<Item = &'a Collection>) -> Self {
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        let mut untagged = 0;
+        for col in collections {
+            let tags: HashSet<&str> = col
+                .tags
+                .iter()
+                .map(|t| t.trim())
+                .filter(|t| !t.is_empty())
+                .collect();
+            if tags.is_empty() {
+                untagged += 1;
+            }
+            for tag in tags {
+                *counts.entry(tag.to_string()).or_default() += 1;
+            }
+        }
+        let mut tags: Vec<(String, u64)> = counts.into_iter().collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Self { tags, untagged }
+    }
+
+    /// Print the `top` most used tags as an aligned two-column table.
+    fn print(&self, label: &str, top: usize) {
+        println!(
+            "\n== {} ({} tags, {} untagged) ==",
+            label,
+            self.tags.len(),
+            self.untagged
+        );
+        let shown = &self.tags[..top.min(self.tags.len())];
+        let width = shown.iter().map(|(t, _)| t.width()).max().unwrap_or(0);
+        for (tag, count) in shown {
+            println!("  {}  {:>4}", pad_to_width(tag, width), count);
+        }
+    }
+}
+
+/// Tag frequencies overall and per subject type.
+#[derive(Debug, Serialize)]
+pub struct TagReport {
+    pub all: TagCounts,
+    pub by_type: BTreeMap<String, TagCounts>,
+}
+
+impl TagReport {
+    pub fn new(collections: &[Collection]) -> Self {
+        let mut grouped: BTreeMap<&str, Vec<&Collection>> = BTreeMap::new();
+        for col in collections {
+            grouped
+                .entry(subject_type_name(col.subject.subject_type))
+                .or_default()
+                .push(col);
+        }
+        Self {
+            all: TagCounts::from_collections(collections),
+            by_type: grouped
+                .into_iter()
+                .map(|(name, cols)| (name.to_string(), TagCounts::from_collections(cols)))
+                .collect(),
+        }
+    }
+
+    pub fn print(&self, top: usize) {
+        self.all.print("全部", top);
+        for (name, counts) in &self.by_type {
+            counts.print(name, top);
+        }
+        println!();
+    }
+
+    /// Write the full frequency map as `tags.json` in the output directory.
+    pub fn write_json(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join("tags.json");
+        let file = std::fs::File::create(&path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(subject_type: u8, tags: &[&str]) -> Collection {
+        serde_json::from_value(serde_json::json!({
+            "subject_id": 1,
+            "type": 2,
+            "rate": 0,
+            "ep_status": 0,
+            "updated_at": "2024-01-01T00:00:00Z",
+            "comment": null,
+            "tags": tags,
+            "subject": {
+                "id": 1, "name": "A", "name_cn": "", "type": subject_type, "eps": 0, "volumes": 0
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn repeated_tags_count_once_per_item() {
+        let collections = [
+            collection(2, &["科幻", " 科幻", "科幻 ", "经典"]),
+            collection(2, &["科幻"]),
+            collection(1, &[" ", ""]),
+        ];
+        let report = TagReport::new(&collections);
+
+        assert_eq!(
+            report.all.tags,
+            [("科幻".to_string(), 2), ("经典".to_string(), 1)]
+        );
+        assert_eq!(report.all.untagged, 1);
+        assert_eq!(report.by_type["动画"].tags[0], ("科幻".to_string(), 2));
+        assert_eq!(report.by_type["书籍"].untagged, 1);
+    }
+}
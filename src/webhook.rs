@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use reqwest::StatusCode;
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::error::Result;
+use crate::records::TimeZone;
+
+/// Attempts per delivery, including the first.
+const ATTEMPTS: u32 = 3;
+/// Delay before the first retry; doubled for each further one.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Time allowed for each attempt, so a hung endpoint can't stall the run.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Body sent to the webhook: the JSON export wrapped with some metadata.
+#[derive(Serialize)]
+struct Payload<'a> {
+    exported_at: String,
+    /// Time zone of the records' timestamps.
+    timezone: String,
+    username: &'a str,
+    /// Whether `--anonymize` stripped private items and personal fields.
+    anonymized: bool,
+    count: usize,
+    records: &'a [Value],
+}
+
+/// Hex-encoded HMAC-SHA256 of `body` keyed with `secret`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// POST the export to `url`, retrying on 5xx responses and connection errors.
+///
+/// With a `secret`, the body's signature is sent in `X-Signature`. `timezone`
+/// is the zone the records' timestamps are given in; `anonymized` tells whether
+/// they went through `--anonymize`. Requests are sent with `user_agent` and
+/// time out after 30 seconds, after which they are retried.
+/// Returns the status of the last response.
+pub async fn post_export(
+    url: &str,
+    secret: Option<&str>,
+    user_agent: &str,
+    username: &str,
+    timezone: TimeZone,
+    anonymized: bool,
+    records: &[Value],
+) -> Result<StatusCode> {
+    let body = serde_json::to_vec(&Payload {
+        exported_at: Local::now().to_rfc3339(),
+        timezone: timezone.to_string(),
+        username,
+        anonymized,
+        count: records.len(),
+        records,
+    })?;
+    let signature = secret.map(|s| sign(s, &body));
+    let http = reqwest::Client::builder()
+        .user_agent(user_agent)
+        .timeout(TIMEOUT)
+        .build()?;
+
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let mut request = http
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header("X-Signature", signature.as_str());
+        }
+        let result = request.send().await;
+        let retry = match &result {
+            Ok(resp) => resp.status().is_server_error(),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        if !retry || attempt == ATTEMPTS {
+            let resp = result?;
+            debug!("Webhook responded {}", resp.status());
+            return Ok(resp.status());
+        }
+        match &result {
+            Ok(resp) => warn!(
+                "Webhook returned {}, retrying in {:?}",
+                resp.status(),
+                delay
+            ),
+            Err(e) => warn!("Webhook request failed, retrying in {:?}: {}", delay, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
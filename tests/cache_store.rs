@@ -0,0 +1,175 @@
+//! Behavior every `CacheStore` shares, checked on both `Cache` and `MemoryCache`.
+
+use std::path::PathBuf;
+
+use bangumi_tool::{Cache, CacheStore};
+use serde_json::{Value, json};
+
+/// A file cache in a directory of its own, removed when dropped.
+struct TempCache {
+    cache: Cache,
+    dir: PathBuf,
+}
+
+impl TempCache {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "bangumi-tool-store-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        Self {
+            cache: Cache::new(&dir).unwrap(),
+            dir,
+        }
+    }
+}
+
+impl Drop for TempCache {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn empty_markers(cache: &impl CacheStore) {
+    cache.set_empty("1/episodes/1").unwrap();
+
+    assert!(cache.contains("1/episodes/1"));
+    assert!(cache.has("1/episodes/1"));
+    assert_eq!(cache.get::<Value>("1/episodes/1"), None);
+    assert_eq!(cache.entry_size("1/episodes/1"), 0);
+    assert_eq!(cache.keys("1/episodes/").unwrap(), ["1/episodes/1"]);
+    // The marker is a hit, not a miss, so it isn't fetched again.
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.stats().misses, 0);
+    assert_eq!(cache.stats().writes, 1);
+
+    cache.set("1/episodes/1", &json!([1, 2])).unwrap();
+    assert_eq!(cache.get::<Value>("1/episodes/1"), Some(json!([1, 2])));
+    cache.set_empty("1/episodes/1").unwrap();
+    assert_eq!(cache.get::<Value>("1/episodes/1"), None);
+    assert!(cache.contains("1/episodes/1"));
+}
+
+fn missing_keys(cache: &impl CacheStore) {
+    assert!(!cache.contains("1/subjects/9"));
+    assert!(!cache.has("1/subjects/9"));
+    assert_eq!(cache.get::<Value>("1/subjects/9"), None);
+    assert_eq!(cache.get_lines::<Value>("1/done_records"), None);
+    assert_eq!(cache.entry_size("1/subjects/9"), 0);
+    assert_eq!(cache.stats().hits, 0);
+    assert_eq!(cache.stats().misses, 3);
+}
+
+fn journals(cache: &impl CacheStore) {
+    for n in 1..=3 {
+        cache.append("1/done_records", &json!({ "n": n })).unwrap();
+    }
+    assert!(cache.contains("1/done_records"));
+    assert_eq!(
+        cache.get_lines::<Value>("1/done_records"),
+        Some(vec![json!({"n": 1}), json!({"n": 2}), json!({"n": 3})])
+    );
+
+    cache
+        .set_lines("1/done_records", &[json!({"n": 2})])
+        .unwrap();
+    assert_eq!(
+        cache.get_lines::<Value>("1/done_records"),
+        Some(vec![json!({"n": 2})])
+    );
+
+    // An empty journal is still an entry.
+    cache.set_lines::<Value>("1/done_records", &[]).unwrap();
+    assert_eq!(cache.get_lines::<Value>("1/done_records"), Some(vec![]));
+}
+
+fn journal_and_whole_value_replace_each_other(cache: &impl CacheStore) {
+    cache.set("1/done_records", &json!([{"n": 1}])).unwrap();
+    cache
+        .set_lines("1/done_records", &[json!({"n": 2})])
+        .unwrap();
+    assert_eq!(cache.get::<Value>("1/done_records"), None);
+    assert_eq!(
+        cache.get_lines::<Value>("1/done_records"),
+        Some(vec![json!({"n": 2})])
+    );
+
+    cache.set("1/done_records", &json!([{"n": 3}])).unwrap();
+    assert_eq!(cache.get_lines::<Value>("1/done_records"), None);
+    assert_eq!(
+        cache.get::<Value>("1/done_records"),
+        Some(json!([{"n": 3}]))
+    );
+}
+
+fn remove_prefix(cache: &impl CacheStore) {
+    cache.set_lines("1/done_records", &[json!(1)]).unwrap();
+    cache.set_lines("1/done_records_t2_s", &[json!(2)]).unwrap();
+    cache.set("1/collections/50_0", &json!({})).unwrap();
+    cache.set_empty("1/collections/50_50").unwrap();
+    cache
+        .set_etag("1/collections/50_0", Some("\"abc\""))
+        .unwrap();
+    cache.set("1/subjects/1", &json!({})).unwrap();
+    cache.set("2/collections/50_0", &json!({})).unwrap();
+
+    assert_eq!(cache.remove_prefix("1/done_records").unwrap(), 2);
+    assert_eq!(cache.remove_prefix("1/collections/").unwrap(), 2);
+    assert_eq!(cache.remove_prefix("1/collections/").unwrap(), 0);
+
+    assert!(!cache.contains("1/done_records"));
+    assert!(!cache.contains("1/done_records_t2_s"));
+    assert!(!cache.contains("1/collections/50_0"));
+    assert!(!cache.contains("1/collections/50_50"));
+    assert_eq!(cache.etag("1/collections/50_0"), None);
+    assert!(cache.contains("1/subjects/1"));
+    assert!(cache.contains("2/collections/50_0"));
+}
+
+fn etags(cache: &impl CacheStore) {
+    cache.set("1/subjects/1", &json!({})).unwrap();
+    cache.set_etag("1/subjects/1", Some(" \"v1\" ")).unwrap();
+    assert_eq!(cache.etag("1/subjects/1").as_deref(), Some("\"v1\""));
+    cache.set_etag("1/subjects/1", None).unwrap();
+    assert_eq!(cache.etag("1/subjects/1"), None);
+
+    cache.set_etag("1/subjects/1", Some("\"v2\"")).unwrap();
+    cache.remove("1/subjects/1").unwrap();
+    assert_eq!(cache.etag("1/subjects/1"), None);
+    assert!(!cache.contains("1/subjects/1"));
+}
+
+/// A test per check for each store, in modules `file` and `memory`.
+macro_rules! on_both_stores {
+    ($($check:ident),* $(,)?) => {
+        mod file {
+            $(
+                #[test]
+                fn $check() {
+                    let tmp = super::TempCache::new(stringify!($check));
+                    super::$check(&tmp.cache);
+                }
+            )*
+        }
+
+        mod memory {
+            $(
+                #[test]
+                fn $check() {
+                    super::$check(&bangumi_tool::MemoryCache::new());
+                }
+            )*
+        }
+    };
+}
+
+on_both_stores!(
+    empty_markers,
+    missing_keys,
+    journals,
+    journal_and_whole_value_replace_each_other,
+    remove_prefix,
+    etags,
+);
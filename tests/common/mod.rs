@@ -0,0 +1,193 @@
+//! A `BangumiApi` answering from canned fixtures, for testing the fetch
+//! pipeline without the network.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use bangumi_tool::client::Conditional;
+use bangumi_tool::models::{
+    Collection, EpisodeCollection, Paged, PagedCollection, PagedEpisodes, RelatedSubject,
+    SubjectDetail, User, UserProgress,
+};
+use bangumi_tool::{AppError, BangumiApi, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Serves a fixture from `tests/fixtures`, an object with these sections:
+///
+/// - `user`: the `/v0/me` response
+/// - `collections`: every collection, paged as the request asks
+/// - `subjects`, `episodes`, `episode_collections`, `progress`, `relations`:
+///   responses by subject id; episode lists are paged like collections
+///
+/// A subject missing from `subjects` or `episode_collections` answers 404, and
+/// one missing from `progress` has no legacy progress. Every request is logged,
+/// so tests can check what was fetched and what came from the cache.
+pub struct MockApi {
+    fixture: Value,
+    /// Status to reject every episode collections request with, sending
+    /// `fetch_progress` to the legacy endpoint.
+    pub reject_episode_collections: Option<u16>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl MockApi {
+    /// The mock for `tests/fixtures/{name}.json`.
+    pub fn load(name: &str) -> Self {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("{}.json", name));
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e));
+        Self {
+            fixture: serde_json::from_str(&text).unwrap(),
+            reject_episode_collections: None,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The requests made so far, such as `collections 0` or `episodes 101 0`.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Forget the logged requests.
+    pub fn clear_requests(&self) {
+        self.requests.lock().unwrap().clear();
+    }
+
+    /// The fixture's collections, as the list `fetch_collections` returns.
+    pub fn collections(&self) -> Vec<Collection> {
+        parse(self.fixture["collections"].clone())
+    }
+
+    /// Drop a subject from the fixture's collections, as if it were removed
+    /// on the website.
+    pub fn remove_collection(&mut self, subject_id: u64) {
+        if let Some(list) = self.fixture["collections"].as_array_mut() {
+            list.retain(|c| c["subject_id"] != subject_id);
+        }
+    }
+
+    fn log(&self, request: String) {
+        self.requests.lock().unwrap().push(request);
+    }
+
+    fn by_subject(&self, section: &str, id: u64) -> Option<&Value> {
+        self.fixture.get(section)?.get(id.to_string())
+    }
+}
+
+fn not_found(what: &str, id: u64) -> AppError {
+    AppError::Api {
+        status: 404,
+        message: format!("no {} for subject {} in fixture", what, id),
+    }
+}
+
+/// The page of `items` at `offset`, as the API pages a list.
+fn page(items: &Value, limit: u64, offset: u64) -> Value {
+    let items = items.as_array().cloned().unwrap_or_default();
+    let data: Vec<Value> = items
+        .iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .cloned()
+        .collect();
+    serde_json::json!({
+        "total": items.len(),
+        "limit": limit,
+        "offset": offset,
+        "data": data,
+    })
+}
+
+fn parse<T: DeserializeOwned>(value: Value) -> T {
+    serde_json::from_value(value).unwrap()
+}
+
+impl BangumiApi for MockApi {
+    async fn get_me(&self) -> Result<User> {
+        self.log("me".to_string());
+        Ok(parse(self.fixture["user"].clone()))
+    }
+
+    async fn get_collections(
+        &self,
+        _username: &str,
+        limit: u64,
+        offset: u64,
+    ) -> Result<PagedCollection> {
+        self.log(format!("collections {}", offset));
+        Ok(parse(page(&self.fixture["collections"], limit, offset)))
+    }
+
+    async fn get_subject(
+        &self,
+        id: u64,
+        _etag: Option<&str>,
+    ) -> Result<Conditional<SubjectDetail>> {
+        self.log(format!("subject {}", id));
+        let value = self
+            .by_subject("subjects", id)
+            .ok_or_else(|| not_found("subject", id))?;
+        Ok(Conditional::Modified {
+            value: parse(value.clone()),
+            etag: None,
+        })
+    }
+
+    async fn get_subject_relations(&self, id: u64) -> Result<Vec<RelatedSubject>> {
+        self.log(format!("relations {}", id));
+        Ok(self
+            .by_subject("relations", id)
+            .map(|v| parse(v.clone()))
+            .unwrap_or_default())
+    }
+
+    async fn get_episodes(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+        _etag: Option<&str>,
+    ) -> Result<Conditional<PagedEpisodes>> {
+        self.log(format!("episodes {} {}", subject_id, offset));
+        let episodes = self
+            .by_subject("episodes", subject_id)
+            .cloned()
+            .unwrap_or_default();
+        Ok(Conditional::Modified {
+            value: parse(page(&episodes, limit, offset)),
+            etag: None,
+        })
+    }
+
+    async fn get_progress(&self, _uid: u64, subject_id: u64) -> Result<Option<UserProgress>> {
+        self.log(format!("progress {}", subject_id));
+        Ok(self
+            .by_subject("progress", subject_id)
+            .map(|v| parse(v.clone())))
+    }
+
+    async fn get_episode_collections(
+        &self,
+        subject_id: u64,
+        limit: u64,
+        offset: u64,
+    ) -> Result<Paged<EpisodeCollection>> {
+        self.log(format!("episode_collections {} {}", subject_id, offset));
+        if let Some(status) = self.reject_episode_collections {
+            return Err(AppError::Api {
+                status,
+                message: "rejected by the mock".to_string(),
+            });
+        }
+        let entries = self
+            .by_subject("episode_collections", subject_id)
+            .ok_or_else(|| not_found("episode collections", subject_id))?;
+        Ok(parse(page(entries, limit, offset)))
+    }
+}
@@ -0,0 +1,334 @@
+//! The fetch pipeline against `MockApi`, with a `MemoryCache`.
+
+mod common;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bangumi_tool::fetch::{fetch_all_episodes, fetch_progress, fetch_subject};
+use bangumi_tool::models::{ExportRecord, TrackCount};
+use bangumi_tool::records::NO_PROGRESS;
+use bangumi_tool::{
+    AppError, CacheStore, DetailOptions, FetchEvent, MemoryCache, RecordOptions,
+    build_detail_record, fetch_collections, fetch_detail_records,
+};
+use common::MockApi;
+
+const UID: u64 = 42;
+
+fn detail_options() -> DetailOptions {
+    DetailOptions {
+        revalidate: false,
+        offline: false,
+        skip_errors: false,
+        shutdown: Arc::new(AtomicBool::new(false)),
+        done_key: format!("{}/done_records", UID),
+        changed: HashSet::new(),
+        record: RecordOptions::default(),
+    }
+}
+
+fn ids(records: &[ExportRecord]) -> Vec<u64> {
+    records.iter().map(|r| r.subject_id).collect()
+}
+
+fn subject_requests(api: &MockApi) -> Vec<String> {
+    api.requests()
+        .into_iter()
+        .filter(|r| r.starts_with("subject "))
+        .collect()
+}
+
+#[tokio::test]
+async fn collections_are_read_page_by_page() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+
+    let collections = fetch_collections(&api, &cache, UID, "tester", 2, None)
+        .await
+        .unwrap();
+
+    let ids: Vec<u64> = collections.iter().map(|c| c.subject_id).collect();
+    assert_eq!(ids, [101, 102, 103]);
+    assert_eq!(api.requests(), ["collections 0", "collections 2"]);
+}
+
+#[tokio::test]
+async fn cached_collection_pages_are_not_refetched() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    fetch_collections(&api, &cache, UID, "tester", 2, None)
+        .await
+        .unwrap();
+    api.clear_requests();
+
+    let collections = fetch_collections(&api, &cache, UID, "tester", 2, None)
+        .await
+        .unwrap();
+
+    assert_eq!(collections.len(), 3);
+    assert!(api.requests().is_empty());
+}
+
+#[tokio::test]
+async fn detail_records_follow_collection_order() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+
+    let (records, failed) =
+        fetch_detail_records(&api, &cache, UID, &collections, &detail_options(), None)
+            .await
+            .unwrap();
+
+    assert!(failed.is_empty());
+    assert_eq!(ids(&records), [101, 102, 103]);
+    let bebop = &records[0];
+    assert_eq!(bebop.name_cn, "星际牛仔");
+    assert_eq!(bebop.status, "在看");
+    assert_eq!(bebop.completeness, "2/3");
+    assert_eq!(bebop.watched_eps, "1-2");
+    assert_eq!(bebop.rating, "9");
+    assert_eq!(bebop.tags, "科幻, 经典");
+}
+
+#[tokio::test]
+async fn empty_episode_list_falls_back_to_episode_status() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+
+    let (records, _) =
+        fetch_detail_records(&api, &cache, UID, &collections, &detail_options(), None)
+            .await
+            .unwrap();
+
+    let empty = &records[1];
+    assert_eq!(empty.completeness, "0/0");
+    assert_eq!(empty.completeness_pct, "50%");
+    assert_eq!(empty.aired_completeness, "");
+    assert!(!empty.caught_up);
+    // Cached as an empty marker, so the next run doesn't ask again.
+    assert!(cache.has(&format!("{}/episodes/102", UID)));
+}
+
+#[tokio::test]
+async fn missing_progress_reads_as_nothing_watched() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+
+    let (records, _) =
+        fetch_detail_records(&api, &cache, UID, &collections, &detail_options(), None)
+            .await
+            .unwrap();
+
+    let unstarted = &records[2];
+    assert_eq!(unstarted.completeness, "0/2");
+    assert_eq!(unstarted.completeness_pct, "0%");
+    assert_eq!(unstarted.watched_eps, "");
+    let requests = api.requests();
+    assert!(requests.iter().any(|r| r == "episode_collections 103 0"));
+    // A 404 means nothing is marked, not that the v0 endpoint is unavailable.
+    assert!(!requests.iter().any(|r| r == "progress 103"));
+}
+
+#[tokio::test]
+async fn interrupted_pass_resumes_where_it_stopped() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+    let opts = detail_options();
+
+    // Stop after the first record is built.
+    let shutdown = opts.shutdown.clone();
+    let stop = move |event: FetchEvent| {
+        if let FetchEvent::Record(_) = event {
+            shutdown.store(true, Ordering::SeqCst);
+        }
+    };
+    let err = fetch_detail_records(&api, &cache, UID, &collections, &opts, Some(&stop))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::Interrupted { done: 1, total: 3 }));
+    assert_eq!(subject_requests(&api), ["subject 101"]);
+
+    api.clear_requests();
+    opts.shutdown.store(false, Ordering::SeqCst);
+    let (records, _) = fetch_detail_records(&api, &cache, UID, &collections, &opts, None)
+        .await
+        .unwrap();
+
+    assert_eq!(ids(&records), [101, 102, 103]);
+    assert_eq!(subject_requests(&api), ["subject 102", "subject 103"]);
+    assert_eq!(records[0].completeness, "2/3");
+}
+
+#[tokio::test]
+async fn finished_pass_is_resumed_without_requests() {
+    let api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+    let opts = detail_options();
+    let (first, _) = fetch_detail_records(&api, &cache, UID, &collections, &opts, None)
+        .await
+        .unwrap();
+    api.clear_requests();
+
+    let (second, _) = fetch_detail_records(&api, &cache, UID, &collections, &opts, None)
+        .await
+        .unwrap();
+
+    assert!(api.requests().is_empty());
+    assert_eq!(
+        serde_json::to_value(&first).unwrap(),
+        serde_json::to_value(&second).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn item_inserted_mid_list_keeps_records_with_their_subjects() {
+    let mut api = MockApi::load("account");
+    let cache = MemoryCache::new();
+    let opts = detail_options();
+    let full = api.collections();
+
+    // The first run sees the list without the middle item.
+    api.remove_collection(102);
+    let collections = api.collections();
+    let (first, _) = fetch_detail_records(&api, &cache, UID, &collections, &opts, None)
+        .await
+        .unwrap();
+    assert_eq!(ids(&first), [101, 103]);
+
+    // It has since been added on the website, between the two.
+    api.clear_requests();
+    let (records, _) = fetch_detail_records(&api, &cache, UID, &full, &opts, None)
+        .await
+        .unwrap();
+
+    assert_eq!(ids(&records), [101, 102, 103]);
+    assert_eq!(subject_requests(&api), ["subject 102"]);
+    for (record, col) in records.iter().zip(&full) {
+        assert_eq!(record.name, col.subject.name);
+    }
+    assert_eq!(records[0].completeness, "2/3");
+    assert_eq!(records[1].completeness, "0/0");
+    assert_eq!(records[2].completeness, "0/2");
+}
+
+/// The record for subject 101 built from progress fetched with `api`.
+async fn record_from_progress(api: &MockApi) -> (ExportRecord, Vec<u64>) {
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+    let col = &collections[0];
+    let detail = fetch_subject(api, &cache, UID, 101, false, None)
+        .await
+        .unwrap();
+    let episodes = fetch_all_episodes(api, &cache, UID, 101, false, None)
+        .await
+        .unwrap();
+    let progress = fetch_progress(api, &cache, UID, 101, None).await.unwrap();
+    let mut watched = progress.as_ref().unwrap().watched_ids();
+    watched.sort_unstable();
+    let record = build_detail_record(
+        col,
+        &detail,
+        &episodes,
+        &progress,
+        &RecordOptions::default(),
+    );
+    (record, watched)
+}
+
+#[tokio::test]
+async fn both_progress_endpoints_give_the_same_record() {
+    let v0 = MockApi::load("account");
+    let mut legacy = MockApi::load("account");
+    legacy.reject_episode_collections = Some(400);
+
+    let (from_v0, watched_v0) = record_from_progress(&v0).await;
+    let (from_legacy, watched_legacy) = record_from_progress(&legacy).await;
+
+    assert!(!v0.requests().iter().any(|r| r == "progress 101"));
+    assert!(legacy.requests().iter().any(|r| r == "progress 101"));
+    assert_eq!(watched_v0, [1001, 1002, 1004]);
+    assert_eq!(watched_v0, watched_legacy);
+    assert_eq!(from_v0.watched_eps, "1-2");
+    assert_eq!(from_v0.watched_eps, from_legacy.watched_eps);
+    assert_eq!(from_v0.completeness, from_legacy.completeness);
+    assert_eq!(from_v0.stopped_at, from_legacy.stopped_at);
+}
+
+#[tokio::test]
+async fn progress_is_cached_in_one_shape_for_both_endpoints() {
+    let mut legacy = MockApi::load("account");
+    legacy.reject_episode_collections = Some(400);
+    let cache = MemoryCache::new();
+    fetch_progress(&legacy, &cache, UID, 101, None)
+        .await
+        .unwrap();
+
+    // Read back through the v0 mock, which is not asked again.
+    let v0 = MockApi::load("account");
+    let cached = fetch_progress(&v0, &cache, UID, 101, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(v0.requests().is_empty());
+    let mut watched = cached.watched_ids();
+    watched.sort_unstable();
+    assert_eq!(watched, [1001, 1002, 1004]);
+}
+
+#[tokio::test]
+async fn music_records_have_no_episode_progress() {
+    let api = MockApi::load("music");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+
+    let (records, _) =
+        fetch_detail_records(&api, &cache, UID, &collections, &detail_options(), None)
+            .await
+            .unwrap();
+
+    let album = &records[0];
+    assert_eq!(album.status, "听过");
+    assert_eq!(album.completeness, NO_PROGRESS);
+    assert_eq!(album.watched_eps, "");
+    assert_eq!(album.tracks, None);
+    // Neither the track list nor the progress is asked for.
+    assert_eq!(api.requests(), ["subject 301"]);
+}
+
+#[tokio::test]
+async fn music_tracks_are_counted_across_discs() {
+    let api = MockApi::load("music");
+    let cache = MemoryCache::new();
+    let collections = api.collections();
+    let mut opts = detail_options();
+    opts.record.music_tracks = true;
+
+    let (records, _) = fetch_detail_records(&api, &cache, UID, &collections, &opts, None)
+        .await
+        .unwrap();
+
+    let album = &records[0];
+    assert_eq!(album.completeness, NO_PROGRESS);
+    assert_eq!(
+        album.tracks,
+        Some(TrackCount {
+            discs: 2,
+            tracks: 5
+        })
+    );
+    let requests = api.requests();
+    assert!(requests.iter().any(|r| r == "episodes 301 0"));
+    assert!(!requests.iter().any(|r| r.starts_with("progress ")));
+    assert!(
+        !requests
+            .iter()
+            .any(|r| r.starts_with("episode_collections "))
+    );
+}